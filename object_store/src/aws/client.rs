@@ -171,6 +171,8 @@ impl TryFrom<ListContents> for ObjectMeta {
             location: Path::parse(value.key)?,
             last_modified: value.last_modified,
             size: value.size,
+            content_type: None,
+            e_tag: None,
         })
     }
 }
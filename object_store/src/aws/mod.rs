@@ -209,7 +209,7 @@ impl ObjectStore for AmazonS3 {
             })
             .boxed();
 
-        Ok(GetResult::Stream(stream))
+        Ok(GetResult::Stream(stream, None))
     }
 
     async fn get_range(&self, location: &Path, range: Range<usize>) -> Result<Bytes> {
@@ -255,6 +255,8 @@ impl ObjectStore for AmazonS3 {
             location: location.clone(),
             last_modified,
             size: content_length,
+            content_type: None,
+            e_tag: None,
         })
     }
 
@@ -314,7 +316,7 @@ struct S3MultiPartUpload {
 impl CloudMultiPartUploadImpl for S3MultiPartUpload {
     async fn put_multipart_part(
         &self,
-        buf: Vec<u8>,
+        buf: Bytes,
         part_idx: usize,
     ) -> Result<UploadPart, std::io::Error> {
         use reqwest::header::ETAG;
@@ -324,7 +326,7 @@ impl CloudMultiPartUploadImpl for S3MultiPartUpload {
             .client
             .put_request(
                 &self.location,
-                Some(buf.into()),
+                Some(buf),
                 &[("partNumber", &part), ("uploadId", &self.upload_id)],
             )
             .await?;
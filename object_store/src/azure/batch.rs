@@ -0,0 +1,358 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Support for [Blob Batch](https://learn.microsoft.com/en-us/rest/api/storageservices/blob-batch)
+//!
+//! A batch request is a `multipart/mixed` body of up to 256 individually authorized
+//! HTTP subrequests. The response is a `multipart/mixed` body of the corresponding
+//! subresponses, each identified by the `Content-ID` of its subrequest.
+
+use super::credential::{ACCESS_TIER, DELETE_SNAPSHOTS};
+use super::AccessTier;
+use crate::path::Path;
+use rand::Rng;
+use reqwest::header::{HeaderMap, HeaderValue};
+use reqwest::{Method, Request};
+use snafu::{OptionExt, Snafu};
+use std::fmt::Write;
+
+/// The maximum number of subrequests in a single batch request
+pub(crate) const MAX_BATCH_SIZE: usize = 256;
+
+static ERROR_CODE: &str = "x-ms-error-code";
+
+#[derive(Debug, Snafu)]
+pub(crate) enum Error {
+    #[snafu(display("Got invalid batch response: {}", message))]
+    InvalidBatchResponse { message: String },
+
+    #[snafu(display("Batch request failed with status {}: {}", status, message))]
+    BatchFailed { status: u16, message: String },
+
+    #[snafu(display(
+        "Batch subrequest for {} failed with status {} ({}): {}",
+        path,
+        status,
+        code.as_deref().unwrap_or("unknown"),
+        message
+    ))]
+    SubrequestFailed {
+        path: String,
+        status: u16,
+        code: Option<String>,
+        message: String,
+    },
+}
+
+impl From<Error> for crate::Error {
+    fn from(source: Error) -> Self {
+        match &source {
+            Error::SubrequestFailed {
+                path, status: 404, ..
+            } => Self::NotFound {
+                path: path.clone(),
+                source: Box::new(source),
+            },
+            _ => Self::Generic {
+                store: "MicrosoftAzure",
+                source: Box::new(source),
+            },
+        }
+    }
+}
+
+/// A subrequest of a batch request, which has no body
+#[derive(Debug)]
+pub(crate) struct Subrequest {
+    pub method: Method,
+    pub path: Path,
+    pub query: &'static [(&'static str, &'static str)],
+    pub headers: HeaderMap,
+}
+
+impl Subrequest {
+    /// A [Set Blob Tier](https://learn.microsoft.com/en-us/rest/api/storageservices/set-blob-tier)
+    /// subrequest
+    pub fn set_tier(path: Path, tier: AccessTier) -> Self {
+        let mut headers = HeaderMap::new();
+        headers.insert(&ACCESS_TIER, HeaderValue::from_static(tier.as_str()));
+        Self {
+            method: Method::PUT,
+            path,
+            query: &[("comp", "tier")],
+            headers,
+        }
+    }
+
+    /// A [Delete Blob](https://learn.microsoft.com/en-us/rest/api/storageservices/delete-blob)
+    /// subrequest, which also deletes any snapshots of the blob
+    pub fn delete(path: Path) -> Self {
+        let mut headers = HeaderMap::new();
+        headers.insert(&DELETE_SNAPSHOTS, HeaderValue::from_static("include"));
+        Self {
+            method: Method::DELETE,
+            path,
+            query: &[],
+            headers,
+        }
+    }
+}
+
+/// The status of a single subrequest, parsed from the batch response
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Subresponse {
+    status: u16,
+    code: Option<String>,
+    message: String,
+}
+
+impl Subresponse {
+    /// Returns the result of the subrequest for `path`
+    pub fn into_result(self, path: &Path) -> crate::Result<()> {
+        if (200..300).contains(&self.status) {
+            return Ok(());
+        }
+        Err(Error::SubrequestFailed {
+            path: path.to_string(),
+            status: self.status,
+            code: self.code,
+            message: self.message,
+        }
+        .into())
+    }
+}
+
+/// Returns a random boundary for a batch request
+pub(crate) fn new_boundary() -> String {
+    format!("batch_{:032x}", rand::thread_rng().gen::<u128>())
+}
+
+/// Serializes the authorized `requests` as the `multipart/mixed` body of a batch
+/// request, where the `Content-ID` of each is its index
+pub(crate) fn request_body(boundary: &str, requests: &[Request]) -> String {
+    let mut body = String::new();
+    for (idx, request) in requests.iter().enumerate() {
+        let url = request.url();
+        let target = &url[url::Position::BeforePath..];
+        // Writing to a String is infallible
+        let _ = write!(
+            body,
+            "--{boundary}\r\n\
+            Content-Type: application/http\r\n\
+            Content-Transfer-Encoding: binary\r\n\
+            Content-ID: {idx}\r\n\
+            \r\n\
+            {} {target} HTTP/1.1\r\n",
+            request.method(),
+        );
+        for (name, value) in request.headers() {
+            let value = String::from_utf8_lossy(value.as_bytes());
+            let _ = write!(body, "{}: {}\r\n", name, value);
+        }
+        body.push_str("\r\n");
+    }
+    let _ = write!(body, "--{boundary}--\r\n");
+    body
+}
+
+/// Parses the `multipart/mixed` body of a batch response with `content_type`
+/// into the [`Subresponse`] of each of the `count` subrequests, by index
+///
+/// Returns an error if the batch as a whole failed, in which case the response
+/// contains a single subresponse without a `Content-ID`
+pub(crate) fn parse_response(
+    content_type: &str,
+    body: &[u8],
+    count: usize,
+) -> Result<Vec<Option<Subresponse>>, Error> {
+    let boundary = content_type
+        .split(';')
+        .filter_map(|param| param.trim().strip_prefix("boundary="))
+        .map(|boundary| boundary.trim_matches('"'))
+        .next()
+        .context(InvalidBatchResponseSnafu {
+            message: format!("missing boundary in content type '{}'", content_type),
+        })?;
+
+    let body = String::from_utf8_lossy(body).replace("\r\n", "\n");
+    let delimiter = format!("--{}", boundary);
+    let mut responses: Vec<_> = (0..count).map(|_| None).collect();
+
+    // Skip the preamble before the first delimiter
+    for part in body.split(delimiter.as_str()).skip(1) {
+        // The final delimiter is followed by `--`
+        if part.starts_with("--") {
+            break;
+        }
+
+        let (mime_headers, http) = split_headers(part.trim_start_matches('\n'))?;
+        let content_id = header(mime_headers, "content-id");
+
+        let (head, body) = split_headers(http)?;
+        let status_line = head.lines().next().unwrap_or_default();
+        let mut status = status_line.splitn(3, ' ').skip(1);
+        let code = status.next().and_then(|s| s.parse::<u16>().ok()).context(
+            InvalidBatchResponseSnafu {
+                message: format!("invalid status line '{}'", status_line),
+            },
+        )?;
+        let reason = status.next().unwrap_or_default().trim();
+        let message = match body.trim() {
+            "" => reason.to_string(),
+            body => format!("{}: {}", reason, body),
+        };
+
+        let content_id = match content_id {
+            Some(content_id) => content_id,
+            None => {
+                return Err(Error::BatchFailed {
+                    status: code,
+                    message,
+                })
+            }
+        };
+
+        let idx = content_id
+            .parse::<usize>()
+            .ok()
+            .filter(|idx| *idx < count)
+            .context(InvalidBatchResponseSnafu {
+                message: format!("unexpected Content-ID '{}'", content_id),
+            })?;
+
+        responses[idx] = Some(Subresponse {
+            status: code,
+            code: header(head, ERROR_CODE).map(ToString::to_string),
+            message,
+        });
+    }
+    Ok(responses)
+}
+
+/// Splits `s` into the headers and body either side of the first empty line
+fn split_headers(s: &str) -> Result<(&str, &str), Error> {
+    match s.split_once("\n\n") {
+        Some(split) => Ok(split),
+        None if !s.trim().is_empty() => Ok((s, "")),
+        None => Err(Error::InvalidBatchResponse {
+            message: "empty subresponse".to_string(),
+        }),
+    }
+}
+
+/// Returns the value of the header `name` in `headers`, ignoring case
+fn header<'a>(headers: &'a str, name: &str) -> Option<&'a str> {
+    headers.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.trim().eq_ignore_ascii_case(name).then(|| value.trim())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_body() {
+        let client = reqwest::Client::new();
+        let requests = vec![
+            client
+                .put("https://account.blob.core.windows.net/container/a?comp=tier")
+                .header(&ACCESS_TIER, "Cool")
+                .build()
+                .unwrap(),
+            client
+                .delete("https://account.blob.core.windows.net/container/b")
+                .build()
+                .unwrap(),
+        ];
+
+        let body = request_body("batch_1", &requests);
+        assert_eq!(
+            body,
+            "--batch_1\r\n\
+            Content-Type: application/http\r\n\
+            Content-Transfer-Encoding: binary\r\n\
+            Content-ID: 0\r\n\
+            \r\n\
+            PUT /container/a?comp=tier HTTP/1.1\r\n\
+            x-ms-access-tier: Cool\r\n\
+            \r\n\
+            --batch_1\r\n\
+            Content-Type: application/http\r\n\
+            Content-Transfer-Encoding: binary\r\n\
+            Content-ID: 1\r\n\
+            \r\n\
+            DELETE /container/b HTTP/1.1\r\n\
+            \r\n\
+            --batch_1--\r\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let body = "--batchresponse_1\r\n\
+            Content-Type: application/http\r\n\
+            Content-ID: 1\r\n\
+            \r\n\
+            HTTP/1.1 404 The specified blob does not exist.\r\n\
+            x-ms-error-code: BlobNotFound\r\n\
+            Content-Length: 216\r\n\
+            \r\n\
+            <?xml version=\"1.0\" encoding=\"utf-8\"?><Error><Code>BlobNotFound</Code></Error>\r\n\
+            --batchresponse_1\r\n\
+            Content-Type: application/http\r\n\
+            Content-ID: 0\r\n\
+            \r\n\
+            HTTP/1.1 202 Accepted\r\n\
+            x-ms-request-id: 778fdc83\r\n\
+            \r\n\
+            --batchresponse_1--";
+
+        let content_type = "multipart/mixed; boundary=batchresponse_1";
+        let responses = parse_response(content_type, body.as_bytes(), 3).unwrap();
+        assert_eq!(responses.len(), 3);
+        assert_eq!(responses[0].as_ref().unwrap().status, 202);
+        assert_eq!(responses[0].as_ref().unwrap().code, None);
+        let not_found = responses[1].as_ref().unwrap();
+        assert_eq!(not_found.status, 404);
+        assert_eq!(not_found.code.as_deref(), Some("BlobNotFound"));
+        assert!(not_found
+            .message
+            .starts_with("The specified blob does not exist"));
+        assert!(responses[2].is_none());
+
+        // A failure of the batch as a whole has no Content-ID
+        let body = "--batchresponse_2\r\n\
+            Content-Type: application/http\r\n\
+            \r\n\
+            HTTP/1.1 403 Server failed to authenticate the request.\r\n\
+            x-ms-error-code: AuthenticationFailed\r\n\
+            \r\n\
+            --batchresponse_2--";
+        let content_type = "multipart/mixed; boundary=batchresponse_2";
+        let err = parse_response(content_type, body.as_bytes(), 1).unwrap_err();
+        assert!(
+            matches!(err, Error::BatchFailed { status: 403, .. }),
+            "{}",
+            err
+        );
+
+        let err = parse_response("application/xml", b"", 1).unwrap_err();
+        assert!(err.to_string().contains("missing boundary"), "{}", err);
+    }
+}
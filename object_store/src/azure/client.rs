@@ -15,30 +15,61 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use super::credential::{AzureCredential, CredentialProvider};
+use super::batch::{self, Subrequest, MAX_BATCH_SIZE};
+use super::credential::{authorize_subrequest, AzureCredential, CredentialProvider};
+use super::{
+    AccessTier, BlobProperties, BlockInfo, ExpiryOption, RequestObserverFn, RequestTiming,
+};
 use crate::azure::credential::*;
+use crate::client::backoff::Backoff;
 use crate::client::pagination::stream_paginated;
 use crate::client::retry::RetryExt;
 use crate::path::DELIMITER;
 use crate::util::{deserialize_rfc1123, format_http_range, format_prefix};
 use crate::{
-    BoxStream, ClientOptions, ListResult, ObjectMeta, Path, Result, RetryConfig,
-    StreamExt,
+    BoxStream, ClientOptions, CopyOptions, GetOptions, ListResult, ObjectMeta, Path,
+    PutOptions, Result, RetryConfig, StreamExt,
 };
-use bytes::{Buf, Bytes};
+use bytes::{Buf, Bytes, BytesMut};
 use chrono::{DateTime, Utc};
+use hyper::client::connect::HttpInfo;
 use itertools::Itertools;
-use reqwest::header::CONTENT_TYPE;
+use md5::{Digest, Md5};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use reqwest::header::{
+    HeaderMap, HeaderName, InvalidHeaderName, InvalidHeaderValue, CONTENT_TYPE,
+};
 use reqwest::{
-    header::{HeaderValue, CONTENT_LENGTH, IF_NONE_MATCH, RANGE},
-    Client as ReqwestClient, Method, Response, StatusCode,
+    header::{
+        HeaderValue, AUTHORIZATION, CONTENT_LENGTH, DATE, ETAG, IF_MATCH,
+        IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_UNMODIFIED_SINCE, RANGE,
+    },
+    tls::TlsInfo,
+    Client as ReqwestClient, Method, RequestBuilder, Response, StatusCode,
 };
 use serde::{Deserialize, Serialize};
-use snafu::{ResultExt, Snafu};
+use snafu::{ensure, IntoError, ResultExt, Snafu};
 use std::collections::HashMap;
 use std::ops::Range;
+use std::time::Instant;
+use tracing::{info, warn};
 use url::Url;
 
+/// The characters percent-encoded in the path segments of request urls, which is
+/// everything other than the [RFC 3986] unreserved characters
+///
+/// This is stricter than the encoding [`Url`] applies to path segments, which keeps
+/// characters such as `!` and `*` literal, and ensures the url path, and hence the
+/// canonicalized resource of a shared key signature, matches the canonical form of
+/// the blob name computed by Azure
+///
+/// [RFC 3986]: https://www.rfc-editor.org/rfc/rfc3986#section-2.3
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
 /// A specialized `Error` for object store-related errors
 #[derive(Debug, Snafu)]
 #[allow(missing_docs)]
@@ -49,6 +80,9 @@ pub(crate) enum Error {
         path: String,
     },
 
+    #[snafu(display("Object at {} has not been modified", path))]
+    NotModified { path: String },
+
     #[snafu(display("Error getting get response body {}: {}", path, source))]
     GetResponseBody {
         source: reqwest::Error,
@@ -61,6 +95,53 @@ pub(crate) enum Error {
         path: String,
     },
 
+    #[snafu(display("Error performing append block request {}: {}", path, source))]
+    AppendBlockRequest {
+        source: crate::client::retry::Error,
+        path: String,
+    },
+
+    #[snafu(display(
+        "Cannot append {} bytes to {}, as blocks are limited to {} bytes",
+        size,
+        path,
+        MAX_APPEND_BLOCK_SIZE
+    ))]
+    AppendBlockTooLarge { size: usize, path: String },
+
+    #[snafu(display(
+        "Cannot append to {}, as it has the maximum of {} blocks: {}",
+        path,
+        MAX_APPEND_BLOCKS,
+        source
+    ))]
+    AppendBlockCountExceeded {
+        source: crate::client::retry::Error,
+        path: String,
+    },
+
+    #[snafu(display(
+        "The data uploaded to {} did not match its Content-MD5: {}",
+        path,
+        source
+    ))]
+    UploadChecksumMismatch {
+        source: crate::client::retry::Error,
+        path: String,
+    },
+
+    #[snafu(display(
+        "The data received from {} has Content-MD5 {}, expected {}",
+        path,
+        actual,
+        expected
+    ))]
+    ChecksumMismatch {
+        path: String,
+        expected: String,
+        actual: String,
+    },
+
     #[snafu(display("Error performing delete request {}: {}", path, source))]
     DeleteRequest {
         source: crate::client::retry::Error,
@@ -73,6 +154,61 @@ pub(crate) enum Error {
         path: String,
     },
 
+    #[snafu(display("Copy to {} {}: {}", path, status, description))]
+    CopyFailed {
+        path: String,
+        status: String,
+        description: String,
+    },
+
+    #[snafu(display(
+        "Copy to {} did not complete within {:?}, last progress {}",
+        path,
+        timeout,
+        progress
+    ))]
+    CopyTimeout {
+        path: String,
+        timeout: std::time::Duration,
+        progress: String,
+    },
+
+    #[snafu(display("Error performing set properties request {}: {}", path, source))]
+    SetPropertiesRequest {
+        source: crate::client::retry::Error,
+        path: String,
+    },
+
+    #[snafu(display("Error performing dfs request {}: {}", path, source))]
+    DfsRequest {
+        source: crate::client::retry::Error,
+        path: String,
+    },
+
+    #[snafu(display("Error performing warm up request: {}", source))]
+    WarmUpRequest { source: reqwest::Error },
+
+    #[snafu(display("Error performing batch request: {}", source))]
+    BatchRequest { source: crate::client::retry::Error },
+
+    #[snafu(display("Error getting batch response body: {}", source))]
+    BatchResponseBody { source: reqwest::Error },
+
+    #[snafu(display("Response body exceeded the limit of {} bytes", limit))]
+    ResponseBodyTooLarge { limit: usize },
+
+    #[snafu(display("Invalid metadata key '{}': {}", key, source))]
+    InvalidMetadataKey {
+        source: InvalidHeaderName,
+        key: String,
+    },
+
+    #[snafu(display("Invalid header value '{}': {}", value, source))]
+    InvalidHeaderValue {
+        source: InvalidHeaderValue,
+        value: String,
+    },
+
     #[snafu(display("Error performing list request: {}", source))]
     ListRequest { source: crate::client::retry::Error },
 
@@ -82,19 +218,108 @@ pub(crate) enum Error {
     #[snafu(display("Got invalid list response: {}", source))]
     InvalidListResponse { source: quick_xml::de::DeError },
 
+    #[snafu(display("Got invalid block list response: {}", source))]
+    InvalidBlockListResponse { source: quick_xml::de::DeError },
+
+    #[snafu(display("Error performing user delegation key request: {}", source))]
+    UserDelegationKeyRequest { source: crate::client::retry::Error },
+
+    #[snafu(display("Error getting user delegation key response body: {}", source))]
+    UserDelegationKeyResponseBody { source: reqwest::Error },
+
+    #[snafu(display("Got invalid user delegation key response: {}", source))]
+    InvalidUserDelegationKeyResponse { source: quick_xml::de::DeError },
+
+    #[snafu(display(
+        "Signing a url requires an access key or OAuth credentials, \
+        not a bearer token or SAS token"
+    ))]
+    MissingSigningCredentials,
+
+    #[snafu(display("Signed url expiry {:?} is out of range", expires_in))]
+    InvalidSignedUrlExpiry { expires_in: std::time::Duration },
+
     #[snafu(display("Error authorizing request: {}", source))]
     Authorization {
         source: crate::azure::credential::Error,
     },
+
+    #[snafu(display(
+        "Azure does not support the x-ms-version '{}' the request was sent with, \
+        the account or emulator must support version '{}' used by this client \
+        (Azurite must be upgraded or started with --skipApiVersionCheck): {}",
+        version,
+        AZURE_VERSION.to_str().unwrap_or_default(),
+        source
+    ))]
+    UnsupportedVersion {
+        version: String,
+        source: Box<dyn std::error::Error + Send + Sync + 'static>,
+    },
+}
+
+impl Error {
+    /// Returns the error response of a failed request, if any
+    fn request_error(&self) -> Option<&crate::client::retry::Error> {
+        match self {
+            Self::GetRequest { source, .. }
+            | Self::PutRequest { source, .. }
+            | Self::AppendBlockRequest { source, .. }
+            | Self::DeleteRequest { source, .. }
+            | Self::CopyRequest { source, .. }
+            | Self::SetPropertiesRequest { source, .. }
+            | Self::DfsRequest { source, .. }
+            | Self::ListRequest { source }
+            | Self::BatchRequest { source } => Some(source),
+            _ => None,
+        }
+    }
+
+    /// Returns the rejected value of the `x-ms-version` header if this error is due
+    /// to the service not supporting the version of the REST API requested
+    ///
+    /// <https://learn.microsoft.com/en-us/rest/api/storageservices/versioning-for-the-azure-storage-services>
+    fn unsupported_version(&self) -> Option<String> {
+        let source = self.request_error()?;
+        if source.status() != Some(StatusCode::BAD_REQUEST)
+            || !matches!(
+                source.code(),
+                Some("InvalidHeaderValue" | "UnsupportedHeader")
+            )
+        {
+            return None;
+        }
+        match source.header()? {
+            (name, value) if name.eq_ignore_ascii_case(VERSION.as_str()) => Some(
+                value
+                    .unwrap_or_else(|| AZURE_VERSION.to_str().unwrap_or_default())
+                    .to_string(),
+            ),
+            _ => None,
+        }
+    }
 }
 
 impl From<Error> for crate::Error {
     fn from(err: Error) -> Self {
+        if let Some(version) = err.unsupported_version() {
+            return Self::Generic {
+                store: "MicrosoftAzure",
+                source: Box::new(Error::UnsupportedVersion {
+                    version,
+                    source: Box::new(err),
+                }),
+            };
+        }
+
         match err {
             Error::GetRequest { source, path }
             | Error::DeleteRequest { source, path }
             | Error::CopyRequest { source, path }
             | Error::PutRequest { source, path }
+            | Error::AppendBlockRequest { source, path }
+            | Error::SetPropertiesRequest { source, path }
+            | Error::DfsRequest { source, path }
                 if matches!(source.status(), Some(StatusCode::NOT_FOUND)) =>
             {
                 Self::NotFound {
@@ -110,6 +335,34 @@ impl From<Error> for crate::Error {
                     source: Box::new(source),
                 }
             }
+            // Other conflicts, e.g. with a lease or the type of an existing blob, are
+            // not reported as the blob already existing
+            Error::PutRequest { source, path }
+                if source.code() == Some("BlobAlreadyExists") =>
+            {
+                Self::AlreadyExists {
+                    path,
+                    source: Box::new(source),
+                }
+            }
+            Error::GetRequest { source, path }
+            | Error::PutRequest { source, path }
+            | Error::AppendBlockRequest { source, path }
+            | Error::DeleteRequest { source, path }
+            | Error::CopyRequest { source, path }
+            | Error::SetPropertiesRequest { source, path }
+            | Error::DfsRequest { source, path }
+                if matches!(source.status(), Some(StatusCode::PRECONDITION_FAILED)) =>
+            {
+                Self::Precondition {
+                    path,
+                    source: Box::new(source),
+                }
+            }
+            Error::NotModified { path } => Self::NotModified {
+                path: path.clone(),
+                source: Box::new(Error::NotModified { path }),
+            },
             _ => Self::Generic {
                 store: "MicrosoftAzure",
                 source: Box::new(err),
@@ -118,30 +371,132 @@ impl From<Error> for crate::Error {
     }
 }
 
+/// Returns a credential authorizing requests with the OAuth bearer `token`
+fn bearer_credential(token: &str) -> Result<AzureCredential> {
+    // we do the conversion to a HeaderValue here, since it is fallible
+    // and we want to use it in an infallible function
+    let value = HeaderValue::from_str(&format!("Bearer {}", token)).map_err(|err| {
+        crate::Error::Generic {
+            store: "MicrosoftAzure",
+            source: Box::new(err),
+        }
+    })?;
+    Ok(AzureCredential::AuthorizationToken(value))
+}
+
+/// Returns true if `e` is due to the request being rejected as unauthenticated,
+/// e.g. as it was signed with a revoked access key or an expired token
+///
+/// Responses to `HEAD` requests have no body to read the error code from, in which
+/// case only a `401` is treated as such, as a `403` may be a genuine authorization
+/// failure that a different credential would not resolve
+fn is_authentication_failure(e: &crate::client::retry::Error, head: bool) -> bool {
+    match (e.status(), e.code()) {
+        (Some(StatusCode::FORBIDDEN | StatusCode::UNAUTHORIZED), Some(code)) => {
+            matches!(code, "AuthenticationFailed" | "InvalidAuthenticationInfo")
+        }
+        (Some(StatusCode::UNAUTHORIZED), None) => head,
+        _ => false,
+    }
+}
+
+/// Returns true if `builder` is for a `HEAD` request
+fn is_head(builder: &RequestBuilder) -> bool {
+    let request = builder.try_clone().and_then(|b| b.build().ok());
+    matches!(request, Some(r) if r.method() == Method::HEAD)
+}
+
 /// Configuration for [AzureClient]
 #[derive(Debug)]
 pub struct AzureConfig {
     pub account: String,
+    /// The account used to sign requests with shared key authorization, which may
+    /// differ from `account` when requests are sent through a custom domain
+    pub signing_account: String,
     pub container: String,
     pub credentials: CredentialProvider,
+    /// The account key, if configured, retained for operations that can only be
+    /// signed with it even when requests are authorized with another credential
+    pub account_key: Option<String>,
     pub retry_config: RetryConfig,
     pub service: Url,
-    pub is_emulator: bool,
+    /// The Data Lake Storage Gen2 (`dfs`) endpoint of the account, required by
+    /// hierarchical namespace operations
+    pub dfs_service: Url,
+    pub use_path_style: bool,
+    pub dry_run: bool,
+    pub lenient_headers: bool,
     pub client_options: ClientOptions,
+    pub request_observer: Option<RequestObserver>,
+    /// Retry requests rejected as unauthenticated once with a new OAuth token
+    pub refresh_on_auth_failure: bool,
+    /// The header to send the idempotency key of an operation as, if any
+    pub idempotency_key_header: Option<HeaderName>,
+    /// The access tier of blobs written without an explicit tier, if not the
+    /// default tier of the account
+    pub default_access_tier: Option<AccessTier>,
+    /// The maximum size of a metadata response body buffered before parsing
+    pub max_response_body_size: Option<usize>,
+    /// The `x-ms-meta-*` headers of the metadata of every blob written, unless
+    /// overridden by the metadata of the write
+    pub default_metadata: HeaderMap,
+    /// The maximum time waited for an asynchronous copy to complete
+    pub copy_timeout: std::time::Duration,
+}
+
+/// A [`RequestObserverFn`] invoked with the timing of every request
+#[derive(Clone)]
+pub struct RequestObserver(pub RequestObserverFn);
+
+impl crate::Debug for RequestObserver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "RequestObserver")
+    }
 }
 
 impl AzureConfig {
     fn path_url(&self, path: &Path) -> Url {
+        self.url_for(&self.service, path)
+    }
+
+    /// Returns the url of the account, without a container
+    fn account_url(&self) -> Url {
         let mut url = self.service.clone();
-        {
-            let mut path_mut = url.path_segments_mut().unwrap();
-            if self.is_emulator {
-                path_mut.push(&self.account);
-            }
-            path_mut.push(&self.container).extend(path.parts());
+        if self.use_path_style {
+            let mut url_path = url.path().trim_end_matches('/').to_string();
+            push_segment(&mut url_path, &self.account);
+            url.set_path(&url_path);
         }
         url
     }
+
+    /// Returns the url of `path` on the `dfs` endpoint
+    ///
+    /// Shared key signing is the same as for the `blob` endpoint, as the
+    /// canonicalized resource only depends on the account and url path
+    pub fn dfs_path_url(&self, path: &Path) -> Url {
+        self.url_for(&self.dfs_service, path)
+    }
+
+    fn url_for(&self, service: &Url, path: &Path) -> Url {
+        let mut url = service.clone();
+        let mut url_path = url.path().trim_end_matches('/').to_string();
+        if self.use_path_style {
+            push_segment(&mut url_path, &self.account);
+        }
+        push_segment(&mut url_path, &self.container);
+        for part in path.parts() {
+            push_segment(&mut url_path, part.as_ref());
+        }
+        url.set_path(&url_path);
+        url
+    }
+}
+
+/// Append `segment` to the url path `path`, percent-encoded with [`PATH_SEGMENT`]
+fn push_segment(path: &mut String, segment: &str) {
+    path.push('/');
+    path.extend(utf8_percent_encode(segment, PATH_SEGMENT));
 }
 
 #[derive(Debug)]
@@ -163,25 +518,24 @@ impl AzureClient {
     }
 
     async fn get_credential(&self) -> Result<AzureCredential> {
+        let retry = &self.config.retry_config;
         match &self.config.credentials {
-            CredentialProvider::AccessKey(key) => {
-                Ok(AzureCredential::AccessKey(key.to_owned()))
+            CredentialProvider::AccessKey(keys) => {
+                Ok(AzureCredential::AccessKey(keys.active().to_owned()))
             }
             CredentialProvider::ClientSecret(cred) => {
                 let token = cred
-                    .fetch_token(&self.client, &self.config.retry_config)
+                    .fetch_token(&self.client, retry)
                     .await
                     .context(AuthorizationSnafu)?;
-                Ok(AzureCredential::AuthorizationToken(
-                    // we do the conversion to a HeaderValue here, since it is fallible
-                    // and we wna to use it in an infallible function
-                    HeaderValue::from_str(&format!("Bearer {}", token)).map_err(
-                        |err| crate::Error::Generic {
-                            store: "MicrosoftAzure",
-                            source: Box::new(err),
-                        },
-                    )?,
-                ))
+                bearer_credential(&token)
+            }
+            CredentialProvider::ManagedIdentity(cred) => {
+                let token = cred
+                    .fetch_token(&self.client, retry)
+                    .await
+                    .context(AuthorizationSnafu)?;
+                bearer_credential(&token)
             }
             CredentialProvider::SASToken(sas) => {
                 Ok(AzureCredential::SASToken(sas.clone()))
@@ -189,6 +543,295 @@ impl AzureClient {
         }
     }
 
+    /// Read the body of a metadata response, such as a listing, into memory,
+    /// failing with `context` if the body cannot be read
+    ///
+    /// Returns [`Error::ResponseBodyTooLarge`] if the body exceeds
+    /// [`AzureConfig::max_response_body_size`]
+    async fn metadata_body<C>(&self, mut response: Response, context: C) -> Result<Bytes>
+    where
+        C: IntoError<Error, Source = reqwest::Error>,
+    {
+        let limit = match self.config.max_response_body_size {
+            Some(limit) => limit,
+            None => {
+                return Ok(response.bytes().await.map_err(|e| context.into_error(e))?)
+            }
+        };
+        let too_large = || Error::ResponseBodyTooLarge { limit };
+
+        if matches!(response.content_length(), Some(len) if len > limit as u64) {
+            return Err(too_large().into());
+        }
+
+        let mut body = BytesMut::new();
+        loop {
+            match response.chunk().await {
+                Ok(Some(chunk)) if body.len() + chunk.len() > limit => {
+                    return Err(too_large().into())
+                }
+                Ok(Some(chunk)) => body.extend_from_slice(&chunk),
+                Ok(None) => return Ok(body.freeze()),
+                Err(e) => return Err(context.into_error(e).into()),
+            }
+        }
+    }
+
+    /// Authorize `builder` with `credential` and send it, reporting its timing to
+    /// the [`RequestObserver`] if any
+    async fn send_authorized(
+        &self,
+        builder: RequestBuilder,
+        credential: &AzureCredential,
+    ) -> Result<Response, crate::client::retry::Error> {
+        self.send_authorized_with_idempotency(builder, credential, true)
+            .await
+    }
+
+    /// Authorize `builder` with `credential` and send it, only retrying failures
+    /// before the request was sent if not `idempotent`
+    ///
+    /// See [`RetryExt::send_retry_with_idempotency`]
+    async fn send_authorized_with_idempotency(
+        &self,
+        builder: RequestBuilder,
+        credential: &AzureCredential,
+        idempotent: bool,
+    ) -> Result<Response, crate::client::retry::Error> {
+        let observer = match &self.config.request_observer {
+            Some(observer) => observer,
+            None => {
+                return self
+                    .send_with_fallback(builder, credential, idempotent)
+                    .await
+            }
+        };
+
+        let request = builder.try_clone().and_then(|builder| builder.build().ok());
+        let start = Instant::now();
+        let result = self
+            .send_with_fallback(builder, credential, idempotent)
+            .await;
+        let duration = start.elapsed();
+
+        if let Some(request) = request {
+            let mut timing = RequestTiming {
+                method: request.method().clone(),
+                path: request.url().path().to_string(),
+                status: None,
+                request_id: None,
+                duration,
+                http_version: None,
+                remote_addr: None,
+                local_addr: None,
+                peer_certificate: None,
+            };
+            match &result {
+                Ok(response) => {
+                    timing.status = Some(response.status());
+                    timing.request_id = response
+                        .headers()
+                        .get(&REQUEST_ID)
+                        .and_then(|id| id.to_str().ok())
+                        .map(ToString::to_string);
+                    timing.http_version = Some(response.version());
+                    timing.remote_addr = response.remote_addr();
+                    timing.local_addr = response
+                        .extensions()
+                        .get::<HttpInfo>()
+                        .map(|info| info.local_addr());
+                    timing.peer_certificate = response
+                        .extensions()
+                        .get::<TlsInfo>()
+                        .and_then(|info| info.peer_certificate())
+                        .map(ToOwned::to_owned);
+                }
+                Err(e) => timing.status = e.status(),
+            }
+            (observer.0)(&timing);
+        }
+        result
+    }
+
+    /// Authorize `builder` with `credential` and send it
+    ///
+    /// If authentication fails and multiple access keys are configured, the request
+    /// is retried once with the next key, which is then used for subsequent requests.
+    /// If authentication with an OAuth token fails, the request is retried once with
+    /// a new token, unless disabled with `refresh_on_auth_failure`
+    async fn send_with_fallback(
+        &self,
+        builder: RequestBuilder,
+        credential: &AzureCredential,
+        idempotent: bool,
+    ) -> Result<Response, crate::client::retry::Error> {
+        let builder = self.with_default_headers(builder);
+        let fallback = builder.try_clone();
+        let result = builder
+            .with_azure_authorization(credential, &self.config.signing_account)
+            .send_retry_with_idempotency(&self.config.retry_config, idempotent)
+            .await;
+
+        let fallback = match fallback {
+            Some(fallback) => fallback,
+            None => return result,
+        };
+
+        match (&self.config.credentials, result) {
+            (
+                CredentialProvider::ClientSecret(_)
+                | CredentialProvider::ManagedIdentity(_),
+                Err(e),
+            ) if self.config.refresh_on_auth_failure
+                && is_authentication_failure(&e, is_head(&fallback)) =>
+            {
+                let rejected = match credential {
+                    AzureCredential::AuthorizationToken(token) => token,
+                    _ => return Err(e),
+                };
+                let rejected = rejected.to_str().unwrap_or_default();
+                let rejected = rejected.strip_prefix("Bearer ").unwrap_or(rejected);
+                let retry = &self.config.retry_config;
+                let refreshed = match &self.config.credentials {
+                    CredentialProvider::ClientSecret(provider) => {
+                        provider.refresh_token(&self.client, retry, rejected).await
+                    }
+                    CredentialProvider::ManagedIdentity(provider) => {
+                        provider.refresh_token(&self.client, retry, rejected).await
+                    }
+                    _ => return Err(e),
+                };
+                let token = match refreshed {
+                    Ok(token) => token,
+                    Err(refresh) => {
+                        warn!("Failed to refresh token: {}", refresh);
+                        return Err(e);
+                    }
+                };
+                let token = match HeaderValue::from_str(&format!("Bearer {}", token)) {
+                    Ok(token) => token,
+                    Err(_) => return Err(e),
+                };
+                info!(
+                    "Authentication failed for account {}, retrying with a new token: {}",
+                    self.config.account, e
+                );
+                fallback
+                    .with_azure_authorization(
+                        &AzureCredential::AuthorizationToken(token),
+                        &self.config.signing_account,
+                    )
+                    .send_retry_with_idempotency(&self.config.retry_config, idempotent)
+                    .await
+            }
+            (CredentialProvider::AccessKey(keys), Err(e))
+                if is_authentication_failure(&e, is_head(&fallback)) =>
+            {
+                let key = match credential {
+                    AzureCredential::AccessKey(key) => key,
+                    _ => return Err(e),
+                };
+                let (idx, next) = match keys.rotate(key) {
+                    Some(next) => next,
+                    None => return Err(e),
+                };
+                warn!(
+                    "Authentication failed for account {}, retrying with access key {}: {}",
+                    self.config.account, idx, e
+                );
+                let result = fallback
+                    .with_azure_authorization(
+                        &AzureCredential::AccessKey(next),
+                        &self.config.signing_account,
+                    )
+                    .send_retry_with_idempotency(&self.config.retry_config, idempotent)
+                    .await;
+                if result.is_ok() {
+                    info!(
+                        "Request for account {} succeeded with access key {}",
+                        self.config.account, idx
+                    );
+                }
+                result
+            }
+            (_, result) => result,
+        }
+    }
+
+    /// Add the [default headers](crate::ClientOptions::with_default_headers) to
+    /// `builder` before it is authorized, as otherwise they are only added when it is
+    /// sent, and so would not be included in a shared key signature
+    ///
+    /// Headers already set on `builder`, or set when authorizing it, take precedence
+    fn with_default_headers(&self, builder: RequestBuilder) -> RequestBuilder {
+        let defaults = match self.config.client_options.default_headers() {
+            Some(defaults) if !defaults.is_empty() => defaults,
+            _ => return builder,
+        };
+        let request = match builder.try_clone().and_then(|b| b.build().ok()) {
+            Some(request) => request,
+            None => return builder,
+        };
+        defaults
+            .iter()
+            .filter(|(name, _)| {
+                !request.headers().contains_key(*name)
+                    && *name != DATE
+                    && *name != AUTHORIZATION
+                    && *name != VERSION
+            })
+            .fold(builder, |builder, (name, value)| {
+                builder.header(name, value)
+            })
+    }
+
+    /// Add `idempotency_key` to `builder` as the configured idempotency key header
+    ///
+    /// The key is retained by the request, and so sent with every retry of it
+    fn with_idempotency_key(
+        &self,
+        builder: RequestBuilder,
+        idempotency_key: Option<&str>,
+    ) -> RequestBuilder {
+        match (&self.config.idempotency_key_header, idempotency_key) {
+            (Some(header), Some(key)) => builder.header(header, key),
+            _ => builder,
+        }
+    }
+
+    /// Set the `x-ms-access-tier` header of a request writing a blob to `tier`, or the
+    /// configured default access tier if `None`
+    fn with_access_tier(
+        &self,
+        builder: RequestBuilder,
+        tier: Option<AccessTier>,
+    ) -> RequestBuilder {
+        match tier.or(self.config.default_access_tier) {
+            Some(tier) => builder.header(&ACCESS_TIER, tier.as_str()),
+            None => builder,
+        }
+    }
+
+    /// Make a single Azure Get Container Properties request, ignoring its status, to
+    /// establish a pooled connection
+    /// <https://learn.microsoft.com/en-us/rest/api/storageservices/get-container-properties>
+    pub async fn warm_up(&self) -> Result<()> {
+        let credential = self.get_credential().await?;
+        let url = self.config.path_url(&Path::default());
+
+        let builder = self
+            .client
+            .request(Method::HEAD, url)
+            .query(&[("restype", "container")]);
+        self.with_default_headers(builder)
+            .with_azure_authorization(&credential, &self.config.signing_account)
+            .send()
+            .await
+            .context(WarmUpRequestSnafu)?;
+
+        Ok(())
+    }
+
     /// Make an Azure PUT request <https://docs.microsoft.com/en-us/rest/api/storageservices/put-blob>
     pub async fn put_request<T: Serialize + crate::Debug + ?Sized + Sync>(
         &self,
@@ -197,13 +840,284 @@ impl AzureClient {
         is_block_op: bool,
         query: &T,
     ) -> Result<Response> {
+        let mut builder = self.put_builder(path, bytes, is_block_op, query);
+        if !is_block_op {
+            builder = self.with_access_tier(builder, None);
+        }
+        self.send_put(builder, path).await
+    }
+
+    /// Make an Azure PUT request of a blob with the additional `headers`, such as from
+    /// [`put_headers`], sending `idempotency_key` if any, written to the access tier
+    /// `tier`, or the configured default if `None`
+    ///
+    /// If not `idempotent` the request is only retried if it was not sent
+    pub async fn put_blob_request(
+        &self,
+        path: &Path,
+        bytes: Bytes,
+        headers: HeaderMap,
+        idempotency_key: Option<&str>,
+        tier: Option<AccessTier>,
+        idempotent: bool,
+    ) -> Result<Response> {
+        let builder = self
+            .put_builder(path, Some(bytes), false, &())
+            .headers(headers);
+        let builder = self.with_idempotency_key(builder, idempotency_key);
+        let builder = self.with_access_tier(builder, tier);
+
+        let credential = self.get_credential().await?;
+        let response = self
+            .send_authorized_with_idempotency(builder, &credential, idempotent)
+            .await
+            .context(PutRequestSnafu {
+                path: path.as_ref(),
+            })?;
+        Ok(response)
+    }
+
+    /// Create an empty append blob at `path`, returning [`crate::Error::AlreadyExists`]
+    /// if there is already a blob at `path`
+    /// <https://learn.microsoft.com/en-us/rest/api/storageservices/put-blob>
+    pub async fn create_append_blob_request(&self, path: &Path) -> Result<()> {
+        let mut builder = self
+            .client
+            .request(Method::PUT, self.config.path_url(path))
+            .header(&BLOB_TYPE, "AppendBlob")
+            .header(IF_NONE_MATCH, "*")
+            .header(CONTENT_LENGTH, HeaderValue::from_static("0"))
+            .headers(self.config.default_metadata.clone());
+
+        if let Some(value) = self.config().client_options.get_content_type(path) {
+            builder = builder.header(CONTENT_TYPE, value);
+        }
+
+        self.send_put(builder, path).await?;
+        Ok(())
+    }
+
+    /// Append `bytes` as a block to the append blob at `path`, only if it is
+    /// `position` bytes long if specified
+    ///
+    /// Without a `position` the request may have been applied when it fails, and
+    /// so is only retried if it was not sent
+    /// <https://learn.microsoft.com/en-us/rest/api/storageservices/append-block>
+    pub async fn append_block_request(
+        &self,
+        path: &Path,
+        bytes: Bytes,
+        position: Option<usize>,
+    ) -> Result<()> {
+        ensure!(
+            bytes.len() <= MAX_APPEND_BLOCK_SIZE,
+            AppendBlockTooLargeSnafu {
+                size: bytes.len(),
+                path: path.as_ref(),
+            }
+        );
+
+        let mut builder =
+            self.put_builder(path, Some(bytes), true, &[("comp", "appendblock")]);
+        if let Some(position) = position {
+            builder = builder.header(&APPEND_POSITION, position);
+        }
+
         let credential = self.get_credential().await?;
+        let result = self
+            .send_authorized_with_idempotency(builder, &credential, position.is_some())
+            .await;
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(source) if source.code() == Some("BlockCountExceedsLimit") => {
+                Err(Error::AppendBlockCountExceeded {
+                    source,
+                    path: path.to_string(),
+                }
+                .into())
+            }
+            Err(source) if source.code() == Some(MD5_MISMATCH) => {
+                Err(Error::UploadChecksumMismatch {
+                    source,
+                    path: path.to_string(),
+                }
+                .into())
+            }
+            Err(source) => Err(Error::AppendBlockRequest {
+                source,
+                path: path.to_string(),
+            }
+            .into()),
+        }
+    }
+
+    /// Stage `bytes` as the block `block_id` of the blob at `path`
+    /// <https://learn.microsoft.com/en-us/rest/api/storageservices/put-block>
+    pub async fn put_block(
+        &self,
+        path: &Path,
+        block_id: BlockId,
+        bytes: Bytes,
+    ) -> Result<()> {
+        let block_id = base64::encode(block_id);
+        self.put_request(
+            path,
+            Some(bytes),
+            true,
+            &[("comp", "block"), ("blockid", &block_id)],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Commit `block_list` as the content of the blob at `path`
+    /// <https://learn.microsoft.com/en-us/rest/api/storageservices/put-block-list>
+    ///
+    /// The commit is retried with the same [`RetryConfig`] on request errors such as
+    /// timeouts and on retryable statuses, honouring any `Retry-After`, as the staged
+    /// blocks can still be committed. Retries reference blocks as `Latest`, so that a
+    /// retry of a commit that succeeded but whose response was lost is not rejected.
+    ///
+    /// Other errors, such as `InvalidBlockList` if a block is no longer staged, are
+    /// returned
+    pub async fn put_block_list(
+        &self,
+        path: &Path,
+        block_list: &BlockList,
+        headers: &HeaderMap,
+    ) -> Result<()> {
+        let config = &self.config.retry_config;
+        let mut backoff = Backoff::new(&config.backoff);
+        let start = Instant::now();
+        let mut body = block_list.to_xml();
+        let mut retries = 0;
+
+        loop {
+            let builder =
+                self.put_builder(path, Some(body.into()), true, &[("comp", "blocklist")]);
+            let builder = self
+                .with_access_tier(builder, None)
+                .headers(self.config.default_metadata.clone())
+                .headers(headers.clone());
+            let credential = self.get_credential().await?;
+            // Retried below rather than when sending, as the body must be changed
+            let result = self
+                .send_authorized_with_idempotency(builder, &credential, false)
+                .await;
+
+            match result {
+                Ok(_) => return Ok(()),
+                Err(e)
+                    if (e.is_request_error()
+                        || matches!(e.status(), Some(s) if config.is_retryable(s)))
+                        && retries < config.max_retries
+                        && start.elapsed() < config.retry_timeout
+                        && !matches!(e.retry_after(), Some(d) if start.elapsed() + d > config.retry_timeout) =>
+                {
+                    let sleep = e.retry_after().unwrap_or_else(|| backoff.next());
+                    retries += 1;
+                    info!(
+                        "Failed to commit block list for {}, backing off for {} seconds, retry {} of {}: {}",
+                        path,
+                        sleep.as_secs_f32(),
+                        retries,
+                        config.max_retries,
+                        e
+                    );
+                    tokio::time::sleep(sleep).await;
+                    body = block_list.to_latest_xml();
+                }
+                Err(source) => {
+                    return Err(Error::PutRequest {
+                        source,
+                        path: path.to_string(),
+                    }
+                    .into())
+                }
+            }
+        }
+    }
+
+    /// Discard the uncommitted blocks of `path`, if it does not exist, by committing
+    /// an empty block list and then deleting the resulting empty blob
+    ///
+    /// Returns `false` without discarding the blocks if `path` already exists, as
+    /// committing a block list would replace its contents
+    ///
+    /// In a dry run no request is made, and `true` is returned
+    pub async fn discard_uncommitted_blocks(&self, path: &Path) -> Result<bool> {
+        if self.config.dry_run {
+            info!("Dry run: skipping discard of staged blocks of {}", path);
+            return Ok(true);
+        }
+
+        let body = BlockList::default().to_xml();
+        let builder = self
+            .put_builder(path, Some(body.into()), true, &[("comp", "blocklist")])
+            .header(IF_NONE_MATCH, "*");
+
+        let response = match self.send_put(builder, path).await {
+            Ok(response) => response,
+            Err(crate::Error::AlreadyExists { .. })
+            | Err(crate::Error::Precondition { .. }) => return Ok(false),
+            Err(e) => return Err(e),
+        };
+
+        // Only delete the empty blob if it has not since been replaced
+        let credential = self.get_credential().await?;
+        let mut builder = self
+            .client
+            .request(Method::DELETE, self.config.path_url(path));
+        if let Some(e_tag) = response.headers().get(ETAG) {
+            builder = builder.header(IF_MATCH, e_tag);
+        }
+
+        self.send_authorized(builder, &credential)
+            .await
+            .context(DeleteRequestSnafu {
+                path: path.as_ref(),
+            })?;
+
+        Ok(true)
+    }
+
+    /// Make an Azure PUT request of a blob, conditional on its current ETag
+    ///
+    /// If `expected_e_tag` is `None` the blob is only created if it does not exist
+    ///
+    /// <https://learn.microsoft.com/en-us/rest/api/storageservices/specifying-conditional-headers-for-blob-service-operations>
+    pub async fn put_if_request(
+        &self,
+        path: &Path,
+        bytes: Bytes,
+        expected_e_tag: Option<&str>,
+    ) -> Result<Response> {
+        let builder = self.put_builder(path, Some(bytes), false, &());
+        let builder = self.with_access_tier(builder, None);
+        let builder = match expected_e_tag {
+            Some(e_tag) => builder.header(IF_MATCH, e_tag),
+            None => builder.header(IF_NONE_MATCH, "*"),
+        };
+        self.send_put(builder, path).await
+    }
+
+    fn put_builder<T: Serialize + crate::Debug + ?Sized + Sync>(
+        &self,
+        path: &Path,
+        bytes: Option<Bytes>,
+        is_block_op: bool,
+        query: &T,
+    ) -> RequestBuilder {
         let url = self.config.path_url(path);
 
         let mut builder = self.client.request(Method::PUT, url);
 
         if !is_block_op {
-            builder = builder.header(&BLOB_TYPE, "BlockBlob").query(query);
+            builder = builder
+                .header(&BLOB_TYPE, "BlockBlob")
+                .headers(self.config.default_metadata.clone())
+                .query(query);
         } else {
             builder = builder.query(query);
         }
@@ -213,76 +1127,321 @@ impl AzureClient {
         }
 
         if let Some(bytes) = bytes {
+            if self.config.client_options.checksum_validation() {
+                builder = builder.header(&CONTENT_MD5, content_md5(&bytes));
+            }
             builder = builder
                 .header(CONTENT_LENGTH, HeaderValue::from(bytes.len()))
                 .body(bytes)
         } else {
             builder = builder.header(CONTENT_LENGTH, HeaderValue::from_static("0"));
         }
+        builder
+    }
+
+    async fn send_put(&self, builder: RequestBuilder, path: &Path) -> Result<Response> {
+        let credential = self.get_credential().await?;
+        match self.send_authorized(builder, &credential).await {
+            Ok(response) => Ok(response),
+            Err(source) if source.code() == Some(MD5_MISMATCH) => {
+                Err(Error::UploadChecksumMismatch {
+                    source,
+                    path: path.to_string(),
+                }
+                .into())
+            }
+            Err(source) => Err(Error::PutRequest {
+                source,
+                path: path.to_string(),
+            }
+            .into()),
+        }
+    }
+
+    /// Make an Azure GET request
+    /// <https://docs.microsoft.com/en-us/rest/api/storageservices/get-blob>
+    /// <https://docs.microsoft.com/en-us/rest/api/storageservices/get-blob-properties>
+    pub async fn get_request(
+        &self,
+        path: &Path,
+        range: Option<Range<usize>>,
+        head: bool,
+    ) -> Result<Response> {
+        let options = GetOptions {
+            range,
+            ..Default::default()
+        };
+        self.get_opts_request(path, &options, head).await
+    }
+
+    /// Make an Azure GET request with the range and conditions of `options`
+    ///
+    /// Returns [`Error::NotModified`] if the response is `304 Not Modified`
+    /// <https://learn.microsoft.com/en-us/rest/api/storageservices/specifying-conditional-headers-for-blob-service-operations>
+    pub async fn get_opts_request(
+        &self,
+        path: &Path,
+        options: &GetOptions,
+        head: bool,
+    ) -> Result<Response> {
+        let credential = self.get_credential().await?;
+        let url = self.config.path_url(path);
+        let method = match head {
+            true => Method::HEAD,
+            false => Method::GET,
+        };
+
+        let mut builder = self
+            .client
+            .request(method, url)
+            .header(CONTENT_LENGTH, HeaderValue::from_static("0"))
+            .body(Bytes::new());
+
+        if let Some(range) = options.range.clone() {
+            // The service only returns the Content-MD5 of a range if requested
+            if self.config.client_options.checksum_validation()
+                && range.len() <= MAX_RANGE_MD5_SIZE
+            {
+                builder = builder.header(&RANGE_GET_CONTENT_MD5, "true");
+            }
+            builder = builder.header(RANGE, format_http_range(range));
+        }
+
+        if let Some(e_tag) = &options.if_match {
+            builder = builder.header(IF_MATCH, e_tag);
+        }
+
+        if let Some(e_tag) = &options.if_none_match {
+            builder = builder.header(IF_NONE_MATCH, e_tag);
+        }
+
+        if let Some(date) = options.if_modified_since {
+            builder =
+                builder.header(IF_MODIFIED_SINCE, date.format(RFC1123_FMT).to_string());
+        }
 
-        let response = builder
-            .with_azure_authorization(&credential, &self.config.account)
-            .send_retry(&self.config.retry_config)
-            .await
-            .context(PutRequestSnafu {
+        if let Some(date) = options.if_unmodified_since {
+            builder =
+                builder.header(IF_UNMODIFIED_SINCE, date.format(RFC1123_FMT).to_string());
+        }
+
+        let response = self.send_authorized(builder, &credential).await.context(
+            GetRequestSnafu {
                 path: path.as_ref(),
-            })?;
+            },
+        )?;
+
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Err(Error::NotModified {
+                path: path.to_string(),
+            }
+            .into());
+        }
 
         Ok(response)
     }
 
-    /// Make an Azure GET request
-    /// <https://docs.microsoft.com/en-us/rest/api/storageservices/get-blob>
-    /// <https://docs.microsoft.com/en-us/rest/api/storageservices/get-blob-properties>
-    pub async fn get_request(
+    /// Make an Azure GET request conditional on the ETag of the blob not matching
+    /// `e_tag`, returning a `304 Not Modified` response if it does
+    /// <https://learn.microsoft.com/en-us/rest/api/storageservices/specifying-conditional-headers-for-blob-service-operations>
+    pub async fn revalidate_request(
         &self,
         path: &Path,
-        range: Option<Range<usize>>,
-        head: bool,
+        e_tag: Option<&str>,
     ) -> Result<Response> {
         let credential = self.get_credential().await?;
         let url = self.config.path_url(path);
-        let method = match head {
-            true => Method::HEAD,
-            false => Method::GET,
-        };
 
         let mut builder = self
             .client
-            .request(method, url)
+            .request(Method::GET, url)
             .header(CONTENT_LENGTH, HeaderValue::from_static("0"))
             .body(Bytes::new());
 
-        if let Some(range) = range {
-            builder = builder.header(RANGE, format_http_range(range));
+        if let Some(e_tag) = e_tag {
+            builder = builder.header(IF_NONE_MATCH, e_tag);
         }
 
-        let response = builder
-            .with_azure_authorization(&credential, &self.config.account)
-            .send_retry(&self.config.retry_config)
-            .await
-            .context(GetRequestSnafu {
+        let response = self.send_authorized(builder, &credential).await.context(
+            GetRequestSnafu {
                 path: path.as_ref(),
-            })?;
+            },
+        )?;
+
+        Ok(response)
+    }
+
+    /// Make an Azure Query Blob Contents request with the XML `body`
+    /// <https://learn.microsoft.com/en-us/rest/api/storageservices/query-blob-contents>
+    pub async fn query_request(&self, path: &Path, body: String) -> Result<Response> {
+        let credential = self.get_credential().await?;
+        let url = self.config.path_url(path);
+
+        let builder = self
+            .client
+            .request(Method::POST, url)
+            .query(&[("comp", "query")])
+            .header(CONTENT_TYPE, "application/xml")
+            .header(CONTENT_LENGTH, HeaderValue::from(body.len()))
+            .body(body);
+
+        let response = self.send_authorized(builder, &credential).await.context(
+            GetRequestSnafu {
+                path: path.as_ref(),
+            },
+        )?;
 
         Ok(response)
     }
 
+    /// Make an Azure Get Block List request for the committed blocks of `path`
+    /// <https://learn.microsoft.com/en-us/rest/api/storageservices/get-block-list>
+    pub async fn get_committed_blocks(&self, path: &Path) -> Result<Vec<BlockInfo>> {
+        let response = self.get_block_list(path, "committed").await?;
+        Ok(response.committed_blocks.into_block_infos())
+    }
+
+    /// Make an Azure Get Block List request for the blocks staged to `path` that
+    /// have not yet been committed
+    pub async fn get_uncommitted_blocks(&self, path: &Path) -> Result<Vec<BlockInfo>> {
+        let response = self.get_block_list(path, "uncommitted").await?;
+        Ok(response.uncommitted_blocks.into_block_infos())
+    }
+
+    async fn get_block_list(
+        &self,
+        path: &Path,
+        list_type: &str,
+    ) -> Result<BlockListResponse> {
+        let credential = self.get_credential().await?;
+        let url = self.config.path_url(path);
+
+        let builder = self
+            .client
+            .request(Method::GET, url)
+            .query(&[("comp", "blocklist"), ("blocklisttype", list_type)]);
+
+        let response = self.send_authorized(builder, &credential).await.context(
+            GetRequestSnafu {
+                path: path.as_ref(),
+            },
+        )?;
+        let response = self
+            .metadata_body(
+                response,
+                GetResponseBodySnafu {
+                    path: path.as_ref(),
+                },
+            )
+            .await?;
+
+        Ok(quick_xml::de::from_reader(response.reader())
+            .context(InvalidBlockListResponseSnafu)?)
+    }
+
+    /// Make an Azure Get User Delegation Key request for a key valid from `start`
+    /// until `expiry`, which must be authorized with OAuth credentials
+    /// <https://learn.microsoft.com/en-us/rest/api/storageservices/get-user-delegation-key>
+    pub async fn user_delegation_key(
+        &self,
+        credential: &AzureCredential,
+        start: &DateTime<Utc>,
+        expiry: &DateTime<Utc>,
+    ) -> Result<UserDelegationKey> {
+        let body = format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+            <KeyInfo><Start>{}</Start><Expiry>{}</Expiry></KeyInfo>",
+            start.format(SAS_TIME_FMT),
+            expiry.format(SAS_TIME_FMT)
+        );
+
+        let builder = self
+            .client
+            .request(Method::POST, self.config.account_url())
+            .query(&[("restype", "service"), ("comp", "userdelegationkey")])
+            .header(CONTENT_LENGTH, HeaderValue::from(body.len()))
+            .body(body);
+
+        let response = self
+            .send_authorized(builder, credential)
+            .await
+            .context(UserDelegationKeyRequestSnafu)?;
+        let response = self
+            .metadata_body(response, UserDelegationKeyResponseBodySnafu)
+            .await?;
+
+        let key = quick_xml::de::from_reader(response.reader())
+            .context(InvalidUserDelegationKeyResponseSnafu)?;
+        Ok(key)
+    }
+
+    /// Returns the url of `path` with a shared access signature granting
+    /// `permissions` for `expires_in`
+    ///
+    /// The signature is a service SAS signed with the account key if configured,
+    /// otherwise a user delegation SAS signed with a key fetched using the OAuth
+    /// credentials of the store
+    pub async fn signed_url(
+        &self,
+        path: &Path,
+        expires_in: std::time::Duration,
+        permissions: &str,
+    ) -> Result<Url> {
+        let start = Utc::now();
+        let expiry = chrono::Duration::from_std(expires_in)
+            .ok()
+            .and_then(|d| start.checked_add_signed(d))
+            .ok_or(Error::InvalidSignedUrlExpiry { expires_in })?;
+
+        let resource = format!(
+            "/blob/{}/{}/{}",
+            self.config.signing_account, self.config.container, path
+        );
+        let pairs = match &self.config.account_key {
+            Some(key) => {
+                blob_sas(&resource, permissions, &expiry, SasKey::AccessKey(key))
+            }
+            None => {
+                let credential = self.get_credential().await?;
+                if !matches!(credential, AzureCredential::AuthorizationToken(_)) {
+                    return Err(Error::MissingSigningCredentials.into());
+                }
+                let key = self
+                    .user_delegation_key(&credential, &start, &expiry)
+                    .await?;
+                blob_sas(
+                    &resource,
+                    permissions,
+                    &expiry,
+                    SasKey::UserDelegation(&key),
+                )
+            }
+        }
+        .context(AuthorizationSnafu)?;
+
+        let mut url = self.config.path_url(path);
+        url.query_pairs_mut().extend_pairs(pairs);
+        Ok(url)
+    }
+
     /// Make an Azure Delete request <https://docs.microsoft.com/en-us/rest/api/storageservices/delete-blob>
     pub async fn delete_request<T: Serialize + ?Sized + Sync>(
         &self,
         path: &Path,
         query: &T,
+        idempotency_key: Option<&str>,
     ) -> Result<()> {
         let credential = self.get_credential().await?;
         let url = self.config.path_url(path);
 
-        self.client
+        let builder = self
+            .client
             .request(Method::DELETE, url)
             .query(query)
-            .header(&DELETE_SNAPSHOTS, "include")
-            .with_azure_authorization(&credential, &self.config.account)
-            .send_retry(&self.config.retry_config)
+            .header(&DELETE_SNAPSHOTS, "include");
+        let builder = self.with_idempotency_key(builder, idempotency_key);
+
+        self.send_authorized(builder, &credential)
             .await
             .context(DeleteRequestSnafu {
                 path: path.as_ref(),
@@ -296,10 +1455,9 @@ impl AzureClient {
         &self,
         from: &Path,
         to: &Path,
-        overwrite: bool,
+        options: &CopyOptions,
     ) -> Result<()> {
         let credential = self.get_credential().await?;
-        let url = self.config.path_url(to);
         let mut source = self.config.path_url(from);
 
         // If using SAS authorization must include the headers in the URL
@@ -308,38 +1466,325 @@ impl AzureClient {
             source.query_pairs_mut().extend_pairs(pairs);
         }
 
+        self.copy_from_url_request(&source, from.as_ref(), to, options)
+            .await
+    }
+
+    /// Make an Azure Copy request from the blob or file at `source`, which must
+    /// either be public or contain a SAS token
+    ///
+    /// `name` identifies the source in errors, as `source` may contain a SAS token
+    pub async fn copy_from_url_request(
+        &self,
+        source: &Url,
+        name: &str,
+        to: &Path,
+        options: &CopyOptions,
+    ) -> Result<()> {
+        let credential = self.get_credential().await?;
+        let url = self.config.path_url(to);
         let mut builder = self
             .client
             .request(Method::PUT, url)
             .header(&COPY_SOURCE, source.to_string())
             .header(CONTENT_LENGTH, HeaderValue::from_static("0"));
 
-        if !overwrite {
+        if options.if_not_exists {
             builder = builder.header(IF_NONE_MATCH, "*");
         }
 
-        builder
-            .with_azure_authorization(&credential, &self.config.account)
-            .send_retry(&self.config.retry_config)
+        if let Some(e_tag) = &options.source_if_match {
+            builder = builder.header(&SOURCE_IF_MATCH, e_tag);
+        }
+
+        if let Some(e_tag) = &options.source_if_none_match {
+            builder = builder.header(&SOURCE_IF_NONE_MATCH, e_tag);
+        }
+
+        if let Some(date) = options.source_if_modified_since {
+            builder = builder.header(
+                &SOURCE_IF_MODIFIED_SINCE,
+                date.format(RFC1123_FMT).to_string(),
+            );
+        }
+
+        let builder =
+            self.with_idempotency_key(builder, options.idempotency_key.as_deref());
+
+        let response = self
+            .send_authorized(builder, &credential)
             .await
-            .context(CopyRequestSnafu {
-                path: from.as_ref(),
-            })?;
+            .context(CopyRequestSnafu { path: name })?;
+
+        self.wait_for_copy(to, response.headers().clone()).await
+    }
+
+    /// Waits until the copy to `path` completes, given the `headers` of the response
+    /// that started it, polling its status with the configured backoff until the
+    /// copy timeout elapses
+    ///
+    /// Copies within an account usually complete before the response, whereas those
+    /// of large blobs or from other accounts complete asynchronously
+    /// <https://learn.microsoft.com/en-us/rest/api/storageservices/get-blob-properties#response-headers>
+    async fn wait_for_copy(&self, path: &Path, mut headers: HeaderMap) -> Result<()> {
+        let mut backoff = Backoff::new(&self.config.retry_config.backoff);
+        let start = Instant::now();
+        loop {
+            match headers.get(&COPY_STATUS).and_then(|v| v.to_str().ok()) {
+                None | Some("success") => return Ok(()),
+                Some("pending") => {}
+                // Either `failed` or `aborted`
+                Some(status) => {
+                    let description = headers
+                        .get(&COPY_STATUS_DESCRIPTION)
+                        .and_then(|v| v.to_str().ok())
+                        .unwrap_or_default();
+                    return Err(Error::CopyFailed {
+                        path: path.to_string(),
+                        status: status.to_string(),
+                        description: description.to_string(),
+                    }
+                    .into());
+                }
+            }
+
+            let sleep = backoff.next();
+            if start.elapsed() + sleep > self.config.copy_timeout {
+                let progress = headers
+                    .get(&COPY_PROGRESS)
+                    .and_then(|v| v.to_str().ok())
+                    .unwrap_or("unknown");
+                return Err(Error::CopyTimeout {
+                    path: path.to_string(),
+                    timeout: self.config.copy_timeout,
+                    progress: progress.to_string(),
+                }
+                .into());
+            }
+            info!(
+                "Copy to {} is pending, polling again in {} seconds",
+                path,
+                sleep.as_secs_f32()
+            );
+            tokio::time::sleep(sleep).await;
+            headers = self.get_request(path, None, true).await?.headers().clone();
+        }
+    }
+
+    /// Make an Azure Set Blob Properties request
+    /// <https://learn.microsoft.com/en-us/rest/api/storageservices/set-blob-properties>
+    pub async fn set_properties_request(
+        &self,
+        path: &Path,
+        properties: &BlobProperties,
+    ) -> Result<()> {
+        let headers = properties_headers(properties)?;
+        self.set_request(path, "properties", headers).await
+    }
+
+    /// Make an Azure Set Blob Metadata request
+    /// <https://learn.microsoft.com/en-us/rest/api/storageservices/set-blob-metadata>
+    pub async fn set_metadata_request(
+        &self,
+        path: &Path,
+        metadata: &HashMap<String, String>,
+    ) -> Result<()> {
+        let headers = metadata_headers(metadata)?;
+        self.set_request(path, "metadata", headers).await
+    }
+
+    /// Make an Azure Set Blob Expiry request
+    /// <https://learn.microsoft.com/en-us/rest/api/storageservices/set-blob-expiry>
+    pub async fn set_expiry_request(
+        &self,
+        path: &Path,
+        expiry: &ExpiryOption,
+    ) -> Result<()> {
+        let headers = expiry_headers(expiry)?;
+        self.set_request(path, "expiry", headers).await
+    }
+
+    async fn set_request(
+        &self,
+        path: &Path,
+        comp: &str,
+        headers: HeaderMap,
+    ) -> Result<()> {
+        let credential = self.get_credential().await?;
+        let url = self.config.path_url(path);
+
+        let builder = self
+            .client
+            .request(Method::PUT, url)
+            .query(&[("comp", comp)])
+            .headers(headers)
+            .header(CONTENT_LENGTH, HeaderValue::from_static("0"));
 
+        self.send_authorized(builder, &credential).await.context(
+            SetPropertiesRequestSnafu {
+                path: path.as_ref(),
+            },
+        )?;
+
+        Ok(())
+    }
+
+    /// Send `subrequests` as Azure Blob Batch requests of at most [`MAX_BATCH_SIZE`]
+    /// subrequests each, returning the result of each subrequest in order
+    ///
+    /// Returns an error if a batch request as a whole fails
+    pub async fn batch(&self, subrequests: Vec<Subrequest>) -> Result<Vec<Result<()>>> {
+        let mut results = Vec::with_capacity(subrequests.len());
+        for chunk in subrequests.chunks(MAX_BATCH_SIZE) {
+            results.extend(self.batch_request(chunk).await?);
+        }
+        Ok(results)
+    }
+
+    /// Make an Azure Blob Batch request
+    /// <https://learn.microsoft.com/en-us/rest/api/storageservices/blob-batch>
+    async fn batch_request(&self, subrequests: &[Subrequest]) -> Result<Vec<Result<()>>> {
+        let credential = self.get_credential().await?;
+
+        let requests = subrequests
+            .iter()
+            .map(|subrequest| {
+                let mut request = self
+                    .client
+                    .request(
+                        subrequest.method.clone(),
+                        self.config.path_url(&subrequest.path),
+                    )
+                    .query(subrequest.query)
+                    .headers(subrequest.headers.clone())
+                    .header(CONTENT_LENGTH, HeaderValue::from_static("0"))
+                    .build()
+                    .map_err(|source| crate::Error::Generic {
+                        store: "MicrosoftAzure",
+                        source: Box::new(source),
+                    })?;
+                authorize_subrequest(
+                    &mut request,
+                    &credential,
+                    &self.config.signing_account,
+                );
+                Ok(request)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let boundary = batch::new_boundary();
+        let body = batch::request_body(&boundary, &requests);
+        let builder = self
+            .client
+            .request(Method::POST, self.config.path_url(&Path::default()))
+            .query(&[("restype", "container"), ("comp", "batch")])
+            .header(
+                CONTENT_TYPE,
+                format!("multipart/mixed; boundary={}", boundary),
+            )
+            .header(CONTENT_LENGTH, HeaderValue::from(body.len()))
+            .body(body);
+
+        let response = self
+            .send_authorized(builder, &credential)
+            .await
+            .context(BatchRequestSnafu)?;
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        let body = self.metadata_body(response, BatchResponseBodySnafu).await?;
+
+        let responses = batch::parse_response(&content_type, &body, requests.len())?;
+        Ok(subrequests
+            .iter()
+            .zip(responses)
+            .map(|(subrequest, response)| match response {
+                Some(response) => response.into_result(&subrequest.path),
+                None => Err(crate::Error::Generic {
+                    store: "MicrosoftAzure",
+                    source: format!(
+                        "Batch response missing subresponse for {}",
+                        subrequest.path
+                    )
+                    .into(),
+                }),
+            })
+            .collect())
+    }
+
+    /// Make a request without a body to `path` on the `dfs` endpoint, as used by
+    /// hierarchical namespace operations such as atomic rename and recursive delete
+    ///
+    /// <https://learn.microsoft.com/en-us/rest/api/storageservices/data-lake-storage-gen2>
+    pub async fn dfs_request<T: Serialize + ?Sized + Sync>(
+        &self,
+        method: Method,
+        path: &Path,
+        query: &T,
+        headers: HeaderMap,
+    ) -> Result<Response> {
+        let credential = self.get_credential().await?;
+        let url = self.config.dfs_path_url(path);
+
+        let builder = self
+            .client
+            .request(method, url)
+            .query(query)
+            .headers(headers)
+            .header(CONTENT_LENGTH, HeaderValue::from_static("0"));
+
+        let response = self.send_authorized(builder, &credential).await.context(
+            DfsRequestSnafu {
+                path: path.as_ref(),
+            },
+        )?;
+
+        Ok(response)
+    }
+
+    /// Atomically rename the blob at `from` to `to`, replacing any blob at `to`, on an
+    /// account with a hierarchical namespace
+    ///
+    /// <https://learn.microsoft.com/en-us/rest/api/storageservices/datalakestoragegen2/path/create>
+    pub async fn rename_request(&self, from: &Path, to: &Path) -> Result<()> {
+        let mut source = String::new();
+        push_segment(&mut source, &self.config.container);
+        for part in from.parts() {
+            push_segment(&mut source, part.as_ref());
+        }
+
+        // As for copies, the source must carry any SAS token
+        if let AzureCredential::SASToken(pairs) = &self.get_credential().await? {
+            let query = url::form_urlencoded::Serializer::new(String::new())
+                .extend_pairs(pairs)
+                .finish();
+            source = format!("{}?{}", source, query);
+        }
+
+        let mut headers = HeaderMap::new();
+        headers.insert(&RENAME_SOURCE, header_value(&source)?);
+        self.dfs_request(Method::PUT, to, &(), headers).await?;
         Ok(())
     }
 
     /// Make an Azure List request <https://docs.microsoft.com/en-us/rest/api/storageservices/list-blobs>
+    ///
+    /// Blobs that have staged blocks but have never been committed are only listed
+    /// if `include_uncommitted` is true
     async fn list_request(
         &self,
         prefix: Option<&str>,
         delimiter: bool,
         token: Option<&str>,
-    ) -> Result<(ListResult, Option<String>)> {
+        include_uncommitted: bool,
+    ) -> Result<(ListResultInternal, Option<String>)> {
         let credential = self.get_credential().await?;
         let url = self.config.path_url(&Path::default());
 
-        let mut query = Vec::with_capacity(5);
+        let mut query = Vec::with_capacity(6);
         query.push(("restype", "container"));
         query.push(("comp", "list"));
 
@@ -355,24 +1800,23 @@ impl AzureClient {
             query.push(("marker", token))
         }
 
+        if include_uncommitted {
+            query.push(("include", "uncommittedblobs"))
+        }
+
+        let builder = self.client.request(Method::GET, url).query(&query);
         let response = self
-            .client
-            .request(Method::GET, url)
-            .query(&query)
-            .with_azure_authorization(&credential, &self.config.account)
-            .send_retry(&self.config.retry_config)
+            .send_authorized(builder, &credential)
             .await
-            .context(ListRequestSnafu)?
-            .bytes()
-            .await
-            .context(ListResponseBodySnafu)?;
+            .context(ListRequestSnafu)?;
+        let response = self.metadata_body(response, ListResponseBodySnafu).await?;
 
         let mut response: ListResultInternal =
             quick_xml::de::from_reader(response.reader())
                 .context(InvalidListResponseSnafu)?;
         let token = response.next_marker.take();
 
-        Ok((response.try_into()?, token))
+        Ok((response, token))
     }
 
     /// Perform a list operation automatically handling pagination
@@ -381,17 +1825,187 @@ impl AzureClient {
         prefix: Option<&Path>,
         delimiter: bool,
     ) -> BoxStream<'_, Result<ListResult>> {
+        self.list_literal_paginated(format_prefix(prefix), delimiter)
+    }
+
+    /// Perform a list operation automatically handling pagination, of the blobs whose
+    /// names start with the string `prefix`, if any
+    pub fn list_literal_paginated(
+        &self,
+        prefix: Option<String>,
+        delimiter: bool,
+    ) -> BoxStream<'_, Result<ListResult>> {
+        stream_paginated(prefix, move |prefix, token| async move {
+            let (r, next_token) = self
+                .list_request(prefix.as_deref(), delimiter, token.as_deref(), false)
+                .await?;
+            Ok((r.try_into()?, prefix, next_token))
+        })
+        .boxed()
+    }
+
+    /// Perform a recursive list operation automatically handling pagination,
+    /// returning the path of every blob, including those with staged blocks that
+    /// have never been committed
+    pub fn list_uncommitted_paginated(
+        &self,
+        prefix: Option<&Path>,
+    ) -> BoxStream<'_, Result<Vec<Path>>> {
         let prefix = format_prefix(prefix);
         stream_paginated(prefix, move |prefix, token| async move {
             let (r, next_token) = self
-                .list_request(prefix.as_deref(), delimiter, token.as_deref())
+                .list_request(prefix.as_deref(), false, token.as_deref(), true)
                 .await?;
-            Ok((r, prefix, next_token))
+            let paths = r
+                .blobs
+                .blobs
+                .into_iter()
+                .map(|blob| Ok(Path::parse(blob.name)?))
+                .collect::<Result<_>>()?;
+            Ok((paths, prefix, next_token))
         })
         .boxed()
     }
 }
 
+static BLOB_CONTENT_TYPE: HeaderName = HeaderName::from_static("x-ms-blob-content-type");
+static BLOB_CONTENT_ENCODING: HeaderName =
+    HeaderName::from_static("x-ms-blob-content-encoding");
+static BLOB_CONTENT_LANGUAGE: HeaderName =
+    HeaderName::from_static("x-ms-blob-content-language");
+static BLOB_CONTENT_DISPOSITION: HeaderName =
+    HeaderName::from_static("x-ms-blob-content-disposition");
+static BLOB_CACHE_CONTROL: HeaderName =
+    HeaderName::from_static("x-ms-blob-cache-control");
+static META_PREFIX: &str = "x-ms-meta-";
+static EXPIRY_OPTION: HeaderName = HeaderName::from_static("x-ms-expiry-option");
+static EXPIRY_TIME: HeaderName = HeaderName::from_static("x-ms-expiry-time");
+static RENAME_SOURCE: HeaderName = HeaderName::from_static("x-ms-rename-source");
+static APPEND_POSITION: HeaderName =
+    HeaderName::from_static("x-ms-blob-condition-appendpos");
+static COPY_STATUS: HeaderName = HeaderName::from_static("x-ms-copy-status");
+static COPY_STATUS_DESCRIPTION: HeaderName =
+    HeaderName::from_static("x-ms-copy-status-description");
+static COPY_PROGRESS: HeaderName = HeaderName::from_static("x-ms-copy-progress");
+static RANGE_GET_CONTENT_MD5: HeaderName =
+    HeaderName::from_static("x-ms-range-get-content-md5");
+
+/// The error code of a request whose data did not match its `Content-MD5`
+static MD5_MISMATCH: &str = "Md5Mismatch";
+
+/// The maximum size of a range whose `Content-MD5` is returned by the service
+const MAX_RANGE_MD5_SIZE: usize = 4 * 1024 * 1024;
+
+/// The maximum size of a block appended to an append blob
+pub(crate) const MAX_APPEND_BLOCK_SIZE: usize = 4 * 1024 * 1024;
+
+/// The maximum number of blocks of an append blob
+pub(crate) const MAX_APPEND_BLOCKS: usize = 50_000;
+
+/// Returns the base64 encoded MD5 of `data`, as in a `Content-MD5` header
+pub(crate) fn content_md5(data: &[u8]) -> String {
+    base64::encode(Md5::digest(data))
+}
+
+/// Returns the `Content-MD5` of the data of `response`, if it has one and checksums
+/// are validated
+pub(crate) fn expected_md5(config: &AzureConfig, response: &Response) -> Option<String> {
+    if !config.client_options.checksum_validation() {
+        return None;
+    }
+    let value = response.headers().get(&CONTENT_MD5)?;
+    Some(value.to_str().ok()?.to_string())
+}
+
+/// Returns an error if the MD5 of `data` received from `path` is not `expected`
+pub(crate) fn verify_md5(path: &Path, expected: &str, data: &[u8]) -> Result<(), Error> {
+    let actual = content_md5(data);
+    ensure!(
+        actual == expected,
+        ChecksumMismatchSnafu {
+            path: path.as_ref(),
+            expected,
+            actual,
+        }
+    );
+    Ok(())
+}
+
+fn header_value(value: &str) -> Result<HeaderValue, Error> {
+    HeaderValue::from_str(value).context(InvalidHeaderValueSnafu { value })
+}
+
+/// Returns the `x-ms-blob-*` headers for a Set Blob Properties request
+fn properties_headers(properties: &BlobProperties) -> Result<HeaderMap, Error> {
+    let fields = [
+        (&BLOB_CONTENT_TYPE, &properties.content_type),
+        (&BLOB_CONTENT_ENCODING, &properties.content_encoding),
+        (&BLOB_CONTENT_LANGUAGE, &properties.content_language),
+        (&BLOB_CONTENT_DISPOSITION, &properties.content_disposition),
+        (&BLOB_CACHE_CONTROL, &properties.cache_control),
+    ];
+
+    let mut headers = HeaderMap::with_capacity(fields.len());
+    for (name, value) in fields {
+        if let Some(value) = value {
+            headers.insert(name.clone(), header_value(value)?);
+        }
+    }
+    Ok(headers)
+}
+
+/// Returns the headers setting the properties and metadata of a blob written with
+/// `options`, for a Put Blob or Put Block List request
+pub(crate) fn put_headers(options: &PutOptions) -> Result<HeaderMap, Error> {
+    let properties = BlobProperties {
+        content_type: options.content_type.clone(),
+        content_encoding: options.content_encoding.clone(),
+        cache_control: options.cache_control.clone(),
+        ..Default::default()
+    };
+    let mut headers = properties_headers(&properties)?;
+    headers.extend(metadata_headers(&options.metadata)?);
+    Ok(headers)
+}
+
+/// Returns the `x-ms-meta-*` headers for a Set Blob Metadata request
+pub(crate) fn metadata_headers(
+    metadata: &HashMap<String, String>,
+) -> Result<HeaderMap, Error> {
+    let mut headers = HeaderMap::with_capacity(metadata.len());
+    for (key, value) in metadata {
+        let name = HeaderName::try_from(format!("{}{}", META_PREFIX, key))
+            .context(InvalidMetadataKeySnafu { key })?;
+        headers.insert(name, header_value(value)?);
+    }
+    Ok(headers)
+}
+
+/// Returns the `x-ms-expiry-*` headers for a Set Blob Expiry request
+///
+/// Relative expiry times are sent in milliseconds
+fn expiry_headers(expiry: &ExpiryOption) -> Result<HeaderMap, Error> {
+    let (option, time) = match expiry {
+        ExpiryOption::RelativeToNow(duration) => {
+            ("RelativeToNow", Some(duration.as_millis().to_string()))
+        }
+        ExpiryOption::RelativeToCreation(duration) => {
+            ("RelativeToCreation", Some(duration.as_millis().to_string()))
+        }
+        ExpiryOption::Absolute(date) => {
+            ("Absolute", Some(date.format(RFC1123_FMT).to_string()))
+        }
+        ExpiryOption::NeverExpire => ("NeverExpire", None),
+    };
+
+    let mut headers = HeaderMap::with_capacity(2);
+    headers.insert(EXPIRY_OPTION.clone(), HeaderValue::from_static(option));
+    if let Some(time) = time {
+        headers.insert(EXPIRY_TIME.clone(), header_value(&time)?);
+    }
+    Ok(headers)
+}
+
 /// Raw / internal response from list requests
 #[derive(Debug, Clone, PartialEq, Deserialize)]
 #[serde(rename_all = "PascalCase")]
@@ -457,7 +2071,7 @@ struct Blob {
     pub version_id: Option<String>,
     pub is_current_version: Option<bool>,
     pub deleted: Option<bool>,
-    pub properties: BlobProperties,
+    pub properties: BlobPropertiesInternal,
     pub metadata: Option<HashMap<String, String>>,
 }
 
@@ -469,6 +2083,8 @@ impl TryFrom<Blob> for ObjectMeta {
             location: Path::parse(value.name)?,
             last_modified: value.properties.last_modified,
             size: value.properties.content_length as usize,
+            content_type: Some(value.properties.content_type),
+            e_tag: Some(value.properties.etag),
         })
     }
 }
@@ -478,13 +2094,14 @@ impl TryFrom<Blob> for ObjectMeta {
 /// the parsed fields to the ones relevant in this crate.
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
 #[serde(rename_all = "PascalCase")]
-struct BlobProperties {
+struct BlobPropertiesInternal {
     #[serde(deserialize_with = "deserialize_rfc1123", rename = "Last-Modified")]
     pub last_modified: DateTime<Utc>,
     pub etag: String,
     #[serde(rename = "Content-Length")]
     pub content_length: u64,
-    #[serde(rename = "Content-Type")]
+    // Not set for blobs that have never been committed
+    #[serde(rename = "Content-Type", default)]
     pub content_type: String,
     #[serde(rename = "Content-Encoding")]
     pub content_encoding: Option<String>,
@@ -492,6 +2109,43 @@ struct BlobProperties {
     pub content_language: Option<String>,
 }
 
+/// Response of a Get Block List request
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BlockListResponse {
+    #[serde(default)]
+    pub committed_blocks: Blocks,
+    #[serde(default)]
+    pub uncommitted_blocks: Blocks,
+}
+
+/// Collection of blocks returned from Get Block List requests
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+struct Blocks {
+    #[serde(rename = "Block", default)]
+    pub blocks: Vec<Block>,
+}
+
+impl Blocks {
+    fn into_block_infos(self) -> Vec<BlockInfo> {
+        self.blocks
+            .into_iter()
+            .map(|block| BlockInfo {
+                id: block.name,
+                size: block.size,
+            })
+            .collect()
+    }
+}
+
+/// Details for a specific block
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Block {
+    pub name: String,
+    pub size: usize,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub(crate) struct BlockId(Bytes);
 
@@ -522,14 +2176,22 @@ pub(crate) struct BlockList {
 }
 
 impl BlockList {
+    /// Returns the XML body of a Put Block List request, committing uncommitted blocks
     pub fn to_xml(&self) -> String {
+        self.xml("Uncommitted")
+    }
+
+    /// Returns the XML body of a Put Block List request, committing the latest version
+    /// of each block, whether committed or uncommitted
+    pub fn to_latest_xml(&self) -> String {
+        self.xml("Latest")
+    }
+
+    fn xml(&self, list: &str) -> String {
         let mut s = String::new();
         s.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<BlockList>\n");
         for block_id in &self.blocks {
-            let node = format!(
-                "\t<Uncommitted>{}</Uncommitted>\n",
-                base64::encode(block_id)
-            );
+            let node = format!("\t<{}>{}</{}>\n", list, base64::encode(block_id), list);
             s.push_str(&node);
         }
 
@@ -544,6 +2206,41 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn path_segment_encoding() {
+        // Blob names and their canonical percent-encoded form, in which only the
+        // RFC 3986 unreserved characters are literal
+        let cases = [
+            ("blob", "blob"),
+            ("a-b.c_d~e", "a-b.c_d~e"),
+            ("a b", "a%20b"),
+            ("!*'()", "%21%2A%27%28%29"),
+            ("a+b=c&d", "a%2Bb%3Dc%26d"),
+            ("$,;:@", "%24%2C%3B%3A%40"),
+            ("#?[]", "%23%3F%5B%5D"),
+            ("\"<>`{}|^", "%22%3C%3E%60%7B%7D%7C%5E"),
+            ("100%", "100%25"),
+            ("caf\u{e9}", "caf%C3%A9"),
+            ("\u{1f600}", "%F0%9F%98%80"),
+        ];
+
+        for (name, expected) in cases {
+            let mut path = String::new();
+            push_segment(&mut path, name);
+            assert_eq!(path, format!("/{}", expected), "{}", name);
+
+            // The signed resource must match the url path sent to Azure
+            let mut url = Url::parse("https://account.blob.core.windows.net").unwrap();
+            url.set_path(&format!("/container{}", path));
+            assert_eq!(
+                canonicalized_resource("account", &url),
+                format!("/account/container/{}", expected),
+                "{}",
+                name
+            );
+        }
+    }
+
     #[test]
     fn deserde_azure() {
         const S: &str = "<?xml version=\"1.0\" encoding=\"utf-8\"?>
@@ -624,8 +2321,12 @@ mod tests {
     <NextMarker />
 </EnumerationResults>";
 
-        let mut _list_blobs_response_internal: ListResultInternal =
+        let list_blobs_response_internal: ListResultInternal =
             quick_xml::de::from_str(S).unwrap();
+
+        let list: ListResult = list_blobs_response_internal.try_into().unwrap();
+        assert_eq!(list.objects.len(), 3);
+        assert_eq!(list.objects[0].e_tag.as_deref(), Some("0x8D93C7D4629C227"));
     }
 
     #[test]
@@ -699,6 +2400,37 @@ mod tests {
             quick_xml::de::from_str(S).unwrap();
     }
 
+    #[test]
+    fn deserde_block_list() {
+        const S: &str = "<?xml version=\"1.0\" encoding=\"utf-8\"?>
+<BlockList>
+    <CommittedBlocks>
+        <Block>
+            <Name>MDA=</Name>
+            <Size>5242880</Size>
+        </Block>
+        <Block>
+            <Name>MDE=</Name>
+            <Size>1024</Size>
+        </Block>
+    </CommittedBlocks>
+</BlockList>";
+
+        let response: BlockListResponse = quick_xml::de::from_str(S).unwrap();
+        let names: Vec<_> = response
+            .committed_blocks
+            .blocks
+            .iter()
+            .map(|b| (b.name.as_str(), b.size))
+            .collect();
+        assert_eq!(names, vec![("MDA=", 5242880), ("MDE=", 1024)]);
+
+        const EMPTY: &str = "<?xml version=\"1.0\" encoding=\"utf-8\"?>
+<BlockList><CommittedBlocks /></BlockList>";
+        let response: BlockListResponse = quick_xml::de::from_str(EMPTY).unwrap();
+        assert!(response.committed_blocks.blocks.is_empty());
+    }
+
     #[test]
     fn to_xml() {
         const S: &str = "<?xml version=\"1.0\" encoding=\"utf-8\"?>
@@ -716,4 +2448,33 @@ mod tests {
 
         assert_eq!(res, S)
     }
+
+    #[test]
+    fn set_properties_headers() {
+        let properties = BlobProperties {
+            content_type: Some("text/plain".to_string()),
+            cache_control: Some("no-cache".to_string()),
+            ..Default::default()
+        };
+        let headers = properties_headers(&properties).unwrap();
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers[&BLOB_CONTENT_TYPE], "text/plain");
+        assert_eq!(headers[&BLOB_CACHE_CONTROL], "no-cache");
+
+        let properties = BlobProperties {
+            content_language: Some("en\r\n".to_string()),
+            ..Default::default()
+        };
+        properties_headers(&properties).unwrap_err();
+    }
+
+    #[test]
+    fn set_metadata_headers() {
+        let metadata = HashMap::from([("Owner".to_string(), "team-a".to_string())]);
+        let headers = metadata_headers(&metadata).unwrap();
+        assert_eq!(headers["x-ms-meta-owner"], "team-a");
+
+        let metadata = HashMap::from([("not valid".to_string(), "a".to_string())]);
+        metadata_headers(&metadata).unwrap_err();
+    }
 }
@@ -17,9 +17,10 @@
 
 use crate::client::retry::RetryExt;
 use crate::client::token::{TemporaryToken, TokenCache};
-use crate::util::hmac_sha256;
+use crate::util::{hmac_sha256, maybe_spawn_blocking};
 use crate::RetryConfig;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
+use rand::Rng;
 use reqwest::header::ACCEPT;
 use reqwest::{
     header::{
@@ -27,22 +28,35 @@ use reqwest::{
         CONTENT_LANGUAGE, CONTENT_LENGTH, CONTENT_TYPE, DATE, IF_MATCH,
         IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_UNMODIFIED_SINCE, RANGE,
     },
-    Client, Method, RequestBuilder,
+    Client, Method, Request, RequestBuilder,
 };
 use snafu::{ResultExt, Snafu};
 use std::borrow::Cow;
+use std::path::PathBuf;
 use std::str;
-use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::warn;
 use url::Url;
 
-static AZURE_VERSION: HeaderValue = HeaderValue::from_static("2021-08-06");
-static VERSION: HeaderName = HeaderName::from_static("x-ms-version");
+pub(crate) static AZURE_VERSION: HeaderValue = HeaderValue::from_static("2021-08-06");
+pub(crate) static VERSION: HeaderName = HeaderName::from_static("x-ms-version");
 pub(crate) static BLOB_TYPE: HeaderName = HeaderName::from_static("x-ms-blob-type");
+pub(crate) static ACCESS_TIER: HeaderName = HeaderName::from_static("x-ms-access-tier");
 pub(crate) static DELETE_SNAPSHOTS: HeaderName =
     HeaderName::from_static("x-ms-delete-snapshots");
 pub(crate) static COPY_SOURCE: HeaderName = HeaderName::from_static("x-ms-copy-source");
-static CONTENT_MD5: HeaderName = HeaderName::from_static("content-md5");
+pub(crate) static REQUEST_ID: HeaderName = HeaderName::from_static("x-ms-request-id");
+pub(crate) static SOURCE_IF_MATCH: HeaderName =
+    HeaderName::from_static("x-ms-source-if-match");
+pub(crate) static SOURCE_IF_NONE_MATCH: HeaderName =
+    HeaderName::from_static("x-ms-source-if-none-match");
+pub(crate) static SOURCE_IF_MODIFIED_SINCE: HeaderName =
+    HeaderName::from_static("x-ms-source-if-modified-since");
+pub(crate) static CONTENT_MD5: HeaderName = HeaderName::from_static("content-md5");
 pub(crate) static RFC1123_FMT: &str = "%a, %d %h %Y %T GMT";
+/// The format of times in a shared access signature
+pub(crate) static SAS_TIME_FMT: &str = "%Y-%m-%dT%H:%M:%SZ";
 const CONTENT_TYPE_JSON: &str = "application/json";
 
 #[derive(Debug, Snafu)]
@@ -52,6 +66,9 @@ pub enum Error {
 
     #[snafu(display("Error getting token response body: {}", source))]
     TokenResponseBody { source: reqwest::Error },
+
+    #[snafu(display("Invalid key for signing a shared access signature: {}", source))]
+    InvalidSasKey { source: base64::DecodeError },
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -59,9 +76,52 @@ pub type Result<T, E = Error> = std::result::Result<T, E>;
 /// Provides credentials for use when signing requests
 #[derive(Debug)]
 pub enum CredentialProvider {
-    AccessKey(String),
+    AccessKey(AccessKeys),
     SASToken(Vec<(String, String)>),
     ClientSecret(ClientSecretOAuthProvider),
+    ManagedIdentity(ImdsManagedIdentityOAuthProvider),
+}
+
+/// The access keys of an account, such as its primary and secondary keys,
+/// of which the active key is used to sign requests
+#[derive(Debug)]
+pub struct AccessKeys {
+    keys: Vec<String>,
+    active: AtomicUsize,
+}
+
+impl AccessKeys {
+    /// Create a new [`AccessKeys`], initially using the first of `keys`
+    pub fn new(keys: Vec<String>) -> Self {
+        assert!(!keys.is_empty(), "at least one access key is required");
+        Self {
+            keys,
+            active: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the active key
+    pub fn active(&self) -> &str {
+        &self.keys[self.active.load(Ordering::Relaxed)]
+    }
+
+    /// Makes the key after `failed` active, returning its index and value, or
+    /// `None` if there is only a single key
+    ///
+    /// If another request already switched away from `failed`, the currently
+    /// active key is returned instead of skipping over it
+    pub fn rotate(&self, failed: &str) -> Option<(usize, String)> {
+        if self.keys.len() < 2 {
+            return None;
+        }
+        let current = self.active.load(Ordering::Relaxed);
+        let next = match self.keys[current] == failed {
+            true => (current + 1) % self.keys.len(),
+            false => current,
+        };
+        self.active.store(next, Ordering::Relaxed);
+        Some((next, self.keys[next].clone()))
+    }
 }
 
 pub(crate) enum AzureCredential {
@@ -143,6 +203,161 @@ impl CredentialExt for RequestBuilder {
     }
 }
 
+/// Authorize `request`, a subrequest of a blob batch request, with `credential`
+///
+/// Unlike [`CredentialExt::with_azure_authorization`], `x-ms-version` is only set on
+/// the batch request itself, as Azure rejects subrequests that specify it
+/// <https://learn.microsoft.com/en-us/rest/api/storageservices/blob-batch#request-body>
+pub(crate) fn authorize_subrequest(
+    request: &mut Request,
+    credential: &AzureCredential,
+    account: &str,
+) {
+    let date = Utc::now().format(RFC1123_FMT).to_string();
+    // we formatted the data string ourselves, so unwrapping should be fine
+    request
+        .headers_mut()
+        .insert(DATE, HeaderValue::from_str(&date).unwrap());
+
+    match credential {
+        AzureCredential::AccessKey(key) => {
+            let signature = generate_authorization(
+                request.headers(),
+                request.url(),
+                request.method(),
+                account,
+                key.as_str(),
+            );
+            // "signature" is a base 64 encoded string so it should never contain illegal characters.
+            request.headers_mut().insert(
+                AUTHORIZATION,
+                HeaderValue::from_str(signature.as_str()).unwrap(),
+            );
+        }
+        AzureCredential::AuthorizationToken(token) => {
+            request.headers_mut().insert(AUTHORIZATION, token.clone());
+        }
+        AzureCredential::SASToken(query_pairs) => {
+            request
+                .url_mut()
+                .query_pairs_mut()
+                .extend_pairs(query_pairs);
+        }
+    }
+}
+
+/// A key obtained with OAuth credentials for signing user delegation SAS
+///
+/// <https://learn.microsoft.com/en-us/rest/api/storageservices/get-user-delegation-key#response-body>
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct UserDelegationKey {
+    pub signed_oid: String,
+    pub signed_tid: String,
+    pub signed_start: String,
+    pub signed_expiry: String,
+    pub signed_service: String,
+    pub signed_version: String,
+    pub value: String,
+}
+
+/// The key a shared access signature is signed with
+pub(crate) enum SasKey<'a> {
+    /// The access key of the account, signing a service SAS
+    AccessKey(&'a str),
+    /// A user delegation key, signing a user delegation SAS
+    UserDelegation(&'a UserDelegationKey),
+}
+
+/// Returns the query pairs of a shared access signature granting `permissions`
+/// on a blob until `expiry`, where `resource` is `/blob/{account}/{container}/{blob}`
+///
+/// <https://learn.microsoft.com/en-us/rest/api/storageservices/create-service-sas>
+/// <https://learn.microsoft.com/en-us/rest/api/storageservices/create-user-delegation-sas>
+pub(crate) fn blob_sas(
+    resource: &str,
+    permissions: &str,
+    expiry: &DateTime<Utc>,
+    key: SasKey<'_>,
+) -> Result<Vec<(&'static str, String)>> {
+    let version = AZURE_VERSION.to_str().unwrap();
+    let expiry = expiry.format(SAS_TIME_FMT).to_string();
+
+    let mut pairs = vec![
+        ("sv", version.to_string()),
+        ("sr", "b".to_string()),
+        ("sp", permissions.to_string()),
+        ("se", expiry.clone()),
+    ];
+
+    // Unused optional fields, such as the start time, are signed as empty strings
+    let (secret, string_to_sign) = match key {
+        SasKey::AccessKey(key) => {
+            let fields = [
+                permissions,
+                "",
+                &expiry,
+                resource,
+                "",
+                "",
+                "",
+                version,
+                "b",
+                "",
+                "",
+                "",
+                "",
+                "",
+                "",
+                "",
+            ];
+            (key, fields.join("\n"))
+        }
+        SasKey::UserDelegation(key) => {
+            pairs.extend([
+                ("skoid", key.signed_oid.clone()),
+                ("sktid", key.signed_tid.clone()),
+                ("skt", key.signed_start.clone()),
+                ("ske", key.signed_expiry.clone()),
+                ("sks", key.signed_service.clone()),
+                ("skv", key.signed_version.clone()),
+            ]);
+            let fields = [
+                permissions,
+                "",
+                &expiry,
+                resource,
+                &key.signed_oid,
+                &key.signed_tid,
+                &key.signed_start,
+                &key.signed_expiry,
+                &key.signed_service,
+                &key.signed_version,
+                "",
+                "",
+                "",
+                "",
+                "",
+                version,
+                "b",
+                "",
+                "",
+                "",
+                "",
+                "",
+                "",
+                "",
+            ];
+            (key.value.as_str(), fields.join("\n"))
+        }
+    };
+
+    let secret = base64::decode(secret).context(InvalidSasKeySnafu)?;
+    let signature = hmac_sha256(secret, string_to_sign);
+    pairs.push(("sig", base64::encode(signature)));
+    Ok(pairs)
+}
+
 /// Generate signed key for authorization via access keys
 /// <https://docs.microsoft.com/en-us/rest/api/storageservices/authorize-with-shared-key>
 fn generate_authorization(
@@ -220,7 +435,7 @@ fn canonicalize_header(headers: &HeaderMap) -> String {
 }
 
 /// <https://docs.microsoft.com/en-us/rest/api/storageservices/authorize-with-shared-key#constructing-the-canonicalized-resource-string>
-fn canonicalized_resource(account: &str, uri: &Url) -> String {
+pub(crate) fn canonicalized_resource(account: &str, uri: &Url) -> String {
     let mut can_res: String = String::new();
     can_res.push('/');
     can_res.push_str(account);
@@ -271,6 +486,20 @@ fn lexy_sort<'a>(
     values
 }
 
+/// Write `data` to a new file at `path` that, on unix, only the owner can read
+fn write_private(path: &std::path::Path, data: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(data)
+}
+
 #[derive(serde::Deserialize, Debug)]
 struct TokenResponse {
     access_token: String,
@@ -285,8 +514,24 @@ pub struct ClientSecretOAuthProvider {
     client_id: String,
     client_secret: String,
     cache: TokenCache<String>,
+    cache_path: Option<PathBuf>,
 }
 
+/// A token persisted to the file configured with
+/// [`ClientSecretOAuthProvider::with_cache_path`]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct PersistedToken {
+    token_url: String,
+    client_id: String,
+    scope: String,
+    access_token: String,
+    /// Seconds since the UNIX epoch at which the token expires
+    expires_at: u64,
+}
+
+/// The OAuth scope used to access Azure Storage in the public cloud
+const DEFAULT_SCOPE: &str = "https://storage.azure.com/.default";
+
 impl ClientSecretOAuthProvider {
     /// Create a new [`ClientSecretOAuthProvider`] for an azure backed store
     pub fn new(
@@ -294,30 +539,165 @@ impl ClientSecretOAuthProvider {
         client_secret: String,
         tenant_id: String,
         authority_host: Option<String>,
+        scope: Option<String>,
     ) -> Self {
         let authority_host = authority_host
             .unwrap_or_else(|| authority_hosts::AZURE_PUBLIC_CLOUD.to_owned());
 
         Self {
-            scope: "https://storage.azure.com/.default".to_owned(),
+            scope: scope.unwrap_or_else(|| DEFAULT_SCOPE.to_owned()),
             token_url: format!("{}/{}/oauth2/v2.0/token", authority_host, tenant_id),
             client_id,
             client_secret,
             cache: TokenCache::default(),
+            cache_path: None,
         }
     }
 
+    /// Persist tokens to the file at `path`, and reuse a token persisted there by
+    /// another process if it was issued for the same client and is still valid
+    pub fn with_cache_path(mut self, path: PathBuf) -> Self {
+        self.cache_path = Some(path);
+        self
+    }
+
     /// Fetch a token
+    ///
+    /// The token is cached until it is within 5 minutes of expiry, and concurrent
+    /// calls while it is being fetched wait for the same token rather than each
+    /// requesting one. As a provider only requests tokens for a single scope, the
+    /// cached token is always for that scope
     pub async fn fetch_token(
         &self,
         client: &Client,
         retry: &RetryConfig,
     ) -> Result<String> {
         self.cache
-            .get_or_insert_with(|| self.fetch_token_inner(client, retry))
+            .get_or_insert_with(|| async {
+                if let Some(token) = self.read_persisted_token().await {
+                    return Ok(token);
+                }
+                let token = self.fetch_token_inner(client, retry).await?;
+                self.persist_token(&token).await;
+                Ok(token)
+            })
+            .await
+    }
+
+    /// Fetch a new token to replace `rejected`, a token that was rejected by Azure,
+    /// e.g. as it expired while the request was in flight
+    ///
+    /// Unlike [`Self::fetch_token`], a token persisted to the cache file is not
+    /// reused, as it may be `rejected`. If the token was already replaced by a
+    /// concurrent request, the replacement is returned
+    pub async fn refresh_token(
+        &self,
+        client: &Client,
+        retry: &RetryConfig,
+        rejected: &str,
+    ) -> Result<String> {
+        self.cache.invalidate(&rejected.to_string()).await;
+        self.cache
+            .get_or_insert_with(|| async {
+                let token = self.fetch_token_inner(client, retry).await?;
+                self.persist_token(&token).await;
+                Ok(token)
+            })
             .await
     }
 
+    /// Read the token persisted to the cache file, if any, ignoring it if it was
+    /// issued for a different client or is close to expiry
+    async fn read_persisted_token(&self) -> Option<TemporaryToken<String>> {
+        let path = self.cache_path.as_ref()?;
+        let read_path = path.clone();
+        let data = match maybe_spawn_blocking(move || Ok(std::fs::read(read_path))).await
+        {
+            Ok(Ok(data)) => data,
+            Ok(Err(e)) if e.kind() == std::io::ErrorKind::NotFound => return None,
+            Ok(Err(e)) => {
+                warn!("Failed to read token cache {}: {}", path.display(), e);
+                return None;
+            }
+            Err(e) => {
+                warn!("Failed to read token cache {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        let persisted: PersistedToken = match serde_json::from_slice(&data) {
+            Ok(persisted) => persisted,
+            Err(e) => {
+                warn!("Ignoring invalid token cache {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        if persisted.token_url != self.token_url
+            || persisted.client_id != self.client_id
+            || persisted.scope != self.scope
+        {
+            return None;
+        }
+
+        // Tokens are refreshed 5 minutes before they expire
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+        let remaining = persisted.expires_at.checked_sub(now)?;
+        if remaining <= 300 {
+            return None;
+        }
+        Some(TemporaryToken {
+            token: persisted.access_token,
+            expiry: Instant::now() + Duration::from_secs(remaining),
+        })
+    }
+
+    /// Write `token` to the cache file, if any, logging any error
+    async fn persist_token(&self, token: &TemporaryToken<String>) {
+        let path = match &self.cache_path {
+            Some(path) => path,
+            None => return,
+        };
+        let remaining = token.expiry.saturating_duration_since(Instant::now());
+        let expires_at = SystemTime::now() + remaining;
+        let persisted = PersistedToken {
+            token_url: self.token_url.clone(),
+            client_id: self.client_id.clone(),
+            scope: self.scope.clone(),
+            access_token: token.token.clone(),
+            expires_at: expires_at
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        };
+        let data = serde_json::to_vec(&persisted).expect("token is serializable");
+
+        // Write to a new file readable only by the current user, and rename it into
+        // place so that readers never see a partial file. Its name is unique to this
+        // write, so that concurrent writers never write to the same file
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let tmp = path.with_file_name(format!(
+            ".{}.{}.{:016x}.tmp",
+            name,
+            std::process::id(),
+            rand::thread_rng().gen::<u64>()
+        ));
+        let target = path.clone();
+        let written = maybe_spawn_blocking(move || {
+            let result =
+                write_private(&tmp, &data).and_then(|_| std::fs::rename(&tmp, &target));
+            if result.is_err() {
+                let _ = std::fs::remove_file(&tmp);
+            }
+            Ok(result)
+        })
+        .await;
+
+        match written {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => warn!("Failed to write token cache {}: {}", path.display(), e),
+            Err(e) => warn!("Failed to write token cache {}: {}", path.display(), e),
+        }
+    }
+
     /// Fetch a fresh token
     async fn fetch_token_inner(
         &self,
@@ -348,3 +728,138 @@ impl ClientSecretOAuthProvider {
         Ok(token)
     }
 }
+
+/// The IMDS endpoint from which tokens for a managed identity are fetched
+const DEFAULT_MSI_ENDPOINT: &str =
+    "http://169.254.169.254/metadata/identity/oauth2/token";
+
+/// The version of the IMDS token API used
+const MSI_API_VERSION: &str = "2018-02-01";
+
+#[derive(serde::Deserialize, Debug)]
+struct ImdsTokenResponse {
+    access_token: String,
+    #[serde(deserialize_with = "deserialize_expires_in")]
+    expires_in: u64,
+}
+
+/// IMDS returns `expires_in` as a string, whereas other endpoints return a number
+fn deserialize_expires_in<'de, D>(deserializer: D) -> std::result::Result<u64, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum ExpiresIn {
+        Number(u64),
+        String(String),
+    }
+
+    match serde::Deserialize::deserialize(deserializer)? {
+        ExpiresIn::Number(expires_in) => Ok(expires_in),
+        ExpiresIn::String(expires_in) => {
+            expires_in.parse().map_err(serde::de::Error::custom)
+        }
+    }
+}
+
+/// Fetches OAuth tokens for the managed identity of an Azure VM or AKS node from the
+/// Azure Instance Metadata Service (IMDS)
+///
+/// <https://learn.microsoft.com/en-us/azure/active-directory/managed-identities-azure-resources/how-to-use-vm-token#get-a-token-using-http>
+#[derive(Debug)]
+pub struct ImdsManagedIdentityOAuthProvider {
+    msi_endpoint: String,
+    resource: String,
+    client_id: Option<String>,
+    object_id: Option<String>,
+    msi_res_id: Option<String>,
+    cache: TokenCache<String>,
+}
+
+impl ImdsManagedIdentityOAuthProvider {
+    /// Create a new [`ImdsManagedIdentityOAuthProvider`]
+    ///
+    /// A user-assigned identity may be selected by one of `client_id`, `object_id` or
+    /// `msi_res_id`, otherwise the system-assigned identity is used. The resource for
+    /// which tokens are requested is derived from the OAuth `scope`
+    pub fn new(
+        client_id: Option<String>,
+        object_id: Option<String>,
+        msi_res_id: Option<String>,
+        msi_endpoint: Option<String>,
+        scope: Option<String>,
+    ) -> Self {
+        let scope = scope.unwrap_or_else(|| DEFAULT_SCOPE.to_owned());
+        let resource = scope.strip_suffix("/.default").unwrap_or(&scope);
+
+        Self {
+            msi_endpoint: msi_endpoint.unwrap_or_else(|| DEFAULT_MSI_ENDPOINT.to_owned()),
+            resource: resource.to_owned(),
+            client_id,
+            object_id,
+            msi_res_id,
+            cache: TokenCache::default(),
+        }
+    }
+
+    /// Fetch a token
+    pub async fn fetch_token(
+        &self,
+        client: &Client,
+        retry: &RetryConfig,
+    ) -> Result<String> {
+        self.cache
+            .get_or_insert_with(|| self.fetch_token_inner(client, retry))
+            .await
+    }
+
+    /// Fetch a new token to replace `rejected`, a token that was rejected by Azure,
+    /// unless it was already replaced by a concurrent request
+    pub async fn refresh_token(
+        &self,
+        client: &Client,
+        retry: &RetryConfig,
+        rejected: &str,
+    ) -> Result<String> {
+        self.cache.invalidate(&rejected.to_string()).await;
+        self.fetch_token(client, retry).await
+    }
+
+    /// Fetch a fresh token
+    async fn fetch_token_inner(
+        &self,
+        client: &Client,
+        retry: &RetryConfig,
+    ) -> Result<TemporaryToken<String>> {
+        let mut query = vec![
+            ("api-version", MSI_API_VERSION),
+            ("resource", self.resource.as_str()),
+        ];
+        if let Some(client_id) = &self.client_id {
+            query.push(("client_id", client_id));
+        }
+        if let Some(object_id) = &self.object_id {
+            query.push(("object_id", object_id));
+        }
+        if let Some(msi_res_id) = &self.msi_res_id {
+            query.push(("msi_res_id", msi_res_id));
+        }
+
+        let response: ImdsTokenResponse = client
+            .request(Method::GET, &self.msi_endpoint)
+            .header("metadata", "true")
+            .query(&query)
+            .send_retry(retry)
+            .await
+            .context(TokenRequestSnafu)?
+            .json()
+            .await
+            .context(TokenResponseBodySnafu)?;
+
+        Ok(TemporaryToken {
+            token: response.access_token,
+            expiry: Instant::now() + Duration::from_secs(response.expires_in),
+        })
+    }
+}
@@ -20,39 +20,95 @@
 //! ## Streaming uploads
 //!
 //! [ObjectStore::put_multipart] will upload data in blocks and write a blob from those
-//! blocks. Data is buffered internally to make blocks of at least 5MB and blocks
-//! are uploaded concurrently.
+//! blocks. Data is buffered internally to make blocks of at least 5MB, configurable
+//! with [`MicrosoftAzureBuilder::with_block_size`], and blocks are uploaded concurrently.
 //!
-//! [ObjectStore::abort_multipart] is a no-op, since Azure Blob Store doesn't provide
-//! a way to drop old blocks. Instead unused blocks are automatically cleaned up
-//! after 7 days.
+//! Azure Blob Store doesn't provide a way to drop uncommitted blocks, which are
+//! instead automatically cleaned up after 7 days. However, committing a block list
+//! discards any blocks it does not reference, and so [ObjectStore::abort_multipart]
+//! discards the blocks staged for a blob that does not yet exist by committing an
+//! empty block list and then deleting the resulting empty blob. Blocks staged for
+//! an existing blob are left to expire, as committing a block list would replace its
+//! properties and metadata.
+//!
+//! Writers dropped before completion can discard their blocks in the same way, see
+//! [`MultipartDropBehavior`], and by default uploads that fail discard their blocks
+//! before returning the error, see [`MicrosoftAzureBuilder::with_abort_multipart_on_error`].
+//!
+//! Committing the block list is retried on transient errors without uploading the
+//! blocks again. If the commit is instead rejected with `InvalidBlockList`, as blocks
+//! were discarded by a concurrent commit to the same blob, only
+//! [ObjectStore::put_multipart_bytes] stages those blocks again and retries, as it
+//! retains the data of every block at no cost. The writers returned by
+//! [ObjectStore::put_multipart] do not retain blocks once uploaded, and so return
+//! the error.
 use self::client::{BlockId, BlockList};
 use crate::{
-    multipart::{CloudMultiPartUpload, CloudMultiPartUploadImpl, UploadPart},
-    path::Path,
-    ClientOptions, GetResult, ListResult, MultipartId, ObjectMeta, ObjectStore, Result,
-    RetryConfig,
+    multipart::{
+        put_parts, CloudMultiPartUpload, CloudMultiPartUploadImpl, UploadPart,
+        DEFAULT_MIN_PART_SIZE,
+    },
+    path::{Path, DELIMITER},
+    util::ensure_source_newer,
+    Capabilities, ChecksumAlgorithm, ClientOptions, CopyOptions, DeleteOptions,
+    GetOptions, GetResult, GetResultMeta, ListResult, MultipartId, ObjectMeta,
+    ObjectStore, PutOptions, PutResult, Result, RetryConfig, Revalidated,
+    UploadConcurrency,
 };
 use async_trait::async_trait;
 use bytes::Bytes;
 use chrono::{TimeZone, Utc};
-use futures::{stream::BoxStream, StreamExt, TryStreamExt};
+use futures::future::BoxFuture;
+use futures::{ready, stream::BoxStream, FutureExt, StreamExt, TryStreamExt};
+use md5::{Digest, Md5};
 use percent_encoding::percent_decode_str;
+use rand::Rng;
+use reqwest::header::{
+    HeaderMap, HeaderName, ACCEPT_RANGES, CACHE_CONTROL, CONTENT_DISPOSITION,
+    CONTENT_ENCODING, CONTENT_LANGUAGE, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE,
+    ETAG, LAST_MODIFIED,
+};
 use serde::{Deserialize, Serialize};
-use snafu::{OptionExt, ResultExt, Snafu};
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
 use std::fmt::{Debug, Formatter};
 use std::io;
 use std::ops::Range;
+use std::pin::Pin;
 use std::sync::Arc;
-use std::{collections::BTreeSet, str::FromStr};
-use tokio::io::AsyncWrite;
+use std::task::{Context, Poll};
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::PathBuf,
+    str::FromStr,
+};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::Semaphore;
+use tracing::{info, warn};
 use url::Url;
 
-use crate::util::{str_is_truthy, RFC1123_FMT};
+use crate::util::{
+    cancellable, merge_ranges, slice_ranges, str_is_truthy, CancellableWriter,
+    OBJECT_STORE_COALESCE_DEFAULT, OBJECT_STORE_COALESCE_PARALLEL, RFC1123_FMT,
+};
 pub use credential::authority_hosts;
+pub use query::{DelimitedFormat, QueryFormat};
 
+mod batch;
 mod client;
 mod credential;
+mod query;
+
+/// The endpoint suffix of the Azure public cloud
+const DEFAULT_ENDPOINT_SUFFIX: &str = "core.windows.net";
+
+/// The endpoint suffixes of Azure clouds, along with their authority host
+/// <https://learn.microsoft.com/en-us/azure/storage/common/storage-account-overview#storage-account-endpoints>
+const CLOUD_ENDPOINT_SUFFIXES: &[(&str, &str)] = &[
+    (DEFAULT_ENDPOINT_SUFFIX, authority_hosts::AZURE_PUBLIC_CLOUD),
+    ("core.usgovcloudapi.net", authority_hosts::AZURE_GOVERNMENT),
+    ("core.chinacloudapi.cn", authority_hosts::AZURE_CHINA),
+    ("core.cloudapi.de", authority_hosts::AZURE_GERMANY),
+];
 
 /// The well-known account used by Azurite and the legacy Azure Storage Emulator.
 /// <https://docs.microsoft.com/azure/storage/common/storage-use-azurite#well-known-storage-account-and-key>
@@ -63,6 +119,24 @@ const EMULATOR_ACCOUNT: &str = "devstoreaccount1";
 const EMULATOR_ACCOUNT_KEY: &str =
     "Eby8vdM02xNOcqFlqUwJPLlmEtlCDXJ1OUzFT50uSRZ6IFsuFq2UVErCz4I6tq/K1SZFPTOtr/KBHBeksoGMGw==";
 
+/// The maximum size of a block staged with the `x-ms-version` used by this client,
+/// which is 100 MiB for versions before 2019-12-12
+/// <https://learn.microsoft.com/en-us/rest/api/storageservices/put-block#remarks>
+const MAX_BLOCK_SIZE: usize = 4000 * 1024 * 1024;
+
+/// The maximum number of committed blocks in a block blob
+/// <https://learn.microsoft.com/en-us/rest/api/storageservices/understanding-block-blobs--append-blobs--and-page-blobs#about-block-blobs>
+const MAX_BLOCKS: usize = 50_000;
+
+/// The number of concurrent batch requests made by [`ObjectStore::delete_stream`]
+const BATCH_DELETE_CONCURRENCY: usize = 4;
+
+/// The number of concurrent Get Block List requests of [`MicrosoftAzure::find_incomplete_uploads`]
+const BLOCK_LIST_CONCURRENCY: usize = 8;
+
+/// The default maximum time waited for an asynchronous copy to complete
+const DEFAULT_COPY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30 * 60);
+
 /// A specialized `Error` for Azure object store-related errors
 #[derive(Debug, Snafu)]
 #[allow(missing_docs)]
@@ -73,6 +147,9 @@ enum Error {
     #[snafu(display("Content-Length Header missing from response"))]
     MissingContentLength,
 
+    #[snafu(display("ETag Header missing from response"))]
+    MissingEtag,
+
     #[snafu(display("Invalid last modified '{}': {}", last_modified, source))]
     InvalidLastModified {
         last_modified: String,
@@ -109,6 +186,12 @@ enum Error {
     #[snafu(display("Account must be specified"))]
     MissingAccount {},
 
+    #[snafu(display(
+        "Invalid storage account name '{}', must be 3 to 24 lowercase letters and numbers",
+        account
+    ))]
+    InvalidAccountName { account: String },
+
     #[snafu(display("Container name must be specified"))]
     MissingContainerName {},
 
@@ -127,14 +210,82 @@ enum Error {
     #[snafu(display("URL did not match any known pattern for scheme: {}", url))]
     UrlNotRecognised { url: String },
 
+    #[snafu(display(
+        "Connection string element {} is not of the form Key=Value",
+        index
+    ))]
+    MalformedConnectionString { index: usize },
+
+    #[snafu(display("Connection string is missing required component {}", component))]
+    MissingConnectionStringComponent { component: &'static str },
+
+    #[snafu(display(
+        "Invalid DefaultEndpointsProtocol '{}' in connection string, expected http or https",
+        protocol
+    ))]
+    InvalidConnectionStringProtocol { protocol: String },
+
     #[snafu(display("Failed parsing an SAS key"))]
     DecodeSasKey { source: std::str::Utf8Error },
 
+    #[snafu(display("Failed decoding path of url {}", url))]
+    DecodeUrlPath {
+        url: String,
+        source: std::str::Utf8Error,
+    },
+
     #[snafu(display("Missing component in SAS query pair"))]
     MissingSasComponent {},
 
     #[snafu(display("Configuration key: '{}' is not known.", key))]
     UnknownConfigurationKey { key: String },
+
+    #[snafu(display(
+        "Maximum buffered bytes {} must be at least the multipart part size of {} bytes",
+        max_buffered_bytes,
+        part_size
+    ))]
+    MaxBufferedBytesTooSmall {
+        max_buffered_bytes: usize,
+        part_size: usize,
+    },
+
+    #[snafu(display(
+        "Block size {} must be between 1 and the maximum block size of {} bytes",
+        block_size,
+        max
+    ))]
+    InvalidBlockSize { block_size: usize, max: usize },
+
+    #[snafu(display(
+        "Invalid upload concurrency {:?}, must be at least 1 with min at most max",
+        concurrency
+    ))]
+    InvalidUploadConcurrency { concurrency: UploadConcurrency },
+
+    #[snafu(display(
+        "Range {}..{} is out of bounds for object of {} bytes",
+        start,
+        end,
+        len
+    ))]
+    RangeOutOfBounds {
+        start: usize,
+        end: usize,
+        len: usize,
+    },
+
+    #[snafu(display(
+        "Invalid access tier '{}', expected one of Hot, Cool or Archive",
+        value
+    ))]
+    InvalidAccessTier { value: String },
+
+    #[snafu(display("Invalid idempotency key header '{}': {}", name, source))]
+    InvalidIdempotencyKeyHeader {
+        name: String,
+        source: reqwest::header::InvalidHeaderName,
+    },
 }
 
 impl From<Error> for super::Error {
@@ -152,975 +303,8216 @@ impl From<Error> for super::Error {
     }
 }
 
+/// Extracts the [`ObjectMeta`] of `location` from the headers of a Get Blob Properties
+/// response, see [`MicrosoftAzureBuilder::with_lenient_headers`] for `lenient`
+fn object_meta(
+    location: &Path,
+    headers: &HeaderMap,
+    lenient: bool,
+) -> Result<ObjectMeta, Error> {
+    let last_modified = match headers.get(LAST_MODIFIED) {
+        Some(last_modified) => {
+            let last_modified = last_modified.to_str().context(BadHeaderSnafu)?;
+            Utc.datetime_from_str(last_modified, RFC1123_FMT)
+                .context(InvalidLastModifiedSnafu { last_modified })?
+        }
+        None if lenient => {
+            warn!("Last-Modified header missing for {}, using epoch", location);
+            Utc.timestamp_opt(0, 0).unwrap()
+        }
+        None => return Err(Error::MissingLastModified),
+    };
+
+    let content_length = match (headers.get(CONTENT_LENGTH), headers.get(CONTENT_RANGE)) {
+        (Some(content_length), _) => content_length.to_str().context(BadHeaderSnafu)?,
+        // Content-Range is of the form `bytes {start}-{end}/{total}`
+        (None, Some(content_range)) if lenient => content_range
+            .to_str()
+            .context(BadHeaderSnafu)?
+            .rsplit_once('/')
+            .map(|(_, total)| total)
+            .context(MissingContentLengthSnafu)?,
+        (None, _) => return Err(Error::MissingContentLength),
+    };
+    let size = content_length
+        .parse()
+        .context(InvalidContentLengthSnafu { content_length })?;
+
+    let content_type = match headers.get(CONTENT_TYPE) {
+        Some(content_type) => Some(content_type.to_str().context(BadHeaderSnafu)?),
+        None => None,
+    };
+
+    let e_tag = match headers.get(ETAG) {
+        Some(e_tag) => Some(e_tag.to_str().context(BadHeaderSnafu)?),
+        None => None,
+    };
+
+    Ok(ObjectMeta {
+        location: location.clone(),
+        last_modified,
+        size,
+        content_type: content_type.map(ToString::to_string),
+        e_tag: e_tag.map(ToString::to_string),
+    })
+}
+
 /// Interface for [Microsoft Azure Blob Storage](https://azure.microsoft.com/en-us/services/storage/blobs/).
 #[derive(Debug)]
 pub struct MicrosoftAzure {
     client: Arc<client::AzureClient>,
+    buffer_budget: Option<Arc<Semaphore>>,
+    checksum_algorithm: Option<ChecksumAlgorithm>,
+    multipart_drop_behavior: MultipartDropBehavior,
+    abort_multipart_on_error: bool,
+    block_size: usize,
+    upload_concurrency: UploadConcurrency,
+    versioning: bool,
+    hierarchical_namespace: bool,
+    append_create: bool,
 }
 
-impl std::fmt::Display for MicrosoftAzure {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "MicrosoftAzure {{ account: {}, container: {} }}",
-            self.client.config().account,
-            self.client.config().container
-        )
-    }
+/// Returns `range` of `bytes`, the full contents of an object, truncating the end
+/// of the range to the length of the object as a server would
+fn slice_range(bytes: &Bytes, range: &Range<usize>) -> Result<Bytes, Error> {
+    let end = range.end.min(bytes.len());
+    ensure!(
+        range.start <= end,
+        RangeOutOfBoundsSnafu {
+            start: range.start,
+            end: range.end,
+            len: bytes.len()
+        }
+    );
+    Ok(bytes.slice(range.start..end))
 }
 
-#[async_trait]
-impl ObjectStore for MicrosoftAzure {
-    async fn put(&self, location: &Path, bytes: Bytes) -> Result<()> {
-        self.client
-            .put_request(location, Some(bytes), false, &())
-            .await?;
-        Ok(())
+/// The timing of a request made to Azure, as reported to a [`RequestObserverFn`]
+///
+/// Along with the timing, this reports the details of the connection that the HTTP
+/// client exposes: the HTTP version, the local and remote addresses, and optionally
+/// the certificate of the server. The negotiated TLS version and cipher suite are
+/// not reported, as the HTTP client provides no way to obtain them
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct RequestTiming {
+    /// The HTTP method of the request
+    pub method: reqwest::Method,
+    /// The path of the request URL
+    pub path: String,
+    /// The status of the final response, if one was received
+    pub status: Option<reqwest::StatusCode>,
+    /// The `x-ms-request-id` of a successful response, which identifies the request
+    /// in Azure's logs and support cases
+    pub request_id: Option<String>,
+    /// The time from first sending the request until the headers of the final
+    /// response were received, including any retries, as observed by the client
+    ///
+    /// This does not include the time to read the body of the response
+    pub duration: std::time::Duration,
+    /// The HTTP version of the final response
+    pub http_version: Option<reqwest::Version>,
+    /// The address of the server the final response was received from
+    pub remote_addr: Option<std::net::SocketAddr>,
+    /// The local address of the connection the final response was received on
+    ///
+    /// Requests sent over the same connection have the same local and remote address,
+    /// and so this identifies if a pooled connection was reused, see
+    /// [`ClientOptions::with_connection_reuse`]
+    pub local_addr: Option<std::net::SocketAddr>,
+    /// The DER encoded certificate presented by the server of an HTTPS connection,
+    /// if enabled with [`ClientOptions::with_tls_info`]
+    ///
+    /// The TLS version of the connection is only known to be at least that set with
+    /// [`ClientOptions::with_min_tls_version`]
+    pub peer_certificate: Option<Vec<u8>>,
+}
+
+/// A callback invoked with the [`RequestTiming`] of every request made by
+/// [`MicrosoftAzure`], see [`MicrosoftAzureBuilder::with_request_observer`]
+pub type RequestObserverFn = Arc<dyn Fn(&RequestTiming) + Send + Sync>;
+
+/// What to do with the blocks staged by a multipart upload that is dropped before
+/// being completed, which Azure otherwise only removes after 7 days
+///
+/// Staged blocks can only be discarded if there is no existing blob at the location
+/// of the upload, as committing the empty block list this requires would replace
+/// its contents. Otherwise, and if discarding fails, a warning is logged
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MultipartDropBehavior {
+    /// Log a warning that the staged blocks have not been discarded
+    #[default]
+    Warn,
+    /// Spawn a task on the current tokio runtime to discard the staged blocks
+    ///
+    /// This is best-effort, as the task may not complete, for example if the runtime
+    /// shuts down. Callers that need the blocks to be discarded should instead call
+    /// [`ObjectStore::abort_multipart`] before dropping the upload
+    Abort,
+}
+
+/// Returns the Azure error code, e.g. `AuthorizationPermissionMismatch`, returned by
+/// the service for the request that failed with `err`, if any
+///
+/// This allows branching on [error codes] not otherwise modelled by [`crate::Error`]
+///
+/// [error codes]: https://learn.microsoft.com/en-us/rest/api/storageservices/blob-service-error-codes
+pub fn error_code(err: &crate::Error) -> Option<&str> {
+    let mut source = std::error::Error::source(err);
+    while let Some(err) = source {
+        if let Some(err) = err.downcast_ref::<crate::client::retry::Error>() {
+            return err.code();
+        }
+        source = err.source();
     }
+    None
+}
 
-    async fn put_multipart(
-        &self,
-        location: &Path,
-    ) -> Result<(MultipartId, Box<dyn AsyncWrite + Unpin + Send>)> {
-        let inner = AzureMultiPartUpload {
-            client: Arc::clone(&self.client),
-            location: location.to_owned(),
+/// The properties of a blob, as returned by [`MicrosoftAzure::head_with_properties`]
+/// and [`MicrosoftAzure::get_with_properties`]
+///
+/// Of these, only the HTTP properties, from `content_type` to `cache_control`, are
+/// updated by [`MicrosoftAzure::set_properties`], and the other fields are ignored
+///
+/// <https://learn.microsoft.com/en-us/rest/api/storageservices/get-blob-properties#response-headers>
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct BlobProperties {
+    /// The `Content-Type` of the blob
+    pub content_type: Option<String>,
+    /// The `Content-Encoding` of the blob
+    pub content_encoding: Option<String>,
+    /// The `Content-Language` of the blob
+    pub content_language: Option<String>,
+    /// The `Content-Disposition` of the blob
+    pub content_disposition: Option<String>,
+    /// The `Cache-Control` of the blob
+    pub cache_control: Option<String>,
+    /// The user-defined metadata of the blob, such as set by [`ObjectStore::put_opts`]
+    ///
+    /// The keys are in lowercase, as they are sent as case-insensitive
+    /// `x-ms-meta-<key>` headers
+    pub metadata: HashMap<String, String>,
+    /// The type of the blob, assumed to be [`BlobType::Block`] if not returned
+    pub blob_type: BlobType,
+    /// The lease of the blob
+    pub lease: BlobLease,
+    /// The object replication state of the blob
+    pub replication: ObjectReplication,
+    /// The status and headers of the response the properties were parsed from
+    pub response: ResponseInfo,
+}
+
+impl BlobProperties {
+    fn from_response(response: &reqwest::Response) -> Result<Self, Error> {
+        let headers = response.headers();
+        let header = |name: HeaderName| {
+            headers
+                .get(name)
+                .map(|value| value.to_str().context(BadHeaderSnafu))
+                .transpose()
+                .map(|value| value.map(ToString::to_string))
+        };
+        let metadata = headers
+            .iter()
+            .filter_map(|(name, value)| {
+                let key = name.as_str().strip_prefix("x-ms-meta-")?;
+                let value = value.to_str().context(BadHeaderSnafu);
+                Some(value.map(|value| (key.to_string(), value.to_string())))
+            })
+            .collect::<Result<_, _>>()?;
+        let blob_type = match headers.get(&credential::BLOB_TYPE) {
+            Some(value) => value.to_str().context(BadHeaderSnafu)?.into(),
+            None => BlobType::Block,
         };
-        Ok((String::new(), Box::new(CloudMultiPartUpload::new(inner, 8))))
-    }
 
-    async fn abort_multipart(
-        &self,
-        _location: &Path,
-        _multipart_id: &MultipartId,
-    ) -> Result<()> {
-        // There is no way to drop blocks that have been uploaded. Instead, they simply
-        // expire in 7 days.
-        Ok(())
+        Ok(Self {
+            content_type: header(CONTENT_TYPE)?,
+            content_encoding: header(CONTENT_ENCODING)?,
+            content_language: header(CONTENT_LANGUAGE)?,
+            content_disposition: header(CONTENT_DISPOSITION)?,
+            cache_control: header(CACHE_CONTROL)?,
+            metadata,
+            blob_type,
+            lease: BlobLease::from_headers(headers),
+            replication: ObjectReplication::from_headers(headers),
+            response: ResponseInfo::new(response),
+        })
     }
+}
 
-    async fn get(&self, location: &Path) -> Result<GetResult> {
-        let response = self.client.get_request(location, None, false).await?;
-        let stream = response
-            .bytes_stream()
-            .map_err(|source| crate::Error::Generic {
-                store: "MicrosoftAzure",
-                source: Box::new(source),
-            })
-            .boxed();
+/// The object replication state of a blob, see [`BlobProperties::replication`]
+///
+/// Replication policies are configured on the storage account, and so cannot be
+/// set on individual blobs
+///
+/// <https://learn.microsoft.com/en-us/azure/storage/blobs/object-replication-overview>
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ObjectReplication {
+    /// The `x-ms-or-policy-id` of a destination blob, identifying the policy that
+    /// replicated it
+    pub policy_id: Option<String>,
+    /// The status of each replication rule applying to a source blob, from its
+    /// `x-ms-or-{policy-id}_{rule-id}` headers
+    pub rules: Vec<ReplicationRuleStatus>,
+}
 
-        Ok(GetResult::Stream(stream))
+impl ObjectReplication {
+    /// Returns true if this is a source blob and it has been replicated by all of
+    /// its replication rules
+    pub fn is_complete(&self) -> bool {
+        !self.rules.is_empty()
+            && self
+                .rules
+                .iter()
+                .all(|rule| rule.status == ReplicationStatus::Complete)
     }
 
-    async fn get_range(&self, location: &Path, range: Range<usize>) -> Result<Bytes> {
-        let bytes = self
-            .client
-            .get_request(location, Some(range), false)
-            .await?
-            .bytes()
-            .await
-            .map_err(|source| client::Error::GetResponseBody {
-                source,
-                path: location.to_string(),
-            })?;
-        Ok(bytes)
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let mut replication = Self::default();
+        for (name, value) in headers {
+            let (name, value) = match (
+                name.as_str().strip_prefix(OBJECT_REPLICATION_PREFIX),
+                value.to_str(),
+            ) {
+                (Some(name), Ok(value)) => (name, value),
+                _ => continue,
+            };
+
+            if name == "policy-id" {
+                replication.policy_id = Some(value.to_string());
+            } else if let Some((policy_id, rule_id)) = name.split_once('_') {
+                replication.rules.push(ReplicationRuleStatus {
+                    policy_id: policy_id.to_string(),
+                    rule_id: rule_id.to_string(),
+                    status: ReplicationStatus::from(value),
+                });
+            }
+        }
+        replication
     }
+}
 
-    async fn head(&self, location: &Path) -> Result<ObjectMeta> {
-        use reqwest::header::{CONTENT_LENGTH, LAST_MODIFIED};
+/// The status of a single object replication rule for a source blob
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplicationRuleStatus {
+    /// The ID of the replication policy
+    pub policy_id: String,
+    /// The ID of the rule within the policy
+    pub rule_id: String,
+    /// The replication status of the blob for this rule
+    pub status: ReplicationStatus,
+}
 
-        // Extract meta from headers
-        // https://docs.microsoft.com/en-us/rest/api/storageservices/get-blob-properties
-        let response = self.client.get_request(location, None, true).await?;
-        let headers = response.headers();
+/// The status of the replication of a source blob by an object replication rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ReplicationStatus {
+    /// The blob has been replicated to the destination
+    Complete,
+    /// The blob failed to replicate to the destination
+    Failed,
+    /// A status not recognised by this crate
+    Other(String),
+}
 
-        let last_modified = headers
-            .get(LAST_MODIFIED)
-            .ok_or(Error::MissingLastModified)?
-            .to_str()
-            .context(BadHeaderSnafu)?;
-        let last_modified = Utc
-            .datetime_from_str(last_modified, RFC1123_FMT)
-            .context(InvalidLastModifiedSnafu { last_modified })?;
-
-        let content_length = headers
-            .get(CONTENT_LENGTH)
-            .ok_or(Error::MissingContentLength)?
-            .to_str()
-            .context(BadHeaderSnafu)?;
-        let content_length = content_length
-            .parse()
-            .context(InvalidContentLengthSnafu { content_length })?;
-
-        Ok(ObjectMeta {
-            location: location.clone(),
-            last_modified,
-            size: content_length,
-        })
+impl From<&str> for ReplicationStatus {
+    fn from(value: &str) -> Self {
+        match value {
+            "complete" => Self::Complete,
+            "failed" => Self::Failed,
+            other => Self::Other(other.to_string()),
+        }
     }
+}
 
-    async fn delete(&self, location: &Path) -> Result<()> {
-        self.client.delete_request(location, &()).await
-    }
+static OBJECT_REPLICATION_PREFIX: &str = "x-ms-or-";
 
-    async fn list(
-        &self,
-        prefix: Option<&Path>,
-    ) -> Result<BoxStream<'_, Result<ObjectMeta>>> {
-        let stream = self
-            .client
-            .list_paginated(prefix, false)
-            .map_ok(|r| futures::stream::iter(r.objects.into_iter().map(Ok)))
-            .try_flatten()
-            .boxed();
+/// The status and headers of a response from Azure, as returned by methods such as
+/// [`MicrosoftAzure::put_with_response_info`], and of [`BlobProperties::response`]
+///
+/// This exposes headers not otherwise parsed by this crate, such as those of newer
+/// Azure features, and should not be needed for typical use
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResponseInfo {
+    /// The status of the response
+    pub status: reqwest::StatusCode,
+    /// The headers of the response
+    pub headers: HeaderMap,
+}
 
-        Ok(stream)
+impl ResponseInfo {
+    fn new(response: &reqwest::Response) -> Self {
+        Self {
+            status: response.status(),
+            headers: response.headers().clone(),
+        }
     }
+}
 
-    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> Result<ListResult> {
-        let mut stream = self.client.list_paginated(prefix, true);
+/// The lease of a blob, see [`BlobProperties::lease`]
+///
+/// Each field is `None` if the corresponding header was not returned
+///
+/// <https://learn.microsoft.com/en-us/rest/api/storageservices/lease-blob>
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlobLease {
+    /// The `x-ms-lease-status` of the blob
+    pub status: Option<LeaseStatus>,
+    /// The `x-ms-lease-state` of the blob
+    pub state: Option<LeaseState>,
+    /// The `x-ms-lease-duration` of the blob, only returned if it is leased
+    pub duration: Option<LeaseDuration>,
+}
 
-        let mut common_prefixes = BTreeSet::new();
-        let mut objects = Vec::new();
+impl BlobLease {
+    /// Returns true if the blob is locked by a lease, and so cannot be written or
+    /// deleted without specifying the lease
+    pub fn is_locked(&self) -> bool {
+        self.status == Some(LeaseStatus::Locked)
+    }
 
-        while let Some(result) = stream.next().await {
-            let response = result?;
-            common_prefixes.extend(response.common_prefixes.into_iter());
-            objects.extend(response.objects.into_iter());
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+        Self {
+            status: header(LEASE_STATUS).map(LeaseStatus::from),
+            state: header(LEASE_STATE).map(LeaseState::from),
+            duration: header(LEASE_DURATION).map(LeaseDuration::from),
         }
-
-        Ok(ListResult {
-            common_prefixes: common_prefixes.into_iter().collect(),
-            objects,
-        })
     }
+}
 
-    async fn copy(&self, from: &Path, to: &Path) -> Result<()> {
-        self.client.copy_request(from, to, true).await
+/// Whether a blob is locked by a lease
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LeaseStatus {
+    /// The blob is leased
+    Locked,
+    /// The blob is not leased
+    Unlocked,
+    /// A status not recognised by this crate
+    Other(String),
+}
+
+impl From<&str> for LeaseStatus {
+    fn from(value: &str) -> Self {
+        match value {
+            "locked" => Self::Locked,
+            "unlocked" => Self::Unlocked,
+            other => Self::Other(other.to_string()),
+        }
     }
+}
 
-    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
-        self.client.copy_request(from, to, false).await
+/// The state of the lease of a blob
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LeaseState {
+    /// The blob is not leased and a lease can be acquired
+    Available,
+    /// The blob is leased
+    Leased,
+    /// The lease has expired, and a new lease can be acquired
+    Expired,
+    /// The lease is being broken, and cannot be renewed until the break period ends
+    Breaking,
+    /// The lease has been broken, and a new lease can be acquired
+    Broken,
+    /// A state not recognised by this crate
+    Other(String),
+}
+
+impl From<&str> for LeaseState {
+    fn from(value: &str) -> Self {
+        match value {
+            "available" => Self::Available,
+            "leased" => Self::Leased,
+            "expired" => Self::Expired,
+            "breaking" => Self::Breaking,
+            "broken" => Self::Broken,
+            other => Self::Other(other.to_string()),
+        }
     }
 }
 
-/// Relevant docs: <https://azure.github.io/Storage/docs/application-and-user-data/basics/azure-blob-storage-upload-apis/>
-/// In Azure Blob Store, parts are "blocks"
-/// put_multipart_part -> PUT block
-/// complete -> PUT block list
-/// abort -> No equivalent; blocks are simply dropped after 7 days
-#[derive(Debug, Clone)]
-struct AzureMultiPartUpload {
-    client: Arc<client::AzureClient>,
-    location: Path,
+/// The duration of the lease of a blob
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LeaseDuration {
+    /// The lease does not expire
+    Infinite,
+    /// The lease expires after a fixed duration of between 15 and 60 seconds
+    Fixed,
+    /// A duration not recognised by this crate
+    Other(String),
 }
 
-#[async_trait]
-impl CloudMultiPartUploadImpl for AzureMultiPartUpload {
-    async fn put_multipart_part(
-        &self,
-        buf: Vec<u8>,
-        part_idx: usize,
-    ) -> Result<UploadPart, io::Error> {
-        let content_id = format!("{:20}", part_idx);
-        let block_id: BlockId = content_id.clone().into();
+impl From<&str> for LeaseDuration {
+    fn from(value: &str) -> Self {
+        match value {
+            "infinite" => Self::Infinite,
+            "fixed" => Self::Fixed,
+            other => Self::Other(other.to_string()),
+        }
+    }
+}
 
-        self.client
-            .put_request(
-                &self.location,
-                Some(buf.into()),
-                true,
-                &[("comp", "block"), ("blockid", &base64::encode(block_id))],
-            )
-            .await?;
+/// The type of a blob, see [`BlobProperties::blob_type`]
+///
+/// <https://learn.microsoft.com/en-us/rest/api/storageservices/understanding-block-blobs--append-blobs--and-page-blobs>
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BlobType {
+    /// A blob composed of blocks, as written by this crate
+    #[default]
+    Block,
+    /// A blob composed of blocks that can only be appended to
+    Append,
+    /// A blob composed of 512-byte pages that can be written at random
+    Page,
+    /// A type not recognised by this crate
+    Other(String),
+}
 
-        Ok(UploadPart { content_id })
+impl From<&str> for BlobType {
+    fn from(value: &str) -> Self {
+        match value {
+            "BlockBlob" => Self::Block,
+            "AppendBlob" => Self::Append,
+            "PageBlob" => Self::Page,
+            other => Self::Other(other.to_string()),
+        }
     }
+}
 
-    async fn complete(&self, completed_parts: Vec<UploadPart>) -> Result<(), io::Error> {
-        let blocks = completed_parts
-            .into_iter()
-            .map(|part| BlockId::from(part.content_id))
-            .collect();
+static LEASE_STATUS: &str = "x-ms-lease-status";
+static LEASE_STATE: &str = "x-ms-lease-state";
+static LEASE_DURATION: &str = "x-ms-lease-duration";
 
-        let block_list = BlockList { blocks };
-        let block_xml = block_list.to_xml();
+/// The access tier of a blob, which trades off the cost of storage against the cost
+/// and latency of access
+///
+/// <https://learn.microsoft.com/en-us/azure/storage/blobs/access-tiers-overview>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AccessTier {
+    /// Optimized for frequently accessed data
+    Hot,
+    /// Optimized for infrequently accessed data stored for at least 30 days
+    Cool,
+    /// Offline storage for rarely accessed data stored for at least 180 days, which
+    /// must be rehydrated to another tier before it can be read
+    Archive,
+}
 
-        self.client
-            .put_request(
-                &self.location,
-                Some(block_xml.into()),
-                true,
-                &[("comp", "blocklist")],
-            )
-            .await?;
+impl AccessTier {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Hot => "Hot",
+            Self::Cool => "Cool",
+            Self::Archive => "Archive",
+        }
+    }
+}
 
-        Ok(())
+impl FromStr for AccessTier {
+    type Err = super::Error;
+
+    /// Parses an access tier, ignoring case
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "hot" => Ok(Self::Hot),
+            "cool" => Ok(Self::Cool),
+            "archive" => Ok(Self::Archive),
+            _ => Err(Error::InvalidAccessTier { value: s.into() }.into()),
+        }
     }
 }
 
-/// Configure a connection to Microsoft Azure Blob Storage container using
-/// the specified credentials.
+/// When a blob set with [`MicrosoftAzure::set_expiry`] is deleted
 ///
-/// # Example
-/// ```
-/// # let ACCOUNT = "foo";
-/// # let BUCKET_NAME = "foo";
-/// # let ACCESS_KEY = "foo";
-/// # use object_store::azure::MicrosoftAzureBuilder;
-/// let azure = MicrosoftAzureBuilder::new()
-///  .with_account(ACCOUNT)
-///  .with_access_key(ACCESS_KEY)
-///  .with_container_name(BUCKET_NAME)
-///  .build();
-/// ```
-#[derive(Default, Clone)]
-pub struct MicrosoftAzureBuilder {
-    account_name: Option<String>,
-    access_key: Option<String>,
-    container_name: Option<String>,
-    bearer_token: Option<String>,
-    client_id: Option<String>,
-    client_secret: Option<String>,
-    tenant_id: Option<String>,
-    sas_query_pairs: Option<Vec<(String, String)>>,
-    sas_key: Option<String>,
-    authority_host: Option<String>,
-    url: Option<String>,
-    use_emulator: bool,
-    retry_config: RetryConfig,
-    client_options: ClientOptions,
+/// <https://learn.microsoft.com/en-us/rest/api/storageservices/set-blob-expiry>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpiryOption {
+    /// Expire the blob the given duration from now
+    RelativeToNow(std::time::Duration),
+    /// Expire the blob the given duration after it was created
+    RelativeToCreation(std::time::Duration),
+    /// Expire the blob at the given time
+    Absolute(chrono::DateTime<Utc>),
+    /// Remove any expiry previously set on the blob
+    NeverExpire,
 }
 
-/// Configuration keys for [`MicrosoftAzureBuilder`]
+/// A block of a block blob, as returned by [`MicrosoftAzure::committed_blocks`]
+/// and [`MicrosoftAzure::find_incomplete_uploads`]
 ///
-/// Configuration via keys can be dome via the [`try_with_option`](MicrosoftAzureBuilder::try_with_option)
-/// or [`with_options`](MicrosoftAzureBuilder::try_with_options) methods on the builder.
-///
-/// # Example
-/// ```
-/// use std::collections::HashMap;
-/// use object_store::azure::{MicrosoftAzureBuilder, AzureConfigKey};
-///
-/// let options = HashMap::from([
-///     ("azure_client_id", "my-client-id"),
-///     ("azure_client_secret", "my-account-name"),
-/// ]);
-/// let typed_options = vec![
-///     (AzureConfigKey::AccountName, "my-account-name"),
-/// ];
-/// let azure = MicrosoftAzureBuilder::new()
-///     .try_with_options(options)
-///     .unwrap()
-///     .try_with_options(typed_options)
-///     .unwrap()
-///     .try_with_option(AzureConfigKey::AuthorityId, "my-tenant-id")
-///     .unwrap();
-/// ```
-#[derive(PartialEq, Eq, Hash, Clone, Debug, Copy, Deserialize, Serialize)]
-pub enum AzureConfigKey {
-    /// The name of the azure storage account
-    ///
-    /// Supported keys:
-    /// - `azure_storage_account_name`
-    /// - `account_name`
-    AccountName,
-
-    /// Master key for accessing storage account
-    ///
-    /// Supported keys:
-    /// - `azure_storage_account_key`
-    /// - `azure_storage_access_key`
-    /// - `azure_storage_master_key`
-    /// - `access_key`
-    /// - `account_key`
-    /// - `master_key`
-    AccessKey,
-
-    /// Service principal client id for authorizing requests
-    ///
-    /// Supported keys:
-    /// - `azure_storage_client_id`
-    /// - `azure_client_id`
-    /// - `client_id`
-    ClientId,
-
-    /// Service principal client secret for authorizing requests
-    ///
-    /// Supported keys:
-    /// - `azure_storage_client_secret`
-    /// - `azure_client_secret`
-    /// - `client_secret`
-    ClientSecret,
+/// <https://learn.microsoft.com/en-us/rest/api/storageservices/get-block-list#response-body>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockInfo {
+    /// The base64 encoded ID of the block
+    pub id: String,
+    /// The size of the block in bytes
+    pub size: usize,
+}
 
-    /// Tenant id used in oauth flows
-    ///
-    /// Supported keys:
-    /// - `azure_storage_tenant_id`
-    /// - `azure_storage_authority_id`
-    /// - `azure_tenant_id`
-    /// - `azure_authority_id`
-    /// - `tenant_id`
-    /// - `authority_id`
-    AuthorityId,
+/// Blocks staged to a block blob that have not been committed, such as those of an
+/// upload interrupted by a crash, as returned by [`MicrosoftAzure::find_incomplete_uploads`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncompleteUpload {
+    /// The location of the blob the blocks were staged to
+    pub location: Path,
+    /// The uncommitted blocks, in the order they were staged
+    pub blocks: Vec<BlockInfo>,
+    /// The total size of the uncommitted blocks in bytes
+    pub size: usize,
+}
 
-    /// Shared access signature.
-    ///
-    /// The signature is expected to be percent-encoded, much like they are provided
-    /// in the azure storage explorer or azure portal.
+impl IncompleteUpload {
+    /// Returns the sorted indices of the blocks staged with [`MicrosoftAzure::stage_block`],
+    /// or as the parts of [`ObjectStore::put_multipart`]
     ///
-    /// Supported keys:
-    /// - `azure_storage_sas_key`
-    /// - `azure_storage_sas_token`
-    /// - `sas_key`
-    /// - `sas_token`
-    SasKey,
+    /// Blocks staged by other clients, whose IDs are not derived from an index, are
+    /// skipped
+    pub fn indices(&self) -> Vec<usize> {
+        let mut indices: Vec<_> = self
+            .blocks
+            .iter()
+            .filter_map(|block| {
+                let id = base64::decode(&block.id).ok()?;
+                std::str::from_utf8(&id).ok()?.trim().parse().ok()
+            })
+            .collect();
+        indices.sort_unstable();
+        indices.dedup();
+        indices
+    }
+}
 
-    /// Bearer token
-    ///
-    /// Supported keys:
-    /// - `azure_storage_token`
-    /// - `bearer_token`
-    /// - `token`
-    Token,
+/// The ETag of each object in a listing, keyed by path, as returned by
+/// [`MicrosoftAzure::list_snapshot`]
+pub type ListingSnapshot = HashMap<Path, String>;
 
-    /// Use object store with azurite storage emulator
-    ///
-    /// Supported keys:
-    /// - `azure_storage_use_emulator`
-    /// - `object_store_use_emulator`
-    /// - `use_emulator`
-    UseEmulator,
+/// The changes between a previous [`ListingSnapshot`] and a new listing, as returned
+/// by [`MicrosoftAzure::list_diff`]
+///
+/// Objects are sorted by path
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ListingDiff {
+    /// Objects not present in the previous snapshot
+    pub added: Vec<ObjectMeta>,
+    /// Objects whose ETag differs from the previous snapshot
+    pub modified: Vec<ObjectMeta>,
+    /// Paths in the previous snapshot that no longer exist
+    pub removed: Vec<Path>,
+    /// A snapshot of the new listing, to be passed to the next call
+    pub snapshot: ListingSnapshot,
 }
 
-impl AsRef<str> for AzureConfigKey {
-    fn as_ref(&self) -> &str {
-        match self {
-            Self::AccountName => "azure_storage_account_name",
-            Self::AccessKey => "azure_storage_account_key",
-            Self::ClientId => "azure_storage_client_id",
-            Self::ClientSecret => "azure_storage_client_secret",
-            Self::AuthorityId => "azure_storage_tenant_id",
-            Self::SasKey => "azure_storage_sas_key",
-            Self::Token => "azure_storage_token",
-            Self::UseEmulator => "azure_storage_use_emulator",
+impl ListingDiff {
+    fn new(previous: &ListingSnapshot, current: Vec<ObjectMeta>) -> Self {
+        let mut diff = Self::default();
+        for meta in current {
+            // Listed blobs always have an ETag
+            let e_tag = meta.e_tag.clone().unwrap_or_default();
+            match previous.get(&meta.location) {
+                None => diff.added.push(meta.clone()),
+                Some(previous) if previous != &e_tag => diff.modified.push(meta.clone()),
+                Some(_) => {}
+            }
+            diff.snapshot.insert(meta.location, e_tag);
         }
-    }
-}
 
-impl FromStr for AzureConfigKey {
-    type Err = super::Error;
+        diff.removed = previous
+            .keys()
+            .filter(|location| !diff.snapshot.contains_key(*location))
+            .cloned()
+            .collect();
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "azure_storage_account_key"
-            | "azure_storage_access_key"
-            | "azure_storage_master_key"
-            | "master_key"
-            | "account_key"
-            | "access_key" => Ok(Self::AccessKey),
-            "azure_storage_account_name" | "account_name" => Ok(Self::AccountName),
-            "azure_storage_client_id" | "azure_client_id" | "client_id" => {
-                Ok(Self::ClientId)
-            }
-            "azure_storage_client_secret" | "azure_client_secret" | "client_secret" => {
-                Ok(Self::ClientSecret)
-            }
-            "azure_storage_tenant_id"
-            | "azure_storage_authority_id"
-            | "azure_tenant_id"
-            | "azure_authority_id"
-            | "tenant_id"
-            | "authority_id" => Ok(Self::AuthorityId),
-            "azure_storage_sas_key"
-            | "azure_storage_sas_token"
-            | "sas_key"
-            | "sas_token" => Ok(Self::SasKey),
-            "azure_storage_token" | "bearer_token" | "token" => Ok(Self::Token),
-            "azure_storage_use_emulator" | "use_emulator" => Ok(Self::UseEmulator),
-            _ => Err(Error::UnknownConfigurationKey { key: s.into() }.into()),
-        }
+        diff.added
+            .sort_unstable_by(|a, b| a.location.cmp(&b.location));
+        diff.modified
+            .sort_unstable_by(|a, b| a.location.cmp(&b.location));
+        diff.removed.sort_unstable();
+        diff
     }
 }
 
-impl Debug for MicrosoftAzureBuilder {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "MicrosoftAzureBuilder {{ account: {:?}, container_name: {:?} }}",
-            self.account_name, self.container_name
-        )
+impl MicrosoftAzure {
+    /// Returns the name of the storage account
+    pub fn account(&self) -> &str {
+        &self.client.config().account
     }
-}
 
-impl MicrosoftAzureBuilder {
-    /// Create a new [`MicrosoftAzureBuilder`] with default values.
-    pub fn new() -> Self {
-        Default::default()
+    /// Returns the name of the container
+    pub fn container(&self) -> &str {
+        &self.client.config().container
     }
 
-    /// Create an instance of [`MicrosoftAzureBuilder`] with values pre-populated from environment variables.
-    ///
-    /// Variables extracted from environment:
-    /// * AZURE_STORAGE_ACCOUNT_NAME: storage account name
-    /// * AZURE_STORAGE_ACCOUNT_KEY: storage account master key
-    /// * AZURE_STORAGE_ACCESS_KEY: alias for AZURE_STORAGE_ACCOUNT_KEY
-    /// * AZURE_STORAGE_CLIENT_ID -> client id for service principal authorization
-    /// * AZURE_STORAGE_CLIENT_SECRET -> client secret for service principal authorization
-    /// * AZURE_STORAGE_TENANT_ID -> tenant id used in oauth flows
-    /// # Example
-    /// ```
-    /// use object_store::azure::MicrosoftAzureBuilder;
+    /// Returns the blob service endpoint that requests are sent to, e.g.
+    /// `https://{account}.blob.core.windows.net`
+    pub fn endpoint(&self) -> &Url {
+        &self.client.config().service
+    }
+
+    /// Replace the HTTP properties of the blob at `location` without re-uploading its data
     ///
-    /// let azure = MicrosoftAzureBuilder::from_env()
-    ///     .with_container_name("foo")
-    ///     .build();
-    /// ```
-    pub fn from_env() -> Self {
-        let mut builder = Self::default();
-        for (os_key, os_value) in std::env::vars_os() {
-            if let (Some(key), Some(value)) = (os_key.to_str(), os_value.to_str()) {
-                if key.starts_with("AZURE_") {
-                    if let Ok(config_key) =
-                        AzureConfigKey::from_str(&key.to_ascii_lowercase())
-                    {
-                        builder = builder.try_with_option(config_key, value).unwrap();
-                    }
-                }
-            }
+    /// Note: Azure replaces all of these properties at once, any fields left as `None`
+    /// in `properties` will be cleared on the blob
+    pub async fn set_properties(
+        &self,
+        location: &Path,
+        properties: BlobProperties,
+    ) -> Result<()> {
+        if self.client.config().dry_run {
+            info!("Dry run: skipping set properties of {}", location);
+            return Ok(());
         }
 
-        if let Ok(text) = std::env::var("AZURE_ALLOW_HTTP") {
-            builder.client_options =
-                builder.client_options.with_allow_http(str_is_truthy(&text));
+        self.client
+            .set_properties_request(location, &properties)
+            .await
+    }
+
+    /// Replace the user-defined metadata of the blob at `location` without
+    /// re-uploading its data
+    ///
+    /// Any existing metadata not present in `metadata` is removed. Keys must be
+    /// valid C# identifiers, and are sent as `x-ms-meta-<key>` headers
+    pub async fn set_metadata(
+        &self,
+        location: &Path,
+        metadata: HashMap<String, String>,
+    ) -> Result<()> {
+        if self.client.config().dry_run {
+            info!("Dry run: skipping set metadata of {}", location);
+            return Ok(());
         }
 
-        builder
+        self.client.set_metadata_request(location, &metadata).await
     }
 
-    /// Parse available connection info form a well-known storage URL.
+    /// Set when the blob at `location` is automatically deleted, replacing any
+    /// expiry previously set on it
     ///
-    /// The supported url schemes are:
+    /// This is only supported by accounts with a hierarchical namespace enabled
+    pub async fn set_expiry(&self, location: &Path, expiry: ExpiryOption) -> Result<()> {
+        if self.client.config().dry_run {
+            info!("Dry run: skipping set expiry of {}", location);
+            return Ok(());
+        }
+
+        self.client.set_expiry_request(location, &expiry).await
+    }
+
+    /// Append `bytes` to the append blob at `location` as a single block, creating the
+    /// blob if it does not exist, see [`MicrosoftAzureBuilder::with_append_create`]
     ///
-    /// - `abfs[s]://<container>/<path>` (according to [fsspec](https://github.com/fsspec/adlfs))
-    /// - `abfs[s]://<file_system>@<account_name>.dfs.core.windows.net/<path>`
-    /// - `az://<container>/<path>` (according to [fsspec](https://github.com/fsspec/adlfs))
-    /// - `adl://<container>/<path>` (according to [fsspec](https://github.com/fsspec/adlfs))
-    /// - `azure://<container>/<path>` (custom)
-    /// - `https://<account>.dfs.core.windows.net`
-    /// - `https://<account>.blob.core.windows.net`
+    /// Blocks are limited to 4 MiB, and an append blob to 50,000 blocks, beyond which
+    /// an error is returned. As a failed append may still have been applied, it is only
+    /// retried if it failed to be sent, see [`MicrosoftAzure::append_at`] to safely
+    /// retry appends.
     ///
-    /// Note: Settings derived from the URL will override any others set on this builder
+    /// <https://learn.microsoft.com/en-us/rest/api/storageservices/understanding-block-blobs--append-blobs--and-page-blobs#about-append-blobs>
+    pub async fn append(&self, location: &Path, bytes: Bytes) -> Result<()> {
+        self.append_block(location, bytes, None).await
+    }
+
+    /// Performs [`MicrosoftAzure::append`] only if the blob at `location` is `position`
+    /// bytes long, returning [`Error::Precondition`] otherwise
     ///
-    /// # Example
-    /// ```
-    /// use object_store::azure::MicrosoftAzureBuilder;
+    /// An append that was applied, but whose response was lost, fails if repeated, and
+    /// so appends at a position are retried like other requests. This can be used to
+    /// append each block exactly once, even across retries by the caller.
     ///
-    /// let azure = MicrosoftAzureBuilder::from_env()
-    ///     .with_url("abfss://file_system@account.dfs.core.windows.net/")
-    ///     .build();
-    /// ```
-    pub fn with_url(mut self, url: impl Into<String>) -> Self {
-        self.url = Some(url.into());
-        self
+    /// [`Error::Precondition`]: crate::Error::Precondition
+    pub async fn append_at(
+        &self,
+        location: &Path,
+        bytes: Bytes,
+        position: usize,
+    ) -> Result<()> {
+        self.append_block(location, bytes, Some(position)).await
     }
 
-    /// Set an option on the builder via a key - value pair.
-    pub fn try_with_option(
-        mut self,
-        key: impl AsRef<str>,
-        value: impl Into<String>,
-    ) -> Result<Self> {
-        match AzureConfigKey::from_str(key.as_ref())? {
-            AzureConfigKey::AccessKey => self.access_key = Some(value.into()),
-            AzureConfigKey::AccountName => self.account_name = Some(value.into()),
-            AzureConfigKey::ClientId => self.client_id = Some(value.into()),
-            AzureConfigKey::ClientSecret => self.client_secret = Some(value.into()),
-            AzureConfigKey::AuthorityId => self.tenant_id = Some(value.into()),
-            AzureConfigKey::SasKey => self.sas_key = Some(value.into()),
-            AzureConfigKey::Token => self.bearer_token = Some(value.into()),
-            AzureConfigKey::UseEmulator => {
-                self.use_emulator = str_is_truthy(&value.into())
+    async fn append_block(
+        &self,
+        location: &Path,
+        bytes: Bytes,
+        position: Option<usize>,
+    ) -> Result<()> {
+        if self.client.config().dry_run {
+            info!(
+                "Dry run: skipping append of {} bytes to {}",
+                bytes.len(),
+                location
+            );
+            return Ok(());
+        }
+
+        let result = self
+            .client
+            .append_block_request(location, bytes.clone(), position)
+            .await;
+        match result {
+            Err(crate::Error::NotFound { .. }) if self.append_create => {
+                // Another writer may have created the blob concurrently
+                match self.client.create_append_blob_request(location).await {
+                    Ok(()) | Err(crate::Error::AlreadyExists { .. }) => {}
+                    Err(e) => return Err(e),
+                }
+                self.client
+                    .append_block_request(location, bytes, position)
+                    .await
             }
-        };
-        Ok(self)
+            result => result,
+        }
     }
 
-    /// Hydrate builder from key value pairs
-    pub fn try_with_options<
-        I: IntoIterator<Item = (impl AsRef<str>, impl Into<String>)>,
-    >(
-        mut self,
-        options: I,
-    ) -> Result<Self> {
-        for (key, value) in options {
-            self = self.try_with_option(key, value)?;
+    /// Save `bytes` to `location` without readers ever observing a partially written
+    /// blob, by writing them to a temporary blob next to `location` that is then
+    /// moved into place
+    ///
+    /// With [`MicrosoftAzureBuilder::with_hierarchical_namespace`] the temporary blob
+    /// is renamed. Otherwise it is copied to `location`, only if unchanged since it was
+    /// written, and then deleted. The temporary blob, named `<location>.<random>.tmp`,
+    /// is deleted if this fails, but is visible to listings until then and remains
+    /// if the process exits before it is moved.
+    pub async fn put_atomic(&self, location: &Path, bytes: Bytes) -> Result<()> {
+        if self.client.config().dry_run {
+            info!(
+                "Dry run: skipping atomic put of {} bytes to {}",
+                bytes.len(),
+                location
+            );
+            return Ok(());
         }
-        Ok(self)
-    }
 
-    /// Sets properties on this builder based on a URL
-    ///
-    /// This is a separate member function to allow fallible computation to
-    /// be deferred until [`Self::build`] which in turn allows deriving [`Clone`]
-    fn parse_url(&mut self, url: &str) -> Result<()> {
-        let parsed = Url::parse(url).context(UnableToParseUrlSnafu { url })?;
-        let host = parsed.host_str().context(UrlNotRecognisedSnafu { url })?;
+        let suffix = rand::thread_rng().gen::<u64>();
+        let temporary = Path::parse(format!("{}.{:016x}.tmp", location, suffix))
+            .expect("valid temporary path");
+        let response = self
+            .client
+            .put_request(&temporary, Some(bytes), false, &())
+            .await?;
 
-        let validate = |s: &str| match s.contains('.') {
-            true => Err(UrlNotRecognisedSnafu { url }.build()),
-            false => Ok(s.to_string()),
+        let result = match self.hierarchical_namespace {
+            true => self.client.rename_request(&temporary, location).await,
+            false => {
+                let source_if_match = response
+                    .headers()
+                    .get(ETAG)
+                    .map(|e_tag| e_tag.to_str().context(BadHeaderSnafu))
+                    .transpose()?
+                    .map(ToString::to_string);
+                let options = CopyOptions {
+                    source_if_match,
+                    ..Default::default()
+                };
+                self.client
+                    .copy_request(&temporary, location, &options)
+                    .await
+            }
         };
 
-        match parsed.scheme() {
-            "az" | "adl" | "azure" => self.container_name = Some(validate(host)?),
-            "abfs" | "abfss" => {
-                // abfs(s) might refer to the fsspec convention abfs://<container>/<path>
-                // or the convention for the hadoop driver abfs[s]://<file_system>@<account_name>.dfs.core.windows.net/<path>
-                if parsed.username().is_empty() {
-                    self.container_name = Some(validate(host)?);
-                } else if let Some(a) = host.strip_suffix(".dfs.core.windows.net") {
-                    self.container_name = Some(validate(parsed.username())?);
-                    self.account_name = Some(validate(a)?);
-                } else {
-                    return Err(UrlNotRecognisedSnafu { url }.build().into());
-                }
+        // A renamed blob no longer exists under its temporary name
+        if result.is_err() || !self.hierarchical_namespace {
+            if let Err(e) = self.client.delete_request(&temporary, &(), None).await {
+                warn!("Failed to delete temporary blob {}: {}", temporary, e);
             }
-            "https" => match host.split_once('.') {
-                Some((a, "dfs.core.windows.net"))
-                | Some((a, "blob.core.windows.net")) => {
-                    self.account_name = Some(validate(a)?);
-                }
-                _ => return Err(UrlNotRecognisedSnafu { url }.build().into()),
-            },
-            scheme => return Err(UnknownUrlSchemeSnafu { scheme }.build().into()),
         }
-        Ok(())
+        result
     }
 
-    /// Set the Azure Account (required)
-    pub fn with_account(mut self, account: impl Into<String>) -> Self {
-        self.account_name = Some(account.into());
-        self
-    }
+    /// Copy the blob or file at `source_url`, which may be in another account, to
+    /// `to`, waiting until the copy completes
+    ///
+    /// The source must either be public or have a SAS token granting read access in
+    /// its query, such as one returned by [`MicrosoftAzure::signed_url`]. Copies from
+    /// another account complete asynchronously, and so can take some time for large
+    /// blobs. This also applies to [`ObjectStore::copy`], which returns an error if
+    /// the copy fails or is aborted
+    pub async fn copy_from_url(&self, source_url: &Url, to: &Path) -> Result<()> {
+        if self.client.config().dry_run {
+            info!("Dry run: skipping copy of {} to {}", source_url.path(), to);
+            return Ok(());
+        }
 
-    /// Set the Azure Container Name (required)
-    pub fn with_container_name(mut self, container_name: impl Into<String>) -> Self {
-        self.container_name = Some(container_name.into());
-        self
+        // Omit the query from errors, as it may contain a SAS token
+        let mut name = source_url.clone();
+        name.set_query(None);
+        self.client
+            .copy_from_url_request(source_url, name.as_str(), to, &CopyOptions::default())
+            .await
     }
 
-    /// Set the Azure Access Key (required - one of access key, bearer token, or client credentials)
-    pub fn with_access_key(mut self, access_key: impl Into<String>) -> Self {
-        self.access_key = Some(access_key.into());
-        self
+    /// Returns a url of the blob at `location` that can be used without any other
+    /// credentials to perform the operations granted by `permissions`, such as `"r"`
+    /// to read the blob, until `expires_in` from now
+    ///
+    /// If the store has an access key, see [`MicrosoftAzureBuilder::with_prefer_oauth`],
+    /// the url has a service SAS signed with it. Otherwise the OAuth credentials of the
+    /// store are used to fetch a user delegation key, which must expire within 7 days,
+    /// to sign a user delegation SAS. Stores authorized with a SAS token or bearer
+    /// token cannot sign urls.
+    ///
+    /// <https://learn.microsoft.com/en-us/rest/api/storageservices/delegate-access-with-shared-access-signature>
+    pub async fn signed_url(
+        &self,
+        location: &Path,
+        expires_in: std::time::Duration,
+        permissions: &str,
+    ) -> Result<Url> {
+        self.client
+            .signed_url(location, expires_in, permissions)
+            .await
     }
 
-    /// Set a static bearer token to be used for authorizing requests
-    pub fn with_bearer_token_authorization(
-        mut self,
-        bearer_token: impl Into<String>,
-    ) -> Self {
-        self.bearer_token = Some(bearer_token.into());
-        self
+    /// Returns true if the server responds to a request for the blob at `location`
+    /// with `Accept-Ranges: bytes`, indicating that it supports range requests
+    ///
+    /// Azure itself always supports range requests, but some gateways implementing
+    /// its protocol do not. This is not needed to read ranges, as a server ignoring
+    /// the `Range` header is detected from the response, see [`ObjectStore::get_ranges`]
+    pub async fn accepts_ranges(&self, location: &Path) -> Result<bool> {
+        let response = self.client.get_request(location, None, true).await?;
+        Ok(matches!(
+            response.headers().get(ACCEPT_RANGES),
+            Some(value) if value.as_bytes() == b"bytes"
+        ))
     }
 
-    /// Set a client secret used for client secret authorization
-    pub fn with_client_secret_authorization(
-        mut self,
-        client_id: impl Into<String>,
-        client_secret: impl Into<String>,
-        tenant_id: impl Into<String>,
-    ) -> Self {
-        self.client_id = Some(client_id.into());
-        self.client_secret = Some(client_secret.into());
-        self.tenant_id = Some(tenant_id.into());
-        self
+    /// Fetch `range` of the object at `location`, returning the bytes of the response
+    /// and whether they are the whole object, as returned by a server that ignores
+    /// the `Range` header
+    async fn get_range_response(
+        &self,
+        location: &Path,
+        range: Range<usize>,
+    ) -> Result<(Bytes, bool)> {
+        let response = self
+            .client
+            .get_request(location, Some(range), false)
+            .await?;
+        let whole = response.status() == reqwest::StatusCode::OK;
+        let expected_md5 = client::expected_md5(self.client.config(), &response);
+        let bytes =
+            response
+                .bytes()
+                .await
+                .map_err(|source| client::Error::GetResponseBody {
+                    source,
+                    path: location.to_string(),
+                })?;
+        if let Some(expected) = expected_md5 {
+            client::verify_md5(location, &expected, &bytes)?;
+        }
+        if whole {
+            info!(
+                "Server returned the whole of {} for a range request",
+                location
+            );
+        }
+        Ok((bytes, whole))
     }
 
-    /// Set query pairs appended to the url for shared access signature authorization
-    pub fn with_sas_authorization(
-        mut self,
-        query_pairs: impl Into<Vec<(String, String)>>,
-    ) -> Self {
-        self.sas_query_pairs = Some(query_pairs.into());
-        self
-    }
+    /// Save `bytes` to `location` in the access tier `tier`, avoiding a separate
+    /// request to set the tier once written
+    ///
+    /// Writes to [`AccessTier::Archive`] succeed as soon as the data is stored, but
+    /// the blob is offline: reads fail with a `409 BlobArchived` error until it is
+    /// rehydrated to another tier, for example with [`MicrosoftAzure::batch_set_tier`],
+    /// which can take several hours
+    pub async fn put_with_tier(
+        &self,
+        location: &Path,
+        bytes: Bytes,
+        tier: AccessTier,
+    ) -> Result<()> {
+        if self.client.config().dry_run {
+            info!(
+                "Dry run: skipping put of {} bytes to {} in tier {}",
+                bytes.len(),
+                location,
+                tier.as_str()
+            );
+            return Ok(());
+        }
 
-    /// Set if the Azure emulator should be used (defaults to false)
-    pub fn with_use_emulator(mut self, use_emulator: bool) -> Self {
-        self.use_emulator = use_emulator;
-        self
+        self.client
+            .put_blob_request(location, bytes, HeaderMap::new(), None, Some(tier), true)
+            .await?;
+        Ok(())
     }
 
-    /// Sets what protocol is allowed. If `allow_http` is :
-    /// * false (default):  Only HTTPS are allowed
-    /// * true:  HTTP and HTTPS are allowed
-    pub fn with_allow_http(mut self, allow_http: bool) -> Self {
-        self.client_options = self.client_options.with_allow_http(allow_http);
-        self
-    }
+    /// Set the access tier of many blobs using [Blob Batch] requests, returning the
+    /// result of each item in the order of `items`
+    ///
+    /// Items are sent in batches of up to 256, and so this makes far fewer requests
+    /// than setting the tier of each blob individually. An error is only returned
+    /// if a batch as a whole fails, for example if it is not authorized.
+    ///
+    /// [Blob Batch]: https://learn.microsoft.com/en-us/rest/api/storageservices/blob-batch
+    pub async fn batch_set_tier(
+        &self,
+        items: Vec<(Path, AccessTier)>,
+    ) -> Result<Vec<Result<()>>> {
+        if self.client.config().dry_run {
+            info!("Dry run: skipping set tier of {} blobs", items.len());
+            return Ok(items.iter().map(|_| Ok(())).collect());
+        }
 
-    /// Sets an alternative authority host for OAuth based authorization
-    /// common hosts for azure clouds are defined in [authority_hosts].
-    /// Defaults to <https://login.microsoftonline.com>
-    pub fn with_authority_host(mut self, authority_host: String) -> Self {
-        self.authority_host = Some(authority_host);
-        self
+        let subrequests = items
+            .into_iter()
+            .map(|(path, tier)| batch::Subrequest::set_tier(path, tier))
+            .collect();
+        self.client.batch(subrequests).await
     }
 
-    /// Set the retry configuration
-    pub fn with_retry(mut self, retry_config: RetryConfig) -> Self {
-        self.retry_config = retry_config;
-        self
+    /// Delete many blobs, and their snapshots, using [Blob Batch] requests, returning
+    /// the result of each item in the order of `locations`
+    ///
+    /// Locations are sent in batches of up to 256, which is far more efficient than
+    /// deleting each blob individually. Blobs that do not exist result in
+    /// [`crate::Error::NotFound`]. An error is only returned if a batch as a whole
+    /// fails, for example if it is not authorized.
+    ///
+    /// [Blob Batch]: https://learn.microsoft.com/en-us/rest/api/storageservices/blob-batch
+    pub async fn batch_delete(&self, locations: Vec<Path>) -> Result<Vec<Result<()>>> {
+        if self.client.config().dry_run {
+            info!("Dry run: skipping delete of {} blobs", locations.len());
+            return Ok(locations.iter().map(|_| Ok(())).collect());
+        }
+
+        let subrequests = locations
+            .into_iter()
+            .map(batch::Subrequest::delete)
+            .collect();
+        self.client.batch(subrequests).await
     }
 
-    /// Set the proxy_url to be used by the underlying client
-    pub fn with_proxy_url(mut self, proxy_url: impl Into<String>) -> Self {
-        self.client_options = self.client_options.with_proxy_url(proxy_url);
-        self
+    /// Returns the blocks committed to the block blob at `location`, in the order
+    /// they make up the blob
+    ///
+    /// Blobs uploaded with a single request, rather than with
+    /// [`ObjectStore::put_multipart`], have no blocks
+    pub async fn committed_blocks(&self, location: &Path) -> Result<Vec<BlockInfo>> {
+        self.client.get_committed_blocks(location).await
     }
 
-    /// Sets the client options, overriding any already set
-    pub fn with_client_options(mut self, options: ClientOptions) -> Self {
-        self.client_options = options;
-        self
+    /// Returns the blobs under `prefix` with staged blocks that have not been
+    /// committed, sorted by location
+    ///
+    /// This can be used on startup to recover uploads interrupted by a crash, either
+    /// by staging the missing blocks with [`MicrosoftAzure::stage_block`] and then
+    /// committing them with [`MicrosoftAzure::commit_blocks`], see
+    /// [`IncompleteUpload::indices`], or by discarding them with
+    /// [`ObjectStore::abort_multipart`]. Uncommitted blocks otherwise expire after 7 days
+    ///
+    /// This sends a Get Block List request for every blob under `prefix`, as blobs
+    /// that have already been committed can also have uncommitted blocks
+    pub async fn find_incomplete_uploads(
+        &self,
+        prefix: Option<&Path>,
+    ) -> Result<Vec<IncompleteUpload>> {
+        let paths: Vec<Vec<Path>> = self
+            .client
+            .list_uncommitted_paginated(prefix)
+            .try_collect()
+            .await?;
+
+        futures::stream::iter(paths.into_iter().flatten())
+            .map(|location| async move {
+                let blocks = match self.client.get_uncommitted_blocks(&location).await {
+                    Ok(blocks) => blocks,
+                    // The blob was deleted since it was listed
+                    Err(crate::Error::NotFound { .. }) => return Ok(None),
+                    Err(e) => return Err(e),
+                };
+                if blocks.is_empty() {
+                    return Ok(None);
+                }
+                let size = blocks.iter().map(|block| block.size).sum();
+                Ok(Some(IncompleteUpload {
+                    location,
+                    blocks,
+                    size,
+                }))
+            })
+            .buffered(BLOCK_LIST_CONCURRENCY)
+            .try_filter_map(|upload| futures::future::ready(Ok(upload)))
+            .try_collect()
+            .await
     }
 
-    /// Configure a connection to container with given name on Microsoft Azure
-    /// Blob store.
-    pub fn build(mut self) -> Result<MicrosoftAzure> {
-        if let Some(url) = self.url.take() {
-            self.parse_url(&url)?;
+    /// Stage `data` as the block with logical index `index` of the block blob at
+    /// `location`, to be committed with [`MicrosoftAzure::commit_blocks`]
+    ///
+    /// As the ID of the block is derived from `index`, blocks can be staged
+    /// concurrently and in any order, for example by parallel producers. Staging a
+    /// block with the same index again replaces it, and blocks that are never
+    /// committed expire after 7 days
+    pub async fn stage_block(
+        &self,
+        location: &Path,
+        index: usize,
+        data: Bytes,
+    ) -> Result<()> {
+        if self.client.config().dry_run {
+            info!("Dry run: skipping staging block {} of {}", index, location);
+            return Ok(());
         }
 
-        let container = self.container_name.ok_or(Error::MissingContainerName {})?;
+        self.client
+            .put_block(location, block_content_id(index).into(), data)
+            .await
+    }
 
-        let (is_emulator, storage_url, auth, account) = if self.use_emulator {
-            let account_name = self
-                .account_name
-                .unwrap_or_else(|| EMULATOR_ACCOUNT.to_string());
-            // Allow overriding defaults. Values taken from
-            // from https://docs.rs/azure_storage/0.2.0/src/azure_storage/core/clients/storage_account_client.rs.html#129-141
-            let url = url_from_env("AZURITE_BLOB_STORAGE_URL", "http://127.0.0.1:10000")?;
-            let account_key = self
-                .access_key
-                .unwrap_or_else(|| EMULATOR_ACCOUNT_KEY.to_string());
-            let credential = credential::CredentialProvider::AccessKey(account_key);
+    /// Commit the blocks staged with [`MicrosoftAzure::stage_block`] with `indices`
+    /// as the content of the block blob at `location`
+    ///
+    /// The blocks are committed in order of their index, regardless of the order
+    /// they were staged or of `indices`, and any other staged blocks are discarded
+    pub async fn commit_blocks(
+        &self,
+        location: &Path,
+        indices: impl IntoIterator<Item = usize>,
+    ) -> Result<()> {
+        let mut indices: Vec<_> = indices.into_iter().collect();
+        indices.sort_unstable();
+        indices.dedup();
 
-            self.client_options = self.client_options.with_allow_http(true);
-            (true, url, credential, account_name)
-        } else {
-            let account_name = self.account_name.ok_or(Error::MissingAccount {})?;
-            let account_url = format!("https://{}.blob.core.windows.net", &account_name);
-            let url = Url::parse(&account_url)
-                .context(UnableToParseUrlSnafu { url: account_url })?;
-            let credential = if let Some(bearer_token) = self.bearer_token {
-                Ok(credential::CredentialProvider::AccessKey(bearer_token))
-            } else if let Some(access_key) = self.access_key {
-                Ok(credential::CredentialProvider::AccessKey(access_key))
-            } else if let (Some(client_id), Some(client_secret), Some(tenant_id)) =
-                (self.client_id, self.client_secret, self.tenant_id)
-            {
-                let client_credential = credential::ClientSecretOAuthProvider::new(
-                    client_id,
-                    client_secret,
-                    tenant_id,
-                    self.authority_host,
-                );
-                Ok(credential::CredentialProvider::ClientSecret(
-                    client_credential,
-                ))
-            } else if let Some(query_pairs) = self.sas_query_pairs {
-                Ok(credential::CredentialProvider::SASToken(query_pairs))
-            } else if let Some(sas) = self.sas_key {
-                Ok(credential::CredentialProvider::SASToken(split_sas(&sas)?))
-            } else {
-                Err(Error::MissingCredentials {})
-            }?;
-            (false, url, credential, account_name)
-        };
+        if self.client.config().dry_run {
+            info!(
+                "Dry run: skipping commit of {} blocks to {}",
+                indices.len(),
+                location
+            );
+            return Ok(());
+        }
 
-        let config = client::AzureConfig {
-            account,
-            is_emulator,
-            container,
-            retry_config: self.retry_config,
-            client_options: self.client_options,
-            service: storage_url,
-            credentials: auth,
-        };
+        let blocks = indices
+            .into_iter()
+            .map(|idx| BlockId::from(block_content_id(idx)))
+            .collect();
+        self.client
+            .put_block_list(location, &BlockList { blocks }, &HeaderMap::new())
+            .await
+    }
 
-        let client = Arc::new(client::AzureClient::new(config)?);
+    /// Establish a pooled connection to Azure, so that the first subsequent request
+    /// does not incur the latency of DNS resolution and the TLS handshake
+    ///
+    /// This sends a single request for the properties of the container, and succeeds
+    /// regardless of its response status, see also
+    /// [`MicrosoftAzureBuilder::with_connection_warmup`]
+    pub async fn warm_up(&self) -> Result<()> {
+        self.client.warm_up().await
+    }
 
-        Ok(MicrosoftAzure { client })
+    /// Performs [`ObjectStore::put_opts`], returning the [`ResponseInfo`] of the
+    /// request, or `None` in dry run mode where no request is made
+    pub async fn put_with_response_info(
+        &self,
+        location: &Path,
+        bytes: Bytes,
+        options: PutOptions,
+    ) -> Result<Option<ResponseInfo>> {
+        let response = self.put_response(location, bytes, options).await?;
+        Ok(response.as_ref().map(ResponseInfo::new))
     }
-}
 
-/// Parses the contents of the environment variable `env_name` as a URL
-/// if present, otherwise falls back to default_url
-fn url_from_env(env_name: &str, default_url: &str) -> Result<Url> {
-    let url = match std::env::var(env_name) {
-        Ok(env_value) => {
-            Url::parse(&env_value).context(UnableToParseEmulatorUrlSnafu {
-                env_name,
-                env_value,
-            })?
+    async fn put_response(
+        &self,
+        location: &Path,
+        bytes: Bytes,
+        options: PutOptions,
+    ) -> Result<Option<reqwest::Response>> {
+        if self.client.config().dry_run {
+            info!(
+                "Dry run: skipping put of {} bytes to {}",
+                bytes.len(),
+                location
+            );
+            return Ok(None);
         }
-        Err(_) => Url::parse(default_url).expect("Failed to parse default URL"),
-    };
-    Ok(url)
-}
 
-fn split_sas(sas: &str) -> Result<Vec<(String, String)>, Error> {
-    let sas = percent_decode_str(sas)
-        .decode_utf8()
-        .context(DecodeSasKeySnafu {})?;
-    let kv_str_pairs = sas
-        .trim_start_matches('?')
-        .split('&')
-        .filter(|s| !s.chars().all(char::is_whitespace));
-    let mut pairs = Vec::new();
-    for kv_pair_str in kv_str_pairs {
-        let (k, v) = kv_pair_str
-            .trim()
-            .split_once('=')
-            .ok_or(Error::MissingSasComponent {})?;
-        pairs.push((k.into(), v.into()))
+        let len = bytes.len() as u64;
+        let headers = client::put_headers(&options)?;
+        let key = options.idempotency_key.as_deref();
+        let idempotent = !options.non_idempotent;
+        let put = self
+            .client
+            .put_blob_request(location, bytes, headers, key, None, idempotent);
+        let response = cancellable(options.cancel, location, put).await?;
+        if let Some(progress) = options.progress {
+            progress(len, Some(len));
+        }
+        Ok(Some(response))
     }
-    Ok(pairs)
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::tests::{
-        copy_if_not_exists, list_uses_directories_correctly, list_with_delimiter,
-        put_get_delete_list, put_get_delete_list_opts, rename_and_copy, stream_get,
-    };
-    use std::collections::HashMap;
-    use std::env;
+    /// Returns the metadata of the blob at `location` along with its [`BlobProperties`],
+    /// all parsed from a single Get Blob Properties request
+    ///
+    /// This can be used to wait until a blob has been replicated to another account
+    /// before reading it there, see [`ObjectReplication::is_complete`], or to determine
+    /// whether a blob is leased before attempting to write it, see [`BlobLease::is_locked`].
+    ///
+    /// The [size](ObjectMeta::size) is that reported by Azure for each type of blob:
+    /// the committed size of block and append blobs, and the declared size of page
+    /// blobs, which includes any pages that have not been written
+    pub async fn head_with_properties(
+        &self,
+        location: &Path,
+    ) -> Result<(ObjectMeta, BlobProperties)> {
+        let response = self.client.get_request(location, None, true).await?;
+        let lenient = self.client.config().lenient_headers;
+        let meta = object_meta(location, response.headers(), lenient)?;
+        Ok((meta, BlobProperties::from_response(&response)?))
+    }
 
-    // Helper macro to skip tests if TEST_INTEGRATION and the Azure environment
-    // variables are not set.
-    macro_rules! maybe_skip_integration {
-        () => {{
-            dotenv::dotenv().ok();
+    /// Returns the result of [`ObjectStore::get_opts`] along with the [`BlobProperties`]
+    /// of the blob, parsed from the same response
+    pub async fn get_with_properties(
+        &self,
+        location: &Path,
+        options: GetOptions,
+    ) -> Result<(GetResult, BlobProperties)> {
+        let request = self.client.get_opts_request(location, &options, false);
+        let response = cancellable(options.cancel.clone(), location, request).await?;
+        let properties = BlobProperties::from_response(&response)?;
+        let config = self.client.config();
+        Ok((get_result(location, options, response, config), properties))
+    }
 
-            let use_emulator = std::env::var("AZURE_USE_EMULATOR").is_ok();
+    /// Query the contents of the blob at `location` with the SQL `expression`,
+    /// returning only the matching records serialized as `output_format`
+    ///
+    /// The blob is parsed as `input_format`, and filtered by Azure, avoiding the
+    /// transfer of records that do not match, see
+    /// <https://learn.microsoft.com/en-us/azure/storage/blobs/query-acceleration-sql-reference>
+    ///
+    /// ```no_run
+    /// # async fn example(azure: object_store::azure::MicrosoftAzure) -> object_store::Result<()> {
+    /// use object_store::azure::QueryFormat;
+    /// use object_store::path::Path;
+    ///
+    /// let location = Path::from("data.csv");
+    /// let expression = "SELECT _1, _3 FROM BlobStorage WHERE _2 = 'active'";
+    /// let result = azure
+    ///     .query(&location, expression, &QueryFormat::csv(), &QueryFormat::json())
+    ///     .await?;
+    /// let records = result.bytes().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn query(
+        &self,
+        location: &Path,
+        expression: &str,
+        input_format: &QueryFormat,
+        output_format: &QueryFormat,
+    ) -> Result<GetResult> {
+        let body = query::query_request_xml(expression, input_format, output_format);
+        let response = self.client.query_request(location, body).await?;
+        Ok(GetResult::Stream(
+            query::decode_query_response(response.bytes_stream().boxed()),
+            None,
+        ))
+    }
 
-            let mut required_vars = vec!["OBJECT_STORE_BUCKET"];
-            if !use_emulator {
-                required_vars.push("AZURE_STORAGE_ACCOUNT");
-                required_vars.push("AZURE_STORAGE_ACCESS_KEY");
+    /// List all objects under `prefix`, returning their ETags
+    pub async fn list_snapshot(&self, prefix: Option<&Path>) -> Result<ListingSnapshot> {
+        Ok(self
+            .list_diff(prefix, &ListingSnapshot::new())
+            .await?
+            .snapshot)
+    }
+
+    /// List all objects under `prefix`, returning those added, modified or removed
+    /// since `previous` was taken
+    ///
+    /// Objects are considered modified if their ETag has changed. Azure does not
+    /// support conditional list requests, so this still lists every object under
+    /// `prefix`, but avoids the need to fetch or compare the objects themselves
+    pub async fn list_diff(
+        &self,
+        prefix: Option<&Path>,
+        previous: &ListingSnapshot,
+    ) -> Result<ListingDiff> {
+        let current = self
+            .client
+            .list_paginated(prefix, false)
+            .map_ok(|r| r.objects)
+            .try_concat()
+            .await?;
+
+        Ok(ListingDiff::new(previous, current))
+    }
+
+    /// Create a [`MicrosoftAzure`] from a full blob URL, returning it along with the
+    /// [`Path`] of the blob within the container
+    ///
+    /// All other configuration, including credentials, is read from the environment
+    /// as by [`MicrosoftAzureBuilder::from_env`]. See [`MicrosoftAzureBuilder::with_url`]
+    /// for the supported URL formats
+    pub fn from_url(url: &str) -> Result<(Self, Path)> {
+        MicrosoftAzureBuilder::from_env()
+            .with_url(url)
+            .build_with_path()
+    }
+
+    /// Perform a multipart upload to `location`, returning an [`AzureMultipartWriter`]
+    ///
+    /// Unlike [`ObjectStore::put_multipart`] the returned writer can be
+    /// [finished](AzureMultipartWriter::finish) to obtain a [`PutResult`], including a
+    /// checksum of the uploaded data if one was configured with
+    /// [`MicrosoftAzureBuilder::with_checksum_algorithm`]
+    pub fn put_multipart_writer(&self, location: &Path) -> AzureMultipartWriter {
+        self.multipart_writer(location, PutOptions::default(), HeaderMap::new())
+    }
+
+    /// Returns a writer uploading to `location`, committed with `headers` such as
+    /// from [`client::put_headers`]
+    fn multipart_writer(
+        &self,
+        location: &Path,
+        options: PutOptions,
+        headers: HeaderMap,
+    ) -> AzureMultipartWriter {
+        let inner = AzureMultiPartUpload {
+            client: Arc::clone(&self.client),
+            location: location.to_owned(),
+            headers,
+            // Retaining uploaded blocks would defeat the bounded buffering of writers
+            staged: None,
+        };
+
+        let mut upload = CloudMultiPartUpload::new(inner, 8)
+            .with_concurrency(self.upload_concurrency)
+            .with_min_part_size(self.block_size)
+            .with_max_part_size(MAX_BLOCK_SIZE);
+        if let Some(budget) = &self.buffer_budget {
+            upload = upload.with_buffer_budget(Arc::clone(budget));
+        }
+        if let Some(algorithm) = self.checksum_algorithm {
+            upload = upload.with_checksum(algorithm);
+        }
+        if let Some(progress) = options.progress {
+            upload = upload.with_progress(progress);
+        }
+        AzureMultipartWriter {
+            upload,
+            client: Arc::clone(&self.client),
+            location: location.to_owned(),
+            drop_behavior: self.multipart_drop_behavior,
+            abort_on_error: self.abort_multipart_on_error,
+            aborting: None,
+            finished: false,
+        }
+    }
+
+    /// Lists the objects under `prefix` after `offset`, which must be within it, in
+    /// order of location
+    ///
+    /// Each level is streamed a page at a time, skipping the objects and directories
+    /// sorting before `offset` as they arrive. Only the directory containing `offset`
+    /// is listed recursively, and those sorting after it are listed in full
+    fn list_after(
+        &self,
+        prefix: Option<Path>,
+        offset: Path,
+    ) -> BoxStream<'_, Result<ObjectMeta>> {
+        enum Entry {
+            Object(ObjectMeta),
+            Directory(Path),
+        }
+
+        self.client
+            .list_paginated(prefix.as_ref(), true)
+            .map_ok(move |page| {
+                // The blobs of a directory sort after those less than its key, and
+                // before those greater than it
+                let objects = page
+                    .objects
+                    .into_iter()
+                    .filter(|meta| meta.location > offset)
+                    .map(|meta| (meta.location.to_string(), Entry::Object(meta)));
+                let directories = page.common_prefixes.into_iter().filter_map(|path| {
+                    let key = directory_key(&path);
+                    (offset.as_ref().starts_with(&key) || key.as_str() > offset.as_ref())
+                        .then_some((key, Entry::Directory(path)))
+                });
+                let mut entries: Vec<_> = objects.chain(directories).collect();
+                entries.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+
+                let offset = offset.clone();
+                let streams = entries.into_iter().map(move |(key, entry)| match entry {
+                    Entry::Object(meta) => {
+                        futures::stream::once(async { Ok(meta) }).boxed()
+                    }
+                    Entry::Directory(path) if offset.as_ref().starts_with(&key) => {
+                        self.list_after(Some(path), offset.clone())
+                    }
+                    Entry::Directory(path) => {
+                        futures::stream::once(async move { self.list(Some(&path)).await })
+                            .try_flatten()
+                            .boxed()
+                    }
+                });
+                futures::stream::iter(streams).flatten()
+            })
+            .try_flatten()
+            .boxed()
+    }
+}
+
+/// Returns the prefix of the locations of the objects in the directory `path`
+fn directory_key(path: &Path) -> String {
+    format!("{}{}", path, DELIMITER)
+}
+
+/// A writer returned by [`MicrosoftAzure::put_multipart_writer`]
+///
+/// If dropped before being shut down, any blocks already staged are handled according
+/// to [`MicrosoftAzureBuilder::with_multipart_drop_behavior`]. If a write fails, they
+/// are discarded before the error is returned unless disabled with
+/// [`MicrosoftAzureBuilder::with_abort_multipart_on_error`]
+pub struct AzureMultipartWriter {
+    upload: CloudMultiPartUpload<AzureMultiPartUpload>,
+    client: Arc<client::AzureClient>,
+    location: Path,
+    drop_behavior: MultipartDropBehavior,
+    abort_on_error: bool,
+    /// The in-progress discard of the staged blocks, and the error to then return
+    aborting: Option<(BoxFuture<'static, ()>, io::Error)>,
+    finished: bool,
+}
+
+impl AzureMultipartWriter {
+    /// Flush any buffered data and commit the upload
+    pub async fn finish(mut self) -> Result<PutResult> {
+        self.shutdown()
+            .await
+            .map_err(|source| crate::Error::Generic {
+                store: "MicrosoftAzure",
+                source: Box::new(source),
+            })?;
+
+        Ok(PutResult {
+            e_tag: None,
+            checksum: self.upload.checksum(),
+        })
+    }
+
+    /// Polls the upload with `f`, discarding the staged blocks before returning an
+    /// error if configured to abort on error
+    fn poll_upload<T>(
+        &mut self,
+        cx: &mut Context<'_>,
+        f: impl FnOnce(
+            Pin<&mut CloudMultiPartUpload<AzureMultiPartUpload>>,
+            &mut Context<'_>,
+        ) -> Poll<Result<T, io::Error>>,
+    ) -> Poll<Result<T, io::Error>> {
+        if self.aborting.is_none() {
+            match f(Pin::new(&mut self.upload), cx) {
+                Poll::Ready(Err(e))
+                    if self.abort_on_error
+                        && !self.finished
+                        && self.upload.has_submitted_parts() =>
+                {
+                    // The staged blocks are discarded here rather than on drop
+                    self.finished = true;
+                    let abort = discard_staged_blocks(
+                        Arc::clone(&self.client),
+                        self.location.clone(),
+                    );
+                    self.aborting = Some((abort.boxed(), e));
+                }
+                result => return result,
             }
-            let unset_vars: Vec<_> = required_vars
-                .iter()
-                .filter_map(|&name| match env::var(name) {
-                    Ok(_) => None,
-                    Err(_) => Some(name),
+        }
+
+        let (abort, _) = self.aborting.as_mut().unwrap();
+        ready!(abort.poll_unpin(cx));
+        let (_, e) = self.aborting.take().unwrap();
+        Poll::Ready(Err(e))
+    }
+}
+
+impl Debug for AzureMultipartWriter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "AzureMultipartWriter")
+    }
+}
+
+impl AsyncWrite for AzureMultipartWriter {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        self.poll_upload(cx, |upload, cx| upload.poll_write(cx, buf))
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), io::Error>> {
+        self.poll_upload(cx, |upload, cx| upload.poll_flush(cx))
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), io::Error>> {
+        let result = self.poll_upload(cx, |upload, cx| upload.poll_shutdown(cx));
+        if let Poll::Ready(Ok(())) = result {
+            self.finished = true;
+        }
+        result
+    }
+}
+
+impl Drop for AzureMultipartWriter {
+    fn drop(&mut self) {
+        // A writer dropped whilst discarding its blocks after an error falls back
+        // to the configured drop behaviour
+        if (self.finished && self.aborting.is_none())
+            || !self.upload.has_submitted_parts()
+        {
+            return;
+        }
+
+        let handle = tokio::runtime::Handle::try_current();
+        let handle = match (self.drop_behavior, handle) {
+            (MultipartDropBehavior::Warn, _) | (_, Err(_)) => {
+                warn!(
+                    "Multipart upload to {} dropped before completion, staged blocks will expire after 7 days",
+                    self.location
+                );
+                return;
+            }
+            (_, Ok(handle)) => handle,
+        };
+
+        handle.spawn(discard_staged_blocks(
+            Arc::clone(&self.client),
+            self.location.clone(),
+        ));
+    }
+}
+
+/// Best-effort discard of the blocks staged by an incomplete multipart upload
+async fn discard_staged_blocks(client: Arc<client::AzureClient>, location: Path) {
+    match client.discard_uncommitted_blocks(&location).await {
+        Ok(true) => info!("Discarded staged blocks of incomplete upload to {}", location),
+        Ok(false) => warn!(
+            "Unable to discard staged blocks of incomplete upload to existing blob {}, they will expire after 7 days",
+            location
+        ),
+        Err(e) => warn!(
+            "Failed to discard staged blocks of incomplete upload to {}: {}",
+            location, e
+        ),
+    }
+}
+
+impl std::fmt::Display for MicrosoftAzure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "MicrosoftAzure {{ account: {}, container: {} }}",
+            self.account(),
+            self.container()
+        )
+    }
+}
+
+#[async_trait]
+impl ObjectStore for MicrosoftAzure {
+    async fn put(&self, location: &Path, bytes: Bytes) -> Result<()> {
+        if self.client.config().dry_run {
+            info!(
+                "Dry run: skipping put of {} bytes to {}",
+                bytes.len(),
+                location
+            );
+            return Ok(());
+        }
+
+        self.client
+            .put_request(location, Some(bytes), false, &())
+            .await?;
+        Ok(())
+    }
+
+    async fn put_opts(
+        &self,
+        location: &Path,
+        bytes: Bytes,
+        options: PutOptions,
+    ) -> Result<()> {
+        self.put_response(location, bytes, options).await?;
+        Ok(())
+    }
+
+    async fn update(
+        &self,
+        location: &Path,
+        expected_e_tag: Option<String>,
+        bytes: Bytes,
+    ) -> Result<PutResult> {
+        if self.client.config().dry_run {
+            info!(
+                "Dry run: skipping update of {} bytes to {}",
+                bytes.len(),
+                location
+            );
+            return Ok(PutResult::default());
+        }
+
+        let response = self
+            .client
+            .put_if_request(location, bytes, expected_e_tag.as_deref())
+            .await?;
+
+        let e_tag = response
+            .headers()
+            .get(ETAG)
+            .map(|e_tag| e_tag.to_str().context(BadHeaderSnafu))
+            .transpose()?
+            .map(ToString::to_string);
+
+        Ok(PutResult {
+            e_tag,
+            checksum: None,
+        })
+    }
+
+    async fn revalidate(
+        &self,
+        location: &Path,
+        cached_e_tag: Option<String>,
+    ) -> Result<Revalidated> {
+        use reqwest::StatusCode;
+
+        let response = self
+            .client
+            .revalidate_request(location, cached_e_tag.as_deref())
+            .await?;
+
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(Revalidated::Cached);
+        }
+
+        let lenient = self.client.config().lenient_headers;
+        let meta = object_meta(location, response.headers(), lenient)?;
+        ensure!(meta.e_tag.is_some(), MissingEtagSnafu);
+
+        let bytes =
+            response
+                .bytes()
+                .await
+                .map_err(|source| client::Error::GetResponseBody {
+                    source,
+                    path: location.to_string(),
+                })?;
+        Ok(Revalidated::Fresh(bytes, meta))
+    }
+
+    async fn put_multipart(
+        &self,
+        location: &Path,
+    ) -> Result<(MultipartId, Box<dyn AsyncWrite + Unpin + Send>)> {
+        Ok((String::new(), Box::new(self.put_multipart_writer(location))))
+    }
+
+    async fn put_multipart_opts(
+        &self,
+        location: &Path,
+        options: PutOptions,
+    ) -> Result<(MultipartId, Box<dyn AsyncWrite + Unpin + Send>)> {
+        let cancel = options.cancel.clone();
+        let headers = client::put_headers(&options)?;
+        let writer = self.multipart_writer(location, options, headers);
+        match cancel {
+            Some(cancel) => {
+                let writer = CancellableWriter::new(writer, cancel, location);
+                Ok((String::new(), Box::new(writer)))
+            }
+            None => Ok((String::new(), Box::new(writer))),
+        }
+    }
+
+    /// Upload `bytes` to `location` as a multipart upload
+    ///
+    /// Unlike [ObjectStore::put_multipart], blocks discarded before the block list
+    /// is committed are staged again, see the [module docs](self#streaming-uploads)
+    async fn put_multipart_bytes(&self, location: &Path, bytes: Bytes) -> Result<()> {
+        // The blocks are slices of `bytes`, and so are retained at no cost
+        let upload = AzureMultiPartUpload {
+            client: Arc::clone(&self.client),
+            location: location.to_owned(),
+            headers: HeaderMap::new(),
+            staged: Some(Default::default()),
+        };
+        // Use larger blocks if needed to stay within the limit on blocks per blob
+        let block_size = self
+            .block_size
+            .max((bytes.len() + MAX_BLOCKS - 1) / MAX_BLOCKS)
+            .min(MAX_BLOCK_SIZE);
+        let result = put_parts(&upload, bytes, block_size, self.upload_concurrency).await;
+        if result.is_err() && self.abort_multipart_on_error {
+            discard_staged_blocks(Arc::clone(&self.client), location.to_owned()).await;
+        }
+        result.map_err(|source| crate::Error::Generic {
+            store: "MicrosoftAzure",
+            source: Box::new(source),
+        })
+    }
+
+    async fn abort_multipart(
+        &self,
+        location: &Path,
+        _multipart_id: &MultipartId,
+    ) -> Result<()> {
+        // Blocks are staged against the blob itself, so no upload ID is needed
+        if !self.client.discard_uncommitted_blocks(location).await? {
+            warn!(
+                "Unable to discard staged blocks of upload to existing blob {}, they will expire after 7 days",
+                location
+            );
+        }
+        Ok(())
+    }
+
+    async fn get(&self, location: &Path) -> Result<GetResult> {
+        self.get_opts(location, GetOptions::default()).await
+    }
+
+    async fn get_opts(&self, location: &Path, options: GetOptions) -> Result<GetResult> {
+        let request = self.client.get_opts_request(location, &options, false);
+        let response = cancellable(options.cancel.clone(), location, request).await?;
+        Ok(get_result(
+            location,
+            options,
+            response,
+            self.client.config(),
+        ))
+    }
+
+    async fn get_range(&self, location: &Path, range: Range<usize>) -> Result<Bytes> {
+        match self.get_range_response(location, range.clone()).await? {
+            (bytes, true) => Ok(slice_range(&bytes, &range)?),
+            (bytes, false) => Ok(bytes),
+        }
+    }
+
+    async fn get_ranges(
+        &self,
+        location: &Path,
+        ranges: &[Range<usize>],
+    ) -> Result<Vec<Bytes>> {
+        if ranges.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // A server that ignores the Range header returns the whole object, from which
+        // every range is sliced, without waiting for the other requests
+        let fetch_ranges = merge_ranges(ranges, OBJECT_STORE_COALESCE_DEFAULT);
+        let mut responses =
+            futures::stream::iter(fetch_ranges.iter().cloned().enumerate())
+                .map(|(idx, range)| async move {
+                    let response = self.get_range_response(location, range).await?;
+                    Ok::<_, crate::Error>((idx, response))
                 })
-                .collect();
-            let unset_var_names = unset_vars.join(", ");
+                .buffer_unordered(OBJECT_STORE_COALESCE_PARALLEL);
+
+        let mut fetched = vec![Bytes::new(); fetch_ranges.len()];
+        while let Some((idx, (bytes, whole))) = responses.try_next().await? {
+            if whole {
+                return ranges
+                    .iter()
+                    .map(|range| Ok(slice_range(&bytes, range)?))
+                    .collect();
+            }
+            fetched[idx] = bytes;
+        }
+        Ok(slice_ranges(ranges, &fetch_ranges, &fetched))
+    }
+
+    async fn head(&self, location: &Path) -> Result<ObjectMeta> {
+        // Extract meta from headers
+        // https://docs.microsoft.com/en-us/rest/api/storageservices/get-blob-properties
+        let response = self.client.get_request(location, None, true).await?;
+        let lenient = self.client.config().lenient_headers;
+        Ok(object_meta(location, response.headers(), lenient)?)
+    }
+
+    async fn delete(&self, location: &Path) -> Result<()> {
+        if self.client.config().dry_run {
+            info!("Dry run: skipping delete of {}", location);
+            return Ok(());
+        }
+
+        self.client.delete_request(location, &(), None).await
+    }
+
+    async fn delete_opts(&self, location: &Path, options: DeleteOptions) -> Result<()> {
+        if self.client.config().dry_run {
+            info!("Dry run: skipping delete of {}", location);
+            return Ok(());
+        }
+
+        let key = options.idempotency_key.as_deref();
+        self.client.delete_request(location, &(), key).await
+    }
+
+    /// Deletes blobs with [`MicrosoftAzure::batch_delete`], making up to 4 concurrent
+    /// requests of up to 256 blobs each
+    ///
+    /// If a batch request as a whole fails its error is returned once in place of
+    /// the results of its locations, and the remaining locations are still deleted
+    fn delete_stream<'a>(
+        &'a self,
+        locations: BoxStream<'a, Result<Path>>,
+    ) -> BoxStream<'a, Result<Path>> {
+        locations
+            .ready_chunks(batch::MAX_BATCH_SIZE)
+            .map(move |chunk| async move {
+                let mut results = Vec::with_capacity(chunk.len());
+                let mut paths = Vec::with_capacity(chunk.len());
+                for location in chunk {
+                    match location {
+                        Ok(path) => paths.push(path),
+                        Err(e) => results.push(Err(e)),
+                    }
+                }
+
+                if !paths.is_empty() {
+                    match self.batch_delete(paths.clone()).await {
+                        Ok(deleted) => results.extend(
+                            paths
+                                .into_iter()
+                                .zip(deleted)
+                                .map(|(path, r)| r.map(|_| path)),
+                        ),
+                        Err(e) => results.push(Err(e)),
+                    }
+                }
+                futures::stream::iter(results)
+            })
+            .buffered(BATCH_DELETE_CONCURRENCY)
+            .flatten()
+            .boxed()
+    }
+
+    async fn list(
+        &self,
+        prefix: Option<&Path>,
+    ) -> Result<BoxStream<'_, Result<ObjectMeta>>> {
+        let stream = self
+            .client
+            .list_paginated(prefix, false)
+            .map_ok(|r| futures::stream::iter(r.objects.into_iter().map(Ok)))
+            .try_flatten()
+            .boxed();
+
+        Ok(stream)
+    }
+
+    /// The Blob service has no parameter to start a listing after a given blob, and
+    /// so this descends with delimited listings along the path of `offset`, skipping
+    /// the directories that sort entirely before it instead of listing their blobs.
+    /// The returned objects are sorted by location
+    async fn list_with_offset(
+        &self,
+        prefix: Option<&Path>,
+        offset: &Path,
+    ) -> Result<BoxStream<'_, Result<ObjectMeta>>> {
+        let prefix = prefix.filter(|p| !p.as_ref().is_empty());
+        let key = prefix.map(directory_key).unwrap_or_default();
+        let offset = offset.clone();
+        if offset.as_ref().starts_with(&key) {
+            Ok(self.list_after(prefix.cloned(), offset))
+        } else if key.as_str() > offset.as_ref() {
+            self.list(prefix).await
+        } else {
+            Ok(futures::stream::empty().boxed())
+        }
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> Result<ListResult> {
+        collect_list_result(self.client.list_paginated(prefix, true)).await
+    }
+
+    /// Lists with `prefix` as the prefix of the request, rather than listing its parent
+    async fn list_with_delimiter_literal(&self, prefix: &Path) -> Result<ListResult> {
+        let prefix = Some(prefix.to_string()).filter(|p| !p.is_empty());
+        collect_list_result(self.client.list_literal_paginated(prefix, true)).await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+        if self.client.config().dry_run {
+            info!("Dry run: skipping copy of {} to {}", from, to);
+            return Ok(());
+        }
+
+        self.client
+            .copy_request(from, to, &CopyOptions::default())
+            .await
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+        if self.client.config().dry_run {
+            info!("Dry run: skipping copy of {} to {} if not exists", from, to);
+            return Ok(());
+        }
+
+        let options = CopyOptions {
+            if_not_exists: true,
+            ..Default::default()
+        };
+        self.client.copy_request(from, to, &options).await
+    }
+
+    /// With a hierarchical namespace, see [`MicrosoftAzureBuilder::with_hierarchical_namespace`],
+    /// `from` is renamed atomically on the `dfs` endpoint, and may also be a directory,
+    /// which is renamed along with its contents in a single request. Otherwise `from`
+    /// is copied to `to` and then deleted
+    async fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        if !self.hierarchical_namespace {
+            self.copy(from, to).await?;
+            return self.delete(from).await;
+        }
+
+        if self.client.config().dry_run {
+            info!("Dry run: skipping rename of {} to {}", from, to);
+            return Ok(());
+        }
+        self.client.rename_request(from, to).await
+    }
+
+    /// Reports [`Capabilities::versioning`] as configured by
+    /// [`MicrosoftAzureBuilder::with_versioning`]
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            conditional_put: true,
+            conditional_get: true,
+            copy_if_not_exists: true,
+            versioning: self.versioning,
+            leases: true,
+            ..Default::default()
+        }
+    }
+
+    async fn copy_opts(
+        &self,
+        from: &Path,
+        to: &Path,
+        options: CopyOptions,
+    ) -> Result<()> {
+        if self.client.config().dry_run {
+            info!("Dry run: skipping copy of {} to {}", from, to);
+            return Ok(());
+        }
+        if options.only_if_source_newer {
+            ensure_source_newer(self, from, to).await?;
+        }
+
+        self.client.copy_request(from, to, &options).await
+    }
+}
+
+/// Relevant docs: <https://azure.github.io/Storage/docs/application-and-user-data/basics/azure-blob-storage-upload-apis/>
+/// In Azure Blob Store, parts are "blocks"
+/// put_multipart_part -> PUT block
+/// complete -> PUT block list
+/// abort -> Commit an empty block list and delete the blob, see [`ObjectStore::abort_multipart`]
+#[derive(Debug, Clone)]
+struct AzureMultiPartUpload {
+    client: Arc<client::AzureClient>,
+    location: Path,
+    /// The properties and metadata of the blob, set when the block list is committed
+    headers: HeaderMap,
+    /// The data of each staged block by ID, if retained to re-stage blocks that are
+    /// no longer staged when the block list is committed
+    staged: Option<Arc<parking_lot::Mutex<HashMap<String, Bytes>>>>,
+}
+
+impl AzureMultiPartUpload {
+    /// Stage again the blocks of `block_list` that are no longer staged, such as those
+    /// discarded by a concurrent commit to the same blob, returning false if the data
+    /// of any of them was not retained
+    async fn restage_blocks(&self, block_list: &BlockList) -> Result<bool> {
+        let staged = match &self.staged {
+            Some(staged) => staged,
+            None => return Ok(false),
+        };
+        let uncommitted: BTreeSet<_> = self
+            .client
+            .get_uncommitted_blocks(&self.location)
+            .await?
+            .into_iter()
+            .map(|block| block.id)
+            .collect();
+
+        let mut missing = vec![];
+        for block_id in &block_list.blocks {
+            let content_id = String::from_utf8_lossy(block_id.as_ref()).into_owned();
+            if uncommitted.contains(&base64::encode(&content_id)) {
+                continue;
+            }
+            match staged.lock().get(&content_id) {
+                Some(data) => missing.push((block_id.clone(), data.clone())),
+                None => return Ok(false),
+            }
+        }
+
+        for (block_id, data) in missing {
+            info!("Staging block again for upload to {}", self.location);
+            self.client
+                .put_block(&self.location, block_id, data)
+                .await?;
+        }
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl CloudMultiPartUploadImpl for AzureMultiPartUpload {
+    async fn put_multipart_part(
+        &self,
+        buf: Bytes,
+        part_idx: usize,
+    ) -> Result<UploadPart, io::Error> {
+        let content_id = block_content_id(part_idx);
+
+        if self.client.config().dry_run {
+            return Ok(UploadPart { content_id });
+        }
+
+        self.client
+            .put_block(&self.location, content_id.clone().into(), buf.clone())
+            .await?;
+        if let Some(staged) = &self.staged {
+            staged.lock().insert(content_id.clone(), buf);
+        }
+
+        Ok(UploadPart { content_id })
+    }
+
+    async fn complete(&self, completed_parts: Vec<UploadPart>) -> Result<(), io::Error> {
+        if self.client.config().dry_run {
+            info!(
+                "Dry run: skipping multipart upload of {} blocks to {}",
+                completed_parts.len(),
+                self.location
+            );
+            return Ok(());
+        }
+
+        let blocks = completed_parts
+            .into_iter()
+            .map(|part| BlockId::from(part.content_id))
+            .collect();
+
+        let block_list = BlockList { blocks };
+        let result = self
+            .client
+            .put_block_list(&self.location, &block_list, &self.headers)
+            .await;
+        match result {
+            Err(e)
+                if error_code(&e) == Some("InvalidBlockList")
+                    && self.restage_blocks(&block_list).await? =>
+            {
+                self.client
+                    .put_block_list(&self.location, &block_list, &self.headers)
+                    .await?
+            }
+            result => result?,
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the content of the ID of the block with logical index `idx`, which sorts in
+/// the same order as the index
+fn block_content_id(idx: usize) -> String {
+    format!("{:20}", idx)
+}
+
+/// Configure a connection to Microsoft Azure Blob Storage container using
+/// the specified credentials.
+///
+/// # Example
+/// ```
+/// # let ACCOUNT = "foo";
+/// # let BUCKET_NAME = "foo";
+/// # let ACCESS_KEY = "foo";
+/// # use object_store::azure::MicrosoftAzureBuilder;
+/// let azure = MicrosoftAzureBuilder::new()
+///  .with_account(ACCOUNT)
+///  .with_access_key(ACCESS_KEY)
+///  .with_container_name(BUCKET_NAME)
+///  .build();
+/// ```
+#[derive(Default, Clone)]
+pub struct MicrosoftAzureBuilder {
+    account_name: Option<String>,
+    access_key: Option<String>,
+    fallback_access_keys: Vec<String>,
+    container_name: Option<String>,
+    bearer_token: Option<String>,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+    tenant_id: Option<String>,
+    sas_query_pairs: Option<Vec<(String, String)>>,
+    sas_key: Option<String>,
+    use_managed_identity: bool,
+    object_id: Option<String>,
+    msi_resource_id: Option<String>,
+    msi_endpoint: Option<String>,
+    authority_host: Option<String>,
+    oauth_scope: Option<String>,
+    signing_account: Option<String>,
+    token_cache_path: Option<PathBuf>,
+    url: Option<String>,
+    connection_string: Option<String>,
+    endpoint: Option<String>,
+    endpoint_suffix: Option<String>,
+    dfs_endpoint: Option<String>,
+    use_path_style: bool,
+    use_emulator: bool,
+    dry_run: bool,
+    lenient_headers: bool,
+    connection_warmup: bool,
+    auto_lowercase_container: bool,
+    max_buffered_bytes: Option<usize>,
+    block_size: Option<usize>,
+    upload_concurrency: Option<UploadConcurrency>,
+    checksum_algorithm: Option<ChecksumAlgorithm>,
+    multipart_drop_behavior: MultipartDropBehavior,
+    abort_multipart_on_error: Option<bool>,
+    request_observer: Option<RequestObserverFn>,
+    refresh_on_auth_failure: Option<bool>,
+    prefer_oauth: bool,
+    idempotency_key_header: Option<String>,
+    default_access_tier: Option<AccessTier>,
+    max_response_body_size: Option<usize>,
+    default_metadata: HashMap<String, String>,
+    copy_timeout: Option<std::time::Duration>,
+    versioning: bool,
+    hierarchical_namespace: Option<bool>,
+    append_create: Option<bool>,
+    retry_config: RetryConfig,
+    client_options: ClientOptions,
+}
+
+/// Configuration keys for [`MicrosoftAzureBuilder`]
+///
+/// Configuration via keys can be dome via the [`try_with_option`](MicrosoftAzureBuilder::try_with_option)
+/// or [`with_options`](MicrosoftAzureBuilder::try_with_options) methods on the builder.
+///
+/// # Example
+/// ```
+/// use std::collections::HashMap;
+/// use object_store::azure::{MicrosoftAzureBuilder, AzureConfigKey};
+///
+/// let options = HashMap::from([
+///     ("azure_client_id", "my-client-id"),
+///     ("azure_client_secret", "my-account-name"),
+/// ]);
+/// let typed_options = vec![
+///     (AzureConfigKey::AccountName, "my-account-name"),
+/// ];
+/// let azure = MicrosoftAzureBuilder::new()
+///     .try_with_options(options)
+///     .unwrap()
+///     .try_with_options(typed_options)
+///     .unwrap()
+///     .try_with_option(AzureConfigKey::AuthorityId, "my-tenant-id")
+///     .unwrap();
+/// ```
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Copy, Deserialize, Serialize)]
+pub enum AzureConfigKey {
+    /// The name of the azure storage account
+    ///
+    /// Supported keys:
+    /// - `azure_storage_account_name`
+    /// - `account_name`
+    AccountName,
+
+    /// Master key for accessing storage account
+    ///
+    /// Supported keys:
+    /// - `azure_storage_account_key`
+    /// - `azure_storage_access_key`
+    /// - `azure_storage_master_key`
+    /// - `access_key`
+    /// - `account_key`
+    /// - `master_key`
+    AccessKey,
+
+    /// Service principal client id for authorizing requests, or the client id of a
+    /// user-assigned managed identity
+    ///
+    /// Supported keys:
+    /// - `azure_storage_client_id`
+    /// - `azure_client_id`
+    /// - `azure_msi_client_id`
+    /// - `client_id`
+    ClientId,
+
+    /// Service principal client secret for authorizing requests
+    ///
+    /// Supported keys:
+    /// - `azure_storage_client_secret`
+    /// - `azure_client_secret`
+    /// - `client_secret`
+    ClientSecret,
+
+    /// Tenant id used in oauth flows
+    ///
+    /// Supported keys:
+    /// - `azure_storage_tenant_id`
+    /// - `azure_storage_authority_id`
+    /// - `azure_tenant_id`
+    /// - `azure_authority_id`
+    /// - `tenant_id`
+    /// - `authority_id`
+    AuthorityId,
+
+    /// Shared access signature.
+    ///
+    /// The signature is expected to be percent-encoded, much like they are provided
+    /// in the azure storage explorer or azure portal.
+    ///
+    /// Supported keys:
+    /// - `azure_storage_sas_key`
+    /// - `azure_storage_sas_token`
+    /// - `sas_key`
+    /// - `sas_token`
+    SasKey,
+
+    /// Bearer token
+    ///
+    /// Supported keys:
+    /// - `azure_storage_token`
+    /// - `bearer_token`
+    /// - `token`
+    Token,
+
+    /// Use object store with azurite storage emulator
+    ///
+    /// Supported keys:
+    /// - `azure_storage_use_emulator`
+    /// - `object_store_use_emulator`
+    /// - `use_emulator`
+    UseEmulator,
+
+    /// Override the endpoint used to communicate with blob storage
+    ///
+    /// Supported keys:
+    /// - `azure_storage_endpoint`
+    /// - `azure_endpoint`
+    /// - `endpoint`
+    Endpoint,
+
+    /// The suffix of the endpoints of the Azure cloud of the account, e.g.
+    /// `core.usgovcloudapi.net` for Azure Government
+    ///
+    /// See [`MicrosoftAzureBuilder::with_endpoint_suffix`]
+    ///
+    /// Supported keys:
+    /// - `azure_storage_endpoint_suffix`
+    /// - `azure_endpoint_suffix`
+    /// - `endpoint_suffix`
+    EndpointSuffix,
+
+    /// Include the account name in the request path instead of the host
+    ///
+    /// Supported keys:
+    /// - `azure_storage_use_path_style`
+    /// - `use_path_style`
+    UsePathStyle,
+
+    /// The scope requested by OAuth based authorization
+    ///
+    /// Supported keys:
+    /// - `azure_storage_oauth_scope`
+    /// - `azure_oauth_scope`
+    /// - `oauth_scope`
+    OAuthScope,
+
+    /// The account name used to sign requests with shared key authorization
+    ///
+    /// Supported keys:
+    /// - `azure_storage_signing_account`
+    /// - `signing_account`
+    SigningAccount,
+
+    /// File to persist OAuth tokens to, so they can be reused by later processes
+    ///
+    /// Supported keys:
+    /// - `azure_storage_token_cache_path`
+    /// - `token_cache_path`
+    TokenCachePath,
+
+    /// Authorize requests with the managed identity of the VM or AKS node, if no
+    /// other credentials are configured
+    ///
+    /// Supported keys:
+    /// - `azure_use_managed_identity`
+    /// - `azure_msi_enabled`
+    /// - `use_managed_identity`
+    UseManagedIdentity,
+
+    /// Object id of a user-assigned managed identity
+    ///
+    /// Supported keys:
+    /// - `azure_object_id`
+    /// - `azure_msi_object_id`
+    /// - `object_id`
+    ObjectId,
+
+    /// Azure resource id of a user-assigned managed identity
+    ///
+    /// Supported keys:
+    /// - `azure_msi_resource_id`
+    /// - `azure_msi_res_id`
+    /// - `msi_resource_id`
+    MsiResourceId,
+
+    /// Endpoint from which managed identity tokens are fetched
+    ///
+    /// Supported keys:
+    /// - `azure_msi_endpoint`
+    /// - `azure_identity_endpoint`
+    /// - `msi_endpoint`
+    MsiEndpoint,
+
+    /// The access tier of blobs written without an explicit tier, one of `Hot`,
+    /// `Cool` or `Archive`
+    ///
+    /// See [`MicrosoftAzureBuilder::with_default_access_tier`]
+    ///
+    /// Supported keys:
+    /// - `azure_storage_access_tier`
+    /// - `access_tier`
+    AccessTier,
+
+    /// Azure Storage connection string
+    ///
+    /// See [`MicrosoftAzureBuilder::with_connection_string`]
+    ///
+    /// Supported keys:
+    /// - `azure_storage_connection_string`
+    /// - `connection_string`
+    ConnectionString,
+
+    /// Create append blobs that do not exist when appending to them
+    ///
+    /// See [`MicrosoftAzureBuilder::with_append_create`]
+    ///
+    /// Supported keys:
+    /// - `azure_storage_append_create`
+    /// - `append_create`
+    AppendCreate,
+}
+
+impl AsRef<str> for AzureConfigKey {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::AccountName => "azure_storage_account_name",
+            Self::AccessKey => "azure_storage_account_key",
+            Self::ClientId => "azure_storage_client_id",
+            Self::ClientSecret => "azure_storage_client_secret",
+            Self::AuthorityId => "azure_storage_tenant_id",
+            Self::SasKey => "azure_storage_sas_key",
+            Self::Token => "azure_storage_token",
+            Self::UseEmulator => "azure_storage_use_emulator",
+            Self::Endpoint => "azure_storage_endpoint",
+            Self::EndpointSuffix => "azure_storage_endpoint_suffix",
+            Self::UsePathStyle => "azure_storage_use_path_style",
+            Self::OAuthScope => "azure_storage_oauth_scope",
+            Self::SigningAccount => "azure_storage_signing_account",
+            Self::TokenCachePath => "azure_storage_token_cache_path",
+            Self::UseManagedIdentity => "azure_use_managed_identity",
+            Self::ObjectId => "azure_object_id",
+            Self::MsiResourceId => "azure_msi_resource_id",
+            Self::MsiEndpoint => "azure_msi_endpoint",
+            Self::AccessTier => "azure_storage_access_tier",
+            Self::ConnectionString => "azure_storage_connection_string",
+            Self::AppendCreate => "azure_storage_append_create",
+        }
+    }
+}
+
+impl FromStr for AzureConfigKey {
+    type Err = super::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "azure_storage_account_key"
+            | "azure_storage_access_key"
+            | "azure_storage_master_key"
+            | "master_key"
+            | "account_key"
+            | "access_key" => Ok(Self::AccessKey),
+            "azure_storage_account_name" | "account_name" => Ok(Self::AccountName),
+            "azure_storage_client_id"
+            | "azure_client_id"
+            | "azure_msi_client_id"
+            | "client_id" => Ok(Self::ClientId),
+            "azure_storage_client_secret" | "azure_client_secret" | "client_secret" => {
+                Ok(Self::ClientSecret)
+            }
+            "azure_storage_tenant_id"
+            | "azure_storage_authority_id"
+            | "azure_tenant_id"
+            | "azure_authority_id"
+            | "tenant_id"
+            | "authority_id" => Ok(Self::AuthorityId),
+            "azure_storage_sas_key"
+            | "azure_storage_sas_token"
+            | "sas_key"
+            | "sas_token" => Ok(Self::SasKey),
+            "azure_storage_token" | "bearer_token" | "token" => Ok(Self::Token),
+            "azure_storage_use_emulator" | "use_emulator" => Ok(Self::UseEmulator),
+            "azure_storage_endpoint" | "azure_endpoint" | "endpoint" => {
+                Ok(Self::Endpoint)
+            }
+            "azure_storage_endpoint_suffix"
+            | "azure_endpoint_suffix"
+            | "endpoint_suffix" => Ok(Self::EndpointSuffix),
+            "azure_storage_use_path_style" | "use_path_style" => Ok(Self::UsePathStyle),
+            "azure_storage_oauth_scope" | "azure_oauth_scope" | "oauth_scope" => {
+                Ok(Self::OAuthScope)
+            }
+            "azure_storage_signing_account" | "signing_account" => {
+                Ok(Self::SigningAccount)
+            }
+            "azure_storage_token_cache_path" | "token_cache_path" => {
+                Ok(Self::TokenCachePath)
+            }
+            "azure_use_managed_identity"
+            | "azure_msi_enabled"
+            | "use_managed_identity" => Ok(Self::UseManagedIdentity),
+            "azure_object_id" | "azure_msi_object_id" | "object_id" => Ok(Self::ObjectId),
+            "azure_msi_resource_id" | "azure_msi_res_id" | "msi_resource_id" => {
+                Ok(Self::MsiResourceId)
+            }
+            "azure_msi_endpoint" | "azure_identity_endpoint" | "msi_endpoint" => {
+                Ok(Self::MsiEndpoint)
+            }
+            "azure_storage_access_tier" | "access_tier" => Ok(Self::AccessTier),
+            "azure_storage_connection_string" | "connection_string" => {
+                Ok(Self::ConnectionString)
+            }
+            "azure_storage_append_create" | "append_create" => Ok(Self::AppendCreate),
+            _ => Err(Error::UnknownConfigurationKey { key: s.into() }.into()),
+        }
+    }
+}
+
+impl Debug for MicrosoftAzureBuilder {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "MicrosoftAzureBuilder {{ account: {:?}, container_name: {:?} }}",
+            self.account_name, self.container_name
+        )
+    }
+}
+
+impl MicrosoftAzureBuilder {
+    /// Create a new [`MicrosoftAzureBuilder`] with default values.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Create an instance of [`MicrosoftAzureBuilder`] with values pre-populated from environment variables.
+    ///
+    /// Variables extracted from environment:
+    /// * AZURE_STORAGE_ACCOUNT_NAME: storage account name
+    /// * AZURE_STORAGE_ACCOUNT_KEY: storage account master key
+    /// * AZURE_STORAGE_ACCESS_KEY: alias for AZURE_STORAGE_ACCOUNT_KEY
+    /// * AZURE_STORAGE_CLIENT_ID -> client id for service principal authorization
+    /// * AZURE_STORAGE_CLIENT_SECRET -> client secret for service principal authorization
+    /// * AZURE_STORAGE_TENANT_ID -> tenant id used in oauth flows
+    /// # Example
+    /// ```
+    /// use object_store::azure::MicrosoftAzureBuilder;
+    ///
+    /// let azure = MicrosoftAzureBuilder::from_env()
+    ///     .with_container_name("foo")
+    ///     .build();
+    /// ```
+    pub fn from_env() -> Self {
+        let mut builder = Self::default();
+        for (os_key, os_value) in std::env::vars_os() {
+            if let (Some(key), Some(value)) = (os_key.to_str(), os_value.to_str()) {
+                if key.starts_with("AZURE_") {
+                    if let Ok(config_key) =
+                        AzureConfigKey::from_str(&key.to_ascii_lowercase())
+                    {
+                        builder = builder.try_with_option(config_key, value).unwrap();
+                    }
+                }
+            }
+        }
+
+        if let Ok(text) = std::env::var("AZURE_ALLOW_HTTP") {
+            builder.client_options =
+                builder.client_options.with_allow_http(str_is_truthy(&text));
+        }
+
+        builder
+    }
+
+    /// Parse available connection info form a well-known storage URL.
+    ///
+    /// The supported url schemes are:
+    ///
+    /// - `abfs[s]://<container>/<path>` (according to [fsspec](https://github.com/fsspec/adlfs))
+    /// - `abfs[s]://<file_system>@<account_name>.dfs.core.windows.net/<path>`
+    /// - `az://<container>/<path>` (according to [fsspec](https://github.com/fsspec/adlfs))
+    /// - `adl://<container>/<path>` (according to [fsspec](https://github.com/fsspec/adlfs))
+    /// - `azure://<container>/<path>` (custom)
+    /// - `https://<account>.dfs.core.windows.net[/<container>/<path>]`
+    /// - `https://<account>.blob.core.windows.net[/<container>/<path>]`
+    ///
+    /// The query string of an `https` url, such as a SAS url copied from the Azure
+    /// portal, is used as the shared access signature of the store
+    ///
+    /// Note: Settings derived from the URL will override any others set on this builder
+    ///
+    /// # Example
+    /// ```
+    /// use object_store::azure::MicrosoftAzureBuilder;
+    ///
+    /// let azure = MicrosoftAzureBuilder::from_env()
+    ///     .with_url("abfss://file_system@account.dfs.core.windows.net/")
+    ///     .build();
+    /// ```
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = Some(url.into());
+        self
+    }
+
+    /// Set an option on the builder via a key - value pair.
+    ///
+    /// Surrounding whitespace and a leading UTF-8 byte order mark are removed from
+    /// credentials, such as access keys and SAS tokens, as for the other setters
+    pub fn try_with_option(
+        mut self,
+        key: impl AsRef<str>,
+        value: impl Into<String>,
+    ) -> Result<Self> {
+        match AzureConfigKey::from_str(key.as_ref())? {
+            AzureConfigKey::AccessKey => self.access_key = Some(trim_credential(value)),
+            AzureConfigKey::AccountName => self.account_name = Some(value.into()),
+            AzureConfigKey::ClientId => self.client_id = Some(value.into()),
+            AzureConfigKey::ClientSecret => {
+                self.client_secret = Some(trim_credential(value))
+            }
+            AzureConfigKey::AuthorityId => self.tenant_id = Some(value.into()),
+            AzureConfigKey::SasKey => self.sas_key = Some(trim_credential(value)),
+            AzureConfigKey::Token => self.bearer_token = Some(trim_credential(value)),
+            AzureConfigKey::UseEmulator => {
+                self.use_emulator = str_is_truthy(&value.into())
+            }
+            AzureConfigKey::Endpoint => self.endpoint = Some(value.into()),
+            AzureConfigKey::EndpointSuffix => self.endpoint_suffix = Some(value.into()),
+            AzureConfigKey::UsePathStyle => {
+                self.use_path_style = str_is_truthy(&value.into())
+            }
+            AzureConfigKey::OAuthScope => self.oauth_scope = Some(value.into()),
+            AzureConfigKey::SigningAccount => self.signing_account = Some(value.into()),
+            AzureConfigKey::TokenCachePath => {
+                self.token_cache_path = Some(PathBuf::from(value.into()))
+            }
+            AzureConfigKey::UseManagedIdentity => {
+                self.use_managed_identity = str_is_truthy(&value.into())
+            }
+            AzureConfigKey::ObjectId => self.object_id = Some(value.into()),
+            AzureConfigKey::MsiResourceId => self.msi_resource_id = Some(value.into()),
+            AzureConfigKey::MsiEndpoint => self.msi_endpoint = Some(value.into()),
+            AzureConfigKey::AccessTier => {
+                self.default_access_tier = Some(value.into().parse()?)
+            }
+            AzureConfigKey::AppendCreate => {
+                self.append_create = Some(str_is_truthy(&value.into()))
+            }
+            AzureConfigKey::ConnectionString => {
+                self.connection_string = Some(value.into())
+            }
+        };
+        Ok(self)
+    }
+
+    /// Hydrate builder from key value pairs
+    pub fn try_with_options<
+        I: IntoIterator<Item = (impl AsRef<str>, impl Into<String>)>,
+    >(
+        mut self,
+        options: I,
+    ) -> Result<Self> {
+        for (key, value) in options {
+            self = self.try_with_option(key, value)?;
+        }
+        Ok(self)
+    }
+
+    /// Sets properties on this builder based on a URL, returning the [`Path`]
+    /// of the blob within the container it refers to
+    ///
+    /// This is a separate member function to allow fallible computation to
+    /// be deferred until [`Self::build`] which in turn allows deriving [`Clone`]
+    fn parse_url(&mut self, url: &str) -> Result<Path> {
+        let parsed = Url::parse(url).context(UnableToParseUrlSnafu { url })?;
+        let host = parsed.host_str().context(UrlNotRecognisedSnafu { url })?;
+
+        let validate = |s: &str| match s.contains('.') {
+            true => Err(UrlNotRecognisedSnafu { url }.build()),
+            false => Ok(s.to_string()),
+        };
+
+        let mut path = parsed.path();
+        match parsed.scheme() {
+            "az" | "adl" | "azure" => self.container_name = Some(validate(host)?),
+            "abfs" | "abfss" => {
+                // abfs(s) might refer to the fsspec convention abfs://<container>/<path>
+                // or the convention for the hadoop driver abfs[s]://<file_system>@<account_name>.dfs.core.windows.net/<path>
+                if parsed.username().is_empty() {
+                    self.container_name = Some(validate(host)?);
+                } else if let Some((a, "dfs", suffix)) = self.split_host(host) {
+                    self.container_name = Some(validate(parsed.username())?);
+                    self.account_name = Some(validate(a)?);
+                    self.endpoint_suffix = Some(suffix.to_string());
+                    self.hierarchical_namespace.get_or_insert(true);
+                } else {
+                    return Err(UrlNotRecognisedSnafu { url }.build().into());
+                }
+            }
+            "https" => match self.split_host(host) {
+                Some((a, service @ ("dfs" | "blob"), suffix)) => {
+                    self.account_name = Some(validate(a)?);
+                    self.endpoint_suffix = Some(suffix.to_string());
+                    if service == "dfs" {
+                        self.hierarchical_namespace.get_or_insert(true);
+                    }
+
+                    // https://<account>.blob.core.windows.net/<container>/<path>
+                    let (container, blob) = path
+                        .trim_start_matches('/')
+                        .split_once('/')
+                        .unwrap_or((path.trim_start_matches('/'), ""));
+                    if !container.is_empty() {
+                        self.container_name = Some(validate(container)?);
+                    }
+                    path = blob;
+
+                    // https://<account>.blob.core.windows.net/<container>?sv=...&sig=...
+                    if let Some(query) = parsed.query().filter(|q| !q.is_empty()) {
+                        self.sas_query_pairs = Some(split_sas(query)?);
+                    }
+                }
+                _ => return Err(UrlNotRecognisedSnafu { url }.build().into()),
+            },
+            scheme => return Err(UnknownUrlSchemeSnafu { scheme }.build().into()),
+        }
+
+        let path = percent_decode_str(path)
+            .decode_utf8()
+            .context(DecodeUrlPathSnafu { url })?;
+        Ok(Path::parse(path)?)
+    }
+
+    /// Sets properties on this builder based on an Azure Storage connection string
+    ///
+    /// Like [`Self::parse_url`] this is deferred until [`Self::build`]
+    fn parse_connection_string(&mut self, connection_string: &str) -> Result<()> {
+        let mut account_name = None;
+        let mut account_key = None;
+        let mut sas_key = None;
+        let mut blob_endpoint = None;
+        let mut endpoint_suffix = None;
+        let mut protocol = None;
+        let mut use_emulator = false;
+
+        for (index, element) in connection_string.split(';').enumerate() {
+            if element.trim().is_empty() {
+                continue;
+            }
+            let (key, value) = element
+                .split_once('=')
+                .context(MalformedConnectionStringSnafu { index })?;
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "accountname" => account_name = Some(value),
+                "accountkey" => account_key = Some(value),
+                "sharedaccesssignature" => sas_key = Some(value),
+                "blobendpoint" => blob_endpoint = Some(value),
+                "endpointsuffix" => endpoint_suffix = Some(value),
+                "defaultendpointsprotocol" => protocol = Some(value),
+                "usedevelopmentstorage" => use_emulator = str_is_truthy(value),
+                _ => {}
+            }
+        }
+
+        if use_emulator {
+            self.use_emulator = true;
+            return Ok(());
+        }
+
+        let account_name =
+            account_name.context(MissingConnectionStringComponentSnafu {
+                component: "AccountName",
+            })?;
+        match (account_key, sas_key) {
+            (Some(key), _) => self.access_key = Some(key.to_string()),
+            (None, Some(sas)) => self.sas_key = Some(sas.to_string()),
+            (None, None) => {
+                return Err(Error::MissingConnectionStringComponent {
+                    component: "AccountKey",
+                }
+                .into())
+            }
+        }
+
+        let protocol = match protocol {
+            None => "https",
+            Some(p) if p.eq_ignore_ascii_case("https") => "https",
+            Some(p) if p.eq_ignore_ascii_case("http") => "http",
+            Some(p) => {
+                return Err(Error::InvalidConnectionStringProtocol {
+                    protocol: p.to_string(),
+                }
+                .into())
+            }
+        };
+
+        if let Some(suffix) = endpoint_suffix {
+            self.endpoint_suffix = Some(suffix.to_string());
+        }
+        let endpoint = match (blob_endpoint, endpoint_suffix) {
+            (Some(endpoint), _) => Some(endpoint.trim_end_matches('/').to_string()),
+            (None, Some(suffix)) => {
+                Some(format!("{}://{}.blob.{}", protocol, account_name, suffix))
+            }
+            (None, None) => None,
+        };
+        if let Some(endpoint) = endpoint {
+            if endpoint.starts_with("http://") {
+                self.client_options = self.client_options.clone().with_allow_http(true);
+            }
+            self.endpoint = Some(endpoint);
+        }
+        self.account_name = Some(account_name.to_string());
+        Ok(())
+    }
+
+    /// Splits `host` into the account, service and endpoint suffix if it is the host of
+    /// an endpoint of a known Azure cloud, or of the configured endpoint suffix, e.g.
+    /// `account.blob.core.windows.net`
+    fn split_host<'a>(&self, host: &'a str) -> Option<(&'a str, &'a str, &'a str)> {
+        let (account, service) = host.split_once('.')?;
+        let (service, suffix) = service.split_once('.')?;
+        let known = CLOUD_ENDPOINT_SUFFIXES.iter().any(|(s, _)| *s == suffix)
+            || self.endpoint_suffix.as_deref() == Some(suffix);
+        known.then_some((account, service, suffix))
+    }
+
+    /// Create a [`MicrosoftAzure`] instance from the provided values, returning it
+    /// along with the [`Path`] of the blob referred to by the [URL](Self::with_url),
+    /// if any, within the container
+    ///
+    /// # Example
+    /// ```
+    /// use object_store::azure::MicrosoftAzureBuilder;
+    ///
+    /// let (azure, path) = MicrosoftAzureBuilder::new()
+    ///     .with_url("https://account.blob.core.windows.net/container/data/file.parquet")
+    ///     .with_access_key("foo")
+    ///     .build_with_path()
+    ///     .unwrap();
+    /// assert_eq!(path.as_ref(), "data/file.parquet");
+    /// ```
+    pub fn build_with_path(mut self) -> Result<(MicrosoftAzure, Path)> {
+        let path = match self.url.take() {
+            Some(url) => self.parse_url(&url)?,
+            None => Path::default(),
+        };
+        Ok((self.build()?, path))
+    }
+
+    /// Configure the account, credentials and endpoint from an Azure Storage
+    /// [connection string], such as the value of `AZURE_STORAGE_CONNECTION_STRING`
+    ///
+    /// The `AccountName`, `AccountKey`, `SharedAccessSignature`, `BlobEndpoint`,
+    /// `EndpointSuffix` and `DefaultEndpointsProtocol` components are used, along with
+    /// `UseDevelopmentStorage=true`, which selects the [emulator](Self::with_use_emulator).
+    /// Other components are ignored. The connection string is parsed by
+    /// [`Self::build`], which returns an error if it is malformed or lacks an account
+    /// name or credentials.
+    ///
+    /// Note: Settings derived from the connection string will override any others set
+    /// on this builder
+    ///
+    /// # Example
+    /// ```
+    /// use object_store::azure::MicrosoftAzureBuilder;
+    ///
+    /// let azure = MicrosoftAzureBuilder::new()
+    ///     .with_connection_string(
+    ///         "DefaultEndpointsProtocol=https;AccountName=account;AccountKey=a2V5;EndpointSuffix=core.windows.net",
+    ///     )
+    ///     .with_container_name("container")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [connection string]: https://learn.microsoft.com/en-us/azure/storage/common/storage-configure-connection-string
+    pub fn with_connection_string(
+        mut self,
+        connection_string: impl Into<String>,
+    ) -> Self {
+        self.connection_string = Some(connection_string.into());
+        self
+    }
+
+    /// Set the Azure Account (required)
+    pub fn with_account(mut self, account: impl Into<String>) -> Self {
+        self.account_name = Some(account.into());
+        self
+    }
+
+    /// Set the Azure Container Name (required)
+    pub fn with_container_name(mut self, container_name: impl Into<String>) -> Self {
+        self.container_name = Some(container_name.into());
+        self
+    }
+
+    /// Set the Azure Access Key (required - one of access key, bearer token, or client credentials)
+    pub fn with_access_key(mut self, access_key: impl Into<String>) -> Self {
+        self.access_key = Some(trim_credential(access_key));
+        self
+    }
+
+    /// Set multiple Azure Access Keys of the account, in order of preference, such as
+    /// its primary and secondary keys during a key rotation
+    ///
+    /// Requests are signed with the first key, and if rejected with
+    /// `403 AuthenticationFailed` are retried once with the next key, which is then
+    /// used for subsequent requests. This replaces any key set by
+    /// [`Self::with_access_key`]
+    pub fn with_access_keys<I, K>(mut self, access_keys: I) -> Self
+    where
+        I: IntoIterator<Item = K>,
+        K: Into<String>,
+    {
+        let mut keys = access_keys.into_iter().map(trim_credential);
+        self.access_key = keys.next();
+        self.fallback_access_keys = keys.collect();
+        self
+    }
+
+    /// Set a static bearer token to be used for authorizing requests
+    pub fn with_bearer_token_authorization(
+        mut self,
+        bearer_token: impl Into<String>,
+    ) -> Self {
+        self.bearer_token = Some(trim_credential(bearer_token));
+        self
+    }
+
+    /// Set a client secret used for client secret authorization
+    pub fn with_client_secret_authorization(
+        mut self,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+        tenant_id: impl Into<String>,
+    ) -> Self {
+        self.client_id = Some(client_id.into());
+        self.client_secret = Some(trim_credential(client_secret));
+        self.tenant_id = Some(tenant_id.into());
+        self
+    }
+
+    /// Set query pairs appended to the url for shared access signature authorization
+    pub fn with_sas_authorization(
+        mut self,
+        query_pairs: impl Into<Vec<(String, String)>>,
+    ) -> Self {
+        self.sas_query_pairs = Some(query_pairs.into());
+        self
+    }
+
+    /// Set if the Azure emulator should be used (defaults to false)
+    pub fn with_use_emulator(mut self, use_emulator: bool) -> Self {
+        self.use_emulator = use_emulator;
+        self
+    }
+
+    /// Sets the endpoint used to communicate with blob storage, e.g.
+    /// `https://gateway.example.com`, for services implementing the Azure Blob protocol
+    ///
+    /// Defaults to `https://{account}.blob.{endpoint_suffix}`
+    pub fn with_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoint = Some(endpoint.into());
+        self
+    }
+
+    /// Sets the suffix of the endpoints of the Azure cloud of the account, such as
+    /// `core.usgovcloudapi.net` for Azure Government or `core.chinacloudapi.cn` for
+    /// Azure China, from which the default endpoints are derived
+    ///
+    /// Unless an [authority host](Self::with_authority_host) is set, OAuth tokens are
+    /// requested from the authority host of the cloud with this suffix, if known.
+    ///
+    /// Defaults to `core.windows.net`, the suffix of the Azure public cloud
+    pub fn with_endpoint_suffix(mut self, suffix: impl Into<String>) -> Self {
+        self.endpoint_suffix = Some(suffix.into());
+        self
+    }
+
+    /// Sets the Data Lake Storage Gen2 (`dfs`) endpoint, used by operations only
+    /// supported on accounts with a hierarchical namespace
+    ///
+    /// Defaults to `https://{account}.dfs.{endpoint_suffix}`, or if an
+    /// [endpoint](Self::with_endpoint) is set, that endpoint with `.blob.` in its
+    /// host replaced by `.dfs.`
+    pub fn with_dfs_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.dfs_endpoint = Some(endpoint.into());
+        self
+    }
+
+    /// Sets if the account name should be included in the request path, i.e.
+    /// `{endpoint}/{account}/{container}/{blob}`, instead of in the host of the
+    /// endpoint (defaults to false)
+    ///
+    /// Requests are signed with the path-style canonicalized resource in this case
+    pub fn with_use_path_style(mut self, use_path_style: bool) -> Self {
+        self.use_path_style = use_path_style;
+        self
+    }
+
+    /// Set if mutating operations should only be logged, and not performed (defaults to false)
+    ///
+    /// When enabled `put`, `put_multipart`, `delete`, `copy` and `rename` log the operation
+    /// they would have performed at `INFO` level and return `Ok` without issuing any request.
+    /// Read operations such as `get`, `head` and `list` are performed as normal.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Set if [`build`](Self::build) should establish a connection to Azure in the
+    /// background, so that the first request does not incur the latency of DNS
+    /// resolution and the TLS handshake (defaults to false)
+    ///
+    /// This requires `build` to be called within a tokio runtime, otherwise it is
+    /// ignored. To wait for the connection, instead call [`MicrosoftAzure::warm_up`]
+    pub fn with_connection_warmup(mut self, connection_warmup: bool) -> Self {
+        self.connection_warmup = connection_warmup;
+        self
+    }
+
+    /// Set if the container name should be converted to lowercase by
+    /// [`build`](Self::build), as required by Azure (defaults to false)
+    ///
+    /// When enabled a container name containing uppercase characters is logged at
+    /// `WARN` level and lowercased, instead of failing requests with `400 Bad Request`
+    pub fn with_auto_lowercase_container(
+        mut self,
+        auto_lowercase_container: bool,
+    ) -> Self {
+        self.auto_lowercase_container = auto_lowercase_container;
+        self
+    }
+
+    /// Set if `head` should tolerate responses missing the `Last-Modified` or
+    /// `Content-Length` headers, as returned by some gateways and emulators
+    /// (defaults to false)
+    ///
+    /// When enabled a missing `Last-Modified` is logged at `WARN` level and replaced
+    /// by the Unix epoch, and a missing `Content-Length` is derived from the
+    /// `Content-Range` header if present. Otherwise these return an error.
+    pub fn with_lenient_headers(mut self, lenient_headers: bool) -> Self {
+        self.lenient_headers = lenient_headers;
+        self
+    }
+
+    /// Set the maximum number of bytes buffered by multipart uploads across the whole store
+    ///
+    /// Each writer returned by `put_multipart` reserves space for a full part from this
+    /// budget before buffering it, waiting if none is available, and releases it once that
+    /// part has been uploaded. Must be at least the block size, see
+    /// [`Self::with_block_size`].
+    ///
+    /// Default is no limit
+    pub fn with_max_buffered_bytes(mut self, max_buffered_bytes: usize) -> Self {
+        self.max_buffered_bytes = Some(max_buffered_bytes);
+        self
+    }
+
+    /// Set the size in bytes of the blocks staged by multipart uploads, other than
+    /// the last block of each upload
+    ///
+    /// Larger blocks allow larger blobs, as a blob can have at most 50,000 blocks, at
+    /// the cost of buffering more data per upload. For example the default of 5 MiB
+    /// limits streaming uploads to around 244 GiB, whereas 100 MiB blocks allow blobs
+    /// of up to around 4.8 TiB. Each upload buffers up to the block size for each of
+    /// the [concurrently staged](Self::with_multipart_concurrency) blocks.
+    ///
+    /// [`build`](Self::build) returns an error if this is zero or exceeds the maximum
+    /// block size of 4000 MiB supported by Azure. Writes larger than the block size
+    /// may be staged as larger blocks, up to the maximum block size.
+    ///
+    /// Default is 5 MiB
+    pub fn with_block_size(mut self, block_size: usize) -> Self {
+        self.block_size = Some(block_size);
+        self
+    }
+
+    /// Set the number of blocks of a multipart upload that are staged concurrently
+    ///
+    /// This is shorthand for [`Self::with_upload_concurrency`] with
+    /// [`UploadConcurrency::Fixed`], and [`build`](Self::build) returns an error if
+    /// it is zero
+    ///
+    /// Default is 8
+    pub fn with_multipart_concurrency(self, concurrency: usize) -> Self {
+        self.with_upload_concurrency(UploadConcurrency::Fixed(concurrency))
+    }
+
+    /// Set the number of blocks of a multipart upload that are staged concurrently,
+    /// see [`UploadConcurrency`]
+    ///
+    /// Default is [`UploadConcurrency::Fixed`] with 8 concurrent blocks
+    pub fn with_upload_concurrency(mut self, concurrency: UploadConcurrency) -> Self {
+        self.upload_concurrency = Some(concurrency);
+        self
+    }
+
+    /// Set the algorithm used to compute a checksum of the data written by
+    /// [`MicrosoftAzure::put_multipart_writer`], returned by [`AzureMultipartWriter::finish`]
+    ///
+    /// Default is no checksum
+    pub fn with_checksum_algorithm(mut self, algorithm: ChecksumAlgorithm) -> Self {
+        self.checksum_algorithm = Some(algorithm);
+        self
+    }
+
+    /// Set what to do with the blocks staged by a multipart upload that is dropped
+    /// before being completed, see [`MultipartDropBehavior`]
+    ///
+    /// Default is [`MultipartDropBehavior::Warn`]
+    pub fn with_multipart_drop_behavior(
+        mut self,
+        behavior: MultipartDropBehavior,
+    ) -> Self {
+        self.multipart_drop_behavior = behavior;
+        self
+    }
+
+    /// Set whether a multipart upload that fails, for example because a block could
+    /// not be staged, discards the blocks it has already staged before returning the
+    /// error, rather than leaving them to expire after 7 days
+    ///
+    /// As with [`MultipartDropBehavior`], blocks are only discarded if there is no
+    /// existing blob at the location of the upload
+    ///
+    /// Default is true
+    pub fn with_abort_multipart_on_error(mut self, abort: bool) -> Self {
+        self.abort_multipart_on_error = Some(abort);
+        self
+    }
+
+    /// Set a callback invoked with the [`RequestTiming`] of every request, for example
+    /// to record metrics of the latency of requests to Azure
+    ///
+    /// The callback is invoked once the response headers have been received, and
+    /// should not block
+    pub fn with_request_observer(mut self, observer: RequestObserverFn) -> Self {
+        self.request_observer = Some(observer);
+        self
+    }
+
+    /// Sets whether a request rejected as unauthenticated when authorized with an
+    /// OAuth token, such as one that expired while the request was in flight, is
+    /// retried once with a newly fetched token
+    ///
+    /// This has no effect on other credentials. Default is true
+    pub fn with_refresh_on_auth_failure(mut self, refresh: bool) -> Self {
+        self.refresh_on_auth_failure = Some(refresh);
+        self
+    }
+
+    /// Sets whether OAuth credentials take precedence over an access key when both
+    /// are configured
+    ///
+    /// Credentials are otherwise chosen in the following order, using the first
+    /// one that is configured:
+    ///
+    /// 1. A bearer token
+    /// 2. An access key, and any fallback access keys
+    /// 3. A client secret, with its client id and tenant id
+    /// 4. A SAS token, either as query pairs or as a key
+    /// 5. A managed identity
+    ///
+    /// When enabled, a configured client secret or managed identity is used to
+    /// authorize requests instead of the access key. The access key is still
+    /// retained by the store for features that can only be signed with the
+    /// account key, such as service SAS generation.
+    ///
+    /// Default is false
+    pub fn with_prefer_oauth(mut self, prefer: bool) -> Self {
+        self.prefer_oauth = prefer;
+        self
+    }
+
+    /// Sets the name of the header to send the idempotency key of an operation as,
+    /// such as one deduplicating retried requests in a proxy in front of Azure
+    ///
+    /// See [`PutOptions::idempotency_key`]. By default idempotency keys are not sent
+    pub fn with_idempotency_key_header(mut self, name: impl Into<String>) -> Self {
+        self.idempotency_key_header = Some(name.into());
+        self
+    }
+
+    /// Sets the maximum size in bytes of the XML response body of a list or other
+    /// metadata request, such as Get Block List, that is buffered before parsing
+    ///
+    /// Larger responses fail with an error instead of being read into memory. The
+    /// data of objects is streamed and not subject to this limit. Default is no limit
+    pub fn with_max_response_body_size(mut self, max: usize) -> Self {
+        self.max_response_body_size = Some(max);
+        self
+    }
+
+    /// Sets metadata stored with every blob written by [`ObjectStore::put`], multipart
+    /// uploads and other writes, such as to tag blobs with the application writing them
+    ///
+    /// Metadata of the same key given for an individual write, see [`PutOptions::metadata`],
+    /// takes precedence. Building fails if a key is not a valid header name
+    pub fn with_default_metadata(mut self, metadata: HashMap<String, String>) -> Self {
+        self.default_metadata = metadata;
+        self
+    }
+
+    /// Sets the maximum time to wait for a copy that completes asynchronously, such
+    /// as one from another account, see [`MicrosoftAzure::copy_from_url`]
+    ///
+    /// The copy is polled with the backoff of the [`RetryConfig`], and an error
+    /// reporting its last progress returned once this elapses, although the copy
+    /// continues in the background. Default is 30 minutes
+    pub fn with_copy_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.copy_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the access tier of blobs written by [`ObjectStore::put`], multipart
+    /// uploads and other writes, instead of the default tier of the account
+    ///
+    /// Writes to [`AccessTier::Archive`] succeed, but the blob cannot be read until
+    /// it has been rehydrated to another tier. See [`MicrosoftAzure::put_with_tier`]
+    /// to set the tier of individual blobs
+    pub fn with_default_access_tier(mut self, tier: AccessTier) -> Self {
+        self.default_access_tier = Some(tier);
+        self
+    }
+
+    /// Sets if blob versioning is enabled on the storage account, as reported by
+    /// [`ObjectStore::capabilities`]
+    ///
+    /// This is an account setting, which this only declares and does not change
+    /// the requests made. Default is false
+    pub fn with_versioning(mut self, versioning: bool) -> Self {
+        self.versioning = versioning;
+        self
+    }
+
+    /// Sets if [`MicrosoftAzure::append`] creates an append blob if there is none
+    ///
+    /// If false, appending to a blob that does not exist returns [`Error::NotFound`],
+    /// for example so a writer does not recreate a blob removed by a retention policy.
+    /// Default is true
+    ///
+    /// [`Error::NotFound`]: crate::Error::NotFound
+    pub fn with_append_create(mut self, append_create: bool) -> Self {
+        self.append_create = Some(append_create);
+        self
+    }
+
+    /// Sets if the storage account has a hierarchical namespace, allowing blobs and
+    /// directories to be renamed atomically on the `dfs` endpoint, see
+    /// [`Self::with_dfs_endpoint`]
+    ///
+    /// Used by [`ObjectStore::rename`] and [`MicrosoftAzure::put_atomic`]. Default is
+    /// true if the url of [`Self::with_url`] is of a `dfs` endpoint, such as
+    /// `abfss://<file_system>@<account>.dfs.core.windows.net`, and false otherwise
+    pub fn with_hierarchical_namespace(mut self, hierarchical_namespace: bool) -> Self {
+        self.hierarchical_namespace = Some(hierarchical_namespace);
+        self
+    }
+
+    /// Sets what protocol is allowed. If `allow_http` is :
+    /// * false (default):  Only HTTPS are allowed
+    /// * true:  HTTP and HTTPS are allowed
+    pub fn with_allow_http(mut self, allow_http: bool) -> Self {
+        self.client_options = self.client_options.with_allow_http(allow_http);
+        self
+    }
+
+    /// Sets an alternative authority host for OAuth based authorization
+    /// common hosts for azure clouds are defined in [authority_hosts].
+    /// Defaults to the authority host of the cloud with the configured
+    /// [endpoint suffix](Self::with_endpoint_suffix) if known, otherwise
+    /// <https://login.microsoftonline.com>
+    pub fn with_authority_host(mut self, authority_host: String) -> Self {
+        self.authority_host = Some(authority_host);
+        self
+    }
+
+    /// Sets the scope requested by OAuth based authorization, for example
+    /// `https://storage.azure.us/.default` in Azure Government.
+    /// Defaults to `https://storage.azure.com/.default`
+    pub fn with_oauth_scope(mut self, oauth_scope: impl Into<String>) -> Self {
+        self.oauth_scope = Some(oauth_scope.into());
+        self
+    }
+
+    /// Sets the account name used to sign requests with shared key authorization,
+    /// independently of the host requests are sent to.
+    ///
+    /// This is required when accessing an account through a custom domain, such as a
+    /// CDN, whose requests are still authorized against the underlying account.
+    /// Defaults to the account name
+    pub fn with_signing_account(mut self, signing_account: impl Into<String>) -> Self {
+        self.signing_account = Some(signing_account.into());
+        self
+    }
+
+    /// Authorize requests with OAuth tokens for the managed identity of the Azure VM
+    /// or AKS node, fetched from the Azure Instance Metadata Service (IMDS)
+    ///
+    /// This is only used if no other credentials are configured. The system-assigned
+    /// identity is used, unless a user-assigned identity is selected with
+    /// [`Self::with_client_id`], [`Self::with_object_id`] or [`Self::with_msi_resource_id`]
+    pub fn with_managed_identity(mut self) -> Self {
+        self.use_managed_identity = true;
+        self
+    }
+
+    /// Sets the client id of the user-assigned managed identity to use, see
+    /// [`Self::with_managed_identity`]
+    pub fn with_client_id(mut self, client_id: impl Into<String>) -> Self {
+        self.client_id = Some(client_id.into());
+        self
+    }
+
+    /// Sets the object id of the user-assigned managed identity to use, see
+    /// [`Self::with_managed_identity`]
+    pub fn with_object_id(mut self, object_id: impl Into<String>) -> Self {
+        self.object_id = Some(object_id.into());
+        self
+    }
+
+    /// Sets the Azure resource id of the user-assigned managed identity to use, see
+    /// [`Self::with_managed_identity`]
+    pub fn with_msi_resource_id(mut self, msi_resource_id: impl Into<String>) -> Self {
+        self.msi_resource_id = Some(msi_resource_id.into());
+        self
+    }
+
+    /// Sets the endpoint from which managed identity tokens are fetched, see
+    /// [`Self::with_managed_identity`].
+    /// Defaults to <http://169.254.169.254/metadata/identity/oauth2/token>
+    pub fn with_msi_endpoint(mut self, msi_endpoint: impl Into<String>) -> Self {
+        self.msi_endpoint = Some(msi_endpoint.into());
+        self
+    }
+
+    /// Sets a file to persist OAuth tokens obtained with a client secret to, so that
+    /// short-lived processes can reuse a token that is still valid instead of
+    /// requesting a new one.
+    ///
+    /// The file contains the token, and so must be kept private. It is created
+    /// readable only by the current user on unix
+    pub fn with_token_cache_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.token_cache_path = Some(path.into());
+        self
+    }
+
+    /// Set the retry configuration
+    pub fn with_retry(mut self, retry_config: RetryConfig) -> Self {
+        self.retry_config = retry_config;
+        self
+    }
+
+    /// Set the proxy_url to be used by the underlying client
+    pub fn with_proxy_url(mut self, proxy_url: impl Into<String>) -> Self {
+        self.client_options = self.client_options.with_proxy_url(proxy_url);
+        self
+    }
+
+    /// Sets the client options, overriding any already set
+    pub fn with_client_options(mut self, options: ClientOptions) -> Self {
+        self.client_options = options;
+        self
+    }
+
+    /// Configure a connection to container with given name on Microsoft Azure
+    /// Blob store.
+    pub fn build(mut self) -> Result<MicrosoftAzure> {
+        if let Some(url) = self.url.take() {
+            self.parse_url(&url)?;
+        }
+
+        if let Some(connection_string) = self.connection_string.take() {
+            self.parse_connection_string(&connection_string)?;
+        }
+
+        let mut container = self.container_name.ok_or(Error::MissingContainerName {})?;
+        if self.auto_lowercase_container && container.chars().any(char::is_uppercase) {
+            let lowercase = container.to_lowercase();
+            warn!(
+                "Azure container names must be lowercase, using '{}' instead of '{}'",
+                lowercase, container
+            );
+            container = lowercase;
+        }
+
+        let (use_path_style, storage_url, dfs_url, auth, account) = if self.use_emulator {
+            let account_name = self
+                .account_name
+                .unwrap_or_else(|| EMULATOR_ACCOUNT.to_string());
+            // Allow overriding defaults. Values taken from
+            // from https://docs.rs/azure_storage/0.2.0/src/azure_storage/core/clients/storage_account_client.rs.html#129-141
+            let url = url_from_env("AZURITE_BLOB_STORAGE_URL", "http://127.0.0.1:10000")?;
+            let account_key = self
+                .access_key
+                .get_or_insert_with(|| EMULATOR_ACCOUNT_KEY.to_string())
+                .clone();
+            let credential = credential::CredentialProvider::AccessKey(
+                credential::AccessKeys::new(vec![account_key]),
+            );
+
+            self.client_options = self.client_options.with_allow_http(true);
+            (true, url.clone(), url, credential, account_name)
+        } else {
+            let account_name = self.account_name.ok_or(Error::MissingAccount {})?;
+            ensure!(
+                is_valid_account_name(&account_name),
+                InvalidAccountNameSnafu {
+                    account: account_name
+                }
+            );
+            let suffix = self
+                .endpoint_suffix
+                .as_deref()
+                .unwrap_or(DEFAULT_ENDPOINT_SUFFIX)
+                .trim_matches('.');
+            let account_url = match &self.endpoint {
+                Some(endpoint) => endpoint.clone(),
+                None => format!("https://{}.blob.{}", &account_name, suffix),
+            };
+            let url = Url::parse(&account_url)
+                .context(UnableToParseUrlSnafu { url: account_url })?;
+            let dfs_url = match (self.dfs_endpoint, &self.endpoint) {
+                (Some(endpoint), _) => Url::parse(&endpoint)
+                    .context(UnableToParseUrlSnafu { url: endpoint })?,
+                (None, Some(_)) => dfs_url_from_blob(&url),
+                (None, None) => {
+                    let dfs_url = format!("https://{}.dfs.{}", &account_name, suffix);
+                    Url::parse(&dfs_url)
+                        .context(UnableToParseUrlSnafu { url: dfs_url })?
+                }
+            };
+            let managed_identity = self.use_managed_identity.then(|| {
+                credential::ImdsManagedIdentityOAuthProvider::new(
+                    self.client_id.clone(),
+                    self.object_id.take(),
+                    self.msi_resource_id.take(),
+                    self.msi_endpoint.take(),
+                    self.oauth_scope.clone(),
+                )
+            });
+            let has_oauth = managed_identity.is_some()
+                || (self.client_id.is_some()
+                    && self.client_secret.is_some()
+                    && self.tenant_id.is_some());
+            let credential = if let Some(bearer_token) = self.bearer_token {
+                Ok(credential::CredentialProvider::AccessKey(
+                    credential::AccessKeys::new(vec![bearer_token]),
+                ))
+            } else if let Some(access_key) = self
+                .access_key
+                .clone()
+                .filter(|_| !(self.prefer_oauth && has_oauth))
+            {
+                let keys = std::iter::once(access_key)
+                    .chain(self.fallback_access_keys)
+                    .collect();
+                Ok(credential::CredentialProvider::AccessKey(
+                    credential::AccessKeys::new(keys),
+                ))
+            } else if let (Some(client_id), Some(client_secret), Some(tenant_id)) =
+                (self.client_id, self.client_secret, self.tenant_id)
+            {
+                let authority_host = self.authority_host.or_else(|| {
+                    CLOUD_ENDPOINT_SUFFIXES
+                        .iter()
+                        .find(|(s, _)| *s == suffix)
+                        .map(|(_, host)| host.to_string())
+                });
+                let mut client_credential = credential::ClientSecretOAuthProvider::new(
+                    client_id,
+                    client_secret,
+                    tenant_id,
+                    authority_host,
+                    self.oauth_scope,
+                );
+                if let Some(path) = self.token_cache_path {
+                    client_credential = client_credential.with_cache_path(path);
+                }
+                Ok(credential::CredentialProvider::ClientSecret(
+                    client_credential,
+                ))
+            } else if let Some(query_pairs) = self.sas_query_pairs {
+                Ok(credential::CredentialProvider::SASToken(query_pairs))
+            } else if let Some(sas) = self.sas_key {
+                Ok(credential::CredentialProvider::SASToken(split_sas(&sas)?))
+            } else if let Some(provider) = managed_identity {
+                Ok(credential::CredentialProvider::ManagedIdentity(provider))
+            } else {
+                Err(Error::MissingCredentials {})
+            }?;
+            (self.use_path_style, url, dfs_url, credential, account_name)
+        };
+
+        let block_size = self.block_size.unwrap_or(DEFAULT_MIN_PART_SIZE);
+        ensure!(
+            block_size > 0 && block_size <= MAX_BLOCK_SIZE,
+            InvalidBlockSizeSnafu {
+                block_size,
+                max: MAX_BLOCK_SIZE
+            }
+        );
+
+        let upload_concurrency = self.upload_concurrency.unwrap_or_default();
+        let valid = match upload_concurrency {
+            UploadConcurrency::Fixed(n) => n > 0,
+            UploadConcurrency::Adaptive { min, max } => min > 0 && min <= max,
+        };
+        ensure!(
+            valid,
+            InvalidUploadConcurrencySnafu {
+                concurrency: upload_concurrency
+            }
+        );
+
+        let buffer_budget = match self.max_buffered_bytes {
+            Some(max) if max < block_size => {
+                return Err(Error::MaxBufferedBytesTooSmall {
+                    max_buffered_bytes: max,
+                    part_size: block_size,
+                }
+                .into())
+            }
+            Some(max) => Some(Arc::new(Semaphore::new(max))),
+            None => None,
+        };
+
+        let idempotency_key_header = self
+            .idempotency_key_header
+            .map(|name| {
+                HeaderName::from_bytes(name.as_bytes())
+                    .context(InvalidIdempotencyKeyHeaderSnafu { name })
+            })
+            .transpose()?;
+
+        let config = client::AzureConfig {
+            signing_account: self.signing_account.unwrap_or_else(|| account.clone()),
+            account,
+            use_path_style,
+            container,
+            dry_run: self.dry_run,
+            lenient_headers: self.lenient_headers,
+            retry_config: self.retry_config,
+            client_options: self.client_options,
+            service: storage_url,
+            dfs_service: dfs_url,
+            credentials: auth,
+            account_key: self.access_key,
+            request_observer: self.request_observer.map(client::RequestObserver),
+            refresh_on_auth_failure: self.refresh_on_auth_failure.unwrap_or(true),
+            idempotency_key_header,
+            default_access_tier: self.default_access_tier,
+            max_response_body_size: self.max_response_body_size,
+            default_metadata: client::metadata_headers(&self.default_metadata)?,
+            copy_timeout: self.copy_timeout.unwrap_or(DEFAULT_COPY_TIMEOUT),
+        };
+
+        let client = Arc::new(client::AzureClient::new(config)?);
+
+        if self.connection_warmup {
+            match tokio::runtime::Handle::try_current() {
+                Ok(handle) => {
+                    let client = Arc::clone(&client);
+                    handle.spawn(async move {
+                        if let Err(e) = client.warm_up().await {
+                            warn!("Failed to warm up connection: {}", e);
+                        }
+                    });
+                }
+                Err(_) => warn!("Unable to warm up connection outside a tokio runtime"),
+            }
+        }
+
+        Ok(MicrosoftAzure {
+            client,
+            buffer_budget,
+            checksum_algorithm: self.checksum_algorithm,
+            multipart_drop_behavior: self.multipart_drop_behavior,
+            abort_multipart_on_error: self.abort_multipart_on_error.unwrap_or(true),
+            block_size,
+            upload_concurrency,
+            versioning: self.versioning,
+            hierarchical_namespace: self.hierarchical_namespace.unwrap_or_default(),
+            append_create: self.append_create.unwrap_or(true),
+        })
+    }
+}
+
+/// Collects the pages of a listing with a delimiter into a single [`ListResult`]
+async fn collect_list_result(
+    mut stream: BoxStream<'_, Result<ListResult>>,
+) -> Result<ListResult> {
+    let mut common_prefixes = BTreeSet::new();
+    let mut objects = Vec::new();
+
+    while let Some(result) = stream.next().await {
+        let response = result?;
+        common_prefixes.extend(response.common_prefixes.into_iter());
+        objects.extend(response.objects.into_iter());
+    }
+
+    Ok(ListResult {
+        common_prefixes: common_prefixes.into_iter().collect(),
+        objects,
+    })
+}
+
+/// Returns the [`GetResult`] streaming the body of `response`, applying `options`
+///
+/// If checksums are validated, the stream fails after its last chunk if the data
+/// does not match the `Content-MD5` of `response`
+fn get_result(
+    location: &Path,
+    options: GetOptions,
+    response: reqwest::Response,
+    config: &client::AzureConfig,
+) -> GetResult {
+    let total = response.content_length();
+    let meta = get_result_meta(location, response.headers(), config.lenient_headers);
+    let expected_md5 = client::expected_md5(config, &response);
+    let mut stream = response
+        .bytes_stream()
+        .map_err(|source| crate::Error::Generic {
+            store: "MicrosoftAzure",
+            source: Box::new(source),
+        })
+        .boxed();
+    if let Some(expected) = expected_md5 {
+        stream = verify_md5_stream(stream, location.clone(), expected);
+    }
+
+    let mut result = GetResult::Stream(stream, meta);
+    if let Some(progress) = options.progress {
+        result = result.with_progress(progress, total);
+    }
+    if let Some(size) = options.read_buffer_size {
+        result = result.with_read_buffer_size(size);
+    }
+    if let Some(cancel) = options.cancel {
+        result = result.with_cancel(cancel, location);
+    }
+    result
+}
+
+/// Returns `stream`, followed by an error if the MD5 of its data is not `expected`
+fn verify_md5_stream(
+    stream: BoxStream<'static, Result<Bytes>>,
+    location: Path,
+    expected: String,
+) -> BoxStream<'static, Result<Bytes>> {
+    let state = (stream, Some(Md5::new()));
+    futures::stream::unfold(state, move |(mut stream, hasher)| {
+        let location = location.clone();
+        let expected = expected.clone();
+        async move {
+            let mut hasher = hasher?;
+            match stream.next().await {
+                Some(Ok(bytes)) => {
+                    hasher.update(&bytes);
+                    Some((Ok(bytes), (stream, Some(hasher))))
+                }
+                Some(Err(e)) => Some((Err(e), (stream, None))),
+                None => {
+                    let actual = base64::encode(hasher.finalize());
+                    if actual == expected {
+                        return None;
+                    }
+                    let err = client::Error::ChecksumMismatch {
+                        path: location.to_string(),
+                        expected,
+                        actual,
+                    };
+                    Some((Err(err.into()), (stream, None)))
+                }
+            }
+        }
+    })
+    .boxed()
+}
+
+/// Returns the metadata of the object returned by a get request, or `None` if its
+/// headers are missing or invalid
+///
+/// The size of a range request is the total size given by its `Content-Range`
+fn get_result_meta(
+    location: &Path,
+    headers: &HeaderMap,
+    lenient: bool,
+) -> Option<GetResultMeta> {
+    let mut object = object_meta(location, headers, lenient).ok()?;
+    let range = match headers.get(CONTENT_RANGE) {
+        Some(content_range) => {
+            // Content-Range is of the form `bytes {start}-{end}/{total}`
+            let (range, total) = content_range
+                .to_str()
+                .ok()?
+                .strip_prefix("bytes ")?
+                .split_once('/')?;
+            let (start, end) = range.split_once('-')?;
+            let end: usize = end.parse().ok()?;
+            object.size = total.parse().ok()?;
+            Some(start.parse().ok()?..end + 1)
+        }
+        None => None,
+    };
+    Some(GetResultMeta { object, range })
+}
+
+/// Returns true if `account` is a valid storage account name, otherwise the URL
+/// derived from it would refer to the wrong host
+///
+/// <https://learn.microsoft.com/en-us/azure/azure-resource-manager/management/resource-name-rules#microsoftstorage>
+fn is_valid_account_name(account: &str) -> bool {
+    (3..=24).contains(&account.len())
+        && account
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit())
+}
+
+/// Parses the contents of the environment variable `env_name` as a URL
+/// if present, otherwise falls back to default_url
+fn url_from_env(env_name: &str, default_url: &str) -> Result<Url> {
+    let url = match std::env::var(env_name) {
+        Ok(env_value) => {
+            Url::parse(&env_value).context(UnableToParseEmulatorUrlSnafu {
+                env_name,
+                env_value,
+            })?
+        }
+        Err(_) => Url::parse(default_url).expect("Failed to parse default URL"),
+    };
+    Ok(url)
+}
+
+/// Returns the `dfs` endpoint corresponding to the `blob` endpoint `url`
+fn dfs_url_from_blob(url: &Url) -> Url {
+    let mut dfs_url = url.clone();
+    if let Some(host) = url.host_str() {
+        if host.contains(".blob.") {
+            // Replacing a label of a valid host cannot produce an invalid one
+            dfs_url
+                .set_host(Some(&host.replacen(".blob.", ".dfs.", 1)))
+                .unwrap();
+        }
+    }
+    dfs_url
+}
+
+/// Strips a leading UTF-8 byte order mark and surrounding whitespace, such as a
+/// trailing newline, from a credential read from a file
+fn trim_credential(value: impl Into<String>) -> String {
+    let value = value.into();
+    let trimmed = value.trim_start_matches('\u{feff}').trim();
+    match trimmed.len() == value.len() {
+        true => value,
+        false => trimmed.to_string(),
+    }
+}
+
+/// Splits a SAS token, with or without a leading `?`, into its decoded query pairs
+///
+/// Each key and value is decoded separately, so that percent-encoded delimiters
+/// within a value, such as in `sig`, are preserved
+fn split_sas(sas: &str) -> Result<Vec<(String, String)>, Error> {
+    let decode = |s: &str| {
+        percent_decode_str(s)
+            .decode_utf8()
+            .map(String::from)
+            .context(DecodeSasKeySnafu {})
+    };
+    let kv_str_pairs = sas
+        .trim()
+        .trim_start_matches('?')
+        .split('&')
+        .filter(|s| !s.chars().all(char::is_whitespace));
+    let mut pairs = Vec::new();
+    for kv_pair_str in kv_str_pairs {
+        let (k, v) = kv_pair_str
+            .trim()
+            .split_once('=')
+            .ok_or(Error::MissingSasComponent {})?;
+        pairs.push((decode(k)?, decode(v)?))
+    }
+    Ok(pairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::mock_server::MockServer;
+    use crate::tests::{
+        copy_if_not_exists, list_uses_directories_correctly, list_with_delimiter,
+        list_with_offset, put_get_delete_list, put_get_delete_list_opts, rename_and_copy,
+        stream_get,
+    };
+    use hyper::header::{
+        AUTHORIZATION, ETAG, IF_MATCH, IF_MODIFIED_SINCE, IF_NONE_MATCH,
+        IF_UNMODIFIED_SINCE, RANGE,
+    };
+    use hyper::{Body, Response, StatusCode};
+    use reqwest::Method;
+    use std::collections::HashMap;
+    use std::env;
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    // Helper macro to skip tests if TEST_INTEGRATION and the Azure environment
+    // variables are not set.
+    macro_rules! maybe_skip_integration {
+        () => {{
+            dotenv::dotenv().ok();
+
+            let use_emulator = std::env::var("AZURE_USE_EMULATOR").is_ok();
+
+            let mut required_vars = vec!["OBJECT_STORE_BUCKET"];
+            if !use_emulator {
+                required_vars.push("AZURE_STORAGE_ACCOUNT");
+                required_vars.push("AZURE_STORAGE_ACCESS_KEY");
+            }
+            let unset_vars: Vec<_> = required_vars
+                .iter()
+                .filter_map(|&name| match env::var(name) {
+                    Ok(_) => None,
+                    Err(_) => Some(name),
+                })
+                .collect();
+            let unset_var_names = unset_vars.join(", ");
+
+            let force = std::env::var("TEST_INTEGRATION");
+
+            if force.is_ok() && !unset_var_names.is_empty() {
+                panic!(
+                    "TEST_INTEGRATION is set, \
+                        but variable(s) {} need to be set",
+                    unset_var_names
+                )
+            } else if force.is_err() {
+                eprintln!(
+                    "skipping Azure integration test - set {}TEST_INTEGRATION to run",
+                    if unset_var_names.is_empty() {
+                        String::new()
+                    } else {
+                        format!("{} and ", unset_var_names)
+                    }
+                );
+                return;
+            } else {
+                let builder = MicrosoftAzureBuilder::new()
+                    .with_container_name(
+                        env::var("OBJECT_STORE_BUCKET")
+                            .expect("already checked OBJECT_STORE_BUCKET"),
+                    )
+                    .with_use_emulator(use_emulator);
+                if !use_emulator {
+                    builder
+                        .with_account(
+                            env::var("AZURE_STORAGE_ACCOUNT").unwrap_or_default(),
+                        )
+                        .with_access_key(
+                            env::var("AZURE_STORAGE_ACCESS_KEY").unwrap_or_default(),
+                        )
+                } else {
+                    builder
+                }
+            }
+        }};
+    }
+
+    /// Returns a builder for the account `account` and container `container` of `mock`,
+    /// authorized with the emulator key
+    fn mock_builder(mock: &MockServer) -> MicrosoftAzureBuilder {
+        MicrosoftAzureBuilder::new()
+            .with_account("account")
+            .with_container_name("container")
+            .with_access_key(EMULATOR_ACCOUNT_KEY)
+            .with_endpoint(mock.url())
+            .with_use_path_style(true)
+            .with_allow_http(true)
+    }
+
+    #[tokio::test]
+    async fn azure_blob_test() {
+        let use_emulator = env::var("AZURE_USE_EMULATOR").is_ok();
+        let integration = maybe_skip_integration!().build().unwrap();
+        // Azurite doesn't support listing with spaces - https://github.com/localstack/localstack/issues/6328
+        put_get_delete_list_opts(&integration, use_emulator).await;
+        list_uses_directories_correctly(&integration).await;
+        list_with_delimiter(&integration).await;
+        list_with_offset(&integration).await;
+        rename_and_copy(&integration).await;
+        copy_if_not_exists(&integration).await;
+        stream_get(&integration).await;
+        set_properties(&integration).await;
+        signed_url(&integration).await;
+    }
+
+    async fn signed_url(integration: &MicrosoftAzure) {
+        let location = Path::from("signed_url");
+        integration
+            .put(&location, Bytes::from("data"))
+            .await
+            .unwrap();
+
+        let url = integration
+            .signed_url(&location, Duration::from_secs(60), "r")
+            .await
+            .unwrap();
+        let response = reqwest::get(url).await.unwrap().error_for_status().unwrap();
+        assert_eq!(response.bytes().await.unwrap(), "data");
+
+        integration.delete(&location).await.unwrap();
+    }
+
+    async fn set_properties(integration: &MicrosoftAzure) {
+        let location = Path::from("set_properties");
+        integration
+            .put(&location, Bytes::from("data"))
+            .await
+            .unwrap();
+
+        let properties = BlobProperties {
+            content_type: Some("text/plain".to_string()),
+            cache_control: Some("no-cache".to_string()),
+            ..Default::default()
+        };
+        integration
+            .set_properties(&location, properties)
+            .await
+            .unwrap();
+
+        let metadata = HashMap::from([("owner".to_string(), "test".to_string())]);
+        integration.set_metadata(&location, metadata).await.unwrap();
+
+        let meta = integration.head(&location).await.unwrap();
+        assert_eq!(meta.size, 4);
+
+        integration.delete(&location).await.unwrap();
+        let err = integration
+            .set_properties(&location, BlobProperties::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, crate::Error::NotFound { .. }), "{}", err);
+        assert_eq!(error_code(&err), Some("BlobNotFound"));
+    }
+
+    // test for running integration test against actual blob service with service principal
+    // credentials. To run make sure all environment variables are set and remove the ignore
+    #[tokio::test]
+    #[ignore]
+    async fn azure_blob_test_sp() {
+        dotenv::dotenv().ok();
+        let builder = MicrosoftAzureBuilder::new()
+            .with_account(
+                env::var("AZURE_STORAGE_ACCOUNT")
+                    .expect("must be set AZURE_STORAGE_ACCOUNT"),
+            )
+            .with_container_name(
+                env::var("OBJECT_STORE_BUCKET").expect("must be set OBJECT_STORE_BUCKET"),
+            )
+            .with_access_key(
+                env::var("AZURE_STORAGE_ACCESS_KEY")
+                    .expect("must be set AZURE_STORAGE_CLIENT_ID"),
+            );
+        let integration = builder.build().unwrap();
+
+        put_get_delete_list(&integration).await;
+        list_uses_directories_correctly(&integration).await;
+        list_with_delimiter(&integration).await;
+        rename_and_copy(&integration).await;
+        copy_if_not_exists(&integration).await;
+        stream_get(&integration).await;
+    }
+
+    #[test]
+    fn azure_blob_test_urls() {
+        let mut builder = MicrosoftAzureBuilder::new();
+        builder
+            .parse_url("abfss://file_system@account.dfs.core.windows.net/")
+            .unwrap();
+        assert_eq!(builder.account_name, Some("account".to_string()));
+        assert_eq!(builder.container_name, Some("file_system".to_string()));
+        assert_eq!(builder.hierarchical_namespace, Some(true));
+
+        let mut builder = MicrosoftAzureBuilder::new();
+        builder.parse_url("abfs://container/path").unwrap();
+        assert_eq!(builder.container_name, Some("container".to_string()));
+        assert_eq!(builder.hierarchical_namespace, None);
+
+        let mut builder = MicrosoftAzureBuilder::new();
+        builder.parse_url("az://container").unwrap();
+        assert_eq!(builder.container_name, Some("container".to_string()));
+
+        let mut builder = MicrosoftAzureBuilder::new();
+        builder.parse_url("az://container/path").unwrap();
+        assert_eq!(builder.container_name, Some("container".to_string()));
+
+        let mut builder = MicrosoftAzureBuilder::new();
+        builder
+            .parse_url("https://account.dfs.core.windows.net/")
+            .unwrap();
+        assert_eq!(builder.account_name, Some("account".to_string()));
+        assert_eq!(builder.hierarchical_namespace, Some(true));
+
+        // Unless explicitly disabled
+        let mut builder = MicrosoftAzureBuilder::new().with_hierarchical_namespace(false);
+        builder
+            .parse_url("https://account.dfs.core.windows.net/")
+            .unwrap();
+        assert_eq!(builder.hierarchical_namespace, Some(false));
+
+        let mut builder = MicrosoftAzureBuilder::new();
+        builder
+            .parse_url("https://account.blob.core.windows.net/")
+            .unwrap();
+        assert_eq!(builder.account_name, Some("account".to_string()));
+        assert_eq!(builder.hierarchical_namespace, None);
+
+        let mut builder = MicrosoftAzureBuilder::new();
+        let path = builder
+            .parse_url("https://account.blob.core.windows.net/container/a/b%20c")
+            .unwrap();
+        assert_eq!(builder.account_name, Some("account".to_string()));
+        assert_eq!(builder.container_name, Some("container".to_string()));
+        assert_eq!(path, Path::from_iter(["a", "b c"]));
+
+        let mut builder = MicrosoftAzureBuilder::new();
+        let path = builder
+            .parse_url("abfss://file_system@account.dfs.core.windows.net/a/b")
+            .unwrap();
+        assert_eq!(path, Path::from("a/b"));
+
+        let mut builder = MicrosoftAzureBuilder::new();
+        let path = builder.parse_url("az://container/a/b").unwrap();
+        assert_eq!(path, Path::from("a/b"));
+
+        let mut builder = MicrosoftAzureBuilder::new();
+        let path = builder.parse_url("az://container").unwrap();
+        assert_eq!(path, Path::default());
+
+        let err_cases = [
+            "mailto://account.blob.core.windows.net/",
+            "az://blob.mydomain/",
+            "abfs://container.foo/path",
+            "abfss://file_system@account.foo.dfs.core.windows.net/",
+            "abfss://file_system.bar@account.dfs.core.windows.net/",
+            "https://blob.mydomain/",
+            "https://blob.foo.dfs.core.windows.net/",
+        ];
+        let mut builder = MicrosoftAzureBuilder::new();
+        for case in err_cases {
+            builder.parse_url(case).unwrap_err();
+        }
+    }
+
+    #[test]
+    fn azure_invalid_account_name() {
+        for account in ["my.account", "MyAccount", "ab", "a".repeat(25).as_str()] {
+            let err = MicrosoftAzureBuilder::new()
+                .with_account(account)
+                .with_container_name("container")
+                .with_access_key(EMULATOR_ACCOUNT_KEY)
+                .build()
+                .unwrap_err()
+                .to_string();
+            assert!(err.contains(&format!("'{}'", account)), "{}", err);
+        }
+
+        MicrosoftAzureBuilder::new()
+            .with_account("account123")
+            .with_container_name("container")
+            .with_access_key(EMULATOR_ACCOUNT_KEY)
+            .build()
+            .unwrap();
+    }
+
+    #[test]
+    fn azure_test_config_from_map() {
+        let azure_client_id = "object_store:fake_access_key_id";
+        let azure_storage_account_name = "object_store:fake_secret_key";
+        let azure_storage_token = "object_store:fake_default_region";
+        let options = HashMap::from([
+            ("azure_client_id", azure_client_id),
+            ("azure_storage_account_name", azure_storage_account_name),
+            ("azure_storage_token", azure_storage_token),
+            ("azure_storage_endpoint", "https://gateway.example.com"),
+            ("azure_storage_use_path_style", "true"),
+            ("azure_oauth_scope", "https://storage.azure.us/.default"),
+            ("azure_storage_signing_account", "signing"),
+            ("azure_storage_token_cache_path", "/tmp/token.json"),
+            ("azure_use_managed_identity", "true"),
+            ("azure_msi_object_id", "object"),
+            ("azure_msi_res_id", "resource"),
+            ("azure_msi_endpoint", "http://localhost/token"),
+        ]);
+
+        let builder = MicrosoftAzureBuilder::new()
+            .try_with_options(options)
+            .unwrap();
+        assert_eq!(builder.client_id.unwrap(), azure_client_id);
+        assert_eq!(builder.account_name.unwrap(), azure_storage_account_name);
+        assert_eq!(builder.bearer_token.unwrap(), azure_storage_token);
+        assert_eq!(builder.endpoint.unwrap(), "https://gateway.example.com");
+        assert!(builder.use_path_style);
+        assert_eq!(
+            builder.oauth_scope.unwrap(),
+            "https://storage.azure.us/.default"
+        );
+        assert_eq!(builder.signing_account.unwrap(), "signing");
+        assert_eq!(
+            builder.token_cache_path.unwrap(),
+            PathBuf::from("/tmp/token.json")
+        );
+        assert!(builder.use_managed_identity);
+        assert_eq!(builder.object_id.unwrap(), "object");
+        assert_eq!(builder.msi_resource_id.unwrap(), "resource");
+        assert_eq!(builder.msi_endpoint.unwrap(), "http://localhost/token");
+    }
+
+    #[test]
+    fn azure_test_config_from_typed_map() {
+        let azure_client_id = "object_store:fake_access_key_id".to_string();
+        let azure_storage_account_name = "object_store:fake_secret_key".to_string();
+        let azure_storage_token = "object_store:fake_default_region".to_string();
+        let options = HashMap::from([
+            (AzureConfigKey::ClientId, azure_client_id.clone()),
+            (
+                AzureConfigKey::AccountName,
+                azure_storage_account_name.clone(),
+            ),
+            (AzureConfigKey::Token, azure_storage_token.clone()),
+        ]);
+
+        let builder = MicrosoftAzureBuilder::new()
+            .try_with_options(&options)
+            .unwrap();
+        assert_eq!(builder.client_id.unwrap(), azure_client_id);
+        assert_eq!(builder.account_name.unwrap(), azure_storage_account_name);
+        assert_eq!(builder.bearer_token.unwrap(), azure_storage_token);
+    }
+
+    #[test]
+    fn azure_test_config_fallible_options() {
+        let azure_client_id = "object_store:fake_access_key_id".to_string();
+        let azure_storage_token = "object_store:fake_default_region".to_string();
+        let options = HashMap::from([
+            ("azure_client_id", azure_client_id),
+            ("invalid-key", azure_storage_token),
+        ]);
+
+        let builder = MicrosoftAzureBuilder::new().try_with_options(&options);
+        assert!(builder.is_err());
+    }
+
+    #[tokio::test]
+    async fn azure_dry_run() {
+        let mock = MockServer::new();
+
+        let integration = mock_builder(&mock).with_dry_run(true).build().unwrap();
+
+        // None of these should issue a request
+        let a = Path::from("a");
+        let b = Path::from("b");
+        integration.put(&a, Bytes::from("data")).await.unwrap();
+        integration.copy(&a, &b).await.unwrap();
+        integration.copy_if_not_exists(&a, &b).await.unwrap();
+        integration.rename(&a, &b).await.unwrap();
+        integration.delete(&b).await.unwrap();
+
+        let (_, mut writer) = integration.put_multipart(&a).await.unwrap();
+        writer.write_all(b"data").await.unwrap();
+        writer.shutdown().await.unwrap();
+        integration
+            .abort_multipart(&a, &String::new())
+            .await
+            .unwrap();
+
+        // Nor should discarding the blocks of a writer dropped mid-upload
+        let integration = mock_builder(&mock)
+            .with_dry_run(true)
+            .with_multipart_drop_behavior(MultipartDropBehavior::Abort)
+            .build()
+            .unwrap();
+        let (_, mut writer) = integration.put_multipart(&a).await.unwrap();
+        writer
+            .write_all(&vec![0; DEFAULT_MIN_PART_SIZE])
+            .await
+            .unwrap();
+        writer.flush().await.unwrap();
+        drop(writer);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(mock.requests(), 0);
+
+        // Reads are still performed
+        mock.push_fn(|req| {
+            assert_eq!(req.method(), Method::GET);
+            assert_eq!(req.uri().path(), "/account/container/a");
+            Response::builder()
+                .header(LAST_MODIFIED, "Thu, 01 Jan 1970 00:00:00 GMT")
+                .body(Body::from("data"))
+                .unwrap()
+        });
+        let bytes = integration.get(&a).await.unwrap().bytes().await.unwrap();
+        assert_eq!(bytes, "data");
+        assert_eq!(mock.requests(), 1);
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_multipart_checksum() {
+        let integration = MicrosoftAzureBuilder::new()
+            .with_account("account")
+            .with_container_name("container")
+            .with_access_key(EMULATOR_ACCOUNT_KEY)
+            .with_dry_run(true)
+            .with_checksum_algorithm(ChecksumAlgorithm::Sha256)
+            .build()
+            .unwrap();
+
+        let mut writer = integration.put_multipart_writer(&Path::from("a"));
+        writer.write_all(b"hello ").await.unwrap();
+        writer.write_all(b"world").await.unwrap();
+        let result = writer.finish().await.unwrap();
+
+        let checksum = result.checksum.unwrap();
+        assert_eq!(checksum.algorithm, ChecksumAlgorithm::Sha256);
+        assert_eq!(
+            checksum.to_hex(),
+            "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+        );
+
+        let integration = MicrosoftAzureBuilder::new()
+            .with_account("account")
+            .with_container_name("container")
+            .with_access_key(EMULATOR_ACCOUNT_KEY)
+            .with_dry_run(true)
+            .with_checksum_algorithm(ChecksumAlgorithm::Md5)
+            .build()
+            .unwrap();
+
+        let mut writer = integration.put_multipart_writer(&Path::from("a"));
+        writer.write_all(b"hello world").await.unwrap();
+        let checksum = writer.finish().await.unwrap().checksum.unwrap();
+        assert_eq!(checksum.algorithm, ChecksumAlgorithm::Md5);
+        assert_eq!(checksum.to_hex(), "5eb63bbbe01eeed093cb22bb8f5acdc3");
+
+        // No checksum unless configured
+        let integration = MicrosoftAzureBuilder::new()
+            .with_account("account")
+            .with_container_name("container")
+            .with_access_key(EMULATOR_ACCOUNT_KEY)
+            .with_dry_run(true)
+            .build()
+            .unwrap();
+        let mut writer = integration.put_multipart_writer(&Path::from("a"));
+        writer.write_all(b"hello world").await.unwrap();
+        assert_eq!(writer.finish().await.unwrap().checksum, None);
+    }
+
+    #[tokio::test]
+    async fn azure_path_style_endpoint() {
+        let mock = MockServer::new();
+
+        let integration = mock_builder(&mock).build().unwrap();
+
+        mock.push_fn(|req| {
+            assert_eq!(req.uri().path(), "/account/container/blob");
+            let auth = req.headers()[AUTHORIZATION].to_str().unwrap();
+            assert!(auth.starts_with("SharedKey account:"), "{}", auth);
+            Response::new(Body::empty())
+        });
+        integration
+            .put(&Path::from("blob"), Bytes::from("data"))
+            .await
+            .unwrap();
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_retry_block_list() {
+        let mock = MockServer::new();
+
+        let retry = RetryConfig {
+            backoff: Default::default(),
+            max_retries: 2,
+            retry_timeout: std::time::Duration::from_secs(1000),
+            max_dns_retries: 0,
+            retryable_statuses: vec![429],
+        };
+        let options =
+            ClientOptions::new().with_timeout(std::time::Duration::from_millis(100));
+        let integration = mock_builder(&mock)
+            .with_client_options(options)
+            .with_allow_http(true)
+            .with_retry(retry)
+            .build()
+            .unwrap();
+
+        let bodies = Arc::new(parking_lot::Mutex::new(vec![]));
+
+        // Stage a single block
+        mock.push(Response::new(Body::empty()));
+
+        // The first commit times out, and is retried
+        for delay in [200, 0] {
+            let bodies = Arc::clone(&bodies);
+            mock.push_async_fn(move |req| async move {
+                let query = req.uri().query().unwrap_or_default().to_string();
+                assert!(query.contains("comp=blocklist"), "{}", query);
+                let body = hyper::body::to_bytes(req).await.unwrap();
+                bodies.lock().push(body);
+                tokio::time::sleep(std::time::Duration::from_millis(delay)).await;
+                Response::new(Body::empty())
+            });
+        }
+
+        let location = Path::from("a");
+        let mut writer = integration.put_multipart_writer(&location);
+        writer.write_all(b"hello").await.unwrap();
+        writer.finish().await.unwrap();
+
+        let sent = std::mem::take(&mut *bodies.lock());
+        assert_eq!(sent.len(), 2);
+        let first = std::str::from_utf8(&sent[0]).unwrap();
+        assert!(first.contains("<Uncommitted>"), "{}", first);
+        let second = std::str::from_utf8(&sent[1]).unwrap();
+        assert!(second.contains("<Latest>"), "{}", second);
+
+        // As are server errors
+        mock.push(Response::new(Body::empty()));
+        for status in [StatusCode::SERVICE_UNAVAILABLE, StatusCode::OK] {
+            let bodies = Arc::clone(&bodies);
+            mock.push_async_fn(move |req| async move {
+                let body = hyper::body::to_bytes(req).await.unwrap();
+                bodies.lock().push(body);
+                Response::builder()
+                    .status(status)
+                    .body(Body::empty())
+                    .unwrap()
+            });
+        }
+
+        let mut writer = integration.put_multipart_writer(&location);
+        writer.write_all(b"hello").await.unwrap();
+        writer.finish().await.unwrap();
+
+        let sent = std::mem::take(&mut *bodies.lock());
+        assert_eq!(sent.len(), 2);
+        let second = std::str::from_utf8(&sent[1]).unwrap();
+        assert!(second.contains("<Latest>"), "{}", second);
+
+        // And retryable statuses, after the delay of any Retry-After
+        mock.push(Response::new(Body::empty()));
+        mock.push(
+            Response::builder()
+                .status(StatusCode::TOO_MANY_REQUESTS)
+                .header("Retry-After", "0")
+                .body(Body::empty())
+                .unwrap(),
+        );
+        mock.push(Response::new(Body::empty()));
+
+        let mut writer = integration.put_multipart_writer(&location);
+        writer.write_all(b"hello").await.unwrap();
+        writer.finish().await.unwrap();
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_restage_block_list() {
+        let mock = MockServer::new();
+
+        let integration = mock_builder(&mock).with_block_size(4).build().unwrap();
+
+        // Stage two blocks, the second of which is then discarded
+        let staged = Arc::new(parking_lot::Mutex::new(vec![]));
+        for _ in 0..2 {
+            let staged = Arc::clone(&staged);
+            mock.push_async_fn(move |req| async move {
+                let query = req.uri().query().unwrap().to_string();
+                let body = hyper::body::to_bytes(req).await.unwrap();
+                staged.lock().push((query, body));
+                Response::new(Body::empty())
+            });
+        }
+        mock.push(
+            Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(
+                    "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+                    <Error><Code>InvalidBlockList</Code></Error>",
+                ))
+                .unwrap(),
+        );
+        let first = base64::encode(block_content_id(0));
+        let block_list = format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+            <BlockList><UncommittedBlocks>\
+            <Block><Name>{}</Name><Size>4</Size></Block>\
+            </UncommittedBlocks></BlockList>",
+            first
+        );
+        mock.push_fn(move |req| {
+            let query = req.uri().query().unwrap();
+            assert!(query.contains("blocklisttype=uncommitted"), "{}", query);
+            Response::new(Body::from(block_list))
+        });
+
+        // Only the discarded block is staged again, before committing again
+        let second = base64::encode(block_content_id(1));
+        let expected = format!("blockid={}", second.replace('=', "%3D"));
+        mock.push_async_fn(move |req| async move {
+            let query = req.uri().query().unwrap();
+            assert!(query.ends_with(&expected), "{}", query);
+            let body = hyper::body::to_bytes(req).await.unwrap();
+            assert_eq!(body, "data");
+            Response::new(Body::empty())
+        });
+        mock.push_fn(|req| {
+            assert_eq!(req.uri().query(), Some("comp=blocklist"));
+            Response::new(Body::empty())
+        });
+
+        integration
+            .put_multipart_bytes(&Path::from("file"), Bytes::from("blobdata"))
+            .await
+            .unwrap();
+        assert_eq!(staged.lock().len(), 2);
+
+        // Blocks of a writer are not retained, and so cannot be staged again
+        mock.push(Response::new(Body::empty()));
+        mock.push(
+            Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(
+                    "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+                    <Error><Code>InvalidBlockList</Code></Error>",
+                ))
+                .unwrap(),
+        );
+        let mut writer = integration.put_multipart_writer(&Path::from("file"));
+        writer.write_all(b"data").await.unwrap();
+        let err = writer.finish().await.unwrap_err();
+        assert_eq!(error_code(&err), Some("InvalidBlockList"));
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_multipart_drop() {
+        let mock = MockServer::new();
+
+        let integration = mock_builder(&mock)
+            .with_multipart_drop_behavior(MultipartDropBehavior::Abort)
+            .build()
+            .unwrap();
+
+        // Stage a single block
+        mock.push(Response::new(Body::empty()));
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let sender = tx.clone();
+        mock.push_async_fn(move |req| async move {
+            assert_eq!(req.method(), Method::PUT);
+            assert_eq!(req.headers()[IF_NONE_MATCH], "*");
+            let body = hyper::body::to_bytes(req).await.unwrap();
+            sender.send(body).unwrap();
+            Response::builder()
+                .status(StatusCode::CREATED)
+                .header(ETAG, "\"empty\"")
+                .body(Body::empty())
+                .unwrap()
+        });
+        mock.push_fn(move |req| {
+            assert_eq!(req.method(), Method::DELETE);
+            assert_eq!(req.headers()[IF_MATCH], "\"empty\"");
+            tx.send(Bytes::new()).unwrap();
+            Response::builder()
+                .status(StatusCode::ACCEPTED)
+                .body(Body::empty())
+                .unwrap()
+        });
+
+        let location = Path::from("a");
+        let (_, mut writer) = integration.put_multipart(&location).await.unwrap();
+        writer
+            .write_all(&vec![0; DEFAULT_MIN_PART_SIZE])
+            .await
+            .unwrap();
+        writer.flush().await.unwrap();
+        drop(writer);
+
+        let body = rx.recv().await.unwrap();
+        let body = std::str::from_utf8(&body).unwrap();
+        assert!(!body.contains("<Uncommitted>"), "{}", body);
+        rx.recv().await.unwrap();
+
+        // Nothing is staged if dropped before a part is uploaded
+        let (_, mut writer) = integration.put_multipart(&location).await.unwrap();
+        writer.write_all(b"hello").await.unwrap();
+        drop(writer);
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_multipart_abort_on_error() {
+        let mock = MockServer::new();
+
+        let integration = mock_builder(&mock).build().unwrap();
+
+        let requests = Arc::new(parking_lot::Mutex::new(vec![]));
+        let push_failure = |mock: &MockServer| {
+            mock.push(
+                Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body(Body::empty())
+                    .unwrap(),
+            );
+            let captured = Arc::clone(&requests);
+            mock.push_fn(move |req| {
+                assert_eq!(req.headers()[IF_NONE_MATCH], "*");
+                captured.lock().push(req.method().clone());
+                Response::builder()
+                    .status(StatusCode::CREATED)
+                    .body(Body::empty())
+                    .unwrap()
+            });
+            let captured = Arc::clone(&requests);
+            mock.push_fn(move |req| {
+                captured.lock().push(req.method().clone());
+                Response::builder()
+                    .status(StatusCode::ACCEPTED)
+                    .body(Body::empty())
+                    .unwrap()
+            });
+        };
+
+        // The staged blocks are discarded before the error is returned
+        push_failure(&mock);
+        let location = Path::from("a");
+        let (_, mut writer) = integration.put_multipart(&location).await.unwrap();
+        writer
+            .write_all(&vec![0; DEFAULT_MIN_PART_SIZE])
+            .await
+            .unwrap();
+        writer.flush().await.unwrap_err();
+        assert_eq!(*requests.lock(), vec![Method::PUT, Method::DELETE]);
+        drop(writer);
+
+        requests.lock().clear();
+        push_failure(&mock);
+        let data = Bytes::from(vec![0; DEFAULT_MIN_PART_SIZE]);
+        integration
+            .put_multipart_bytes(&location, data)
+            .await
+            .unwrap_err();
+        assert_eq!(*requests.lock(), vec![Method::PUT, Method::DELETE]);
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_abort_multipart() {
+        let mock = MockServer::new();
+
+        let integration = mock_builder(&mock).build().unwrap();
+
+        // The blocks of a new blob are discarded by committing an empty block list,
+        // and the resulting empty blob deleted
+        mock.push_fn(|req| {
+            assert_eq!(req.method(), Method::PUT);
+            assert_eq!(req.uri().query(), Some("comp=blocklist"));
+            assert_eq!(req.headers()[IF_NONE_MATCH], "*");
+            Response::builder()
+                .status(StatusCode::CREATED)
+                .header(ETAG, "\"empty\"")
+                .body(Body::empty())
+                .unwrap()
+        });
+        mock.push_fn(|req| {
+            assert_eq!(req.method(), Method::DELETE);
+            assert_eq!(req.headers()[IF_MATCH], "\"empty\"");
+            Response::builder()
+                .status(StatusCode::ACCEPTED)
+                .body(Body::empty())
+                .unwrap()
+        });
+
+        let location = Path::from("file");
+        integration
+            .abort_multipart(&location, &String::new())
+            .await
+            .unwrap();
+
+        // An existing blob is left unchanged
+        mock.push(
+            Response::builder()
+                .status(StatusCode::CONFLICT)
+                .body(Body::from("<Error><Code>BlobAlreadyExists</Code></Error>"))
+                .unwrap(),
+        );
+        integration
+            .abort_multipart(&location, &String::new())
+            .await
+            .unwrap();
+
+        mock.push(
+            Response::builder()
+                .status(StatusCode::FORBIDDEN)
+                .body(Body::empty())
+                .unwrap(),
+        );
+        integration
+            .abort_multipart(&location, &String::new())
+            .await
+            .unwrap_err();
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_connection_warmup() {
+        let mock = MockServer::new();
+
+        let build = |warmup| {
+            mock_builder(&mock)
+                .with_connection_warmup(warmup)
+                .build()
+                .unwrap()
+        };
+
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        mock.push_fn(move |req| {
+            assert_eq!(req.method(), Method::HEAD);
+            assert_eq!(req.uri().path(), "/account/container");
+            assert_eq!(req.uri().query(), Some("restype=container"));
+            sender.send(()).unwrap();
+            Response::new(Body::empty())
+        });
+        let _integration = build(true);
+        receiver.await.unwrap();
+
+        // The response status is ignored
+        mock.push(
+            Response::builder()
+                .status(StatusCode::FORBIDDEN)
+                .body(Body::empty())
+                .unwrap(),
+        );
+        build(false).warm_up().await.unwrap();
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_request_observer() {
+        let mock = MockServer::new();
+
+        let timings = Arc::new(parking_lot::Mutex::new(vec![]));
+        let captured = Arc::clone(&timings);
+        let integration = mock_builder(&mock)
+            .with_retry(RetryConfig {
+                max_retries: 0,
+                ..Default::default()
+            })
+            .with_request_observer(Arc::new(move |timing: &RequestTiming| {
+                captured.lock().push(timing.clone())
+            }))
+            .build()
+            .unwrap();
+
+        mock.push_async_fn(|_| async {
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            Response::builder()
+                .header("x-ms-request-id", "request-1")
+                .body(Body::from("hello"))
+                .unwrap()
+        });
+        mock.push(
+            Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::empty())
+                .unwrap(),
+        );
+
+        let location = Path::from("file");
+        integration.get(&location).await.unwrap();
+        integration.delete(&location).await.unwrap_err();
+
+        let timings = std::mem::take(&mut *timings.lock());
+        assert_eq!(timings.len(), 2);
+        assert_eq!(timings[0].method, Method::GET);
+        assert_eq!(timings[0].path, "/account/container/file");
+        assert_eq!(timings[0].status, Some(StatusCode::OK));
+        assert_eq!(timings[0].request_id.as_deref(), Some("request-1"));
+        assert!(timings[0].duration >= std::time::Duration::from_millis(20));
+        assert_eq!(timings[1].method, Method::DELETE);
+        assert_eq!(timings[1].status, Some(StatusCode::NOT_FOUND));
+        assert_eq!(timings[1].request_id, None);
+
+        // Details of the connection of successful responses are reported
+        let remote_addr = timings[0].remote_addr.unwrap();
+        assert_eq!(mock.url(), format!("http://{}", remote_addr));
+        assert_eq!(timings[0].http_version, Some(reqwest::Version::HTTP_11));
+        assert!(timings[0].local_addr.is_some());
+        assert_eq!(timings[0].peer_certificate, None);
+        assert_eq!(timings[1].remote_addr, None);
+
+        // Including if a connection was reused
+        async fn local_addrs(
+            mock: &MockServer,
+            reuse: bool,
+        ) -> Vec<std::net::SocketAddr> {
+            let addrs = Arc::new(parking_lot::Mutex::new(vec![]));
+            let captured = Arc::clone(&addrs);
+            let integration = mock_builder(mock)
+                .with_client_options(ClientOptions::new().with_connection_reuse(reuse))
+                .with_allow_http(true)
+                .with_request_observer(Arc::new(move |timing: &RequestTiming| {
+                    captured.lock().push(timing.local_addr.unwrap())
+                }))
+                .build()
+                .unwrap();
+            for _ in 0..2 {
+                mock.push(Response::new(Body::empty()));
+                let location = Path::from("file");
+                integration.put(&location, Bytes::new()).await.unwrap();
+            }
+            let addrs = std::mem::take(&mut *addrs.lock());
+            addrs
+        }
+        let addrs = local_addrs(&mock, true).await;
+        assert_eq!(addrs[0], addrs[1]);
+        let addrs = local_addrs(&mock, false).await;
+        assert_ne!(addrs[0], addrs[1]);
+
+        mock.shutdown().await
+    }
+
+    #[test]
+    fn azure_auto_lowercase_container() {
+        let builder = MicrosoftAzureBuilder::new()
+            .with_account("account")
+            .with_container_name("MyContainer")
+            .with_access_key(EMULATOR_ACCOUNT_KEY);
+
+        let azure = builder.clone().build().unwrap();
+        assert_eq!(azure.client.config().container, "MyContainer");
+
+        let azure = builder.with_auto_lowercase_container(true).build().unwrap();
+        assert_eq!(azure.client.config().container, "mycontainer");
+
+        let azure = MicrosoftAzureBuilder::new()
+            .with_url("https://account.blob.core.windows.net/MyContainer")
+            .with_access_key(EMULATOR_ACCOUNT_KEY)
+            .with_auto_lowercase_container(true)
+            .build()
+            .unwrap();
+        assert_eq!(azure.client.config().container, "mycontainer");
+    }
+
+    #[test]
+    fn azure_lenient_headers() {
+        let location = Path::from("a");
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_RANGE, "bytes 0-9/10".parse().unwrap());
+
+        let err = object_meta(&location, &headers, false).unwrap_err();
+        assert!(matches!(err, Error::MissingLastModified), "{}", err);
+
+        let meta = object_meta(&location, &headers, true).unwrap();
+        assert_eq!(meta.last_modified, Utc.timestamp_opt(0, 0).unwrap());
+        assert_eq!(meta.size, 10);
+
+        headers.insert(
+            LAST_MODIFIED,
+            "Thu, 01 Jul 2021 10:45:02 GMT".parse().unwrap(),
+        );
+        let err = object_meta(&location, &headers, false).unwrap_err();
+        assert!(matches!(err, Error::MissingContentLength), "{}", err);
+
+        headers.insert(CONTENT_LENGTH, "5".parse().unwrap());
+        let meta = object_meta(&location, &headers, false).unwrap();
+        assert_eq!(meta.size, 5);
+        assert_eq!(meta.content_type, None);
+        assert_eq!(meta.e_tag, None);
+
+        headers.insert(ETAG, "\"0x8D93C7D4629C227\"".parse().unwrap());
+        let meta = object_meta(&location, &headers, false).unwrap();
+        assert_eq!(meta.e_tag.as_deref(), Some("\"0x8D93C7D4629C227\""));
+
+        headers.insert(CONTENT_TYPE, "text/plain".parse().unwrap());
+        let meta = object_meta(&location, &headers, false).unwrap();
+        assert_eq!(meta.content_type.as_deref(), Some("text/plain"));
+        assert_eq!(meta.last_modified.timestamp(), 1625136302);
+
+        headers.remove(CONTENT_LENGTH);
+        headers.remove(CONTENT_RANGE);
+        let err = object_meta(&location, &headers, true).unwrap_err();
+        assert!(matches!(err, Error::MissingContentLength), "{}", err);
+    }
+
+    fn list_response(blobs: &[(&str, &str)]) -> String {
+        let blobs: String = blobs
+            .iter()
+            .map(|(name, e_tag)| {
+                format!(
+                    "<Blob><Name>{}</Name><Properties>\
+                    <Last-Modified>Thu, 01 Jul 2021 10:45:02 GMT</Last-Modified>\
+                    <Etag>{}</Etag><Content-Length>5</Content-Length>\
+                    <Content-Type>text/plain</Content-Type></Properties></Blob>",
+                    name, e_tag
+                )
+            })
+            .collect();
+        format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?><EnumerationResults>\
+            <Blobs>{}</Blobs><NextMarker /></EnumerationResults>",
+            blobs
+        )
+    }
+
+    #[tokio::test]
+    async fn azure_list_diff() {
+        let mock = MockServer::new();
+
+        let integration = mock_builder(&mock).build().unwrap();
+
+        mock.push(Response::new(Body::from(list_response(&[
+            ("a", "0x1"),
+            ("b", "0x2"),
+            ("c", "0x3"),
+        ]))));
+        let snapshot = integration.list_snapshot(None).await.unwrap();
+        assert_eq!(snapshot.len(), 3);
+        assert_eq!(snapshot[&Path::from("b")], "0x2");
+
+        mock.push(Response::new(Body::from(list_response(&[
+            ("a", "0x1"),
+            ("c", "0x4"),
+            ("d", "0x5"),
+        ]))));
+        let diff = integration.list_diff(None, &snapshot).await.unwrap();
+        let locations = |objects: &[ObjectMeta]| -> Vec<_> {
+            objects.iter().map(|x| x.location.clone()).collect()
+        };
+        assert_eq!(locations(&diff.added), vec![Path::from("d")]);
+        assert_eq!(diff.added[0].content_type.as_deref(), Some("text/plain"));
+        assert_eq!(locations(&diff.modified), vec![Path::from("c")]);
+        assert_eq!(diff.removed, vec![Path::from("b")]);
+        assert_eq!(diff.snapshot.len(), 3);
+        assert_eq!(diff.snapshot[&Path::from("c")], "0x4");
+
+        mock.push(Response::new(Body::from(list_response(&[
+            ("a", "0x1"),
+            ("c", "0x4"),
+            ("d", "0x5"),
+        ]))));
+        let unchanged = integration.list_diff(None, &diff.snapshot).await.unwrap();
+        assert!(unchanged.added.is_empty());
+        assert!(unchanged.modified.is_empty());
+        assert!(unchanged.removed.is_empty());
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_put_empty() {
+        let mock = MockServer::new();
+
+        let integration = mock_builder(&mock).build().unwrap();
+
+        mock.push_async_fn(|req| async move {
+            assert_eq!(req.method(), Method::PUT);
+            assert_eq!(req.headers()[CONTENT_LENGTH], "0");
+            assert_eq!(req.headers()["x-ms-blob-type"], "BlockBlob");
+            let body = hyper::body::to_bytes(req).await.unwrap();
+            assert!(body.is_empty());
+            Response::builder()
+                .status(StatusCode::CREATED)
+                .body(Body::empty())
+                .unwrap()
+        });
+
+        integration
+            .put(&Path::from("a"), Bytes::new())
+            .await
+            .unwrap();
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_copy_opts() {
+        let mock = MockServer::new();
+
+        let integration = mock_builder(&mock).build().unwrap();
+
+        let from = Path::from("from");
+        let to = Path::from("to");
+        let since = Utc.with_ymd_and_hms(2023, 1, 2, 3, 4, 5).unwrap();
+        let options = CopyOptions {
+            source_if_match: Some("\"source\"".to_string()),
+            source_if_none_match: Some("\"stale\"".to_string()),
+            source_if_modified_since: Some(since),
+            if_not_exists: true,
+            only_if_source_newer: false,
+            idempotency_key: None,
+        };
+
+        mock.push_fn(|req| {
+            assert_eq!(req.method(), Method::PUT);
+            assert_eq!(req.uri().path(), "/account/container/to");
+            let headers = req.headers();
+            assert!(headers["x-ms-copy-source"]
+                .to_str()
+                .unwrap()
+                .ends_with("/account/container/from"));
+            assert_eq!(headers[IF_NONE_MATCH], "*");
+            assert_eq!(headers["x-ms-source-if-match"], "\"source\"");
+            assert_eq!(headers["x-ms-source-if-none-match"], "\"stale\"");
+            assert_eq!(
+                headers["x-ms-source-if-modified-since"],
+                "Mon, 02 Jan 2023 03:04:05 GMT"
+            );
+            Response::builder()
+                .status(StatusCode::ACCEPTED)
+                .body(Body::empty())
+                .unwrap()
+        });
+        integration
+            .copy_opts(&from, &to, options.clone())
+            .await
+            .unwrap();
+
+        mock.push(
+            Response::builder()
+                .status(StatusCode::PRECONDITION_FAILED)
+                .body(Body::empty())
+                .unwrap(),
+        );
+        let err = integration
+            .copy_opts(&from, &to, options)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, crate::Error::Precondition { .. }), "{}", err);
+
+        mock.push_fn(|req| {
+            let headers = req.headers();
+            assert!(!headers.contains_key(IF_NONE_MATCH));
+            assert!(!headers.contains_key("x-ms-source-if-match"));
+            Response::builder()
+                .status(StatusCode::ACCEPTED)
+                .body(Body::empty())
+                .unwrap()
+        });
+        integration.copy(&from, &to).await.unwrap();
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_access_key_fallback() {
+        let mock = MockServer::new();
+
+        let integration = MicrosoftAzureBuilder::new()
+            .with_account("account")
+            .with_container_name("container")
+            .with_access_keys(["cHJpbWFyeQ==", EMULATOR_ACCOUNT_KEY])
+            .with_endpoint(mock.url())
+            .with_use_path_style(true)
+            .with_allow_http(true)
+            .build()
+            .unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let forbidden = r#"<?xml version="1.0" encoding="utf-8"?>
+            <Error>
+                <Code>AuthenticationFailed</Code>
+                <Message>Server failed to authenticate the request.</Message>
+            </Error>"#;
+
+        let sender = tx.clone();
+        mock.push_fn(move |req| {
+            sender.send(req.headers()[AUTHORIZATION].clone()).unwrap();
+            Response::builder()
+                .status(StatusCode::FORBIDDEN)
+                .body(Body::from(forbidden))
+                .unwrap()
+        });
+        for _ in 0..2 {
+            let sender = tx.clone();
+            mock.push_fn(move |req| {
+                sender.send(req.headers()[AUTHORIZATION].clone()).unwrap();
+                Response::builder()
+                    .status(StatusCode::ACCEPTED)
+                    .body(Body::empty())
+                    .unwrap()
+            });
+        }
+
+        let location = Path::from("file");
+        integration.delete(&location).await.unwrap();
+        // The secondary key is used for subsequent requests
+        integration.delete(&location).await.unwrap();
+
+        let primary = rx.recv().await.unwrap();
+        let secondary = rx.recv().await.unwrap();
+        assert_ne!(primary, secondary);
+        assert_eq!(rx.recv().await.unwrap(), secondary);
+
+        // Other errors are not retried
+        mock.push(
+            Response::builder()
+                .status(StatusCode::FORBIDDEN)
+                .body(Body::from(
+                    "<Error><Code>AuthorizationPermissionMismatch</Code></Error>",
+                ))
+                .unwrap(),
+        );
+        let err = integration.delete(&location).await.unwrap_err();
+        assert!(
+            err.to_string().contains("AuthorizationPermissionMismatch"),
+            "{}",
+            err
+        );
+
+        // Nor are a 403 without an error code, even to a HEAD request
+        for method in [Method::DELETE, Method::HEAD] {
+            mock.push_fn(move |req| {
+                assert_eq!(req.method(), method);
+                Response::builder()
+                    .status(StatusCode::FORBIDDEN)
+                    .body(Body::empty())
+                    .unwrap()
+            });
+        }
+        integration.delete(&location).await.unwrap_err();
+        integration.head(&location).await.unwrap_err();
+
+        // A 401 to a HEAD request is retried with the other key
+        let sender = tx.clone();
+        mock.push_fn(move |req| {
+            assert_eq!(req.method(), Method::HEAD);
+            sender.send(req.headers()[AUTHORIZATION].clone()).unwrap();
+            Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .body(Body::empty())
+                .unwrap()
+        });
+        mock.push_fn(move |req| {
+            assert_eq!(req.method(), Method::HEAD);
+            tx.send(req.headers()[AUTHORIZATION].clone()).unwrap();
+            Response::builder()
+                .header(CONTENT_LENGTH, "0")
+                .header(LAST_MODIFIED, "Tue, 01 Aug 2023 00:00:00 GMT")
+                .body(Body::empty())
+                .unwrap()
+        });
+        integration.head(&location).await.unwrap();
+        assert_ne!(rx.recv().await.unwrap(), rx.recv().await.unwrap());
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_unsupported_version() {
+        let mock = MockServer::new();
+
+        let integration = mock_builder(&mock).build().unwrap();
+
+        let invalid_version = r#"<?xml version="1.0" encoding="utf-8"?>
+            <Error>
+                <Code>InvalidHeaderValue</Code>
+                <Message>The API version 2021-08-06 is not supported by Azurite.</Message>
+                <HeaderName>x-ms-version</HeaderName>
+                <HeaderValue>2021-08-06</HeaderValue>
+            </Error>"#;
+        for _ in 0..2 {
+            mock.push(
+                Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body(Body::from(invalid_version))
+                    .unwrap(),
+            );
+        }
+
+        let location = Path::from("file");
+        let err = integration.get(&location).await.err().unwrap();
+        assert!(matches!(err, crate::Error::Generic { .. }), "{}", err);
+        let message = err.to_string();
+        assert!(message.contains("x-ms-version '2021-08-06'"), "{}", message);
+        assert!(message.contains("--skipApiVersionCheck"), "{}", message);
+
+        let err = integration.delete(&location).await.unwrap_err();
+        assert_eq!(error_code(&err), Some("InvalidHeaderValue"));
+
+        // Other invalid headers are reported as is
+        mock.push(
+            Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(
+                    "<Error><Code>InvalidHeaderValue</Code>\
+                    <HeaderName>x-ms-blob-type</HeaderName></Error>",
+                ))
+                .unwrap(),
+        );
+        let err = integration.delete(&location).await.unwrap_err();
+        let message = err.to_string();
+        assert!(!message.contains("x-ms-version"), "{}", message);
+        assert!(message.contains("(x-ms-blob-type)"), "{}", message);
+
+        mock.shutdown().await
+    }
+
+    /// Responds to a batch request with a subresponse for each subrequest, which
+    /// succeeds unless its path is `missing`
+    async fn batch_response(req: hyper::Request<Body>) -> Response<Body> {
+        let content_type = req.headers()[CONTENT_TYPE].to_str().unwrap();
+        assert!(content_type.starts_with("multipart/mixed; boundary=batch_"));
+        let body = hyper::body::to_bytes(req).await.unwrap();
+        let body = std::str::from_utf8(&body).unwrap();
+        assert!(!body.contains("x-ms-version"), "{}", body);
+
+        let mut response = String::new();
+        let mut request_lines = body.lines().filter(|l| l.ends_with(" HTTP/1.1"));
+        let content_ids = body.lines().filter_map(|l| l.strip_prefix("Content-ID: "));
+        for content_id in content_ids {
+            let target = request_lines.next().unwrap().split(' ').nth(1).unwrap();
+            let status = match target.split('?').next().unwrap().ends_with("/missing") {
+                true => "404 The specified blob does not exist.\r\nx-ms-error-code: BlobNotFound",
+                false => "200 OK",
+            };
+            response.push_str(&format!(
+                "--batchresponse_1\r\nContent-Type: application/http\r\nContent-ID: {}\r\n\r\nHTTP/1.1 {}\r\n\r\n",
+                content_id, status
+            ));
+        }
+        response.push_str("--batchresponse_1--");
+
+        Response::builder()
+            .status(StatusCode::ACCEPTED)
+            .header(CONTENT_TYPE, "multipart/mixed; boundary=batchresponse_1")
+            .body(Body::from(response))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn azure_batch_set_tier() {
+        let mock = MockServer::new();
+
+        let integration = mock_builder(&mock).build().unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        for _ in 0..2 {
+            let tx = tx.clone();
+            mock.push_async_fn(move |req| {
+                assert_eq!(req.method(), Method::POST);
+                assert_eq!(
+                    req.uri().path_and_query().unwrap().as_str(),
+                    "/account/container?restype=container&comp=batch"
+                );
+                tx.send(()).unwrap();
+                batch_response(req)
+            });
+        }
+
+        let mut items: Vec<_> = (0..299)
+            .map(|i| (Path::from(format!("blob{}", i)), AccessTier::Cool))
+            .collect();
+        items.push((Path::from("missing"), AccessTier::Archive));
+
+        let results = integration.batch_set_tier(items).await.unwrap();
+        assert_eq!(results.len(), 300);
+        assert!(results[..299].iter().all(|r| r.is_ok()));
+        let err = results[299].as_ref().unwrap_err();
+        assert!(matches!(err, crate::Error::NotFound { .. }), "{}", err);
+        assert!(err.to_string().contains("BlobNotFound"), "{}", err);
+
+        // Split into batches of 256 subrequests
+        rx.recv().await.unwrap();
+        rx.recv().await.unwrap();
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_batch_delete() {
+        let mock = MockServer::new();
+
+        let integration = mock_builder(&mock).build().unwrap();
+
+        mock.push_async_fn(|req| async move {
+            let (parts, body) = req.into_parts();
+            let bytes = hyper::body::to_bytes(body).await.unwrap();
+            let text = std::str::from_utf8(&bytes).unwrap();
+            assert!(
+                text.contains("DELETE /account/container/a HTTP/1.1"),
+                "{}",
+                text
+            );
+            assert!(text.contains("x-ms-delete-snapshots: include"), "{}", text);
+            assert!(
+                text.contains("authorization: SharedKey account:"),
+                "{}",
+                text
+            );
+            batch_response(hyper::Request::from_parts(parts, Body::from(bytes))).await
+        });
+
+        let locations = vec![Path::from("a"), Path::from("missing"), Path::from("b")];
+        let results = integration.batch_delete(locations).await.unwrap();
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(crate::Error::NotFound { .. })));
+        assert!(results[2].is_ok());
+
+        // A failure of the batch as a whole is returned as an error
+        mock.push(
+            Response::builder()
+                .status(StatusCode::ACCEPTED)
+                .header(CONTENT_TYPE, "multipart/mixed; boundary=batchresponse_2")
+                .body(Body::from(
+                    "--batchresponse_2\r\nContent-Type: application/http\r\n\r\n\
+                    HTTP/1.1 403 Server failed to authenticate the request.\r\n\r\n\
+                    --batchresponse_2--",
+                ))
+                .unwrap(),
+        );
+        let err = integration
+            .batch_delete(vec![Path::from("a")])
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("Batch request failed with status 403"),
+            "{}",
+            err
+        );
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_delete_stream() {
+        let mock = MockServer::new();
+
+        let integration = mock_builder(&mock).build().unwrap();
+
+        mock.push_async_fn(batch_response);
+        mock.push_async_fn(batch_response);
+
+        let mut locations: Vec<_> = (0..299)
+            .map(|i| Ok(Path::from(format!("blob{}", i))))
+            .collect();
+        locations.push(Ok(Path::from("missing")));
+        locations.push(Err(crate::Error::NotImplemented));
+
+        let results: Vec<_> = integration
+            .delete_stream(futures::stream::iter(locations).boxed())
+            .collect()
+            .await;
+
+        // Errors of the input and of each blob are returned without stopping the others
+        assert_eq!(results.len(), 301);
+        let deleted: Vec<_> = results.iter().filter_map(|r| r.as_ref().ok()).collect();
+        assert_eq!(deleted.len(), 299);
+        assert_eq!(deleted[0], &Path::from("blob0"));
+        assert_eq!(deleted[298], &Path::from("blob298"));
+        let not_found = results
+            .iter()
+            .filter(|r| matches!(r, Err(crate::Error::NotFound { .. })))
+            .count();
+        assert_eq!(not_found, 1);
+        let input = results
+            .iter()
+            .filter(|r| matches!(r, Err(crate::Error::NotImplemented)))
+            .count();
+        assert_eq!(input, 1);
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_range_fallback() {
+        let mock = MockServer::new();
+
+        let integration = mock_builder(&mock).build().unwrap();
+
+        let full = |req: hyper::Request<Body>| {
+            assert!(req.headers().contains_key(RANGE));
+            Response::builder()
+                .header(CONTENT_LENGTH, "11")
+                .body(Body::from("hello world"))
+                .unwrap()
+        };
+        let partial = |expected: &'static str, body: &'static str| {
+            move |req: hyper::Request<Body>| {
+                assert_eq!(req.headers()[RANGE], expected);
+                Response::builder()
+                    .status(StatusCode::PARTIAL_CONTENT)
+                    .body(Body::from(body))
+                    .unwrap()
+            }
+        };
+
+        // A server ignoring the Range header is detected from the response, and every
+        // range sliced from the whole object it returns
+        mock.push_fn(full);
+        let location = Path::from("file");
+        let ranges = integration
+            .get_ranges(&location, &[0..5, 6..11, 9..20])
+            .await
+            .unwrap();
+        assert_eq!(ranges, vec!["hello", "world", "ld"]);
+
+        mock.push_fn(full);
+        let range = integration.get_range(&location, 6..11).await.unwrap();
+        assert_eq!(range, "world");
+
+        // Which is decided per request, rather than for the store
+        let far = 2 * OBJECT_STORE_COALESCE_DEFAULT;
+        mock.push_fn(partial("bytes=0-4", "hello"));
+        let range = integration.get_range(&location, 0..5).await.unwrap();
+        assert_eq!(range, "hello");
+
+        // Ranges are fetched concurrently, neither response being sent until both
+        // requests have been received
+        let barrier = Arc::new(tokio::sync::Barrier::new(2));
+        for _ in 0..2 {
+            let barrier = Arc::clone(&barrier);
+            mock.push_async_fn(move |req| async move {
+                barrier.wait().await;
+                let body = match req.headers()[RANGE] == "bytes=0-4" {
+                    true => "hello",
+                    false => "world",
+                };
+                Response::builder()
+                    .status(StatusCode::PARTIAL_CONTENT)
+                    .body(Body::from(body))
+                    .unwrap()
+            });
+        }
+        let ranges = [0..5, far..far + 5];
+        let ranges = integration.get_ranges(&location, &ranges);
+        let ranges = tokio::time::timeout(Duration::from_secs(5), ranges)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(ranges, vec!["hello", "world"]);
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_object_replication() {
+        let mock = MockServer::new();
+
+        let integration = mock_builder(&mock).build().unwrap();
+
+        mock.push(
+            Response::builder()
+                .header(CONTENT_LENGTH, "5")
+                .header(LAST_MODIFIED, "Thu, 01 Jan 1970 00:00:00 GMT")
+                .header("x-ms-or-policy-a_rule-1", "complete")
+                .header("x-ms-or-policy-a_rule-2", "failed")
+                .body(Body::empty())
+                .unwrap(),
+        );
+        mock.push(
+            Response::builder()
+                .header(CONTENT_LENGTH, "5")
+                .header(LAST_MODIFIED, "Thu, 01 Jan 1970 00:00:00 GMT")
+                .header("x-ms-or-policy-id", "policy-a")
+                .body(Body::from("hello"))
+                .unwrap(),
+        );
+
+        let location = Path::from("file");
+        let (meta, properties) =
+            integration.head_with_properties(&location).await.unwrap();
+        let replication = properties.replication;
+        assert_eq!(meta.size, 5);
+        assert_eq!(replication.policy_id, None);
+        let mut rules = replication.rules.clone();
+        rules.sort_unstable_by(|a, b| a.rule_id.cmp(&b.rule_id));
+        assert_eq!(
+            rules,
+            vec![
+                ReplicationRuleStatus {
+                    policy_id: "policy-a".to_string(),
+                    rule_id: "rule-1".to_string(),
+                    status: ReplicationStatus::Complete,
+                },
+                ReplicationRuleStatus {
+                    policy_id: "policy-a".to_string(),
+                    rule_id: "rule-2".to_string(),
+                    status: ReplicationStatus::Failed,
+                },
+            ]
+        );
+        assert!(!replication.is_complete());
+
+        let (result, properties) = integration
+            .get_with_properties(&location, GetOptions::default())
+            .await
+            .unwrap();
+        let replication = properties.replication;
+        assert_eq!(result.bytes().await.unwrap(), "hello");
+        assert_eq!(replication.policy_id.as_deref(), Some("policy-a"));
+        assert!(replication.rules.is_empty());
+        assert!(!replication.is_complete());
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_blob_lease() {
+        let mock = MockServer::new();
+
+        let integration = mock_builder(&mock).build().unwrap();
+
+        mock.push(
+            Response::builder()
+                .header(CONTENT_LENGTH, "5")
+                .header(LAST_MODIFIED, "Thu, 01 Jan 1970 00:00:00 GMT")
+                .header("x-ms-lease-status", "locked")
+                .header("x-ms-lease-state", "leased")
+                .header("x-ms-lease-duration", "infinite")
+                .body(Body::empty())
+                .unwrap(),
+        );
+        mock.push(
+            Response::builder()
+                .header(CONTENT_LENGTH, "5")
+                .header(LAST_MODIFIED, "Thu, 01 Jan 1970 00:00:00 GMT")
+                .header("x-ms-lease-status", "unlocked")
+                .header("x-ms-lease-state", "broken")
+                .body(Body::empty())
+                .unwrap(),
+        );
+
+        let location = Path::from("file");
+        let (meta, properties) =
+            integration.head_with_properties(&location).await.unwrap();
+        let lease = properties.lease;
+        assert_eq!(meta.size, 5);
+        assert_eq!(
+            lease,
+            BlobLease {
+                status: Some(LeaseStatus::Locked),
+                state: Some(LeaseState::Leased),
+                duration: Some(LeaseDuration::Infinite),
+            }
+        );
+        assert!(lease.is_locked());
+
+        let (_, properties) = integration.head_with_properties(&location).await.unwrap();
+        let lease = properties.lease;
+        assert_eq!(lease.status, Some(LeaseStatus::Unlocked));
+        assert_eq!(lease.state, Some(LeaseState::Broken));
+        assert_eq!(lease.duration, None);
+        assert!(!lease.is_locked());
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_revalidate() {
+        let mock = MockServer::new();
+
+        let integration = mock_builder(&mock).build().unwrap();
+
+        mock.push_fn(|req| {
+            assert_eq!(req.method(), Method::GET);
+            assert!(!req.headers().contains_key(IF_NONE_MATCH));
+            Response::builder()
+                .header(ETAG, "\"1\"")
+                .header(LAST_MODIFIED, "Thu, 01 Jan 1970 00:00:00 GMT")
+                .body(Body::from("cached"))
+                .unwrap()
+        });
+        mock.push_fn(|req| {
+            assert_eq!(req.headers()[IF_NONE_MATCH], "\"1\"");
+            Response::builder()
+                .status(StatusCode::NOT_MODIFIED)
+                .header(ETAG, "\"1\"")
+                .body(Body::empty())
+                .unwrap()
+        });
+        mock.push_fn(|req| {
+            assert_eq!(req.headers()[IF_NONE_MATCH], "\"1\"");
+            Response::builder()
+                .header(ETAG, "\"2\"")
+                .header(LAST_MODIFIED, "Thu, 01 Jan 1970 00:00:00 GMT")
+                .body(Body::from("fresh"))
+                .unwrap()
+        });
+
+        let location = Path::from("file");
+        let result = integration.revalidate(&location, None).await.unwrap();
+        let (bytes, meta) = match result {
+            Revalidated::Fresh(bytes, meta) => (bytes, meta),
+            Revalidated::Cached => panic!("expected fresh object"),
+        };
+        assert_eq!(bytes, "cached");
+        assert_eq!(meta.e_tag.unwrap(), "\"1\"");
+        assert_eq!(meta.size, 6);
+
+        let cached = Some("\"1\"".to_string());
+        let result = integration
+            .revalidate(&location, cached.clone())
+            .await
+            .unwrap();
+        assert_eq!(result, Revalidated::Cached);
+
+        let result = integration.revalidate(&location, cached).await.unwrap();
+        let (bytes, meta) = match result {
+            Revalidated::Fresh(bytes, meta) => (bytes, meta),
+            Revalidated::Cached => panic!("expected fresh object"),
+        };
+        assert_eq!(bytes, "fresh");
+        assert_eq!(meta.e_tag.unwrap(), "\"2\"");
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_get_opts_conditions() {
+        let mock = MockServer::new();
+
+        let integration = mock_builder(&mock).build().unwrap();
+
+        let since = Utc.with_ymd_and_hms(2023, 1, 2, 3, 4, 5).unwrap();
+        mock.push_fn(|req| {
+            assert_eq!(req.headers()[IF_MATCH], "\"1\"");
+            assert_eq!(
+                req.headers()[IF_UNMODIFIED_SINCE],
+                "Mon, 02 Jan 2023 03:04:05 GMT"
+            );
+            assert_eq!(req.headers()[RANGE], "bytes=1-3");
+            Response::builder()
+                .status(StatusCode::PARTIAL_CONTENT)
+                .body(Body::from("ell"))
+                .unwrap()
+        });
+        mock.push_fn(|req| {
+            assert_eq!(req.headers()[IF_NONE_MATCH], "\"1\"");
+            assert_eq!(
+                req.headers()[IF_MODIFIED_SINCE],
+                "Mon, 02 Jan 2023 03:04:05 GMT"
+            );
+            Response::builder()
+                .status(StatusCode::NOT_MODIFIED)
+                .body(Body::empty())
+                .unwrap()
+        });
+        mock.push(
+            Response::builder()
+                .status(StatusCode::PRECONDITION_FAILED)
+                .body(Body::empty())
+                .unwrap(),
+        );
+
+        let location = Path::from("file");
+        let options = GetOptions {
+            if_match: Some("\"1\"".to_string()),
+            if_unmodified_since: Some(since),
+            range: Some(1..4),
+            ..Default::default()
+        };
+        let result = integration.get_opts(&location, options).await.unwrap();
+        assert_eq!(result.bytes().await.unwrap(), "ell");
+
+        let options = GetOptions {
+            if_none_match: Some("\"1\"".to_string()),
+            if_modified_since: Some(since),
+            ..Default::default()
+        };
+        let err = integration.get_opts(&location, options).await.unwrap_err();
+        assert!(matches!(err, crate::Error::NotModified { .. }), "{}", err);
+
+        let options = GetOptions {
+            if_match: Some("\"2\"".to_string()),
+            ..Default::default()
+        };
+        let err = integration.get_opts(&location, options).await.unwrap_err();
+        assert!(matches!(err, crate::Error::Precondition { .. }), "{}", err);
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_committed_blocks() {
+        let mock = MockServer::new();
+
+        let integration = mock_builder(&mock).build().unwrap();
+
+        mock.push_fn(|req| {
+            assert_eq!(req.method(), Method::GET);
+            assert_eq!(req.uri().path(), "/account/container/file");
+            let query = req.uri().query().unwrap();
+            assert!(query.contains("comp=blocklist"), "{}", query);
+            assert!(query.contains("blocklisttype=committed"), "{}", query);
+            Response::new(Body::from(
+                r#"<?xml version="1.0" encoding="utf-8"?>
+                <BlockList>
+                    <CommittedBlocks>
+                        <Block><Name>MDA=</Name><Size>10</Size></Block>
+                        <Block><Name>MDE=</Name><Size>4</Size></Block>
+                    </CommittedBlocks>
+                </BlockList>"#,
+            ))
+        });
+
+        let blocks = integration
+            .committed_blocks(&Path::from("file"))
+            .await
+            .unwrap();
+        assert_eq!(
+            blocks,
+            vec![
+                BlockInfo {
+                    id: "MDA=".to_string(),
+                    size: 10
+                },
+                BlockInfo {
+                    id: "MDE=".to_string(),
+                    size: 4
+                },
+            ]
+        );
+
+        mock.push(
+            Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::empty())
+                .unwrap(),
+        );
+        let err = integration
+            .committed_blocks(&Path::from("missing"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, crate::Error::NotFound { .. }), "{}", err);
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_token_cache_path() {
+        let mock = MockServer::new();
+        let dir = tempfile::TempDir::new().unwrap();
+        // The token is written to a temporary file with a different name
+        let cache_path = dir.path().join("token.tmp");
+
+        let build = || {
+            MicrosoftAzureBuilder::new()
+                .with_account("account")
+                .with_container_name("container")
+                .with_client_secret_authorization("client", "secret", "tenant")
+                .with_authority_host(mock.url().to_string())
+                .with_token_cache_path(&cache_path)
+                .with_endpoint(mock.url())
+                .with_use_path_style(true)
+                .with_allow_http(true)
+                .build()
+                .unwrap()
+        };
+
+        mock.push_fn(|req| {
+            assert_eq!(req.uri().path(), "/tenant/oauth2/v2.0/token");
+            Response::new(Body::from(
+                r#"{"access_token": "cached", "expires_in": 3600}"#,
+            ))
+        });
+        for _ in 0..2 {
+            mock.push_fn(|req| {
+                assert_eq!(req.headers()[AUTHORIZATION], "Bearer cached");
+                Response::builder()
+                    .status(StatusCode::ACCEPTED)
+                    .body(Body::empty())
+                    .unwrap()
+            });
+        }
+
+        let location = Path::from("file");
+        build().delete(&location).await.unwrap();
+        assert!(cache_path.exists());
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        // A new store reuses the persisted token without requesting a new one
+        build().delete(&location).await.unwrap();
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_managed_identity() {
+        let mock = MockServer::new();
+
+        let integration = MicrosoftAzureBuilder::new()
+            .with_account("account")
+            .with_container_name("container")
+            .with_managed_identity()
+            .with_client_id("identity")
+            .with_msi_endpoint(format!("{}/metadata/identity/oauth2/token", mock.url()))
+            .with_endpoint(mock.url())
+            .with_use_path_style(true)
+            .with_allow_http(true)
+            .build()
+            .unwrap();
+
+        mock.push_fn(|req| {
+            assert_eq!(req.method(), Method::GET);
+            assert_eq!(req.uri().path(), "/metadata/identity/oauth2/token");
+            assert_eq!(req.headers()["metadata"], "true");
+            let query = req.uri().query().unwrap();
+            assert!(query.contains("api-version=2018-02-01"), "{}", query);
+            assert!(
+                query.contains("resource=https%3A%2F%2Fstorage.azure.com&"),
+                "{}",
+                query
+            );
+            assert!(query.contains("client_id=identity"), "{}", query);
+            Response::new(Body::from(
+                r#"{"access_token": "msi", "expires_in": "3599", "token_type": "Bearer"}"#,
+            ))
+        });
+        // The token is cached
+        for _ in 0..2 {
+            mock.push_fn(|req| {
+                assert_eq!(req.headers()[AUTHORIZATION], "Bearer msi");
+                Response::builder()
+                    .status(StatusCode::ACCEPTED)
+                    .body(Body::empty())
+                    .unwrap()
+            });
+        }
+
+        integration.delete(&Path::from("a")).await.unwrap();
+        integration.delete(&Path::from("b")).await.unwrap();
+
+        // Managed identity is only used if enabled
+        let err = MicrosoftAzureBuilder::new()
+            .with_account("account")
+            .with_container_name("container")
+            .build()
+            .unwrap_err();
+        assert!(err.to_string().contains("authorization option"), "{}", err);
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_refresh_on_auth_failure() {
+        let mock = MockServer::new();
+
+        let build = |refresh| {
+            MicrosoftAzureBuilder::new()
+                .with_account("account")
+                .with_container_name("container")
+                .with_client_secret_authorization("client", "secret", "tenant")
+                .with_authority_host(mock.url().to_string())
+                .with_refresh_on_auth_failure(refresh)
+                .with_endpoint(mock.url())
+                .with_use_path_style(true)
+                .with_allow_http(true)
+                .build()
+                .unwrap()
+        };
+        let token = |token: &'static str| {
+            move |req: hyper::Request<Body>| {
+                assert_eq!(req.uri().path(), "/tenant/oauth2/v2.0/token");
+                Response::new(Body::from(format!(
+                    r#"{{"access_token": "{}", "expires_in": 3600}}"#,
+                    token
+                )))
+            }
+        };
+        let expired = || {
+            Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .body(Body::from(
+                    "<Error><Code>InvalidAuthenticationInfo</Code>\
+                    <Message>Lifetime validation failed. The token is expired.</Message></Error>",
+                ))
+                .unwrap()
+        };
+
+        mock.push_fn(token("expired"));
+        mock.push_fn(move |req| {
+            assert_eq!(req.headers()[AUTHORIZATION], "Bearer expired");
+            expired()
+        });
+        mock.push_fn(token("fresh"));
+        for _ in 0..2 {
+            mock.push_fn(|req| {
+                assert_eq!(req.headers()[AUTHORIZATION], "Bearer fresh");
+                Response::builder()
+                    .status(StatusCode::ACCEPTED)
+                    .body(Body::empty())
+                    .unwrap()
+            });
+        }
+
+        let location = Path::from("file");
+        let integration = build(true);
+        integration.delete(&location).await.unwrap();
+        // The new token is used for subsequent requests
+        integration.delete(&location).await.unwrap();
+
+        // Retrying with a new token can be disabled
+        mock.push_fn(token("expired"));
+        mock.push_fn(move |_| expired());
+        let err = build(false).delete(&location).await.unwrap_err();
+        assert!(
+            err.to_string().contains("InvalidAuthenticationInfo"),
+            "{}",
+            err
+        );
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_token_cache_concurrent() {
+        let mock = MockServer::new();
+
+        let integration = MicrosoftAzureBuilder::new()
+            .with_account("account")
+            .with_container_name("container")
+            .with_client_secret_authorization("client", "secret", "tenant")
+            .with_authority_host(mock.url().to_string())
+            .with_endpoint(mock.url())
+            .with_use_path_style(true)
+            .with_allow_http(true)
+            .build()
+            .unwrap();
+
+        let token_requests = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let requests = 20;
+        // Only one token request is expected, but responses are pushed in the order
+        // requests are received, so each response handles either
+        for _ in 0..requests + 1 {
+            let token_requests = Arc::clone(&token_requests);
+            mock.push_fn(move |req| {
+                if req.uri().path() == "/tenant/oauth2/v2.0/token" {
+                    token_requests.fetch_add(1, Ordering::SeqCst);
+                    return Response::new(Body::from(
+                        r#"{"access_token": "token", "expires_in": 3600}"#,
+                    ));
+                }
+                assert_eq!(req.headers()[AUTHORIZATION], "Bearer token");
+                Response::builder()
+                    .header(CONTENT_LENGTH, "5")
+                    .header(LAST_MODIFIED, "Thu, 01 Jan 1970 00:00:00 GMT")
+                    .body(Body::empty())
+                    .unwrap()
+            });
+        }
+
+        let location = Path::from("file");
+        let heads = (0..requests).map(|_| integration.head(&location));
+        for meta in futures::future::join_all(heads).await {
+            assert_eq!(meta.unwrap().size, 5);
+        }
+        assert_eq!(token_requests.load(Ordering::SeqCst), 1);
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_idempotency_key() {
+        let mock = MockServer::new();
+
+        let integration = mock_builder(&mock)
+            .with_idempotency_key_header("x-idempotency-key")
+            .build()
+            .unwrap();
+
+        let expect_key = |method: Method, key: &'static str, status: StatusCode| {
+            move |req: hyper::Request<Body>| {
+                assert_eq!(req.method(), method);
+                assert_eq!(req.headers()["x-idempotency-key"], key);
+                Response::builder()
+                    .status(status)
+                    .body(Body::empty())
+                    .unwrap()
+            }
+        };
+
+        // A retried request is sent with the same key
+        let error = StatusCode::INTERNAL_SERVER_ERROR;
+        mock.push_fn(expect_key(Method::PUT, "put-1", error));
+        mock.push_fn(expect_key(Method::PUT, "put-1", StatusCode::CREATED));
+        mock.push_fn(expect_key(Method::DELETE, "delete-1", error));
+        mock.push_fn(expect_key(Method::DELETE, "delete-1", StatusCode::ACCEPTED));
+        mock.push_fn(expect_key(Method::PUT, "copy-1", StatusCode::ACCEPTED));
+
+        let location = Path::from("file");
+        let options = PutOptions {
+            idempotency_key: Some("put-1".to_string()),
+            ..Default::default()
+        };
+        integration
+            .put_opts(&location, Bytes::from("data"), options)
+            .await
+            .unwrap();
+
+        let options = DeleteOptions {
+            idempotency_key: Some("delete-1".to_string()),
+        };
+        integration.delete_opts(&location, options).await.unwrap();
+
+        let options = CopyOptions {
+            idempotency_key: Some("copy-1".to_string()),
+            ..Default::default()
+        };
+        integration
+            .copy_opts(&location, &Path::from("copy"), options)
+            .await
+            .unwrap();
+
+        // No key is sent without one
+        mock.push_fn(|req| {
+            assert!(req.headers().get("x-idempotency-key").is_none());
+            Response::new(Body::empty())
+        });
+        integration.delete(&location).await.unwrap();
+
+        mock.shutdown().await;
+
+        let err = MicrosoftAzureBuilder::new()
+            .with_account("account")
+            .with_container_name("container")
+            .with_access_key(EMULATOR_ACCOUNT_KEY)
+            .with_idempotency_key_header("invalid header")
+            .build()
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("Invalid idempotency key header"),
+            "{}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn azure_signing_account() {
+        let mock = MockServer::new();
+
+        let integration = MicrosoftAzureBuilder::new()
+            .with_account("cdn")
+            .with_signing_account("account")
+            .with_container_name("container")
+            .with_access_key(EMULATOR_ACCOUNT_KEY)
+            .with_endpoint(mock.url())
+            .with_use_path_style(true)
+            .with_allow_http(true)
+            .build()
+            .unwrap();
+
+        mock.push_fn(|req| {
+            assert_eq!(req.uri().path(), "/cdn/container/file");
+            let auth = req.headers()[AUTHORIZATION].to_str().unwrap();
+            assert!(auth.starts_with("SharedKey account:"), "{}", auth);
+            Response::builder()
+                .status(StatusCode::ACCEPTED)
+                .body(Body::empty())
+                .unwrap()
+        });
+        integration.delete(&Path::from("file")).await.unwrap();
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_oauth_scope() {
+        let mock = MockServer::new();
+
+        let integration = MicrosoftAzureBuilder::new()
+            .with_account("account")
+            .with_container_name("container")
+            .with_client_secret_authorization("client_id", "client_secret", "tenant")
+            .with_authority_host(mock.url().to_string())
+            .with_oauth_scope("https://storage.azure.us/.default")
+            .with_endpoint(mock.url())
+            .with_use_path_style(true)
+            .with_allow_http(true)
+            .build()
+            .unwrap();
+
+        mock.push_async_fn(|req| async move {
+            assert_eq!(req.uri().path(), "/tenant/oauth2/v2.0/token");
+            let body = hyper::body::to_bytes(req).await.unwrap();
+            let form: HashMap<String, String> =
+                url::form_urlencoded::parse(&body).into_owned().collect();
+            assert_eq!(form["scope"], "https://storage.azure.us/.default");
+            Response::new(Body::from(
+                r#"{"access_token": "token", "expires_in": 3600}"#,
+            ))
+        });
+        mock.push_fn(|req| {
+            let auth = req.headers().get(AUTHORIZATION).unwrap();
+            assert_eq!(auth, "Bearer token");
+            Response::new(Body::empty())
+        });
+
+        integration.delete(&Path::from("a")).await.unwrap();
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_cancel() {
+        let mock = MockServer::new();
+
+        let integration = mock_builder(&mock).build().unwrap();
+
+        mock.push_async_fn(|_| async {
+            tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+            Response::new(Body::from("hello world"))
+        });
+
+        let cancel = tokio_util::sync::CancellationToken::new();
+        let options = GetOptions {
+            cancel: Some(cancel.clone()),
+            ..Default::default()
+        };
+        let location = Path::from("a");
+        let get = integration.get_opts(&location, options);
+        let (result, _) = tokio::join!(get, async {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            cancel.cancel();
+        });
+        let err = result.unwrap_err();
+        assert!(matches!(err, crate::Error::Cancelled { .. }), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn azure_progress() {
+        let mock = MockServer::new();
+
+        let integration = mock_builder(&mock).build().unwrap();
+
+        let events = Arc::new(parking_lot::Mutex::new(vec![]));
+        let captured = Arc::clone(&events);
+        let progress: crate::ProgressFn = Arc::new(move |transferred, total| {
+            captured.lock().push((transferred, total))
+        });
+
+        // Downloads report the total from the Content-Length
+        mock.push(Response::new(Body::from("hello world")));
+        let options = GetOptions {
+            progress: Some(Arc::clone(&progress)),
+            ..Default::default()
+        };
+        let location = Path::from("a");
+        let result = integration.get_opts(&location, options).await.unwrap();
+        assert_eq!(result.bytes().await.unwrap(), "hello world");
+        assert_eq!(std::mem::take(&mut *events.lock()), vec![(11, Some(11))]);
+
+        // Multipart uploads report as each block is uploaded
+        let block = vec![0; DEFAULT_MIN_PART_SIZE / 2];
+        for _ in 0..3 {
+            mock.push(Response::new(Body::empty()));
+        }
+        let options = PutOptions {
+            progress: Some(progress),
+            ..Default::default()
+        };
+        let (_, mut writer) = integration
+            .put_multipart_opts(&location, options)
+            .await
+            .unwrap();
+        writer.write_all(&block).await.unwrap();
+        writer.write_all(&block).await.unwrap();
+        writer.write_all(b"end").await.unwrap();
+        writer.shutdown().await.unwrap();
+
+        let len = block.len() as u64 * 2;
+        assert_eq!(
+            std::mem::take(&mut *events.lock()),
+            vec![(len, None), (len + 3, None)]
+        );
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_update() {
+        let mock = MockServer::new();
+
+        let integration = mock_builder(&mock).build().unwrap();
+        let location = Path::from("config");
+
+        // Create only if not exists
+        mock.push_fn(|req| {
+            assert_eq!(req.headers()[IF_NONE_MATCH], "*");
+            assert!(!req.headers().contains_key(IF_MATCH));
+            Response::builder()
+                .status(StatusCode::CREATED)
+                .header(ETAG, "\"0x1\"")
+                .body(Body::empty())
+                .unwrap()
+        });
+        let result = integration
+            .update(&location, None, Bytes::from("a"))
+            .await
+            .unwrap();
+        assert_eq!(result.e_tag.as_deref(), Some("\"0x1\""));
+
+        // Replace if ETag matches
+        mock.push_fn(|req| {
+            assert_eq!(req.headers()[IF_MATCH], "\"0x1\"");
+            assert!(!req.headers().contains_key(IF_NONE_MATCH));
+            Response::builder()
+                .status(StatusCode::CREATED)
+                .header(ETAG, "\"0x2\"")
+                .body(Body::empty())
+                .unwrap()
+        });
+        let result = integration
+            .update(&location, result.e_tag, Bytes::from("b"))
+            .await
+            .unwrap();
+        assert_eq!(result.e_tag.as_deref(), Some("\"0x2\""));
+
+        mock.push(
+            Response::builder()
+                .status(StatusCode::PRECONDITION_FAILED)
+                .body(Body::empty())
+                .unwrap(),
+        );
+        let err = integration
+            .update(&location, Some("\"0x1\"".to_string()), Bytes::from("c"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, crate::Error::Precondition { .. }), "{}", err);
+
+        mock.push(
+            Response::builder()
+                .status(StatusCode::CONFLICT)
+                .body(Body::from("<Error><Code>BlobAlreadyExists</Code></Error>"))
+                .unwrap(),
+        );
+        let err = integration
+            .update(&location, None, Bytes::from("c"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, crate::Error::AlreadyExists { .. }), "{}", err);
+
+        // Other conflicts are not reported as the blob already existing
+        mock.push(
+            Response::builder()
+                .status(StatusCode::CONFLICT)
+                .body(Body::from("<Error><Code>LeaseIdMissing</Code></Error>"))
+                .unwrap(),
+        );
+        let err = integration
+            .update(&location, None, Bytes::from("c"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, crate::Error::Generic { .. }), "{}", err);
+
+        mock.shutdown().await
+    }
+
+    #[test]
+    fn azure_dfs_url() {
+        let builder = MicrosoftAzureBuilder::new()
+            .with_account("account")
+            .with_container_name("container")
+            .with_access_key(EMULATOR_ACCOUNT_KEY);
+
+        let integration = builder.clone().build().unwrap();
+        let url = integration.client.config().dfs_path_url(&Path::from("a/b"));
+        assert_eq!(
+            url.as_str(),
+            "https://account.dfs.core.windows.net/container/a/b"
+        );
+
+        let integration = builder
+            .clone()
+            .with_endpoint("https://account.blob.example.com")
+            .build()
+            .unwrap();
+        let url = integration.client.config().dfs_path_url(&Path::from("a"));
+        assert_eq!(url.as_str(), "https://account.dfs.example.com/container/a");
+
+        let integration = builder
+            .with_endpoint("https://gateway.example.com")
+            .with_use_path_style(true)
+            .build()
+            .unwrap();
+        let url = integration.client.config().dfs_path_url(&Path::from("a"));
+        assert_eq!(
+            url.as_str(),
+            "https://gateway.example.com/account/container/a"
+        );
+    }
+
+    #[tokio::test]
+    async fn azure_dfs_request() {
+        let mock = MockServer::new();
+
+        let integration = MicrosoftAzureBuilder::new()
+            .with_account("account")
+            .with_container_name("container")
+            .with_access_key(EMULATOR_ACCOUNT_KEY)
+            .with_dfs_endpoint(mock.url())
+            .with_allow_http(true)
+            .build()
+            .unwrap();
+
+        mock.push_fn(|req| {
+            assert_eq!(req.uri().path(), "/container/a");
+            assert_eq!(req.uri().query(), Some("recursive=true"));
+            let auth = req.headers()[AUTHORIZATION].to_str().unwrap();
+            assert!(auth.starts_with("SharedKey account:"), "{}", auth);
+            Response::new(Body::empty())
+        });
+        integration
+            .client
+            .dfs_request(
+                Method::DELETE,
+                &Path::from("a"),
+                &[("recursive", "true")],
+                Default::default(),
+            )
+            .await
+            .unwrap();
+
+        mock.shutdown().await
+    }
+
+    #[test]
+    fn azure_test_max_buffered_bytes() {
+        let builder = MicrosoftAzureBuilder::new()
+            .with_account("account")
+            .with_container_name("container")
+            .with_access_key(EMULATOR_ACCOUNT_KEY);
+
+        let err = builder
+            .clone()
+            .with_max_buffered_bytes(1024)
+            .build()
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("must be at least the multipart part size"),
+            "{}",
+            err
+        );
+
+        builder
+            .clone()
+            .with_max_buffered_bytes(DEFAULT_MIN_PART_SIZE * 4)
+            .build()
+            .unwrap();
+
+        let err = builder
+            .with_block_size(DEFAULT_MIN_PART_SIZE * 8)
+            .with_max_buffered_bytes(DEFAULT_MIN_PART_SIZE * 4)
+            .build()
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("must be at least the multipart part size"),
+            "{}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn azure_block_size() {
+        let mock = MockServer::new();
+
+        let builder = mock_builder(&mock);
+
+        for block_size in [0, MAX_BLOCK_SIZE + 1] {
+            let err = builder
+                .clone()
+                .with_block_size(block_size)
+                .build()
+                .unwrap_err()
+                .to_string();
+            assert!(err.contains("maximum block size"), "{}", err);
+        }
+
+        let integration = builder.with_block_size(1024).build().unwrap();
+        let blocks = Arc::new(parking_lot::Mutex::new(vec![]));
+        let push_blocks = |count: usize| {
+            for _ in 0..count {
+                let captured = Arc::clone(&blocks);
+                mock.push_fn(move |req| {
+                    assert_eq!(
+                        req.uri().query().unwrap().split('&').next(),
+                        Some("comp=block")
+                    );
+                    captured.lock().push(req.headers()[CONTENT_LENGTH].clone());
+                    Response::new(Body::empty())
+                });
+            }
+            mock.push_fn(|req| {
+                assert_eq!(req.uri().query(), Some("comp=blocklist"));
+                Response::new(Body::empty())
+            });
+        };
+
+        // Blocks are staged once at least the block size has been written
+        push_blocks(2);
+        let location = Path::from("file");
+        let (_, mut writer) = integration.put_multipart(&location).await.unwrap();
+        writer.write_all(&[0; 1000]).await.unwrap();
+        writer.write_all(&[0; 1000]).await.unwrap();
+        writer.write_all(&[0; 10]).await.unwrap();
+        writer.shutdown().await.unwrap();
+        assert_eq!(std::mem::take(&mut *blocks.lock()), ["2000", "10"]);
+
+        push_blocks(3);
+        let data = Bytes::from(vec![0; 2500]);
+        integration
+            .put_multipart_bytes(&location, data)
+            .await
+            .unwrap();
+        let mut sizes = std::mem::take(&mut *blocks.lock());
+        sizes.sort_unstable_by_key(|size| size.to_str().unwrap().len());
+        assert_eq!(sizes, ["452", "1024", "1024"]);
+
+        mock.shutdown().await
+    }
+
+    #[test]
+    fn azure_test_split_sas() {
+        let raw_sas = "?sv=2021-10-04&st=2023-01-04T17%3A48%3A57Z&se=2023-01-04T18%3A15%3A00Z&sr=c&sp=rcwl&sig=C7%2BZeEOWbrxPA3R0Cw%2Fw1EZz0%2B4KBvQexeKZKe%2BB6h0%3D";
+        let expected = vec![
+            ("sv".to_string(), "2021-10-04".to_string()),
+            ("st".to_string(), "2023-01-04T17:48:57Z".to_string()),
+            ("se".to_string(), "2023-01-04T18:15:00Z".to_string()),
+            ("sr".to_string(), "c".to_string()),
+            ("sp".to_string(), "rcwl".to_string()),
+            (
+                "sig".to_string(),
+                "C7+ZeEOWbrxPA3R0Cw/w1EZz0+4KBvQexeKZKe+B6h0=".to_string(),
+            ),
+        ];
+        let pairs = split_sas(raw_sas).unwrap();
+        assert_eq!(expected, pairs);
+
+        let pairs = split_sas(raw_sas.trim_start_matches('?')).unwrap();
+        assert_eq!(expected, pairs);
+
+        // Encoded delimiters are decoded within their value
+        let pairs = split_sas("sv=2021-10-04&sig=a%26b%3Dc").unwrap();
+        assert_eq!(pairs[1], ("sig".to_string(), "a&b=c".to_string()));
+
+        let err = split_sas("sv=2021-10-04&sig").unwrap_err();
+        assert!(matches!(err, Error::MissingSasComponent {}), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn azure_sas_url() {
+        let sas = "sv=2021-10-04&st=2023-01-04T17%3A48%3A57Z&se=2023-01-04T18%3A15%3A00Z&sr=c&sp=rcwl&sig=C7%2BZeEOWbrxPA3R0Cw%2Fw1EZz0%2B4KBvQexeKZKe%2BB6h0%3D";
+        let url = format!("https://account.blob.core.windows.net/container?{sas}");
+
+        let mut builder = MicrosoftAzureBuilder::new();
+        builder.parse_url(&url).unwrap();
+        assert_eq!(builder.account_name.as_deref(), Some("account"));
+        assert_eq!(builder.container_name.as_deref(), Some("container"));
+        assert_eq!(builder.sas_query_pairs, Some(split_sas(sas).unwrap()));
+
+        let mock = MockServer::new();
+        let integration = MicrosoftAzureBuilder::new()
+            .with_url(url)
+            .with_endpoint(mock.url())
+            .with_use_path_style(true)
+            .with_allow_http(true)
+            .build()
+            .unwrap();
+
+        mock.push_fn(move |req: hyper::Request<Body>| {
+            assert_eq!(req.uri().path(), "/account/container/file");
+            assert_eq!(req.uri().query(), Some(sas));
+            Response::builder()
+                .header(CONTENT_LENGTH, "0")
+                .header(LAST_MODIFIED, "Mon, 02 Jan 2023 00:00:00 GMT")
+                .body(Body::empty())
+                .unwrap()
+        });
+        integration.head(&Path::from("file")).await.unwrap();
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_access_tier() {
+        let mock = MockServer::new();
+
+        let integration = mock_builder(&mock)
+            .try_with_option("access_tier", "cool")
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let expect_tier = |query: &'static str, tier: Option<&'static str>| {
+            move |req: hyper::Request<Body>| {
+                assert_eq!(req.method(), Method::PUT);
+                let actual = req.uri().query().unwrap_or_default();
+                assert!(actual.starts_with(query), "{}", actual);
+                let header = req.headers().get("x-ms-access-tier");
+                assert_eq!(header.map(|v| v.to_str().unwrap()), tier);
+                Response::builder()
+                    .status(StatusCode::CREATED)
+                    .body(Body::empty())
+                    .unwrap()
+            }
+        };
+
+        // The default tier applies to all writes, but not to staged blocks
+        mock.push_fn(expect_tier("", Some("Cool")));
+        mock.push_fn(expect_tier("", Some("Archive")));
+        mock.push_fn(expect_tier("comp=block&", None));
+        mock.push_fn(expect_tier("comp=blocklist", Some("Cool")));
+
+        let location = Path::from("file");
+        integration.put(&location, "data".into()).await.unwrap();
+        integration
+            .put_with_tier(&location, "data".into(), AccessTier::Archive)
+            .await
+            .unwrap();
+
+        let (_, mut writer) = integration.put_multipart(&location).await.unwrap();
+        writer.write_all(b"data").await.unwrap();
+        writer.shutdown().await.unwrap();
+
+        let err = MicrosoftAzureBuilder::new()
+            .try_with_option(AzureConfigKey::AccessTier, "frozen")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Invalid access tier 'frozen'"), "{}", err);
+        assert_eq!(
+            "ARCHIVE".parse::<AccessTier>().unwrap(),
+            AccessTier::Archive
+        );
+
+        mock.shutdown().await
+    }
+
+    #[test]
+    fn azure_capabilities() {
+        let builder = MicrosoftAzureBuilder::new()
+            .with_account("account")
+            .with_container_name("container")
+            .with_access_key(EMULATOR_ACCOUNT_KEY);
+
+        let capabilities = builder.clone().build().unwrap().capabilities();
+        assert!(capabilities.conditional_put);
+        assert!(capabilities.conditional_get);
+        assert!(capabilities.copy_if_not_exists);
+        assert!(capabilities.leases);
+        assert!(!capabilities.atomic_rename);
+        assert!(!capabilities.versioning);
+
+        let integration = builder.with_versioning(true).build().unwrap();
+        assert!(integration.capabilities().versioning);
+    }
+
+    #[test]
+    fn azure_connection_string() {
+        let parse = |connection_string: &str| {
+            let mut builder = MicrosoftAzureBuilder::new();
+            builder
+                .parse_connection_string(connection_string)
+                .map(|_| builder)
+        };
+
+        let builder = parse(
+            "DefaultEndpointsProtocol=https;AccountName=account;AccountKey=a2V5==;EndpointSuffix=core.chinacloudapi.cn",
+        )
+        .unwrap();
+        assert_eq!(builder.account_name.as_deref(), Some("account"));
+        assert_eq!(builder.access_key.as_deref(), Some("a2V5=="));
+        assert_eq!(
+            builder.endpoint.as_deref(),
+            Some("https://account.blob.core.chinacloudapi.cn")
+        );
+
+        let builder = parse(
+            "AccountName=account;SharedAccessSignature=sv=2021-08-06&sig=abc;BlobEndpoint=http://gateway.example.com/;QueueEndpoint=http://queue;",
+        )
+        .unwrap();
+        assert_eq!(builder.sas_key.as_deref(), Some("sv=2021-08-06&sig=abc"));
+        assert_eq!(builder.access_key, None);
+        assert_eq!(
+            builder.endpoint.as_deref(),
+            Some("http://gateway.example.com")
+        );
+
+        let builder = parse("UseDevelopmentStorage=true").unwrap();
+        assert!(builder.use_emulator);
+
+        let err = parse("AccountKey=a2V5").unwrap_err().to_string();
+        assert!(
+            err.contains("missing required component AccountName"),
+            "{}",
+            err
+        );
+        let err = parse("AccountName=account").unwrap_err().to_string();
+        assert!(
+            err.contains("missing required component AccountKey"),
+            "{}",
+            err
+        );
+        let err = parse("AccountName=account;a2V5").unwrap_err().to_string();
+        assert!(err.contains("element 1 is not of the form"), "{}", err);
+        assert!(!err.contains("a2V5"), "{}", err);
+        let err =
+            parse("AccountName=account;AccountKey=a2V5;DefaultEndpointsProtocol=ftp")
+                .unwrap_err()
+                .to_string();
+        assert!(
+            err.contains("Invalid DefaultEndpointsProtocol 'ftp'"),
+            "{}",
+            err
+        );
+
+        // Configured with a config key, and parsed when built
+        let azure = MicrosoftAzureBuilder::new()
+            .try_with_option(
+                "azure_storage_connection_string",
+                "AccountName=account;AccountKey=a2V5;EndpointSuffix=core.windows.net",
+            )
+            .unwrap()
+            .with_container_name("container")
+            .build()
+            .unwrap();
+        assert_eq!(
+            azure.client.config().service.as_str(),
+            "https://account.blob.core.windows.net/"
+        );
+        let err = MicrosoftAzureBuilder::new()
+            .with_connection_string("AccountName=account")
+            .with_container_name("container")
+            .build()
+            .unwrap_err();
+        assert!(err.to_string().contains("AccountKey"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn azure_put_non_idempotent() {
+        let mock = MockServer::new();
+
+        let integration = mock_builder(&mock).build().unwrap();
+
+        let server_error = || {
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        // A server error may occur once the request has been applied
+        mock.push(server_error());
+        let options = PutOptions {
+            non_idempotent: true,
+            ..Default::default()
+        };
+        let location = Path::from("file");
+        let err = integration
+            .put_opts(&location, "data".into(), options)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("after 0 retries"), "{}", err);
+
+        // Whereas idempotent requests are retried
+        mock.push(server_error());
+        mock.push_fn(|req| {
+            assert_eq!(req.method(), Method::PUT);
+            Response::builder()
+                .status(StatusCode::CREATED)
+                .body(Body::empty())
+                .unwrap()
+        });
+        integration
+            .put_opts(&location, "data".into(), PutOptions::default())
+            .await
+            .unwrap();
+
+        mock.shutdown().await
+    }
+
+    #[test]
+    fn azure_endpoint_suffix() {
+        let builder = MicrosoftAzureBuilder::new()
+            .with_account("account")
+            .with_container_name("container")
+            .with_access_key(EMULATOR_ACCOUNT_KEY)
+            .with_endpoint_suffix("core.usgovcloudapi.net");
+        let azure = builder.build().unwrap();
+        assert_eq!(azure.account(), "account");
+        assert_eq!(azure.container(), "container");
+        assert_eq!(
+            azure.endpoint().host_str(),
+            Some("account.blob.core.usgovcloudapi.net")
+        );
+
+        let builder = MicrosoftAzureBuilder::new()
+            .with_account("account")
+            .with_container_name("container")
+            .with_access_key(EMULATOR_ACCOUNT_KEY)
+            .try_with_option("azure_endpoint_suffix", "core.chinacloudapi.cn")
+            .unwrap();
+        let azure = builder.build().unwrap();
+        assert_eq!(
+            azure.client.config().service.host_str(),
+            Some("account.blob.core.chinacloudapi.cn")
+        );
+
+        let mut builder = MicrosoftAzureBuilder::new();
+        builder
+            .parse_url("https://account.blob.core.usgovcloudapi.net/container")
+            .unwrap();
+        assert_eq!(builder.account_name.as_deref(), Some("account"));
+        assert_eq!(
+            builder.endpoint_suffix.as_deref(),
+            Some("core.usgovcloudapi.net")
+        );
+
+        let mut builder = MicrosoftAzureBuilder::new();
+        builder
+            .parse_url("abfss://file_system@account.dfs.core.chinacloudapi.cn/")
+            .unwrap();
+        assert_eq!(builder.account_name.as_deref(), Some("account"));
+        assert_eq!(builder.container_name.as_deref(), Some("file_system"));
+        assert_eq!(
+            builder.endpoint_suffix.as_deref(),
+            Some("core.chinacloudapi.cn")
+        );
+
+        // Unknown suffixes are only recognised once configured
+        let mut builder = MicrosoftAzureBuilder::new();
+        builder
+            .parse_url("https://account.blob.storage.example.com/container")
+            .unwrap_err();
+        let mut builder =
+            MicrosoftAzureBuilder::new().with_endpoint_suffix("storage.example.com");
+        builder
+            .parse_url("https://account.blob.storage.example.com/container")
+            .unwrap();
+        assert_eq!(builder.account_name.as_deref(), Some("account"));
+    }
+
+    #[test]
+    fn azure_upload_concurrency() {
+        let builder = MicrosoftAzureBuilder::new()
+            .with_account("account")
+            .with_container_name("container")
+            .with_access_key(EMULATOR_ACCOUNT_KEY);
+
+        let azure = builder
+            .clone()
+            .with_upload_concurrency(UploadConcurrency::Adaptive { min: 2, max: 16 })
+            .build()
+            .unwrap();
+        assert_eq!(
+            azure.upload_concurrency,
+            UploadConcurrency::Adaptive { min: 2, max: 16 }
+        );
+
+        let azure = builder
+            .clone()
+            .with_multipart_concurrency(32)
+            .with_block_size(100 * 1024 * 1024)
+            .build()
+            .unwrap();
+        assert_eq!(azure.upload_concurrency, UploadConcurrency::Fixed(32));
+        assert_eq!(azure.block_size, 100 * 1024 * 1024);
+
+        for block_size in [0, MAX_BLOCK_SIZE + 1] {
+            let err = builder
+                .clone()
+                .with_block_size(block_size)
+                .build()
+                .unwrap_err()
+                .to_string();
+            assert!(err.contains("Block size"), "{}", err);
+        }
+
+        for concurrency in [
+            UploadConcurrency::Fixed(0),
+            UploadConcurrency::Adaptive { min: 0, max: 4 },
+            UploadConcurrency::Adaptive { min: 8, max: 4 },
+        ] {
+            let err = builder
+                .clone()
+                .with_upload_concurrency(concurrency)
+                .build()
+                .unwrap_err()
+                .to_string();
+            assert!(err.contains("Invalid upload concurrency"), "{}", err);
+        }
+    }
+
+    #[tokio::test]
+    async fn azure_error_status() {
+        let mock = MockServer::new();
+        let integration = mock_builder(&mock).build().unwrap();
+        let location = Path::from("missing");
+        let status = |status| {
+            Response::builder()
+                .status(status)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        mock.push(status(StatusCode::NOT_FOUND));
+        let err = integration.get(&location).await.unwrap_err();
+        assert!(matches!(err, crate::Error::NotFound { .. }), "{}", err);
+
+        mock.push(status(StatusCode::NOT_FOUND));
+        let err = integration.head(&location).await.unwrap_err();
+        assert!(matches!(err, crate::Error::NotFound { .. }), "{}", err);
+
+        mock.push(status(StatusCode::NOT_FOUND));
+        let err = integration.delete(&location).await.unwrap_err();
+        assert!(matches!(err, crate::Error::NotFound { .. }), "{}", err);
+
+        mock.push(status(StatusCode::PRECONDITION_FAILED));
+        let err = integration
+            .set_properties(&location, BlobProperties::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, crate::Error::Precondition { .. }), "{}", err);
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_copy_only_if_source_newer() {
+        let mock = MockServer::new();
+        let integration = mock_builder(&mock).build().unwrap();
+
+        // The source and destination are fetched concurrently, in either order
+        let head = |dest_modified: &'static str| {
+            move |req: hyper::Request<Body>| {
+                assert_eq!(req.method(), Method::HEAD);
+                let modified = match req.uri().path() {
+                    "/account/container/source" => "Mon, 02 Jan 2023 00:00:00 GMT",
+                    "/account/container/dest" => dest_modified,
+                    path => panic!("unexpected path {}", path),
+                };
+                Response::builder()
+                    .header(CONTENT_LENGTH, "5")
+                    .header(LAST_MODIFIED, modified)
+                    .body(Body::empty())
+                    .unwrap()
+            }
+        };
+        let options = CopyOptions {
+            only_if_source_newer: true,
+            ..Default::default()
+        };
+        let (source, dest) = (Path::from("source"), Path::from("dest"));
+
+        // Skipped if the destination was modified at the same time
+        let same = "Mon, 02 Jan 2023 00:00:00 GMT";
+        mock.push_fn(head(same));
+        mock.push_fn(head(same));
+        let err = integration
+            .copy_opts(&source, &dest, options.clone())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, crate::Error::CopySkipped { .. }), "{}", err);
+
+        // Copied if the destination is older
+        let older = "Sun, 01 Jan 2023 00:00:00 GMT";
+        mock.push_fn(head(older));
+        mock.push_fn(head(older));
+        mock.push_fn(|req| {
+            assert_eq!(req.method(), Method::PUT);
+            assert_eq!(req.uri().path(), "/account/container/dest");
+            Response::new(Body::empty())
+        });
+        integration
+            .copy_opts(&source, &dest, options)
+            .await
+            .unwrap();
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_blob_type() {
+        let mock = MockServer::new();
+        let integration = mock_builder(&mock).build().unwrap();
+
+        let head = |blob_type: Option<&str>, size: &str| {
+            let mut builder = Response::builder()
+                .header(CONTENT_LENGTH, size)
+                .header(LAST_MODIFIED, "Thu, 01 Jan 1970 00:00:00 GMT");
+            if let Some(blob_type) = blob_type {
+                builder = builder.header("x-ms-blob-type", blob_type);
+            }
+            builder.body(Body::empty()).unwrap()
+        };
+        let location = Path::from("blob");
+
+        mock.push(head(Some("PageBlob"), "1024"));
+        let (meta, properties) =
+            integration.head_with_properties(&location).await.unwrap();
+        assert_eq!(meta.size, 1024);
+        assert_eq!(properties.blob_type, BlobType::Page);
+
+        mock.push(head(Some("AppendBlob"), "10"));
+        let (meta, properties) =
+            integration.head_with_properties(&location).await.unwrap();
+        assert_eq!(meta.size, 10);
+        assert_eq!(properties.blob_type, BlobType::Append);
+
+        mock.push(head(None, "5"));
+        let (_, properties) = integration.head_with_properties(&location).await.unwrap();
+        assert_eq!(properties.blob_type, BlobType::Block);
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_stage_blocks_out_of_order() {
+        let mock = MockServer::new();
+        let integration = mock_builder(&mock).build().unwrap();
+        let location = Path::from("blob");
+        let block_id = |idx: usize| base64::encode(format!("{:20}", idx));
+
+        for idx in [2, 0, 1] {
+            let expected = block_id(idx);
+            mock.push_fn(move |req| {
+                assert_eq!(req.method(), Method::PUT);
+                let query = req.uri().query().unwrap().as_bytes();
+                let blockid = url::form_urlencoded::parse(query)
+                    .find(|(k, _)| k == "blockid")
+                    .unwrap();
+                assert_eq!(blockid.1, expected);
+                Response::new(Body::empty())
+            });
+            let data = Bytes::from(vec![idx as u8; 4]);
+            integration.stage_block(&location, idx, data).await.unwrap();
+        }
+
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        mock.push_async_fn(move |req| async move {
+            let body = hyper::body::to_bytes(req).await.unwrap();
+            tx.send(body).unwrap();
+            Response::new(Body::empty())
+        });
+        integration
+            .commit_blocks(&location, [2, 0, 1])
+            .await
+            .unwrap();
+
+        let body = rx.await.unwrap();
+        let body = std::str::from_utf8(&body).unwrap();
+        let positions: Vec<_> = (0..3)
+            .map(|idx| body.find(&block_id(idx)).unwrap())
+            .collect();
+        assert!(positions.windows(2).all(|w| w[0] < w[1]), "{}", body);
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_default_headers() {
+        use hyper::header::HeaderValue;
+
+        let mock = MockServer::new();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-corp-trace-id", HeaderValue::from_static("sentinel"));
+        headers.insert("x-ms-version", HeaderValue::from_static("1999-01-01"));
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        let integration = mock_builder(&mock)
+            .with_client_options(
+                ClientOptions::new()
+                    .with_default_headers(headers)
+                    .with_content_type_for_suffix("json", "application/json"),
+            )
+            .with_allow_http(true)
+            .build()
+            .unwrap();
+
+        mock.push_fn(|req| {
+            let headers = req.headers();
+            assert_eq!(headers["x-corp-trace-id"], "sentinel");
+            // Headers set by the store take precedence
+            let versions: Vec<_> = headers.get_all("x-ms-version").iter().collect();
+            assert_eq!(versions, vec!["2021-08-06"]);
+            let content_types: Vec<_> = headers.get_all(CONTENT_TYPE).iter().collect();
+            assert_eq!(content_types, vec!["application/json"]);
+            Response::new(Body::empty())
+        });
+        let location = Path::from("file.json");
+        integration.put(&location, Bytes::from("{}")).await.unwrap();
+
+        mock.push_fn(|req| {
+            assert_eq!(req.headers()["x-corp-trace-id"], "sentinel");
+            assert_eq!(req.headers()[CONTENT_TYPE], "text/plain");
+            Response::new(Body::empty())
+        });
+        integration.delete(&location).await.unwrap();
+
+        mock.shutdown().await
+    }
+
+    #[test]
+    fn azure_prefer_oauth() {
+        let builder = || {
+            MicrosoftAzureBuilder::new()
+                .with_account("account")
+                .with_container_name("container")
+                .with_access_key(EMULATOR_ACCOUNT_KEY)
+                .with_client_secret_authorization(
+                    "client_id",
+                    "client_secret",
+                    "tenant_id",
+                )
+        };
+
+        let azure = builder().build().unwrap();
+        let config = azure.client.config();
+        assert!(matches!(
+            config.credentials,
+            credential::CredentialProvider::AccessKey(_)
+        ));
+        assert_eq!(config.account_key.as_deref(), Some(EMULATOR_ACCOUNT_KEY));
+
+        let azure = builder().with_prefer_oauth(true).build().unwrap();
+        let config = azure.client.config();
+        assert!(matches!(
+            config.credentials,
+            credential::CredentialProvider::ClientSecret(_)
+        ));
+        assert_eq!(config.account_key.as_deref(), Some(EMULATOR_ACCOUNT_KEY));
+
+        // Without OAuth credentials the access key is still used
+        let azure = MicrosoftAzureBuilder::new()
+            .with_account("account")
+            .with_container_name("container")
+            .with_access_key(EMULATOR_ACCOUNT_KEY)
+            .with_prefer_oauth(true)
+            .build()
+            .unwrap();
+        assert!(matches!(
+            azure.client.config().credentials,
+            credential::CredentialProvider::AccessKey(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn azure_set_expiry() {
+        let mock = MockServer::new();
+        let integration = mock_builder(&mock).build().unwrap();
+
+        let location = Path::from("scratch");
+        let cases = [
+            (
+                ExpiryOption::RelativeToNow(std::time::Duration::from_secs(60)),
+                "RelativeToNow",
+                Some("60000"),
+            ),
+            (
+                ExpiryOption::RelativeToCreation(std::time::Duration::from_millis(1500)),
+                "RelativeToCreation",
+                Some("1500"),
+            ),
+            (
+                ExpiryOption::Absolute(
+                    Utc.with_ymd_and_hms(2023, 1, 2, 3, 4, 5).unwrap(),
+                ),
+                "Absolute",
+                Some("Mon, 02 Jan 2023 03:04:05 GMT"),
+            ),
+            (ExpiryOption::NeverExpire, "NeverExpire", None),
+        ];
+
+        for (expiry, option, time) in cases {
+            mock.push_fn(move |req: hyper::Request<Body>| {
+                assert_eq!(req.method(), Method::PUT);
+                assert_eq!(req.uri().path(), "/account/container/scratch");
+                assert_eq!(req.uri().query(), Some("comp=expiry"));
+                let headers = req.headers();
+                assert_eq!(headers.get("x-ms-expiry-option").unwrap(), option);
+                assert_eq!(
+                    headers.get("x-ms-expiry-time").map(|v| v.to_str().unwrap()),
+                    time
+                );
+                Response::new(Body::empty())
+            });
+            integration.set_expiry(&location, expiry).await.unwrap();
+        }
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_max_response_body_size() {
+        let mock = MockServer::new();
+        let body = list_response(&[("a", "0x1"), ("b", "0x2")]);
+
+        let integration = mock_builder(&mock)
+            .with_max_response_body_size(body.len())
+            .build()
+            .unwrap();
+
+        mock.push(Response::new(Body::from(body.clone())));
+        let result = integration.list_with_delimiter(None).await.unwrap();
+        assert_eq!(result.objects.len(), 2);
+
+        // Rejected by its content length
+        let larger = list_response(&[("a", "0x1"), ("b", "0x2"), ("c", "0x3")]);
+        mock.push(Response::new(Body::from(larger.clone())));
+        let err = integration.list_with_delimiter(None).await.unwrap_err();
+        assert!(err.to_string().contains("exceeded the limit"), "{}", err);
+
+        // Rejected while streaming a body without a content length
+        let (mut sender, streamed) = Body::channel();
+        tokio::spawn(async move {
+            for chunk in larger.into_bytes().chunks(64) {
+                let chunk = Bytes::copy_from_slice(chunk);
+                if sender.send_data(chunk).await.is_err() {
+                    break;
+                }
+            }
+        });
+        mock.push(Response::new(streamed));
+        let err = integration.list_with_delimiter(None).await.unwrap_err();
+        assert!(err.to_string().contains("exceeded the limit"), "{}", err);
+
+        mock.shutdown().await
+    }
+
+    #[test]
+    fn azure_blob_sas() {
+        let resource = "/blob/account/container/dir/file.txt";
+        let expiry = Utc.with_ymd_and_hms(2023, 1, 2, 3, 4, 5).unwrap();
+        let signature = |pairs: &[(&str, String)]| {
+            pairs.iter().find(|(k, _)| *k == "sig").unwrap().1.clone()
+        };
+
+        let key = credential::SasKey::AccessKey(EMULATOR_ACCOUNT_KEY);
+        let pairs = credential::blob_sas(resource, "r", &expiry, key).unwrap();
+        let keys: Vec<_> = pairs.iter().map(|(k, _)| *k).collect();
+        assert_eq!(keys, ["sv", "sr", "sp", "se", "sig"]);
+        assert_eq!(pairs[3].1, "2023-01-02T03:04:05Z");
+        assert_eq!(
+            signature(&pairs),
+            "pFr8ZzJofekVZELtdFGSY0HwmMpAeWYePXMlL6nBjBU="
+        );
+
+        let delegation_key = credential::UserDelegationKey {
+            signed_oid: "oid".to_string(),
+            signed_tid: "tid".to_string(),
+            signed_start: "2023-01-01T00:00:00Z".to_string(),
+            signed_expiry: "2023-01-03T00:00:00Z".to_string(),
+            signed_service: "b".to_string(),
+            signed_version: "2021-08-06".to_string(),
+            value: "ZGVsZWdhdGlvbi1rZXktdmFsdWUtMDEyMzQ1Njc4OWFi".to_string(),
+        };
+        let key = credential::SasKey::UserDelegation(&delegation_key);
+        let pairs = credential::blob_sas(resource, "r", &expiry, key).unwrap();
+        let keys: Vec<_> = pairs.iter().map(|(k, _)| *k).collect();
+        assert_eq!(
+            keys,
+            [
+                "sv", "sr", "sp", "se", "skoid", "sktid", "skt", "ske", "sks", "skv",
+                "sig"
+            ]
+        );
+        assert_eq!(
+            signature(&pairs),
+            "HBStaZs5S3agHatAUDEyvJT4yt7VKTkW4hNmnSwW50M="
+        );
+
+        let key = credential::SasKey::AccessKey("not base64!");
+        let err = credential::blob_sas(resource, "r", &expiry, key).unwrap_err();
+        assert!(
+            matches!(err, credential::Error::InvalidSasKey { .. }),
+            "{}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn azure_signed_url() {
+        let mock = MockServer::new();
+        let location = Path::from("dir/file.txt");
+        let query_keys = |url: &Url| -> Vec<String> {
+            url.query_pairs().map(|(k, _)| k.into_owned()).collect()
+        };
+
+        // Signed with the access key
+        let integration = mock_builder(&mock).build().unwrap();
+        let url = integration
+            .signed_url(&location, Duration::from_secs(60), "r")
+            .await
+            .unwrap();
+        assert_eq!(url.path(), "/account/container/dir/file.txt");
+        assert_eq!(query_keys(&url), ["sv", "sr", "sp", "se", "sig"]);
+
+        // The url is usable without any other credentials
+        let expected = url.query().unwrap().to_string();
+        mock.push_fn(move |req: hyper::Request<Body>| {
+            assert_eq!(req.uri().query(), Some(expected.as_str()));
+            assert!(!req.headers().contains_key(AUTHORIZATION));
+            Response::new(Body::from("data"))
+        });
+        let response = reqwest::get(url).await.unwrap();
+        assert_eq!(response.bytes().await.unwrap(), "data");
+
+        // Signed with a user delegation key fetched with OAuth credentials
+        let integration = MicrosoftAzureBuilder::new()
+            .with_account("account")
+            .with_container_name("container")
+            .with_client_secret_authorization("client", "secret", "tenant")
+            .with_authority_host(mock.url().to_string())
+            .with_endpoint(mock.url())
+            .with_use_path_style(true)
+            .with_allow_http(true)
+            .build()
+            .unwrap();
+        mock.push(Response::new(Body::from(
+            r#"{"access_token": "token", "expires_in": 3600}"#,
+        )));
+        mock.push_async_fn(|req: hyper::Request<Body>| async move {
+            assert_eq!(req.method(), Method::POST);
+            assert_eq!(req.uri().path(), "/account");
+            assert_eq!(
+                req.uri().query(),
+                Some("restype=service&comp=userdelegationkey")
+            );
+            assert_eq!(req.headers()[AUTHORIZATION], "Bearer token");
+            let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+            let body = String::from_utf8(body.to_vec()).unwrap();
+            assert!(body.contains("<KeyInfo><Start>"), "{}", body);
+            Response::new(Body::from(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?><UserDelegationKey>\
+                <SignedOid>oid</SignedOid><SignedTid>tid</SignedTid>\
+                <SignedStart>2023-01-01T00:00:00Z</SignedStart>\
+                <SignedExpiry>2023-01-03T00:00:00Z</SignedExpiry>\
+                <SignedService>b</SignedService><SignedVersion>2021-08-06</SignedVersion>\
+                <Value>ZGVsZWdhdGlvbi1rZXktdmFsdWUtMDEyMzQ1Njc4OWFi</Value>\
+                </UserDelegationKey>",
+            ))
+        });
+        let url = integration
+            .signed_url(&location, Duration::from_secs(60), "r")
+            .await
+            .unwrap();
+        assert_eq!(
+            query_keys(&url),
+            [
+                "sv", "sr", "sp", "se", "skoid", "sktid", "skt", "ske", "sks", "skv",
+                "sig"
+            ]
+        );
+
+        // A SAS token cannot sign urls
+        let integration = MicrosoftAzureBuilder::new()
+            .with_account("account")
+            .with_container_name("container")
+            .with_sas_authorization(vec![("sig".to_string(), "sig".to_string())])
+            .with_endpoint(mock.url())
+            .with_use_path_style(true)
+            .with_allow_http(true)
+            .build()
+            .unwrap();
+        let err = integration
+            .signed_url(&location, Duration::from_secs(60), "r")
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("requires an access key"),
+            "{}",
+            err
+        );
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_response_info() {
+        let mock = MockServer::new();
+        let integration = mock_builder(&mock).build().unwrap();
+
+        let location = Path::from("file");
+        let response = |status| {
+            Response::builder()
+                .status(status)
+                .header(CONTENT_LENGTH, "5")
+                .header(LAST_MODIFIED, "Mon, 02 Jan 2023 00:00:00 GMT")
+                .header("x-ms-new-feature", "enabled")
+                .body(Body::from("hello"))
+                .unwrap()
+        };
+
+        mock.push(response(StatusCode::CREATED));
+        let info = integration
+            .put_with_response_info(&location, Bytes::from("hello"), Default::default())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.status, StatusCode::CREATED);
+        assert_eq!(info.headers["x-ms-new-feature"], "enabled");
+
+        mock.push(response(StatusCode::OK));
+        let (result, properties) = integration
+            .get_with_properties(&location, Default::default())
+            .await
+            .unwrap();
+        let info = properties.response;
+        assert_eq!(info.status, StatusCode::OK);
+        assert_eq!(info.headers["x-ms-new-feature"], "enabled");
+        assert_eq!(result.bytes().await.unwrap(), "hello");
+
+        mock.push_fn(move |req: hyper::Request<Body>| {
+            assert_eq!(req.method(), Method::HEAD);
+            response(StatusCode::OK)
+        });
+        let (meta, properties) =
+            integration.head_with_properties(&location).await.unwrap();
+        let info = properties.response;
+        assert_eq!(meta.size, 5);
+        assert_eq!(info.headers["x-ms-new-feature"], "enabled");
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_get_result_meta() {
+        let mock = MockServer::new();
+        let integration = mock_builder(&mock).build().unwrap();
+
+        let location = Path::from("file");
+        mock.push(
+            Response::builder()
+                .header(CONTENT_LENGTH, "11")
+                .header(LAST_MODIFIED, "Mon, 02 Jan 2023 00:00:00 GMT")
+                .header(ETAG, "0x1")
+                .body(Body::from("hello world"))
+                .unwrap(),
+        );
+        let result = integration.get(&location).await.unwrap();
+        let meta = result.meta().unwrap();
+        assert_eq!(meta.object.location, location);
+        assert_eq!(meta.object.size, 11);
+        assert_eq!(meta.object.e_tag.as_deref(), Some("0x1"));
+        assert_eq!(meta.range, None);
+        assert_eq!(result.bytes().await.unwrap(), "hello world");
+
+        mock.push(
+            Response::builder()
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(CONTENT_LENGTH, "3")
+                .header(CONTENT_RANGE, "bytes 1-3/11")
+                .header(LAST_MODIFIED, "Mon, 02 Jan 2023 00:00:00 GMT")
+                .body(Body::from("ell"))
+                .unwrap(),
+        );
+        let options = GetOptions {
+            range: Some(1..4),
+            ..Default::default()
+        };
+        let result = integration.get_opts(&location, options).await.unwrap();
+        let meta = result.meta().unwrap();
+        assert_eq!(meta.object.size, 11);
+        assert_eq!(meta.range, Some(1..4));
+
+        // Metadata is omitted rather than failing the request if headers are missing
+        mock.push(Response::new(Body::from("hello world")));
+        let result = integration.get(&location).await.unwrap();
+        assert!(result.meta().is_none());
+        assert_eq!(result.bytes().await.unwrap(), "hello world");
+
+        mock.shutdown().await
+    }
+
+    #[test]
+    fn azure_trim_credentials() {
+        let builder = MicrosoftAzureBuilder::new()
+            .try_with_option("azure_storage_account_key", "\u{feff}key\n")
+            .unwrap()
+            .try_with_option("azure_storage_client_secret", "secret\r\n")
+            .unwrap()
+            .try_with_option("azure_storage_sas_key", "\u{feff}sv=2021-10-04&sig=abc\n")
+            .unwrap()
+            .try_with_option("azure_storage_token", " token\n")
+            .unwrap();
+        assert_eq!(builder.access_key.as_deref(), Some("key"));
+        assert_eq!(builder.client_secret.as_deref(), Some("secret"));
+        assert_eq!(builder.sas_key.as_deref(), Some("sv=2021-10-04&sig=abc"));
+        assert_eq!(builder.bearer_token.as_deref(), Some("token"));
+
+        let builder = MicrosoftAzureBuilder::new()
+            .with_access_keys(["\u{feff}primary\n", "secondary\n"])
+            .with_client_secret_authorization("client", "\u{feff}secret", "tenant")
+            .with_bearer_token_authorization("token\n");
+        assert_eq!(builder.access_key.as_deref(), Some("primary"));
+        assert_eq!(builder.fallback_access_keys, ["secondary"]);
+        assert_eq!(builder.client_secret.as_deref(), Some("secret"));
+        assert_eq!(builder.bearer_token.as_deref(), Some("token"));
+
+        let builder = MicrosoftAzureBuilder::new().with_access_key("key\n");
+        assert_eq!(builder.access_key.as_deref(), Some("key"));
+
+        // A key read from a file can be used to sign requests
+        let azure = MicrosoftAzureBuilder::new()
+            .with_account("account")
+            .with_container_name("container")
+            .with_access_key(format!("\u{feff}{}\n", EMULATOR_ACCOUNT_KEY))
+            .build()
+            .unwrap();
+        assert_eq!(
+            azure.client.config().account_key.as_deref(),
+            Some(EMULATOR_ACCOUNT_KEY)
+        );
+    }
+
+    #[tokio::test]
+    async fn azure_put_atomic() {
+        let mock = MockServer::new();
+
+        let builder = MicrosoftAzureBuilder::new()
+            .with_account("account")
+            .with_container_name("container")
+            .with_access_key(EMULATOR_ACCOUNT_KEY)
+            .with_endpoint(mock.url())
+            .with_dfs_endpoint(mock.url())
+            .with_allow_http(true);
+        let is_temporary = |path: &str| {
+            let name = path.strip_prefix("/container/a%20b.").unwrap();
+            name.len() == 20 && name.ends_with(".tmp")
+        };
+
+        // Without a hierarchical namespace, the temporary blob is copied and deleted
+        let integration = builder.clone().build().unwrap();
+        mock.push_fn(move |req| {
+            assert_eq!(req.method(), Method::PUT);
+            assert!(is_temporary(req.uri().path()), "{}", req.uri());
+            Response::builder()
+                .header(ETAG, "\"0x1\"")
+                .body(Body::empty())
+                .unwrap()
+        });
+        mock.push_fn(move |req| {
+            assert_eq!(req.method(), Method::PUT);
+            assert_eq!(req.uri().path(), "/container/a%20b");
+            let source = req.headers()["x-ms-copy-source"].to_str().unwrap();
+            assert!(is_temporary(&source[source.find("/container").unwrap()..]));
+            assert_eq!(req.headers()["x-ms-source-if-match"], "\"0x1\"");
+            Response::builder()
+                .status(StatusCode::ACCEPTED)
+                .body(Body::empty())
+                .unwrap()
+        });
+        mock.push_fn(move |req| {
+            assert_eq!(req.method(), Method::DELETE);
+            assert!(is_temporary(req.uri().path()), "{}", req.uri());
+            Response::builder()
+                .status(StatusCode::ACCEPTED)
+                .body(Body::empty())
+                .unwrap()
+        });
+        integration
+            .put_atomic(&Path::from("a b"), Bytes::from("data"))
+            .await
+            .unwrap();
+
+        // With one, it is renamed
+        let integration = builder.with_hierarchical_namespace(true).build().unwrap();
+        mock.push_fn(move |req| {
+            assert!(is_temporary(req.uri().path()), "{}", req.uri());
+            Response::new(Body::empty())
+        });
+        mock.push_fn(move |req| {
+            assert_eq!(req.method(), Method::PUT);
+            assert_eq!(req.uri().path(), "/container/a%20b");
+            let source = req.headers()["x-ms-rename-source"].to_str().unwrap();
+            assert!(is_temporary(source), "{}", source);
+            Response::builder()
+                .status(StatusCode::CREATED)
+                .body(Body::empty())
+                .unwrap()
+        });
+        integration
+            .put_atomic(&Path::from("a b"), Bytes::from("data"))
+            .await
+            .unwrap();
+
+        // Unless the rename fails, when it is deleted
+        mock.push(Response::new(Body::empty()));
+        mock.push(
+            Response::builder()
+                .status(StatusCode::CONFLICT)
+                .body(Body::empty())
+                .unwrap(),
+        );
+        mock.push_fn(move |req| {
+            assert_eq!(req.method(), Method::DELETE);
+            assert!(is_temporary(req.uri().path()), "{}", req.uri());
+            Response::builder()
+                .status(StatusCode::ACCEPTED)
+                .body(Body::empty())
+                .unwrap()
+        });
+        let err = integration
+            .put_atomic(&Path::from("a b"), Bytes::from("data"))
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Error performing dfs request"), "{}", err);
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_list_with_delimiter_literal() {
+        let mock = MockServer::new();
+
+        let integration = mock_builder(&mock).build().unwrap();
+
+        let response = |prefixes: &str, blobs: &str| {
+            Response::new(Body::from(format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?><EnumerationResults>\
+                <Blobs>{}{}</Blobs><NextMarker /></EnumerationResults>",
+                prefixes, blobs
+            )))
+        };
+        let blob = |name: &str| {
+            format!(
+                "<Blob><Name>{}</Name><Properties>\
+                <Last-Modified>Thu, 01 Jul 2021 10:45:02 GMT</Last-Modified>\
+                <Etag>0x1</Etag><Content-Length>5</Content-Length>\
+                <Content-Type>text/plain</Content-Type></Properties></Blob>",
+                name
+            )
+        };
+
+        // `foo` is a directory, and so only `foo/` is listed
+        let body = response("", &blob("foo/a"));
+        mock.push_fn(move |req| {
+            let query = req.uri().query().unwrap();
+            assert!(query.contains("prefix=foo%2F&delimiter=%2F"), "{}", query);
+            body
+        });
+        let result = integration
+            .list_with_delimiter(Some(&Path::from("foo")))
+            .await
+            .unwrap();
+        assert!(result.common_prefixes.is_empty());
+        assert_eq!(result.objects[0].location, Path::from("foo/a"));
+
+        // Whereas as a literal prefix it also matches `foobar/`
+        let body = response(
+            "<BlobPrefix><Name>foo/</Name></BlobPrefix>\
+            <BlobPrefix><Name>foobar/</Name></BlobPrefix>",
+            &blob("foo.txt"),
+        );
+        mock.push_fn(move |req| {
+            let query = req.uri().query().unwrap();
+            assert!(query.contains("prefix=foo&delimiter=%2F"), "{}", query);
+            body
+        });
+        let result = integration
+            .list_with_delimiter_literal(&Path::from("foo"))
+            .await
+            .unwrap();
+        assert_eq!(
+            result.common_prefixes,
+            vec![Path::from("foo"), Path::from("foobar")]
+        );
+        assert_eq!(result.objects[0].location, Path::from("foo.txt"));
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_append() {
+        let mock = MockServer::new();
+
+        let builder = mock_builder(&mock);
+        let integration = builder.clone().build().unwrap();
+        let location = Path::from("log");
+        let status = |status: StatusCode| {
+            Response::builder()
+                .status(status)
+                .body(Body::empty())
+                .unwrap()
+        };
+        let error = |status: StatusCode, code: &str| {
+            Response::builder()
+                .status(status)
+                .body(Body::from(format!("<Error><Code>{}</Code></Error>", code)))
+                .unwrap()
+        };
+
+        // Creates the blob if it does not exist
+        mock.push(error(StatusCode::NOT_FOUND, "BlobNotFound"));
+        mock.push_fn(|req| {
+            assert_eq!(req.method(), Method::PUT);
+            assert_eq!(req.uri().path(), "/account/container/log");
+            assert_eq!(req.uri().query(), None);
+            assert_eq!(req.headers()["x-ms-blob-type"], "AppendBlob");
+            assert_eq!(req.headers()[IF_NONE_MATCH], "*");
+            Response::builder()
+                .status(StatusCode::CREATED)
+                .body(Body::empty())
+                .unwrap()
+        });
+        mock.push_async_fn(|req| async move {
+            assert_eq!(req.uri().query(), Some("comp=appendblock"));
+            assert!(!req.headers().contains_key("x-ms-blob-condition-appendpos"));
+            let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+            assert_eq!(body.as_ref(), b"line 1\n");
+            Response::builder()
+                .status(StatusCode::CREATED)
+                .body(Body::empty())
+                .unwrap()
+        });
+        integration
+            .append(&location, Bytes::from("line 1\n"))
+            .await
+            .unwrap();
+
+        // Appends at a position
+        mock.push_fn(|req| {
+            assert_eq!(req.uri().query(), Some("comp=appendblock"));
+            assert_eq!(req.headers()["x-ms-blob-condition-appendpos"], "7");
+            Response::builder()
+                .status(StatusCode::CREATED)
+                .body(Body::empty())
+                .unwrap()
+        });
+        integration
+            .append_at(&location, Bytes::from("line 2\n"), 7)
+            .await
+            .unwrap();
+
+        mock.push(error(
+            StatusCode::PRECONDITION_FAILED,
+            "AppendPositionConditionNotMet",
+        ));
+        let err = integration
+            .append_at(&location, Bytes::from("line 2\n"), 7)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, crate::Error::Precondition { .. }), "{}", err);
+
+        // Appends without a position are not retried
+        mock.push(status(StatusCode::INTERNAL_SERVER_ERROR));
+        let err = integration
+            .append(&location, Bytes::from("line 3\n"))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("append block request"), "{}", err);
+
+        // Reports the limits of append blobs
+        mock.push(error(StatusCode::CONFLICT, "BlockCountExceedsLimit"));
+        let err = integration
+            .append(&location, Bytes::from("line 3\n"))
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("maximum of 50000 blocks"), "{}", err);
+
+        let large = Bytes::from(vec![0; 4 * 1024 * 1024 + 1]);
+        let err = integration
+            .append(&location, large)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("Cannot append 4194305 bytes to log, as blocks are limited"),
+            "{}",
+            err
+        );
+
+        // Does not create the blob if disabled
+        let integration = builder
+            .try_with_option(AzureConfigKey::AppendCreate, "false")
+            .unwrap()
+            .build()
+            .unwrap();
+        mock.push(error(StatusCode::NOT_FOUND, "BlobNotFound"));
+        let err = integration
+            .append(&location, Bytes::from("line 1\n"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, crate::Error::NotFound { .. }), "{}", err);
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_put_attributes() {
+        let mock = MockServer::new();
+
+        let integration = mock_builder(&mock).build().unwrap();
+        let location = Path::from("index.html.gz");
+        let options = || PutOptions {
+            content_type: Some("text/html".to_string()),
+            content_encoding: Some("gzip".to_string()),
+            cache_control: Some("max-age=3600".to_string()),
+            metadata: HashMap::from([("owner".to_string(), "web".to_string())]),
+            ..Default::default()
+        };
+        let assert_attributes = |headers: &hyper::HeaderMap| {
+            assert_eq!(headers["x-ms-blob-content-type"], "text/html");
+            assert_eq!(headers["x-ms-blob-content-encoding"], "gzip");
+            assert_eq!(headers["x-ms-blob-cache-control"], "max-age=3600");
+            assert_eq!(headers["x-ms-meta-owner"], "web");
+        };
+
+        // Set on Put Blob
+        mock.push_fn(move |req| {
+            assert_eq!(req.uri().query(), None);
+            assert_attributes(req.headers());
+            Response::new(Body::empty())
+        });
+        integration
+            .put_opts(&location, Bytes::from("data"), options())
+            .await
+            .unwrap();
+
+        // And when committing a multipart upload
+        mock.push_fn(|req| {
+            assert!(req.uri().query().unwrap().starts_with("comp=block"));
+            assert!(!req.headers().contains_key("x-ms-meta-owner"));
+            Response::new(Body::empty())
+        });
+        mock.push_fn(move |req| {
+            assert_eq!(req.uri().query(), Some("comp=blocklist"));
+            assert_attributes(req.headers());
+            Response::new(Body::empty())
+        });
+        let (_, mut writer) = integration
+            .put_multipart_opts(&location, options())
+            .await
+            .unwrap();
+        writer.write_all(b"data").await.unwrap();
+        writer.shutdown().await.unwrap();
+
+        // Invalid metadata keys are rejected
+        let mut invalid = options();
+        invalid
+            .metadata
+            .insert("not valid".to_string(), "a".to_string());
+        integration
+            .put_opts(&location, Bytes::from("data"), invalid)
+            .await
+            .unwrap_err();
+
+        // Which can be read back
+        mock.push(
+            Response::builder()
+                .header(CONTENT_LENGTH, "4")
+                .header(LAST_MODIFIED, "Thu, 01 Jul 2021 10:45:02 GMT")
+                .header(CONTENT_TYPE, "text/html")
+                .header(CONTENT_ENCODING, "gzip")
+                .header(CACHE_CONTROL, "max-age=3600")
+                .header("x-ms-meta-Owner", "web")
+                .body(Body::empty())
+                .unwrap(),
+        );
+        let (meta, properties) =
+            integration.head_with_properties(&location).await.unwrap();
+        assert_eq!(meta.size, 4);
+        assert_eq!(properties.content_type.as_deref(), Some("text/html"));
+        assert_eq!(properties.content_encoding.as_deref(), Some("gzip"));
+        assert_eq!(properties.content_language, None);
+        assert_eq!(properties.cache_control.as_deref(), Some("max-age=3600"));
+        assert_eq!(
+            properties.metadata,
+            HashMap::from([("owner".to_string(), "web".to_string())])
+        );
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_default_metadata() {
+        let mock = MockServer::new();
+
+        let integration = mock_builder(&mock)
+            .with_default_metadata(HashMap::from([
+                ("owner".to_string(), "ingest".to_string()),
+                ("team".to_string(), "data".to_string()),
+            ]))
+            .build()
+            .unwrap();
+        let location = Path::from("file");
 
-            let force = std::env::var("TEST_INTEGRATION");
+        // Sent with every Put Blob
+        mock.push_fn(|req| {
+            assert_eq!(req.headers()["x-ms-meta-owner"], "ingest");
+            assert_eq!(req.headers()["x-ms-meta-team"], "data");
+            Response::new(Body::empty())
+        });
+        integration
+            .put(&location, Bytes::from("data"))
+            .await
+            .unwrap();
 
-            if force.is_ok() && !unset_var_names.is_empty() {
-                panic!(
-                    "TEST_INTEGRATION is set, \
-                        but variable(s) {} need to be set",
-                    unset_var_names
-                )
-            } else if force.is_err() {
-                eprintln!(
-                    "skipping Azure integration test - set {}TEST_INTEGRATION to run",
-                    if unset_var_names.is_empty() {
-                        String::new()
-                    } else {
-                        format!("{} and ", unset_var_names)
-                    }
-                );
-                return;
-            } else {
-                let builder = MicrosoftAzureBuilder::new()
-                    .with_container_name(
-                        env::var("OBJECT_STORE_BUCKET")
-                            .expect("already checked OBJECT_STORE_BUCKET"),
-                    )
-                    .with_use_emulator(use_emulator);
-                if !use_emulator {
-                    builder
-                        .with_account(
-                            env::var("AZURE_STORAGE_ACCOUNT").unwrap_or_default(),
-                        )
-                        .with_access_key(
-                            env::var("AZURE_STORAGE_ACCESS_KEY").unwrap_or_default(),
-                        )
-                } else {
-                    builder
-                }
-            }
-        }};
+        // Metadata of the write takes precedence
+        mock.push_fn(|req| {
+            let owners: Vec<_> =
+                req.headers().get_all("x-ms-meta-owner").iter().collect();
+            assert_eq!(owners, vec!["backfill"]);
+            assert_eq!(req.headers()["x-ms-meta-team"], "data");
+            assert_eq!(req.headers()["x-ms-meta-run"], "7");
+            Response::new(Body::empty())
+        });
+        let options = PutOptions {
+            metadata: HashMap::from([
+                ("owner".to_string(), "backfill".to_string()),
+                ("run".to_string(), "7".to_string()),
+            ]),
+            ..Default::default()
+        };
+        integration
+            .put_opts(&location, Bytes::from("data"), options)
+            .await
+            .unwrap();
+
+        // And when committing a multipart upload, but not staging blocks
+        mock.push_fn(|req| {
+            assert!(req.uri().query().unwrap().starts_with("comp=block"));
+            assert!(!req.headers().contains_key("x-ms-meta-owner"));
+            Response::new(Body::empty())
+        });
+        mock.push_fn(|req| {
+            assert_eq!(req.uri().query(), Some("comp=blocklist"));
+            assert_eq!(req.headers()["x-ms-meta-owner"], "ingest");
+            assert_eq!(req.headers()["x-ms-meta-team"], "data");
+            Response::new(Body::empty())
+        });
+        let (_, mut writer) = integration.put_multipart(&location).await.unwrap();
+        writer.write_all(b"data").await.unwrap();
+        writer.shutdown().await.unwrap();
+
+        // Invalid keys are rejected when building
+        MicrosoftAzureBuilder::new()
+            .with_account("account")
+            .with_container_name("container")
+            .with_access_key(EMULATOR_ACCOUNT_KEY)
+            .with_default_metadata(HashMap::from([(
+                "not valid".to_string(),
+                "a".to_string(),
+            )]))
+            .build()
+            .unwrap_err();
+
+        mock.shutdown().await;
     }
 
     #[tokio::test]
-    async fn azure_blob_test() {
-        let use_emulator = env::var("AZURE_USE_EMULATOR").is_ok();
-        let integration = maybe_skip_integration!().build().unwrap();
-        // Azurite doesn't support listing with spaces - https://github.com/localstack/localstack/issues/6328
-        put_get_delete_list_opts(&integration, use_emulator).await;
-        list_uses_directories_correctly(&integration).await;
-        list_with_delimiter(&integration).await;
-        rename_and_copy(&integration).await;
-        copy_if_not_exists(&integration).await;
-        stream_get(&integration).await;
+    async fn azure_find_incomplete_uploads() {
+        let mock = MockServer::new();
+
+        let integration = mock_builder(&mock).build().unwrap();
+
+        // `uploads/b` has never been committed, and so has no content type
+        mock.push_fn(|req| {
+            let query = req.uri().query().unwrap();
+            assert!(query.contains("prefix=uploads%2F"), "{}", query);
+            assert!(query.contains("include=uncommittedblobs"), "{}", query);
+            let blob = |name: &str, content_type: &str| {
+                format!(
+                    "<Blob><Name>{}</Name><Properties>\
+                    <Last-Modified>Thu, 01 Jul 2021 10:45:02 GMT</Last-Modified>\
+                    <Etag>0x1</Etag><Content-Length>0</Content-Length>{}\
+                    </Properties></Blob>",
+                    name, content_type
+                )
+            };
+            Response::new(Body::from(format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?><EnumerationResults>\
+                <Blobs>{}{}{}</Blobs><NextMarker /></EnumerationResults>",
+                blob("uploads/a", "<Content-Type>text/plain</Content-Type>"),
+                blob("uploads/b", ""),
+                blob("uploads/c", ""),
+            )))
+        });
+
+        // Block lists are requested concurrently, and so in any order
+        let block_list = |req: hyper::Request<Body>| {
+            let query = req.uri().query().unwrap();
+            assert!(query.contains("blocklisttype=uncommitted"), "{}", query);
+            let block = |id: &str, size: usize| {
+                format!("<Block><Name>{}</Name><Size>{}</Size></Block>", id, size)
+            };
+            let blocks = match req.uri().path() {
+                "/account/container/uploads/a" => String::new(),
+                "/account/container/uploads/b" => [
+                    block(&base64::encode(block_content_id(2)), 4),
+                    block(&base64::encode(block_content_id(0)), 10),
+                    block("b3RoZXI=", 1),
+                ]
+                .concat(),
+                _ => {
+                    return Response::builder()
+                        .status(StatusCode::NOT_FOUND)
+                        .body(Body::empty())
+                        .unwrap()
+                }
+            };
+            Response::new(Body::from(format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+                <BlockList><UncommittedBlocks>{}</UncommittedBlocks></BlockList>",
+                blocks
+            )))
+        };
+        for _ in 0..3 {
+            mock.push_fn(block_list);
+        }
+
+        let uploads = integration
+            .find_incomplete_uploads(Some(&Path::from("uploads")))
+            .await
+            .unwrap();
+        assert_eq!(uploads.len(), 1);
+        assert_eq!(uploads[0].location, Path::from("uploads/b"));
+        assert_eq!(uploads[0].blocks.len(), 3);
+        assert_eq!(uploads[0].size, 15);
+        assert_eq!(uploads[0].indices(), vec![0, 2]);
+
+        mock.shutdown().await
     }
 
-    // test for running integration test against actual blob service with service principal
-    // credentials. To run make sure all environment variables are set and remove the ignore
     #[tokio::test]
-    #[ignore]
-    async fn azure_blob_test_sp() {
-        dotenv::dotenv().ok();
-        let builder = MicrosoftAzureBuilder::new()
-            .with_account(
-                env::var("AZURE_STORAGE_ACCOUNT")
-                    .expect("must be set AZURE_STORAGE_ACCOUNT"),
-            )
-            .with_container_name(
-                env::var("OBJECT_STORE_BUCKET").expect("must be set OBJECT_STORE_BUCKET"),
-            )
-            .with_access_key(
-                env::var("AZURE_STORAGE_ACCESS_KEY")
-                    .expect("must be set AZURE_STORAGE_CLIENT_ID"),
-            );
+    async fn azure_checksum_validation() {
+        let mock = MockServer::new();
+
+        let builder = mock_builder(&mock);
+        let integration = builder
+            .clone()
+            .with_client_options(ClientOptions::new().with_checksum_validation(true))
+            .with_allow_http(true)
+            .build()
+            .unwrap();
+        let location = Path::from("file");
+        // The MD5 of "data" and "at"
+        let md5_data = "jXd/OF09/siBXSD3SWAm3A==";
+        let md5_at = "fQ2zgKW5Wouh2gvKJBq9oQ==";
+        let response = |md5: &str, body: &'static str| {
+            Response::builder()
+                .header("Content-MD5", md5)
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        // Uploads send the MD5 of their data
+        mock.push_fn(move |req| {
+            assert_eq!(req.headers()["Content-MD5"], md5_data);
+            Response::new(Body::empty())
+        });
+        integration
+            .put(&location, Bytes::from("data"))
+            .await
+            .unwrap();
+
+        mock.push_fn(move |req| {
+            assert!(req.uri().query().unwrap().starts_with("comp=block"));
+            assert_eq!(req.headers()["Content-MD5"], md5_data);
+            Response::new(Body::empty())
+        });
+        integration
+            .stage_block(&location, 0, Bytes::from("data"))
+            .await
+            .unwrap();
+
+        // Which the service rejects if it does not match
+        mock.push(
+            Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from("<Error><Code>Md5Mismatch</Code></Error>"))
+                .unwrap(),
+        );
+        let err = integration
+            .put(&location, Bytes::from("data"))
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("did not match its Content-MD5"), "{}", err);
+
+        // Downloads are verified
+        mock.push(response(md5_data, "data"));
+        let data = integration.get(&location).await.unwrap().bytes().await;
+        assert_eq!(data.unwrap(), "data");
+
+        mock.push(response(md5_at, "data"));
+        let err = integration.get(&location).await.unwrap().bytes().await;
+        let err = err.unwrap_err().to_string();
+        assert!(err.contains(&format!("expected {}", md5_at)), "{}", err);
+
+        // Including those of ranges, whose MD5 must be requested
+        mock.push_fn(move |req| {
+            assert_eq!(req.headers()["x-ms-range-get-content-md5"], "true");
+            let mut response = response(md5_at, "at");
+            *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+            response
+        });
+        let data = integration.get_range(&location, 1..3).await.unwrap();
+        assert_eq!(data, "at");
+
+        mock.push(response(md5_data, "at"));
+        let err = integration.get_range(&location, 1..3).await.unwrap_err();
+        assert!(err.to_string().contains("has Content-MD5"), "{}", err);
+
+        // Nothing is validated by default
         let integration = builder.build().unwrap();
+        mock.push_fn(|req| {
+            assert!(!req.headers().contains_key("Content-MD5"));
+            Response::new(Body::empty())
+        });
+        integration
+            .put(&location, Bytes::from("data"))
+            .await
+            .unwrap();
 
-        put_get_delete_list(&integration).await;
-        list_uses_directories_correctly(&integration).await;
-        list_with_delimiter(&integration).await;
-        rename_and_copy(&integration).await;
-        copy_if_not_exists(&integration).await;
-        stream_get(&integration).await;
+        mock.push(response(md5_at, "data"));
+        let data = integration.get(&location).await.unwrap().bytes().await;
+        assert_eq!(data.unwrap(), "data");
+
+        mock.shutdown().await
     }
 
-    #[test]
-    fn azure_blob_test_urls() {
-        let mut builder = MicrosoftAzureBuilder::new();
-        builder
-            .parse_url("abfss://file_system@account.dfs.core.windows.net/")
-            .unwrap();
-        assert_eq!(builder.account_name, Some("account".to_string()));
-        assert_eq!(builder.container_name, Some("file_system".to_string()));
+    #[tokio::test]
+    async fn azure_copy_status() {
+        let mock = MockServer::new();
 
-        let mut builder = MicrosoftAzureBuilder::new();
-        builder.parse_url("abfs://container/path").unwrap();
-        assert_eq!(builder.container_name, Some("container".to_string()));
+        let integration = mock_builder(&mock).build().unwrap();
+        let status = |status: &str| {
+            Response::builder()
+                .status(StatusCode::ACCEPTED)
+                .header("x-ms-copy-status", status)
+                .header("x-ms-copy-status-description", "500 InternalError")
+                .body(Body::empty())
+                .unwrap()
+        };
+        let expect_head = |copy_status: &'static str| {
+            move |req: hyper::Request<Body>| {
+                assert_eq!(req.method(), Method::HEAD);
+                assert_eq!(req.uri().path(), "/account/container/to");
+                let mut response = status(copy_status);
+                *response.status_mut() = StatusCode::OK;
+                response
+            }
+        };
+        let from = Path::from("from");
+        let to = Path::from("to");
 
-        let mut builder = MicrosoftAzureBuilder::new();
-        builder.parse_url("az://container").unwrap();
-        assert_eq!(builder.container_name, Some("container".to_string()));
+        // Pending copies are polled until they complete
+        mock.push(status("pending"));
+        mock.push_fn(expect_head("pending"));
+        mock.push_fn(expect_head("success"));
+        integration.copy(&from, &to).await.unwrap();
 
-        let mut builder = MicrosoftAzureBuilder::new();
-        builder.parse_url("az://container/path").unwrap();
-        assert_eq!(builder.container_name, Some("container".to_string()));
+        // Failures are returned with their description
+        mock.push(status("pending"));
+        mock.push_fn(expect_head("failed"));
+        let err = integration.copy(&from, &to).await.unwrap_err().to_string();
+        assert!(
+            err.contains("Copy to to failed: 500 InternalError"),
+            "{}",
+            err
+        );
 
-        let mut builder = MicrosoftAzureBuilder::new();
-        builder
-            .parse_url("https://account.dfs.core.windows.net/")
+        mock.push(status("aborted"));
+        let err = integration.copy(&from, &to).await.unwrap_err().to_string();
+        assert!(err.contains("aborted"), "{}", err);
+
+        // Blobs in other accounts are copied from their url
+        let source = Url::parse(
+            "https://other.blob.core.windows.net/container/from?sv=2021-08-06&sig=secret",
+        )
+        .unwrap();
+        let expected = source.to_string();
+        mock.push_fn(move |req| {
+            assert_eq!(req.method(), Method::PUT);
+            assert_eq!(req.uri().path(), "/account/container/to");
+            assert_eq!(req.headers()["x-ms-copy-source"], expected.as_str());
+            status("success")
+        });
+        integration.copy_from_url(&source, &to).await.unwrap();
+
+        // Without the SAS token in errors
+        mock.push(
+            Response::builder()
+                .status(StatusCode::FORBIDDEN)
+                .body(Body::empty())
+                .unwrap(),
+        );
+        let err = integration
+            .copy_from_url(&source, &to)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("other.blob.core.windows.net/container/from"),
+            "{}",
+            err
+        );
+        assert!(!err.contains("secret"), "{}", err);
+
+        // Polling stops once the copy timeout elapses
+        let backoff = crate::BackoffConfig {
+            init_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(100),
+            base: 2.,
+            jitter: Some(0.),
+        };
+        let integration = mock_builder(&mock)
+            .with_retry(RetryConfig {
+                backoff,
+                ..Default::default()
+            })
+            .with_copy_timeout(Duration::from_millis(150))
+            .build()
             .unwrap();
-        assert_eq!(builder.account_name, Some("account".to_string()));
+        mock.push(status("pending"));
+        let mut head = status("pending");
+        let progress = hyper::header::HeaderValue::from_static("1024/4096");
+        head.headers_mut().insert("x-ms-copy-progress", progress);
+        mock.push(head);
+        let err = integration.copy(&from, &to).await.unwrap_err().to_string();
+        assert!(
+            err.contains("did not complete within 150ms, last progress 1024/4096"),
+            "{}",
+            err
+        );
 
-        let mut builder = MicrosoftAzureBuilder::new();
-        builder
-            .parse_url("https://account.blob.core.windows.net/")
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn azure_rename() {
+        let mock = MockServer::new();
+
+        let builder = mock_builder(&mock).with_dfs_endpoint(mock.url());
+
+        // Without a hierarchical namespace, the blob is copied and then deleted
+        let integration = builder.clone().build().unwrap();
+        mock.push_fn(|req| {
+            assert_eq!(req.method(), Method::PUT);
+            assert_eq!(req.uri().path(), "/account/container/b");
+            let source = req.headers()["x-ms-copy-source"].to_str().unwrap();
+            assert!(source.ends_with("/account/container/a"), "{}", source);
+            Response::builder()
+                .status(StatusCode::ACCEPTED)
+                .body(Body::empty())
+                .unwrap()
+        });
+        mock.push_fn(|req| {
+            assert_eq!(req.method(), Method::DELETE);
+            assert_eq!(req.uri().path(), "/account/container/a");
+            Response::builder()
+                .status(StatusCode::ACCEPTED)
+                .body(Body::empty())
+                .unwrap()
+        });
+        integration
+            .rename(&Path::from("a"), &Path::from("b"))
+            .await
             .unwrap();
-        assert_eq!(builder.account_name, Some("account".to_string()));
 
-        let err_cases = [
-            "mailto://account.blob.core.windows.net/",
-            "az://blob.mydomain/",
-            "abfs://container.foo/path",
-            "abfss://file_system@account.foo.dfs.core.windows.net/",
-            "abfss://file_system.bar@account.dfs.core.windows.net/",
-            "https://blob.mydomain/",
-            "https://blob.foo.dfs.core.windows.net/",
-        ];
-        let mut builder = MicrosoftAzureBuilder::new();
-        for case in err_cases {
-            builder.parse_url(case).unwrap_err();
+        // With one, blobs and directories are renamed with a single request
+        let integration = builder.with_hierarchical_namespace(true).build().unwrap();
+        for (from, to) in [("a", "b"), ("dir/a", "other/dir")] {
+            mock.push_fn(move |req| {
+                assert_eq!(req.method(), Method::PUT);
+                assert_eq!(req.uri().path(), format!("/account/container/{}", to));
+                assert_eq!(
+                    req.headers()["x-ms-rename-source"],
+                    format!("/container/{}", from).as_str()
+                );
+                Response::builder()
+                    .status(StatusCode::CREATED)
+                    .body(Body::empty())
+                    .unwrap()
+            });
+            integration
+                .rename(&Path::from(from), &Path::from(to))
+                .await
+                .unwrap();
         }
+
+        mock.push(
+            Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::empty())
+                .unwrap(),
+        );
+        let err = integration
+            .rename(&Path::from("missing"), &Path::from("b"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, crate::Error::NotFound { .. }), "{}", err);
+
+        mock.shutdown().await
     }
 
-    #[test]
-    fn azure_test_config_from_map() {
-        let azure_client_id = "object_store:fake_access_key_id";
-        let azure_storage_account_name = "object_store:fake_secret_key";
-        let azure_storage_token = "object_store:fake_default_region";
-        let options = HashMap::from([
-            ("azure_client_id", azure_client_id),
-            ("azure_storage_account_name", azure_storage_account_name),
-            ("azure_storage_token", azure_storage_token),
-        ]);
+    #[tokio::test]
+    async fn azure_prefix_store_put_opts() {
+        let mock = MockServer::new();
 
-        let builder = MicrosoftAzureBuilder::new()
-            .try_with_options(options)
+        let integration = mock_builder(&mock).build().unwrap();
+        let integration = crate::prefix::PrefixObjectStore::new(integration, "prefix");
+
+        // The attributes of the object are sent for the full path
+        mock.push_fn(|req| {
+            assert_eq!(req.uri().path(), "/account/container/prefix/file");
+            assert_eq!(req.headers()["x-ms-blob-content-type"], "text/csv");
+            assert_eq!(req.headers()["x-ms-blob-content-encoding"], "gzip");
+            assert_eq!(req.headers()["x-ms-blob-cache-control"], "no-cache");
+            assert_eq!(req.headers()["x-ms-meta-owner"], "ingest");
+            Response::builder()
+                .status(StatusCode::CREATED)
+                .body(Body::empty())
+                .unwrap()
+        });
+        let options = PutOptions {
+            content_type: Some("text/csv".to_string()),
+            content_encoding: Some("gzip".to_string()),
+            cache_control: Some("no-cache".to_string()),
+            metadata: HashMap::from([("owner".to_string(), "ingest".to_string())]),
+            ..Default::default()
+        };
+        integration
+            .put_opts(&Path::from("file"), Bytes::from("data"), options)
+            .await
             .unwrap();
-        assert_eq!(builder.client_id.unwrap(), azure_client_id);
-        assert_eq!(builder.account_name.unwrap(), azure_storage_account_name);
-        assert_eq!(builder.bearer_token.unwrap(), azure_storage_token);
+
+        mock.shutdown().await
     }
 
-    #[test]
-    fn azure_test_config_from_typed_map() {
-        let azure_client_id = "object_store:fake_access_key_id".to_string();
-        let azure_storage_account_name = "object_store:fake_secret_key".to_string();
-        let azure_storage_token = "object_store:fake_default_region".to_string();
-        let options = HashMap::from([
-            (AzureConfigKey::ClientId, azure_client_id.clone()),
-            (
-                AzureConfigKey::AccountName,
-                azure_storage_account_name.clone(),
-            ),
-            (AzureConfigKey::Token, azure_storage_token.clone()),
-        ]);
+    #[tokio::test]
+    async fn azure_limit_store_put_idempotency() {
+        let mock = MockServer::new();
 
-        let builder = MicrosoftAzureBuilder::new()
-            .try_with_options(&options)
+        let integration = mock_builder(&mock)
+            .with_idempotency_key_header("x-idempotency-key")
+            .build()
             .unwrap();
-        assert_eq!(builder.client_id.unwrap(), azure_client_id);
-        assert_eq!(builder.account_name.unwrap(), azure_storage_account_name);
-        assert_eq!(builder.bearer_token.unwrap(), azure_storage_token);
+        let integration = crate::limit::LimitStore::new(integration, 1);
+        let location = Path::from("file");
+
+        // The key of the write is sent through the wrapper
+        mock.push_fn(|req| {
+            assert_eq!(req.headers()["x-idempotency-key"], "put-1");
+            Response::builder()
+                .status(StatusCode::CREATED)
+                .body(Body::empty())
+                .unwrap()
+        });
+        let options = PutOptions {
+            idempotency_key: Some("put-1".to_string()),
+            ..Default::default()
+        };
+        integration
+            .put_opts(&location, "data".into(), options)
+            .await
+            .unwrap();
+
+        // And a non-idempotent write is not retried
+        mock.push(
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::empty())
+                .unwrap(),
+        );
+        let options = PutOptions {
+            non_idempotent: true,
+            ..Default::default()
+        };
+        let err = integration
+            .put_opts(&location, "data".into(), options)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("after 0 retries"), "{}", err);
+
+        mock.shutdown().await
     }
 
-    #[test]
-    fn azure_test_config_fallible_options() {
-        let azure_client_id = "object_store:fake_access_key_id".to_string();
-        let azure_storage_token = "object_store:fake_default_region".to_string();
-        let options = HashMap::from([
-            ("azure_client_id", azure_client_id),
-            ("invalid-key", azure_storage_token),
-        ]);
+    #[tokio::test]
+    async fn azure_list_with_offset() {
+        let mock = MockServer::new();
 
-        let builder = MicrosoftAzureBuilder::new().try_with_options(&options);
-        assert!(builder.is_err());
+        let integration = mock_builder(&mock).build().unwrap();
+
+        let page =
+            |expected: &'static str, prefixes: &[&str], blobs: &[&str], next: &str| {
+                let prefixes: String = prefixes
+                    .iter()
+                    .map(|name| format!("<BlobPrefix><Name>{}</Name></BlobPrefix>", name))
+                    .collect();
+                let blobs: String = blobs
+                    .iter()
+                    .map(|name| {
+                        format!(
+                            "<Blob><Name>{}</Name><Properties>\
+                        <Last-Modified>Thu, 01 Jul 2021 10:45:02 GMT</Last-Modified>\
+                        <Etag>0x1</Etag><Content-Length>5</Content-Length>\
+                        <Content-Type>text/plain</Content-Type></Properties></Blob>",
+                            name
+                        )
+                    })
+                    .collect();
+                let body = format!(
+                    "<?xml version=\"1.0\" encoding=\"utf-8\"?><EnumerationResults>\
+                <Blobs>{}{}</Blobs><NextMarker>{}</NextMarker></EnumerationResults>",
+                    prefixes, blobs, next
+                );
+                mock.push_fn(move |req| {
+                    let query = req.uri().query().unwrap();
+                    assert!(query.contains(expected), "{}", query);
+                    Response::new(Body::from(body))
+                });
+            };
+        let list = |expected, prefixes: &[&str], blobs: &[&str]| {
+            page(expected, prefixes, blobs, "")
+        };
+        async fn collect(
+            stream: BoxStream<'_, Result<ObjectMeta>>,
+        ) -> Result<Vec<String>> {
+            stream
+                .map_ok(|meta| meta.location.to_string())
+                .try_collect()
+                .await
+        }
+
+        // Only the directories containing the offset are listed with a delimiter,
+        // those before it are skipped, and those after it listed in full
+        list("comp=list&delimiter=%2F", &["a/", "b/", "c/"], &[]);
+        list("prefix=b%2F&delimiter=%2F", &["b/c/"], &["b/1", "b/1.txt"]);
+        list("prefix=b%2Fc%2F&delimiter=%2F", &[], &["b/c/1", "b/c/2"]);
+        list("prefix=c%2F", &[], &["c/1"]);
+        let offset = Path::from("b/c/1");
+        let stream = integration.list_with_offset(None, &offset).await.unwrap();
+        assert_eq!(collect(stream).await.unwrap(), ["b/c/2", "c/1"]);
+
+        // Nothing is listed for a prefix sorting before the offset
+        let prefix = Path::from("a");
+        let stream = integration
+            .list_with_offset(Some(&prefix), &offset)
+            .await
+            .unwrap();
+        assert!(collect(stream).await.unwrap().is_empty());
+
+        // Levels are streamed a page at a time, descending into the directory
+        // containing the offset before the next page is requested
+        page("comp=list&delimiter=%2F", &["a/", "b/"], &["0"], "next");
+        list("prefix=b%2F&delimiter=%2F", &[], &["b/c/1", "b/d"]);
+        list("marker=next", &["c/"], &[]);
+        list("prefix=c%2F", &[], &["c/1"]);
+        let stream = integration.list_with_offset(None, &offset).await.unwrap();
+        assert_eq!(collect(stream).await.unwrap(), ["b/d", "c/1"]);
+
+        mock.shutdown().await
     }
 
-    #[test]
-    fn azure_test_split_sas() {
-        let raw_sas = "?sv=2021-10-04&st=2023-01-04T17%3A48%3A57Z&se=2023-01-04T18%3A15%3A00Z&sr=c&sp=rcwl&sig=C7%2BZeEOWbrxPA3R0Cw%2Fw1EZz0%2B4KBvQexeKZKe%2BB6h0%3D";
-        let expected = vec![
-            ("sv".to_string(), "2021-10-04".to_string()),
-            ("st".to_string(), "2023-01-04T17:48:57Z".to_string()),
-            ("se".to_string(), "2023-01-04T18:15:00Z".to_string()),
-            ("sr".to_string(), "c".to_string()),
-            ("sp".to_string(), "rcwl".to_string()),
-            (
-                "sig".to_string(),
-                "C7+ZeEOWbrxPA3R0Cw/w1EZz0+4KBvQexeKZKe+B6h0=".to_string(),
-            ),
-        ];
-        let pairs = split_sas(raw_sas).unwrap();
-        assert_eq!(expected, pairs);
+    #[tokio::test]
+    async fn azure_get_to_file() {
+        let mock = MockServer::new();
+        let integration = mock_builder(&mock).build().unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("downloaded");
+        let location = Path::from("file");
+
+        // The metadata is that of the response, without a separate request
+        mock.push_fn(|req| {
+            assert_eq!(req.method(), Method::GET);
+            Response::builder()
+                .header(CONTENT_LENGTH, "5")
+                .header(LAST_MODIFIED, "Thu, 01 Jan 1970 00:00:00 GMT")
+                .body(Body::from("hello"))
+                .unwrap()
+        });
+        let meta = integration.get_to_file(&location, &dest).await.unwrap();
+        assert_eq!(meta.size, 5);
+        assert_eq!(std::fs::read(&dest).unwrap(), b"hello");
+
+        // A partially downloaded file is removed
+        mock.push_fn(|_| {
+            let (mut sender, body) = Body::channel();
+            tokio::spawn(async move {
+                sender.send_data(Bytes::from("hel")).await.unwrap();
+                sender.abort();
+            });
+            Response::builder()
+                .header(CONTENT_LENGTH, "5")
+                .header(LAST_MODIFIED, "Thu, 01 Jan 1970 00:00:00 GMT")
+                .body(body)
+                .unwrap()
+        });
+        integration.get_to_file(&location, &dest).await.unwrap_err();
+        assert!(!dest.exists());
+
+        mock.shutdown().await
     }
 }
@@ -0,0 +1,662 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Support for [Query Blob Contents](https://learn.microsoft.com/en-us/rest/api/storageservices/query-blob-contents)
+//!
+//! The results of a query are returned as an [Avro object container file] of records
+//! containing either result data, progress, errors or the end of the results. Only
+//! the subset of Avro needed to decode these records is implemented here.
+//!
+//! [Avro object container file]: https://avro.apache.org/docs/1.11.1/specification/#object-container-files
+
+use bytes::{Buf, Bytes, BytesMut};
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use quick_xml::escape::escape;
+use snafu::{ensure, ResultExt, Snafu};
+use std::collections::VecDeque;
+use tracing::warn;
+
+#[derive(Debug, Snafu)]
+enum Error {
+    #[snafu(display("Error getting query response body: {}", source))]
+    QueryResponseBody { source: reqwest::Error },
+
+    #[snafu(display("Got invalid query response: {}", message))]
+    InvalidQueryResponse { message: String },
+
+    #[snafu(display(
+        "Query failed at position {}: {}: {}",
+        position,
+        name,
+        description
+    ))]
+    QueryFailed {
+        name: String,
+        description: String,
+        position: i64,
+    },
+}
+
+impl From<Error> for crate::Error {
+    fn from(source: Error) -> Self {
+        Self::Generic {
+            store: "MicrosoftAzure",
+            source: Box::new(source),
+        }
+    }
+}
+
+/// The serialization of the input or output of [`MicrosoftAzure::query`](super::MicrosoftAzure::query)
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum QueryFormat {
+    /// Delimited text, such as CSV
+    Delimited(DelimitedFormat),
+    /// JSON records separated by `record_separator`
+    Json {
+        /// The character separating records
+        record_separator: char,
+    },
+}
+
+impl QueryFormat {
+    /// CSV without a header row, see [`DelimitedFormat::default`]
+    pub fn csv() -> Self {
+        Self::Delimited(DelimitedFormat::default())
+    }
+
+    /// Newline delimited JSON
+    pub fn json() -> Self {
+        Self::Json {
+            record_separator: '\n',
+        }
+    }
+
+    fn to_xml(&self) -> String {
+        match self {
+            Self::Delimited(format) => {
+                let quote = format.field_quote.map(String::from).unwrap_or_default();
+                let escape_char =
+                    format.escape_char.map(String::from).unwrap_or_default();
+                format!(
+                    "<Format><Type>delimited</Type><DelimitedTextConfiguration>\
+                    <ColumnSeparator>{}</ColumnSeparator>\
+                    <FieldQuote>{}</FieldQuote>\
+                    <RecordSeparator>{}</RecordSeparator>\
+                    <EscapeChar>{}</EscapeChar>\
+                    <HasHeaders>{}</HasHeaders>\
+                    </DelimitedTextConfiguration></Format>",
+                    escape(&String::from(format.column_separator)),
+                    escape(&quote),
+                    escape(&String::from(format.record_separator)),
+                    escape(&escape_char),
+                    format.has_headers,
+                )
+            }
+            Self::Json { record_separator } => format!(
+                "<Format><Type>json</Type><JsonTextConfiguration>\
+                <RecordSeparator>{}</RecordSeparator>\
+                </JsonTextConfiguration></Format>",
+                escape(&String::from(*record_separator))
+            ),
+        }
+    }
+}
+
+/// The configuration of [`QueryFormat::Delimited`]
+///
+/// The default is CSV without a header row, fields quoted with `"` and no
+/// escape character
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DelimitedFormat {
+    /// The character separating columns
+    pub column_separator: char,
+    /// The character used to quote fields, if any
+    pub field_quote: Option<char>,
+    /// The character separating records
+    pub record_separator: char,
+    /// The character used to escape quotes, if any
+    pub escape_char: Option<char>,
+    /// Whether the first record contains the names of the columns
+    pub has_headers: bool,
+}
+
+impl Default for DelimitedFormat {
+    fn default() -> Self {
+        Self {
+            column_separator: ',',
+            field_quote: Some('"'),
+            record_separator: '\n',
+            escape_char: None,
+            has_headers: false,
+        }
+    }
+}
+
+/// Returns the XML body of a Query Blob Contents request
+/// <https://learn.microsoft.com/en-us/rest/api/storageservices/query-blob-contents#request-body>
+pub(crate) fn query_request_xml(
+    expression: &str,
+    input: &QueryFormat,
+    output: &QueryFormat,
+) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<QueryRequest>\
+        <QueryType>SQL</QueryType>\
+        <Expression>{}</Expression>\
+        <InputSerialization>{}</InputSerialization>\
+        <OutputSerialization>{}</OutputSerialization>\
+        </QueryRequest>",
+        escape(expression),
+        input.to_xml(),
+        output.to_xml()
+    )
+}
+
+/// Decodes the Avro encoded `body` of a Query Blob Contents response into a stream
+/// of the result data
+pub(crate) fn decode_query_response(
+    body: BoxStream<'static, reqwest::Result<Bytes>>,
+) -> BoxStream<'static, crate::Result<Bytes>> {
+    futures::stream::try_unfold(
+        (body, QueryDecoder::default()),
+        |(mut body, mut decoder)| async move {
+            loop {
+                if let Some(data) = decoder.next_data()? {
+                    return Ok(Some((data, (body, decoder))));
+                }
+                match body.next().await {
+                    Some(bytes) => decoder.push(&bytes.context(QueryResponseBodySnafu)?),
+                    None => {
+                        decoder.finish()?;
+                        return Ok(None);
+                    }
+                }
+            }
+        },
+    )
+    .boxed()
+}
+
+/// The types of record in a Query Blob Contents response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RecordType {
+    ResultData,
+    Error,
+    Progress,
+    End,
+}
+
+/// The schema and sync marker read from the header of an object container file
+#[derive(Debug)]
+struct Header {
+    /// The records of the union of the schema, in order
+    records: Vec<RecordType>,
+    sync: [u8; 16],
+}
+
+/// Incrementally decodes a Query Blob Contents response
+#[derive(Debug, Default)]
+struct QueryDecoder {
+    buf: BytesMut,
+    header: Option<Header>,
+    data: VecDeque<Bytes>,
+    ended: bool,
+}
+
+impl QueryDecoder {
+    fn push(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Returns the next result data decoded from the bytes pushed so far, if any
+    fn next_data(&mut self) -> Result<Option<Bytes>, Error> {
+        loop {
+            if let Some(data) = self.data.pop_front() {
+                return Ok(Some(data));
+            }
+
+            let mut reader = Reader::new(&self.buf);
+            match &self.header {
+                None => match read_header(&mut reader)? {
+                    Some(header) => {
+                        self.header = Some(header);
+                        self.buf.advance(reader.position);
+                    }
+                    None => return Ok(None),
+                },
+                Some(header) => match read_block(&mut reader, header)? {
+                    Some(block) => {
+                        self.buf.advance(reader.position);
+                        self.decode_block(block)?
+                    }
+                    None => return Ok(None),
+                },
+            }
+        }
+    }
+
+    /// Decode the records of a block, queueing any result data
+    fn decode_block(&mut self, (count, block): (i64, Bytes)) -> Result<(), Error> {
+        let records = &self.header.as_ref().unwrap().records;
+        let mut reader = Reader::new(&block);
+        for _ in 0..count {
+            let index = reader.long().ok_or_else(truncated)?;
+            let record = usize::try_from(index)
+                .ok()
+                .and_then(|index| records.get(index))
+                .ok_or_else(|| invalid(format!("unknown record type {}", index)))?;
+
+            match record {
+                RecordType::ResultData => {
+                    let data = reader.bytes().ok_or_else(truncated)?;
+                    self.data.push_back(block.slice_ref(data));
+                }
+                RecordType::Error => {
+                    let fatal = reader.boolean().ok_or_else(truncated)?;
+                    let name = reader.string().ok_or_else(truncated)?;
+                    let description = reader.string().ok_or_else(truncated)?;
+                    let position = reader.long().ok_or_else(truncated)?;
+                    ensure!(
+                        !fatal,
+                        QueryFailedSnafu {
+                            name,
+                            description,
+                            position
+                        }
+                    );
+                    warn!(
+                        "Query error at position {}: {}: {}",
+                        position, name, description
+                    );
+                }
+                RecordType::Progress => {
+                    reader.long().ok_or_else(truncated)?;
+                    reader.long().ok_or_else(truncated)?;
+                }
+                RecordType::End => {
+                    reader.long().ok_or_else(truncated)?;
+                    self.ended = true;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the response was complete
+    fn finish(&self) -> Result<(), Error> {
+        ensure!(
+            self.ended && self.buf.is_empty(),
+            InvalidQueryResponseSnafu {
+                message: "response ended before the end of the results",
+            }
+        );
+        Ok(())
+    }
+}
+
+/// Returns the error for an Avro value truncated within a complete block
+fn truncated() -> Error {
+    invalid("truncated record")
+}
+
+fn invalid(message: impl Into<String>) -> Error {
+    Error::InvalidQueryResponse {
+        message: message.into(),
+    }
+}
+
+/// Reads the header of an object container file, returning `None` if `reader`
+/// does not yet contain all of it
+fn read_header(reader: &mut Reader<'_>) -> Result<Option<Header>, Error> {
+    let magic = match reader.fixed(4) {
+        Some(magic) => magic,
+        None => return Ok(None),
+    };
+    ensure!(
+        magic == b"Obj\x01",
+        InvalidQueryResponseSnafu {
+            message: "not an avro object container file",
+        }
+    );
+
+    let mut schema = None;
+    let mut codec = None;
+    loop {
+        let count = match reader.long() {
+            Some(0) => break,
+            Some(count) if count < 0 => match reader.long() {
+                Some(_) => -count,
+                None => return Ok(None),
+            },
+            Some(count) => count,
+            None => return Ok(None),
+        };
+        for _ in 0..count {
+            let (key, value) = match (reader.string(), reader.bytes()) {
+                (Some(key), Some(value)) => (key, value),
+                _ => return Ok(None),
+            };
+            match key.as_str() {
+                "avro.schema" => schema = Some(value.to_vec()),
+                "avro.codec" => codec = Some(value.to_vec()),
+                _ => {}
+            }
+        }
+    }
+
+    let sync = match reader.fixed(16) {
+        Some(sync) => sync.try_into().unwrap(),
+        None => return Ok(None),
+    };
+
+    ensure!(
+        matches!(codec.as_deref(), None | Some(b"null")),
+        InvalidQueryResponseSnafu {
+            message: "unsupported avro codec",
+        }
+    );
+    let schema = schema.ok_or_else(|| invalid("missing avro schema"))?;
+    Ok(Some(Header {
+        records: parse_schema(&schema)?,
+        sync,
+    }))
+}
+
+/// Parses the union of record types of the Avro schema of the response
+fn parse_schema(schema: &[u8]) -> Result<Vec<RecordType>, Error> {
+    #[derive(serde::Deserialize)]
+    struct Record {
+        name: String,
+    }
+
+    let records: Vec<Record> = serde_json::from_slice(schema)
+        .map_err(|e| invalid(format!("invalid avro schema: {}", e)))?;
+
+    records
+        .into_iter()
+        .map(|record| {
+            let name = record.name.rsplit('.').next().unwrap_or_default();
+            match name {
+                "resultData" => Ok(RecordType::ResultData),
+                "error" => Ok(RecordType::Error),
+                "progress" => Ok(RecordType::Progress),
+                "end" => Ok(RecordType::End),
+                _ => Err(invalid(format!("unknown record {}", record.name))),
+            }
+        })
+        .collect()
+}
+
+/// Reads a data block, returning its record count and data, or `None` if `reader`
+/// does not yet contain all of it
+fn read_block(
+    reader: &mut Reader<'_>,
+    header: &Header,
+) -> Result<Option<(i64, Bytes)>, Error> {
+    let (count, size) = match (reader.long(), reader.long()) {
+        (Some(count), Some(size)) => (count, size),
+        _ => return Ok(None),
+    };
+    let size = usize::try_from(size)
+        .map_err(|_| invalid(format!("invalid block size {}", size)))?;
+    let (data, sync) = match (reader.fixed(size), reader.fixed(16)) {
+        (Some(data), Some(sync)) => (Bytes::copy_from_slice(data), sync),
+        _ => return Ok(None),
+    };
+    ensure!(
+        sync == header.sync,
+        InvalidQueryResponseSnafu {
+            message: "invalid sync marker",
+        }
+    );
+    Ok(Some((count, data)))
+}
+
+/// Reads Avro encoded values from a buffer, returning `None` if it is too short
+struct Reader<'a> {
+    buf: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf, position: 0 }
+    }
+
+    fn fixed(&mut self, len: usize) -> Option<&'a [u8]> {
+        let end = self.position.checked_add(len)?;
+        let value = self.buf.get(self.position..end)?;
+        self.position = end;
+        Some(value)
+    }
+
+    /// A zig-zag encoded variable length integer
+    fn long(&mut self) -> Option<i64> {
+        let mut value: u64 = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = *self.buf.get(self.position)?;
+            self.position += 1;
+            value |= u64::from(byte & 0x7F) << shift;
+            if byte & 0x80 == 0 {
+                return Some((value >> 1) as i64 ^ -((value & 1) as i64));
+            }
+        }
+        None
+    }
+
+    fn boolean(&mut self) -> Option<bool> {
+        Some(self.fixed(1)?[0] != 0)
+    }
+
+    fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = usize::try_from(self.long()?).ok()?;
+        self.fixed(len)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        Some(String::from_utf8_lossy(self.bytes()?).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The schema of Query Blob Contents responses
+    const SCHEMA: &str = r#"[
+        {"type":"record","name":"com.microsoft.azure.storage.queryBlobContents.resultData","fields":[{"name":"data","type":"bytes"}]},
+        {"type":"record","name":"com.microsoft.azure.storage.queryBlobContents.error","fields":[{"name":"fatal","type":"boolean"},{"name":"name","type":"string"},{"name":"description","type":"string"},{"name":"position","type":"long"}]},
+        {"type":"record","name":"com.microsoft.azure.storage.queryBlobContents.progress","fields":[{"name":"bytesScanned","type":"long"},{"name":"totalBytes","type":"long"}]},
+        {"type":"record","name":"com.microsoft.azure.storage.queryBlobContents.end","fields":[{"name":"totalBytes","type":"long"}]}
+    ]"#;
+
+    const SYNC: [u8; 16] = *b"0123456789abcdef";
+
+    fn long(out: &mut Vec<u8>, value: i64) {
+        let mut value = ((value << 1) ^ (value >> 63)) as u64;
+        loop {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                out.push(byte);
+                return;
+            }
+            out.push(byte | 0x80);
+        }
+    }
+
+    fn bytes(out: &mut Vec<u8>, value: &[u8]) {
+        long(out, value.len() as i64);
+        out.extend_from_slice(value);
+    }
+
+    fn header() -> Vec<u8> {
+        let mut out = b"Obj\x01".to_vec();
+        long(&mut out, 2);
+        bytes(&mut out, b"avro.schema");
+        bytes(&mut out, SCHEMA.as_bytes());
+        bytes(&mut out, b"avro.codec");
+        bytes(&mut out, b"null");
+        long(&mut out, 0);
+        out.extend_from_slice(&SYNC);
+        out
+    }
+
+    fn block(records: &[Vec<u8>]) -> Vec<u8> {
+        let data = records.concat();
+        let mut out = Vec::new();
+        long(&mut out, records.len() as i64);
+        bytes(&mut out, &data);
+        out.extend_from_slice(&SYNC);
+        out
+    }
+
+    fn result_data(data: &[u8]) -> Vec<u8> {
+        let mut out = vec![0];
+        bytes(&mut out, data);
+        out
+    }
+
+    fn progress(scanned: i64) -> Vec<u8> {
+        let mut out = vec![4];
+        long(&mut out, scanned);
+        long(&mut out, 100);
+        out
+    }
+
+    fn end() -> Vec<u8> {
+        let mut out = vec![6];
+        long(&mut out, 100);
+        out
+    }
+
+    fn error(fatal: bool) -> Vec<u8> {
+        let mut out = vec![2, fatal as u8];
+        bytes(&mut out, b"ParseError");
+        bytes(&mut out, b"invalid row");
+        long(&mut out, 42);
+        out
+    }
+
+    async fn decode(response: Vec<u8>, chunk_size: usize) -> crate::Result<Vec<Bytes>> {
+        let chunks: Vec<_> = response
+            .chunks(chunk_size)
+            .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+            .collect();
+        decode_query_response(futures::stream::iter(chunks).boxed())
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_decode_query_response() {
+        let response = [
+            header(),
+            block(&[result_data(b"a,1\n"), progress(50)]),
+            block(&[error(false), result_data(b"b,2\n"), progress(100), end()]),
+        ]
+        .concat();
+
+        for chunk_size in [1, 7, response.len()] {
+            let data = decode(response.clone(), chunk_size).await.unwrap();
+            assert_eq!(data, vec![Bytes::from("a,1\n"), Bytes::from("b,2\n")]);
+        }
+
+        // Fatal errors are returned
+        let response = [header(), block(&[error(true), end()])].concat();
+        let err = decode(response, 10).await.unwrap_err().to_string();
+        assert!(err.contains("ParseError: invalid row"), "{}", err);
+
+        // Truncated responses are detected
+        let response = [header(), block(&[result_data(b"a,1\n")])].concat();
+        let err = decode(response, 10).await.unwrap_err().to_string();
+        assert!(err.contains("before the end of the results"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_query() {
+        use crate::azure::{MicrosoftAzureBuilder, EMULATOR_ACCOUNT_KEY};
+        use crate::client::mock_server::MockServer;
+        use crate::path::Path;
+        use hyper::{Body, Method, Response};
+
+        let mock = MockServer::new();
+        let integration = MicrosoftAzureBuilder::new()
+            .with_account("account")
+            .with_container_name("container")
+            .with_access_key(EMULATOR_ACCOUNT_KEY)
+            .with_endpoint(mock.url())
+            .with_use_path_style(true)
+            .with_allow_http(true)
+            .build()
+            .unwrap();
+
+        mock.push_async_fn(|req| async move {
+            assert_eq!(req.method(), Method::POST);
+            assert_eq!(req.uri().query(), Some("comp=query"));
+            let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+            let body = std::str::from_utf8(&body).unwrap();
+            assert!(body.contains("<Expression>SELECT _1 FROM BlobStorage</Expression>"));
+
+            let response = [header(), block(&[result_data(b"a\n"), end()])].concat();
+            Response::new(Body::from(response))
+        });
+
+        let result = integration
+            .query(
+                &Path::from("data.csv"),
+                "SELECT _1 FROM BlobStorage",
+                &QueryFormat::csv(),
+                &QueryFormat::csv(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.bytes().await.unwrap(), Bytes::from("a\n"));
+
+        mock.shutdown().await
+    }
+
+    #[test]
+    fn test_query_request_xml() {
+        let input = QueryFormat::Delimited(DelimitedFormat {
+            has_headers: true,
+            ..Default::default()
+        });
+        let xml = query_request_xml(
+            "SELECT * FROM BlobStorage WHERE _1 < 5",
+            &input,
+            &QueryFormat::json(),
+        );
+        assert_eq!(
+            xml,
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<QueryRequest>\
+            <QueryType>SQL</QueryType>\
+            <Expression>SELECT * FROM BlobStorage WHERE _1 &lt; 5</Expression>\
+            <InputSerialization><Format><Type>delimited</Type><DelimitedTextConfiguration>\
+            <ColumnSeparator>,</ColumnSeparator><FieldQuote>&quot;</FieldQuote>\
+            <RecordSeparator>\n</RecordSeparator><EscapeChar></EscapeChar>\
+            <HasHeaders>true</HasHeaders></DelimitedTextConfiguration></Format></InputSerialization>\
+            <OutputSerialization><Format><Type>json</Type><JsonTextConfiguration>\
+            <RecordSeparator>\n</RecordSeparator></JsonTextConfiguration></Format></OutputSerialization>\
+            </QueryRequest>"
+        );
+    }
+}
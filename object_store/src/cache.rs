@@ -0,0 +1,858 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! An object store wrapper that caches objects read from the wrapped store on local disk
+
+use crate::util::maybe_spawn_blocking;
+use crate::{
+    BoxStream, Capabilities, CopyOptions, DeleteOptions, Error, GetOptions, GetResult,
+    GetResultMeta, ListResult, MultipartId, ObjectMeta, ObjectStore, Path, PutOptions,
+    PutResult, Result, Revalidated, StreamExt,
+};
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::TryStreamExt;
+use parking_lot::Mutex;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::{Arc, Weak};
+use tokio::io::AsyncWrite;
+use tracing::warn;
+
+/// Store wrapper that caches the objects read with [`get`](ObjectStore::get) from an
+/// inner store in files in a local directory, evicting the least recently used
+/// objects once the cached objects exceed a maximum total size
+///
+/// ```
+/// # use object_store::memory::InMemory;
+/// # use object_store::cache::CachingStore;
+///
+/// // Cache up to 1 GiB of objects in /tmp/object_store_cache
+/// let store = CachingStore::new(InMemory::new(), "/tmp/object_store_cache", 1 << 30);
+/// ```
+///
+/// Cached objects are identified by their location and ETag, and every read of a
+/// cached object is revalidated with the inner store using a conditional
+/// [`get_opts`](ObjectStore::get_opts) request with [`GetOptions::if_none_match`],
+/// that only transfers the object if it has changed. Objects are written to the cache
+/// as they are streamed to the caller, and so are never buffered in memory. Only
+/// stores that report [`Capabilities::conditional_get`] and return the ETag in
+/// [`GetResult::meta`] are cached; reads from any other store always go to the
+/// inner store.
+///
+/// [`get_range`](ObjectStore::get_range) is served from the cache once an object has
+/// been cached by [`get`](ObjectStore::get), and otherwise only fetches the range
+/// from the inner store, discarding the cached copy if it is stale, while
+/// [`get_opts`](ObjectStore::get_opts) always reads from the inner store. Writes through this store discard any cached copy of the
+/// objects they modify. Cached files are removed when the store is dropped.
+#[derive(Debug)]
+pub struct CachingStore<T: ObjectStore> {
+    inner: T,
+    dir: PathBuf,
+    max_size: usize,
+    state: Arc<Mutex<CacheState>>,
+}
+
+/// The objects cached by a [`CachingStore`]
+#[derive(Debug, Default)]
+struct CacheState {
+    entries: HashMap<Path, CacheEntry>,
+    /// The location of each entry, keyed by when it was last used
+    lru: BTreeMap<u64, Path>,
+    /// The total size of the cached objects
+    size: usize,
+    /// Incremented each time an entry is used, and used to name cached files
+    tick: u64,
+}
+
+/// A cached object
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    meta: ObjectMeta,
+    file: PathBuf,
+    size: usize,
+    last_used: u64,
+}
+
+impl CacheState {
+    /// Returns the entry for `location`, marking it as the most recently used
+    fn get(&mut self, location: &Path) -> Option<CacheEntry> {
+        self.tick += 1;
+        let entry = self.entries.get_mut(location)?;
+        self.lru.remove(&entry.last_used);
+        self.lru.insert(self.tick, location.clone());
+        entry.last_used = self.tick;
+        Some(entry.clone())
+    }
+
+    /// Inserts `entry` for `location`, returning the files of any entries it replaces
+    /// or that are evicted to stay within `max_size`
+    fn insert(
+        &mut self,
+        location: &Path,
+        mut entry: CacheEntry,
+        max_size: usize,
+    ) -> Vec<PathBuf> {
+        let mut removed: Vec<_> = self.remove(location).into_iter().collect();
+
+        while self.size + entry.size > max_size {
+            let oldest = match self.lru.keys().next() {
+                Some(oldest) => *oldest,
+                None => break,
+            };
+            let location = self.lru[&oldest].clone();
+            removed.extend(self.remove(&location));
+        }
+
+        self.tick += 1;
+        entry.last_used = self.tick;
+        self.size += entry.size;
+        self.lru.insert(self.tick, location.clone());
+        self.entries.insert(location.clone(), entry);
+        removed
+    }
+
+    /// Removes the entry for `location`, if any, returning its file
+    fn remove(&mut self, location: &Path) -> Option<PathBuf> {
+        let entry = self.entries.remove(location)?;
+        self.lru.remove(&entry.last_used);
+        self.size -= entry.size;
+        Some(entry.file)
+    }
+
+    /// Returns a new file name that is not used by any entry
+    fn next_file(&mut self) -> String {
+        self.tick += 1;
+        format!("{}.cache", self.tick)
+    }
+}
+
+impl<T: ObjectStore> CachingStore<T> {
+    /// Create a new [`CachingStore`] wrapping `inner`, that caches objects in files
+    /// in the directory `dir`, which is created if it does not exist, up to a total
+    /// of `max_size` bytes
+    ///
+    /// Objects larger than `max_size` are not cached
+    pub fn new(inner: T, dir: impl Into<PathBuf>, max_size: usize) -> Self {
+        Self {
+            inner,
+            dir: dir.into(),
+            max_size,
+            state: Default::default(),
+        }
+    }
+
+    /// Returns the total size in bytes of the objects currently cached
+    pub fn cached_size(&self) -> usize {
+        self.state.lock().size
+    }
+
+    /// Discard the cached copy of `location`, if any
+    async fn invalidate(&self, location: &Path) {
+        let removed = self.state.lock().remove(location);
+        remove_files(removed.into_iter().collect()).await;
+    }
+
+    /// Fetch `location` from the inner store unless the cached copy of it is current
+    ///
+    /// Returns the cached entry if it is current, or otherwise the result of the
+    /// inner store, discarding any cached copy as it is stale
+    async fn get_unless_cached(
+        &self,
+        location: &Path,
+        range: Option<Range<usize>>,
+    ) -> Result<Cached> {
+        let cached = self.state.lock().get(location);
+        let options = GetOptions {
+            if_none_match: cached.as_ref().and_then(|entry| entry.meta.e_tag.clone()),
+            range,
+            ..Default::default()
+        };
+
+        match (self.inner.get_opts(location, options).await, cached) {
+            (Err(Error::NotModified { .. }), Some(entry)) => Ok(Cached::File(entry)),
+            (Ok(result), cached) => {
+                if cached.is_some() {
+                    self.invalidate(location).await;
+                }
+                Ok(Cached::Fetched(result))
+            }
+            (Err(e), _) => {
+                if matches!(e, Error::NotFound { .. }) {
+                    self.invalidate(location).await;
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Returns `result` with its data written to a new file as it is streamed, which
+    /// is cached as the object at `location` once the whole object has been read
+    ///
+    /// Objects without an ETag cannot be revalidated, and objects larger than
+    /// `max_size` cannot be cached, and so `result` is returned unchanged for these
+    async fn cache(&self, location: &Path, result: GetResult) -> GetResult {
+        let meta = match result.meta() {
+            Some(meta)
+                if meta.range.is_none()
+                    && meta.object.e_tag.is_some()
+                    && meta.object.size <= self.max_size =>
+            {
+                meta.clone()
+            }
+            _ => return result,
+        };
+        let stream = match result {
+            GetResult::Stream(stream, _) => stream,
+            GetResult::File(_, _) => unreachable!("file results have no metadata"),
+        };
+
+        let file = self.dir.join(self.state.lock().next_file());
+        let dir = self.dir.clone();
+        let path = file.clone();
+        let created = maybe_spawn_blocking(move || {
+            Ok(std::fs::create_dir_all(&dir).and_then(|_| File::create(&path)))
+        })
+        .await;
+
+        let writer = match created {
+            Ok(Ok(f)) => Some(CacheWriter {
+                state: Arc::downgrade(&self.state),
+                max_size: self.max_size,
+                location: location.clone(),
+                meta: meta.object.clone(),
+                path: file,
+                file: Some(f),
+                written: 0,
+                committed: false,
+            }),
+            Ok(Err(e)) => {
+                warn!("Failed to cache {} in {}: {}", location, file.display(), e);
+                None
+            }
+            Err(e) => {
+                warn!("Failed to cache {}: {}", location, e);
+                None
+            }
+        };
+
+        let stream = futures::stream::unfold(
+            (stream, writer),
+            |(mut stream, mut writer)| async move {
+                match stream.next().await {
+                    Some(Ok(chunk)) => {
+                        if let Some(writer) = &mut writer {
+                            writer.write(chunk.clone()).await;
+                        }
+                        Some((Ok(chunk), (stream, writer)))
+                    }
+                    // Dropping the writer discards the partially written file
+                    Some(Err(e)) => Some((Err(e), (stream, None))),
+                    None => {
+                        if let Some(writer) = writer {
+                            writer.finish().await;
+                        }
+                        None
+                    }
+                }
+            },
+        );
+        GetResult::Stream(stream.boxed(), Some(meta))
+    }
+
+    /// Read `range`, or the whole object if `None`, from the file of `entry`
+    ///
+    /// Returns `None` and discards the entry if the file cannot be read, for example
+    /// as it has since been evicted
+    async fn read(
+        &self,
+        location: &Path,
+        entry: CacheEntry,
+        range: Option<Range<usize>>,
+    ) -> Option<Bytes> {
+        let path = entry.file.clone();
+        let read = maybe_spawn_blocking(move || {
+            Ok(File::open(&path).and_then(|mut file| {
+                let range = range.unwrap_or(0..entry.size);
+                let mut buf = vec![0; range.end.saturating_sub(range.start)];
+                file.seek(SeekFrom::Start(range.start as u64))?;
+                file.read_exact(&mut buf)?;
+                Ok(Bytes::from(buf))
+            }))
+        })
+        .await;
+
+        match read {
+            Ok(Ok(bytes)) => Some(bytes),
+            Ok(Err(_)) | Err(_) => {
+                let removed = {
+                    let mut state = self.state.lock();
+                    match state.entries.get(location) {
+                        Some(e) if e.file == entry.file => state.remove(location),
+                        _ => None,
+                    }
+                };
+                remove_files(removed.into_iter().collect()).await;
+                None
+            }
+        }
+    }
+}
+
+/// The result of [`CachingStore::get_unless_cached`]
+enum Cached {
+    /// The cached copy is current
+    File(CacheEntry),
+    /// The result of fetching the object from the inner store
+    Fetched(GetResult),
+}
+
+/// Writes an object streamed from the inner store of a [`CachingStore`] to a new
+/// file, caching it once the whole object has been written
+///
+/// The file is removed if the object is not cached, for example as the stream is
+/// dropped before it completes
+struct CacheWriter {
+    state: Weak<Mutex<CacheState>>,
+    max_size: usize,
+    location: Path,
+    meta: ObjectMeta,
+    path: PathBuf,
+    /// `None` once writing has failed
+    file: Option<File>,
+    written: usize,
+    committed: bool,
+}
+
+impl CacheWriter {
+    /// Append `chunk` to the file, abandoning caching the object if this fails
+    async fn write(&mut self, chunk: Bytes) {
+        let mut file = match self.file.take() {
+            Some(file) => file,
+            None => return,
+        };
+        let len = chunk.len();
+        let written =
+            maybe_spawn_blocking(move || Ok(file.write_all(&chunk).map(|_| file))).await;
+
+        match written {
+            Ok(Ok(file)) => {
+                self.file = Some(file);
+                self.written += len;
+            }
+            Ok(Err(e)) => warn!(
+                "Failed to cache {} in {}: {}",
+                self.location,
+                self.path.display(),
+                e
+            ),
+            Err(e) => warn!("Failed to cache {}: {}", self.location, e),
+        }
+    }
+
+    /// Cache the file if the whole object was written to it
+    async fn finish(mut self) {
+        let state = match self.state.upgrade() {
+            Some(state) => state,
+            None => return,
+        };
+        if self.file.take().is_none() || self.written != self.meta.size {
+            return;
+        }
+
+        let entry = CacheEntry {
+            meta: self.meta.clone(),
+            file: self.path.clone(),
+            size: self.written,
+            last_used: 0,
+        };
+        let removed = state.lock().insert(&self.location, entry, self.max_size);
+        self.committed = true;
+        remove_files(removed).await;
+    }
+}
+
+impl Drop for CacheWriter {
+    fn drop(&mut self) {
+        if self.committed {
+            return;
+        }
+        self.file = None;
+        let path = self.path.clone();
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn_blocking(move || remove_files_blocking([path]));
+            }
+            Err(_) => remove_files_blocking([path]),
+        }
+    }
+}
+
+/// Best-effort removal of cached `files`, without blocking the async runtime
+async fn remove_files(files: Vec<PathBuf>) {
+    if files.is_empty() {
+        return;
+    }
+    let removed = maybe_spawn_blocking(move || {
+        remove_files_blocking(files);
+        Ok(())
+    })
+    .await;
+    if let Err(e) = removed {
+        warn!("Failed to remove cached files: {}", e);
+    }
+}
+
+/// Best-effort removal of cached `files` on the current thread
+fn remove_files_blocking(files: impl IntoIterator<Item = PathBuf>) {
+    for file in files {
+        if let Err(e) = std::fs::remove_file(&file) {
+            warn!("Failed to remove cached file {}: {}", file.display(), e);
+        }
+    }
+}
+
+impl<T: ObjectStore> Drop for CachingStore<T> {
+    fn drop(&mut self) {
+        let state = std::mem::take(&mut *self.state.lock());
+        remove_files_blocking(state.entries.into_values().map(|entry| entry.file));
+    }
+}
+
+impl<T: ObjectStore> std::fmt::Display for CachingStore<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CachingStore({}, {})", self.dir.display(), self.inner)
+    }
+}
+
+#[async_trait]
+impl<T: ObjectStore> ObjectStore for CachingStore<T> {
+    async fn put(&self, location: &Path, bytes: Bytes) -> Result<()> {
+        self.invalidate(location).await;
+        self.inner.put(location, bytes).await
+    }
+
+    async fn put_opts(
+        &self,
+        location: &Path,
+        bytes: Bytes,
+        options: PutOptions,
+    ) -> Result<()> {
+        self.invalidate(location).await;
+        self.inner.put_opts(location, bytes, options).await
+    }
+
+    async fn update(
+        &self,
+        location: &Path,
+        expected_e_tag: Option<String>,
+        bytes: Bytes,
+    ) -> Result<PutResult> {
+        self.invalidate(location).await;
+        self.inner.update(location, expected_e_tag, bytes).await
+    }
+
+    async fn revalidate(
+        &self,
+        location: &Path,
+        cached_e_tag: Option<String>,
+    ) -> Result<Revalidated> {
+        self.inner.revalidate(location, cached_e_tag).await
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+
+    async fn put_multipart(
+        &self,
+        location: &Path,
+    ) -> Result<(MultipartId, Box<dyn AsyncWrite + Unpin + Send>)> {
+        self.invalidate(location).await;
+        self.inner.put_multipart(location).await
+    }
+
+    async fn put_multipart_opts(
+        &self,
+        location: &Path,
+        options: PutOptions,
+    ) -> Result<(MultipartId, Box<dyn AsyncWrite + Unpin + Send>)> {
+        self.invalidate(location).await;
+        self.inner.put_multipart_opts(location, options).await
+    }
+
+    async fn put_multipart_bytes(&self, location: &Path, bytes: Bytes) -> Result<()> {
+        self.invalidate(location).await;
+        self.inner.put_multipart_bytes(location, bytes).await
+    }
+
+    async fn abort_multipart(
+        &self,
+        location: &Path,
+        multipart_id: &MultipartId,
+    ) -> Result<()> {
+        self.inner.abort_multipart(location, multipart_id).await
+    }
+
+    async fn get(&self, location: &Path) -> Result<GetResult> {
+        if !self.inner.capabilities().conditional_get {
+            return self.inner.get(location).await;
+        }
+
+        let entry = match self.get_unless_cached(location, None).await? {
+            Cached::File(entry) => entry,
+            Cached::Fetched(result) => return Ok(self.cache(location, result).await),
+        };
+        let object = entry.meta.clone();
+        let bytes = match self.read(location, entry, None).await {
+            Some(bytes) => bytes,
+            None => return self.inner.get(location).await,
+        };
+        let stream = futures::stream::once(async move { Ok(bytes) }).boxed();
+        let meta = GetResultMeta {
+            object,
+            range: None,
+        };
+        Ok(GetResult::Stream(stream, Some(meta)))
+    }
+
+    async fn get_opts(&self, location: &Path, options: GetOptions) -> Result<GetResult> {
+        self.inner.get_opts(location, options).await
+    }
+
+    async fn get_range(&self, location: &Path, range: Range<usize>) -> Result<Bytes> {
+        if !self.state.lock().entries.contains_key(location) {
+            return self.inner.get_range(location, range).await;
+        }
+
+        // A stale entry is discarded, and only the range fetched from the inner store
+        match self.get_unless_cached(location, Some(range.clone())).await {
+            Ok(Cached::File(entry)) if range.end <= entry.size => {
+                match self.read(location, entry, Some(range.clone())).await {
+                    Some(bytes) => Ok(bytes),
+                    None => self.inner.get_range(location, range).await,
+                }
+            }
+            Ok(Cached::File(_)) | Err(Error::NotImplemented) => {
+                self.inner.get_range(location, range).await
+            }
+            Ok(Cached::Fetched(result)) => result.bytes().await,
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn head(&self, location: &Path) -> Result<ObjectMeta> {
+        self.inner.head(location).await
+    }
+
+    async fn delete(&self, location: &Path) -> Result<()> {
+        self.invalidate(location).await;
+        self.inner.delete(location).await
+    }
+
+    async fn delete_opts(&self, location: &Path, options: DeleteOptions) -> Result<()> {
+        self.invalidate(location).await;
+        self.inner.delete_opts(location, options).await
+    }
+
+    fn delete_stream<'a>(
+        &'a self,
+        locations: BoxStream<'a, Result<Path>>,
+    ) -> BoxStream<'a, Result<Path>> {
+        let locations = locations
+            .and_then(move |location| async move {
+                self.invalidate(&location).await;
+                Ok(location)
+            })
+            .boxed();
+        self.inner.delete_stream(locations)
+    }
+
+    async fn list(
+        &self,
+        prefix: Option<&Path>,
+    ) -> Result<BoxStream<'_, Result<ObjectMeta>>> {
+        self.inner.list(prefix).await
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> Result<ListResult> {
+        self.inner.list_with_delimiter(prefix).await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+        self.invalidate(to).await;
+        self.inner.copy(from, to).await
+    }
+
+    async fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        self.invalidate(from).await;
+        self.invalidate(to).await;
+        self.inner.rename(from, to).await
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+        self.invalidate(to).await;
+        self.inner.copy_if_not_exists(from, to).await
+    }
+
+    async fn copy_opts(
+        &self,
+        from: &Path,
+        to: &Path,
+        options: CopyOptions,
+    ) -> Result<()> {
+        self.invalidate(to).await;
+        self.inner.copy_opts(from, to, options).await
+    }
+
+    async fn rename_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+        self.invalidate(from).await;
+        self.inner.rename_if_not_exists(from, to).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::InMemory;
+    use crate::tests::{
+        list_uses_directories_correctly, list_with_delimiter, put_get_delete_list,
+        rename_and_copy, stream_get,
+    };
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// An [`InMemory`] store that supports conditional gets with
+    /// [`GetOptions::if_none_match`], counting the number of times an object, or a
+    /// range of it, is transferred
+    #[derive(Debug, Default)]
+    struct RevalidatingStore {
+        inner: InMemory,
+        transfers: AtomicUsize,
+    }
+
+    impl std::fmt::Display for RevalidatingStore {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "RevalidatingStore")
+        }
+    }
+
+    #[async_trait]
+    impl ObjectStore for RevalidatingStore {
+        async fn put(&self, location: &Path, bytes: Bytes) -> Result<()> {
+            self.inner.put(location, bytes).await
+        }
+
+        async fn put_multipart(
+            &self,
+            location: &Path,
+        ) -> Result<(MultipartId, Box<dyn AsyncWrite + Unpin + Send>)> {
+            self.inner.put_multipart(location).await
+        }
+
+        async fn abort_multipart(&self, location: &Path, id: &MultipartId) -> Result<()> {
+            self.inner.abort_multipart(location, id).await
+        }
+
+        async fn get(&self, location: &Path) -> Result<GetResult> {
+            self.transfers.fetch_add(1, Ordering::SeqCst);
+            self.inner.get(location).await
+        }
+
+        async fn get_range(&self, location: &Path, range: Range<usize>) -> Result<Bytes> {
+            self.transfers.fetch_add(1, Ordering::SeqCst);
+            self.inner.get_range(location, range).await
+        }
+
+        async fn head(&self, location: &Path) -> Result<ObjectMeta> {
+            self.inner.head(location).await
+        }
+
+        async fn delete(&self, location: &Path) -> Result<()> {
+            self.inner.delete(location).await
+        }
+
+        async fn list(
+            &self,
+            prefix: Option<&Path>,
+        ) -> Result<BoxStream<'_, Result<ObjectMeta>>> {
+            self.inner.list(prefix).await
+        }
+
+        async fn list_with_delimiter(&self, prefix: Option<&Path>) -> Result<ListResult> {
+            self.inner.list_with_delimiter(prefix).await
+        }
+
+        async fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+            self.inner.copy(from, to).await
+        }
+
+        async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+            self.inner.copy_if_not_exists(from, to).await
+        }
+
+        async fn get_opts(
+            &self,
+            location: &Path,
+            options: GetOptions,
+        ) -> Result<GetResult> {
+            let bytes = self.inner.get(location).await?.bytes().await?;
+            let mut hasher = DefaultHasher::new();
+            bytes.hash(&mut hasher);
+            let e_tag = format!("\"{:x}\"", hasher.finish());
+            if options.if_none_match.as_ref() == Some(&e_tag) {
+                return Err(Error::NotModified {
+                    path: location.to_string(),
+                    source: "ETag matches".into(),
+                });
+            }
+            self.transfers.fetch_add(1, Ordering::SeqCst);
+
+            let mut object = self.inner.head(location).await?;
+            object.e_tag = Some(e_tag);
+            let bytes = match &options.range {
+                Some(range) => bytes.slice(range.clone()),
+                None => bytes,
+            };
+            let meta = GetResultMeta {
+                object,
+                range: options.range,
+            };
+            let stream = futures::stream::once(async move { Ok(bytes) }).boxed();
+            Ok(GetResult::Stream(stream, Some(meta)))
+        }
+
+        fn capabilities(&self) -> Capabilities {
+            Capabilities {
+                conditional_get: true,
+                ..Default::default()
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn caching_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let integration = CachingStore::new(InMemory::new(), dir.path(), 1024);
+
+        put_get_delete_list(&integration).await;
+        list_uses_directories_correctly(&integration).await;
+        list_with_delimiter(&integration).await;
+        rename_and_copy(&integration).await;
+        stream_get(&integration).await;
+
+        // InMemory does not support revalidation, so nothing is cached
+        assert_eq!(integration.cached_size(), 0);
+    }
+
+    #[tokio::test]
+    async fn caching_revalidate() {
+        let dir = tempfile::tempdir().unwrap();
+        let integration = CachingStore::new(RevalidatingStore::default(), dir.path(), 10);
+        let transfers = || integration.inner.transfers.load(Ordering::SeqCst);
+
+        let a = Path::from("a");
+        integration.put(&a, "hello".into()).await.unwrap();
+
+        // Ranges of objects that are not cached are fetched from the inner store
+        assert_eq!(integration.get_range(&a, 1..3).await.unwrap(), "el");
+        assert_eq!(transfers(), 1);
+
+        // The first read transfers the object, subsequent reads are from the cache
+        for _ in 0..3 {
+            let result = integration.get(&a).await.unwrap();
+            let meta = result.meta().unwrap().object.clone();
+            assert_eq!(meta.location, a);
+            assert_eq!(meta.size, 5);
+            assert!(meta.e_tag.is_some());
+            assert_eq!(result.bytes().await.unwrap(), "hello");
+        }
+        assert_eq!(integration.get_range(&a, 1..3).await.unwrap(), "el");
+        assert_eq!(transfers(), 2);
+        assert_eq!(integration.cached_size(), 5);
+
+        // An object modified in the inner store is transferred again
+        integration.inner.put(&a, "world".into()).await.unwrap();
+        let bytes = integration.get(&a).await.unwrap().bytes().await.unwrap();
+        assert_eq!(bytes, "world");
+        assert_eq!(transfers(), 3);
+
+        // Only the range of a stale object is transferred, discarding the cached copy
+        integration.inner.put(&a, "hello".into()).await.unwrap();
+        assert_eq!(integration.get_range(&a, 1..3).await.unwrap(), "el");
+        assert_eq!(transfers(), 4);
+        assert_eq!(integration.cached_size(), 0);
+
+        // An object is only cached once it has been read to completion
+        let result = integration.get(&a).await.unwrap();
+        assert_eq!(integration.cached_size(), 0);
+        drop(result);
+        assert_eq!(integration.cached_size(), 0);
+        integration.get(&a).await.unwrap().bytes().await.unwrap();
+        assert_eq!(integration.cached_size(), 5);
+        assert_eq!(transfers(), 6);
+
+        // Writes discard the cached object
+        integration.put(&a, "hello".into()).await.unwrap();
+        assert_eq!(integration.cached_size(), 0);
+
+        // The least recently used object is evicted to stay within the maximum size
+        let b = Path::from("b");
+        let c = Path::from("c");
+        integration.put(&b, "bb".into()).await.unwrap();
+        integration.put(&c, "cccc".into()).await.unwrap();
+        for location in [&a, &b, &a, &c] {
+            integration
+                .get(location)
+                .await
+                .unwrap()
+                .bytes()
+                .await
+                .unwrap();
+        }
+        assert_eq!(integration.cached_size(), 9);
+        let cached = integration.state.lock().entries.contains_key(&b);
+        assert!(!cached);
+
+        // Objects larger than the maximum size are not cached
+        let large = Path::from("large");
+        integration.put(&large, vec![0; 11].into()).await.unwrap();
+        integration
+            .get(&large)
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+        assert_eq!(integration.cached_size(), 9);
+
+        // Objects deleted through this store or the inner store are no longer cached
+        integration.delete(&a).await.unwrap();
+        assert_eq!(integration.cached_size(), 4);
+        integration.inner.delete(&c).await.unwrap();
+        let err = integration.get(&c).await.unwrap_err();
+        assert!(matches!(err, Error::NotFound { .. }), "{}", err);
+        assert_eq!(integration.cached_size(), 0);
+
+        let files = std::fs::read_dir(dir.path()).unwrap().count();
+        assert_eq!(files, 0);
+    }
+}
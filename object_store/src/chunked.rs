@@ -30,8 +30,8 @@ use tokio::io::AsyncWrite;
 
 use crate::path::Path;
 use crate::util::maybe_spawn_blocking;
-use crate::{GetResult, ListResult, ObjectMeta, ObjectStore};
-use crate::{MultipartId, Result};
+use crate::{Capabilities, GetResult, ListResult, ObjectMeta, ObjectStore};
+use crate::{MultipartId, PutOptions, PutResult, Result, Revalidated};
 
 /// Wraps a [`ObjectStore`] and makes its get response return chunks
 /// in a controllable manner.
@@ -66,6 +66,41 @@ impl ObjectStore for ChunkedStore {
         self.inner.put(location, bytes).await
     }
 
+    async fn put_opts(
+        &self,
+        location: &Path,
+        bytes: Bytes,
+        options: PutOptions,
+    ) -> Result<()> {
+        self.inner.put_opts(location, bytes, options).await
+    }
+
+    async fn update(
+        &self,
+        location: &Path,
+        expected_e_tag: Option<String>,
+        bytes: Bytes,
+    ) -> Result<PutResult> {
+        self.inner.update(location, expected_e_tag, bytes).await
+    }
+
+    async fn revalidate(
+        &self,
+        location: &Path,
+        cached_e_tag: Option<String>,
+    ) -> Result<Revalidated> {
+        self.inner.revalidate(location, cached_e_tag).await
+    }
+
+    /// Conditional gets and renames are not forwarded to the inner store
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            conditional_get: false,
+            atomic_rename: false,
+            ..self.inner.capabilities()
+        }
+    }
+
     async fn put_multipart(
         &self,
         location: &Path,
@@ -73,6 +108,10 @@ impl ObjectStore for ChunkedStore {
         self.inner.put_multipart(location).await
     }
 
+    async fn put_multipart_bytes(&self, location: &Path, bytes: Bytes) -> Result<()> {
+        self.inner.put_multipart_bytes(location, bytes).await
+    }
+
     async fn abort_multipart(
         &self,
         location: &Path,
@@ -107,9 +146,10 @@ impl ObjectStore for ChunkedStore {
                         }
                     })
                     .boxed(),
+                    None,
                 ))
             }
-            GetResult::Stream(stream) => {
+            GetResult::Stream(stream, meta) => {
                 let buffer = BytesMut::new();
                 Ok(GetResult::Stream(
                     futures::stream::unfold(
@@ -150,6 +190,7 @@ impl ObjectStore for ChunkedStore {
                         },
                     )
                     .boxed(),
+                    meta,
                 ))
             }
         }
@@ -210,7 +251,7 @@ mod tests {
         for chunk_size in [10, 20, 31] {
             let store = ChunkedStore::new(Arc::clone(&store), chunk_size);
             let mut s = match store.get(&location).await.unwrap() {
-                GetResult::Stream(s) => s,
+                GetResult::Stream(s, _) => s,
                 _ => unreachable!(),
             };
 
@@ -30,6 +30,13 @@ pub struct BackoffConfig {
     pub max_backoff: Duration,
     /// The base of the exponential to use
     pub base: f64,
+    /// The fraction of each backoff to add at random, if any
+    ///
+    /// If set, the `n`th backoff is `min(max_backoff, init_backoff * base^n)` plus a
+    /// random duration of up to this fraction of it, and so 0 disables jitter.
+    /// Otherwise each backoff is chosen at random between `init_backoff` and `base`
+    /// times the previous backoff, up to `max_backoff`
+    pub jitter: Option<f64>,
 }
 
 impl Default for BackoffConfig {
@@ -38,6 +45,7 @@ impl Default for BackoffConfig {
             init_backoff: Duration::from_millis(100),
             max_backoff: Duration::from_secs(15),
             base: 2.,
+            jitter: None,
         }
     }
 }
@@ -51,6 +59,8 @@ pub struct Backoff {
     next_backoff_secs: f64,
     max_backoff_secs: f64,
     base: f64,
+    jitter: Option<f64>,
+    attempt: i32,
     rng: Option<Box<dyn RngCore + Sync + Send>>,
 }
 
@@ -61,6 +71,8 @@ impl std::fmt::Debug for Backoff {
             .field("next_backoff_secs", &self.next_backoff_secs)
             .field("max_backoff_secs", &self.max_backoff_secs)
             .field("base", &self.base)
+            .field("jitter", &self.jitter)
+            .field("attempt", &self.attempt)
             .finish()
     }
 }
@@ -84,12 +96,30 @@ impl Backoff {
             next_backoff_secs: init_backoff,
             max_backoff_secs: config.max_backoff.as_secs_f64(),
             base: config.base,
+            jitter: config.jitter,
+            attempt: 0,
             rng,
         }
     }
 
     /// Returns the next backoff duration to wait for
     pub fn next(&mut self) -> Duration {
+        if let Some(jitter) = self.jitter {
+            let backoff = (self.init_backoff * self.base.powi(self.attempt))
+                .min(self.max_backoff_secs);
+            self.attempt = self.attempt.saturating_add(1);
+
+            let jitter = backoff * jitter;
+            if jitter.is_nan() || jitter <= 0. {
+                return Duration::from_secs_f64(backoff);
+            }
+            let rand_jitter = match self.rng.as_mut() {
+                Some(rng) => rng.gen_range(0. ..jitter),
+                None => thread_rng().gen_range(0. ..jitter),
+            };
+            return Duration::from_secs_f64(backoff + rand_jitter);
+        }
+
         let range = self.init_backoff..(self.next_backoff_secs * self.base);
 
         let rand_backoff = match self.rng.as_mut() {
@@ -120,6 +150,7 @@ mod tests {
             init_backoff: Duration::from_secs_f64(init_backoff_secs),
             max_backoff: Duration::from_secs_f64(max_backoff_secs),
             base,
+            jitter: None,
         };
 
         let assert_fuzzy_eq =
@@ -153,4 +184,42 @@ mod tests {
                 .min(max_backoff_secs);
         }
     }
+
+    #[test]
+    fn test_backoff_jitter() {
+        let mut config = BackoffConfig {
+            init_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(100),
+            base: 3.,
+            jitter: Some(0.),
+        };
+
+        let expected = [1., 3., 9., 27., 81., 100., 100.];
+        let mut backoff = Backoff::new(&config);
+        for value in expected {
+            assert_eq!(backoff.next().as_secs_f64(), value);
+        }
+
+        // Create a static rng that takes the maximum of the range
+        config.jitter = Some(0.5);
+        let rng = Box::new(StepRng::new(u64::MAX, 0));
+        let mut backoff = Backoff::new_with_rng(&config, Some(rng));
+        for value in expected {
+            let next = backoff.next().as_secs_f64();
+            assert!((next - value * 1.5).abs() < 0.0001, "{} != {}", next, value);
+        }
+
+        // And with an rng that takes the minimum
+        let rng = Box::new(StepRng::new(0, 0));
+        let mut backoff = Backoff::new_with_rng(&config, Some(rng));
+        for value in expected {
+            assert_eq!(backoff.next().as_secs_f64(), value);
+        }
+
+        // Does not overflow after many attempts
+        let mut backoff = Backoff::new(&config);
+        for _ in 0..2000 {
+            assert!(backoff.next() <= Duration::from_secs(150));
+        }
+    }
 }
@@ -15,21 +15,26 @@
 // specific language governing permissions and limitations
 // under the License.
 
+use futures::future::BoxFuture;
+use futures::{Future, FutureExt};
 use hyper::service::{make_service_fn, service_fn};
 use hyper::{Body, Request, Response, Server};
 use parking_lot::Mutex;
 use std::collections::VecDeque;
 use std::convert::Infallible;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use tokio::sync::oneshot;
 use tokio::task::JoinHandle;
 
-pub type ResponseFn = Box<dyn FnOnce(Request<Body>) -> Response<Body> + Send>;
+pub type ResponseFn =
+    Box<dyn FnOnce(Request<Body>) -> BoxFuture<'static, Response<Body>> + Send>;
 
 /// A mock server
 pub struct MockServer {
     responses: Arc<Mutex<VecDeque<ResponseFn>>>,
+    requests: Arc<AtomicUsize>,
     shutdown: oneshot::Sender<()>,
     handle: JoinHandle<()>,
     url: String,
@@ -40,15 +45,21 @@ impl MockServer {
         let responses: Arc<Mutex<VecDeque<ResponseFn>>> =
             Arc::new(Mutex::new(VecDeque::with_capacity(10)));
 
+        let requests = Arc::new(AtomicUsize::new(0));
+
         let r = Arc::clone(&responses);
+        let c = Arc::clone(&requests);
         let make_service = make_service_fn(move |_conn| {
             let r = Arc::clone(&r);
+            let c = Arc::clone(&c);
             async move {
                 Ok::<_, Infallible>(service_fn(move |req| {
                     let r = Arc::clone(&r);
+                    c.fetch_add(1, Ordering::SeqCst);
                     async move {
-                        Ok::<_, Infallible>(match r.lock().pop_front() {
-                            Some(r) => r(req),
+                        let next = r.lock().pop_front();
+                        Ok::<_, Infallible>(match next {
+                            Some(r) => r(req).await,
                             None => Response::new(Body::from("Hello World")),
                         })
                     }
@@ -73,6 +84,7 @@ impl MockServer {
 
         Self {
             responses,
+            requests,
             shutdown,
             handle,
             url,
@@ -84,6 +96,11 @@ impl MockServer {
         &self.url
     }
 
+    /// The number of requests received so far
+    pub fn requests(&self) -> usize {
+        self.requests.load(Ordering::SeqCst)
+    }
+
     /// Add a response
     pub fn push(&self, response: Response<Body>) {
         self.push_fn(|_| response)
@@ -94,7 +111,18 @@ impl MockServer {
     where
         F: FnOnce(Request<Body>) -> Response<Body> + Send + 'static,
     {
-        self.responses.lock().push_back(Box::new(f))
+        self.push_async_fn(|req| futures::future::ready(f(req)))
+    }
+
+    /// Add an asynchronous response function
+    pub fn push_async_fn<F, Fut>(&self, f: F)
+    where
+        F: FnOnce(Request<Body>) -> Fut + Send + 'static,
+        Fut: Future<Output = Response<Body>> + Send + 'static,
+    {
+        self.responses
+            .lock()
+            .push_back(Box::new(|req| f(req).boxed()))
     }
 
     /// Shutdown the mock server
@@ -43,6 +43,26 @@ fn map_client_error(e: reqwest::Error) -> super::Error {
 static DEFAULT_USER_AGENT: &str =
     concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"),);
 
+/// The minimum TLS version to negotiate with remote object stores
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TlsVersion {
+    /// TLS 1.2
+    #[default]
+    Tls1_2,
+    /// TLS 1.3
+    Tls1_3,
+}
+
+impl From<TlsVersion> for reqwest::tls::Version {
+    fn from(version: TlsVersion) -> Self {
+        match version {
+            TlsVersion::Tls1_2 => Self::TLS_1_2,
+            TlsVersion::Tls1_3 => Self::TLS_1_3,
+        }
+    }
+}
+
 /// HTTP client configuration for remote object stores
 #[derive(Debug, Clone, Default)]
 pub struct ClientOptions {
@@ -56,11 +76,15 @@ pub struct ClientOptions {
     connect_timeout: Option<Duration>,
     pool_idle_timeout: Option<Duration>,
     pool_max_idle_per_host: Option<usize>,
+    disable_connection_reuse: bool,
     http2_keep_alive_interval: Option<Duration>,
     http2_keep_alive_timeout: Option<Duration>,
     http2_keep_alive_while_idle: bool,
     http1_only: bool,
     http2_only: bool,
+    min_tls_version: TlsVersion,
+    checksum_validation: bool,
+    tls_info: bool,
 }
 
 impl ClientOptions {
@@ -94,6 +118,8 @@ impl ClientOptions {
     }
 
     /// Sets the default headers for every request
+    ///
+    /// Headers set by the store for a request take precedence over these
     pub fn with_default_headers(mut self, headers: HeaderMap) -> Self {
         self.default_headers = Some(headers);
         self
@@ -119,6 +145,37 @@ impl ClientOptions {
         self
     }
 
+    /// Set the minimum TLS version of HTTPS connections
+    ///
+    /// Default is TLS 1.2
+    pub fn with_min_tls_version(mut self, version: TlsVersion) -> Self {
+        self.min_tls_version = version;
+        self
+    }
+
+    /// Sets if the integrity of data is validated with MD5 checksums
+    ///
+    /// Uploads send the `Content-MD5` of their data, which the service validates
+    /// before storing it, and downloads whose response has a `Content-MD5` fail with
+    /// an error if the data received does not match it. Computing checksums costs
+    /// CPU, and this is only supported by [`MicrosoftAzure`](crate::azure::MicrosoftAzure)
+    ///
+    /// Default is false
+    pub fn with_checksum_validation(mut self, validate: bool) -> Self {
+        self.checksum_validation = validate;
+        self
+    }
+
+    /// Sets if the certificate presented by the server of each HTTPS connection is
+    /// retained, so that it can be reported for diagnostics, such as to the request
+    /// observer of [`MicrosoftAzure`](crate::azure::MicrosoftAzure)
+    ///
+    /// Default is false
+    pub fn with_tls_info(mut self, tls_info: bool) -> Self {
+        self.tls_info = tls_info;
+        self
+    }
+
     /// Set an HTTP proxy to use for requests
     pub fn with_proxy_url(mut self, proxy_url: impl Into<String>) -> Self {
         self.proxy_url = Some(proxy_url.into());
@@ -128,7 +185,8 @@ impl ClientOptions {
     /// Set a request timeout
     ///
     /// The timeout is applied from when the request starts connecting until the
-    /// response body has finished
+    /// response body has finished. Idempotent requests that time out are retried
+    /// according to the [`RetryConfig`](crate::RetryConfig) of the store
     pub fn with_timeout(mut self, timeout: Duration) -> Self {
         self.timeout = Some(timeout);
         self
@@ -158,6 +216,18 @@ impl ClientOptions {
         self
     }
 
+    /// Sets if connections are kept open to be reused by subsequent requests
+    ///
+    /// Disabling reuse opens a new connection for every request, which is slower, but
+    /// avoids errors from servers that mishandle persistent connections, as some legacy
+    /// gateways do. These may also require [`Self::with_http1_only`]
+    ///
+    /// Default is true
+    pub fn with_connection_reuse(mut self, reuse: bool) -> Self {
+        self.disable_connection_reuse = !reuse;
+        self
+    }
+
     /// Sets an interval for HTTP2 Ping frames should be sent to keep a connection alive.
     ///
     /// Default is disabled
@@ -206,6 +276,18 @@ impl ClientOptions {
         }
     }
 
+    /// Returns the headers set with [`Self::with_default_headers`], if any
+    #[cfg(feature = "azure")]
+    pub(crate) fn default_headers(&self) -> Option<&HeaderMap> {
+        self.default_headers.as_ref()
+    }
+
+    /// Returns if checksums are validated, see [`Self::with_checksum_validation`]
+    #[cfg(feature = "azure")]
+    pub(crate) fn checksum_validation(&self) -> bool {
+        self.checksum_validation
+    }
+
     pub(crate) fn client(&self) -> super::Result<Client> {
         let mut builder = ClientBuilder::new();
 
@@ -239,6 +321,10 @@ impl ClientOptions {
             builder = builder.pool_max_idle_per_host(max)
         }
 
+        if self.disable_connection_reuse {
+            builder = builder.pool_max_idle_per_host(0)
+        }
+
         if let Some(interval) = self.http2_keep_alive_interval {
             builder = builder.http2_keep_alive_interval(interval)
         }
@@ -260,8 +346,62 @@ impl ClientOptions {
         }
 
         builder
+            .min_tls_version(self.min_tls_version.into())
+            .tls_info(self.tls_info)
             .https_only(!self.allow_http)
             .build()
             .map_err(map_client_error)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Returns the number of connections made to send two requests
+    async fn connections(options: ClientOptions) -> usize {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let accepted = Arc::new(AtomicUsize::new(0));
+
+        let counter = Arc::clone(&accepted);
+        let server = tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                counter.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move {
+                    let mut buf = [0; 1024];
+                    while stream.read(&mut buf).await.unwrap_or(0) > 0 {
+                        let response = b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n";
+                        if stream.write_all(response).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+
+        let client = options.with_allow_http(true).client().unwrap();
+        for _ in 0..2 {
+            let response = client.get(&url).send().await.unwrap();
+            assert!(response.status().is_success());
+        }
+        server.abort();
+        accepted.load(Ordering::SeqCst)
+    }
+
+    #[tokio::test]
+    async fn test_connection_reuse() {
+        assert_eq!(connections(ClientOptions::new()).await, 1);
+
+        let options = ClientOptions::new().with_connection_reuse(false);
+        assert_eq!(connections(options).await, 2);
+
+        let options = ClientOptions::new().with_http1_only();
+        assert_eq!(connections(options).await, 1);
+    }
+}
@@ -18,10 +18,12 @@
 //! A shared HTTP client implementation incorporating retries
 
 use crate::client::backoff::{Backoff, BackoffConfig};
+use chrono::{DateTime, Utc};
 use futures::future::BoxFuture;
 use futures::FutureExt;
-use reqwest::header::LOCATION;
+use reqwest::header::{HeaderValue, LOCATION, RETRY_AFTER};
 use reqwest::{Response, StatusCode};
+use serde::Deserialize;
 use std::time::{Duration, Instant};
 use tracing::info;
 
@@ -30,6 +32,8 @@ use tracing::info;
 pub struct Error {
     retries: usize,
     message: String,
+    response: Option<Box<ErrorResponse>>,
+    retry_after: Option<Duration>,
     source: Option<reqwest::Error>,
 }
 
@@ -58,6 +62,61 @@ impl Error {
     pub fn status(&self) -> Option<StatusCode> {
         self.source.as_ref().and_then(|e| e.status())
     }
+
+    /// Returns true if the request failed without receiving a response, for example
+    /// due to a timeout or a dropped connection
+    pub fn is_request_error(&self) -> bool {
+        matches!(&self.source, Some(source) if source.status().is_none())
+    }
+
+    /// Returns the delay requested by the `Retry-After` header of the response, if any
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.retry_after
+    }
+
+    /// Returns the error code from an XML error response body if any, e.g. `BlobNotFound`
+    pub fn code(&self) -> Option<&str> {
+        self.response.as_ref().map(|r| r.code.as_str())
+    }
+
+    /// Returns the name and, if reported, the value of the request header an XML
+    /// error response identified as the cause of the error, e.g. for an
+    /// `InvalidHeaderValue` error
+    pub fn header(&self) -> Option<(&str, Option<&str>)> {
+        let response = self.response.as_ref()?;
+        let name = response.header_name.as_deref()?;
+        Some((name, response.header_value.as_deref()))
+    }
+}
+
+/// An XML error response body, as returned by Azure, S3 and GCS
+///
+/// <https://learn.microsoft.com/en-us/rest/api/storageservices/status-and-error-codes2>
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ErrorResponse {
+    code: String,
+    message: Option<String>,
+    header_name: Option<String>,
+    header_value: Option<String>,
+}
+
+impl ErrorResponse {
+    fn parse(body: &str) -> Option<Self> {
+        quick_xml::de::from_str(body.trim_start_matches('\u{feff}')).ok()
+    }
+
+    fn to_message(&self) -> String {
+        let message = match &self.message {
+            Some(message) => format!("{}: {}", self.code, message.trim()),
+            None => self.code.clone(),
+        };
+        match (&self.header_name, &self.header_value) {
+            (Some(name), Some(value)) => format!("{} ({}: {})", message, name, value),
+            (Some(name), None) => format!("{} ({})", message, name),
+            _ => message,
+        }
+    }
 }
 
 impl From<Error> for std::io::Error {
@@ -109,6 +168,17 @@ pub struct RetryConfig {
     /// below 5 minutes to avoid errors due to expired credentials
     /// and/or request payloads
     pub retry_timeout: Duration,
+
+    /// The maximum number of times to retry a request that failed to resolve the
+    /// host name, for example as DNS may be unavailable shortly after startup
+    ///
+    /// These are retried with the same backoff and `retry_timeout` as server errors,
+    /// but counted separately from `max_retries`. Set to 0 to disable retries
+    pub max_dns_retries: usize,
+
+    /// Status codes, in addition to server errors, for which a request is retried,
+    /// for example `429 Too Many Requests`
+    pub retryable_statuses: Vec<u16>,
 }
 
 impl Default for RetryConfig {
@@ -117,34 +187,127 @@ impl Default for RetryConfig {
             backoff: Default::default(),
             max_retries: 10,
             retry_timeout: Duration::from_secs(3 * 60),
+            max_dns_retries: 3,
+            retryable_statuses: vec![],
         }
     }
 }
 
+impl RetryConfig {
+    /// Set the backoff before the first retry, see [`BackoffConfig::init_backoff`]
+    pub fn with_initial_backoff(mut self, backoff: Duration) -> Self {
+        self.backoff.init_backoff = backoff;
+        self
+    }
+
+    /// Set the maximum backoff between retries, see [`BackoffConfig::max_backoff`]
+    pub fn with_max_backoff(mut self, backoff: Duration) -> Self {
+        self.backoff.max_backoff = backoff;
+        self
+    }
+
+    /// Set the factor by which the backoff grows with each retry, see [`BackoffConfig::base`]
+    pub fn with_backoff_multiplier(mut self, multiplier: f64) -> Self {
+        self.backoff.base = multiplier;
+        self
+    }
+
+    /// Back off exponentially, adding a random duration of up to `fraction` of each
+    /// backoff, see [`BackoffConfig::jitter`]
+    ///
+    /// A `fraction` of 0 disables jitter. Jitter spreads out the retries of many clients
+    /// that failed at the same time, which would otherwise retry in lockstep
+    pub fn with_jitter(mut self, fraction: f64) -> Self {
+        self.backoff.jitter = Some(fraction);
+        self
+    }
+
+    /// Set the maximum number of times to retry a request, see [`Self::max_retries`]
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Set the length of time from the initial request after which it is no longer
+    /// retried, see [`Self::retry_timeout`]
+    ///
+    /// A request is retried until either this or the maximum number of retries is reached
+    pub fn with_max_elapsed_time(mut self, retry_timeout: Duration) -> Self {
+        self.retry_timeout = retry_timeout;
+        self
+    }
+
+    /// Retry requests that fail with any of `statuses`, in addition to server errors
+    ///
+    /// As for server errors, only idempotent requests are retried
+    pub fn with_retryable_statuses(mut self, statuses: Vec<u16>) -> Self {
+        self.retryable_statuses = statuses;
+        self
+    }
+
+    /// Returns true if a response with `status` may be retried
+    pub(crate) fn is_retryable(&self, status: StatusCode) -> bool {
+        status.is_server_error() || self.retryable_statuses.contains(&status.as_u16())
+    }
+}
+
 pub trait RetryExt {
     /// Dispatch a request with the given retry configuration
     ///
+    /// If a retried response has a `Retry-After` header, the request is retried after
+    /// the delay it specifies instead of the backoff, or not at all if this would exceed
+    /// the `retry_timeout`
+    ///
     /// # Panic
     ///
     /// This will panic if the request body is a stream
     fn send_retry(self, config: &RetryConfig) -> BoxFuture<'static, Result<Response>>;
+
+    /// Dispatch a request with the given retry configuration, which if not
+    /// `idempotent` is only retried if it failed before the request was sent
+    ///
+    /// Server errors and errors such as timeouts may occur after the request has
+    /// been applied, and so are not retried for non-idempotent requests, as retrying
+    /// them could apply the request again. Failures to resolve the host name or to
+    /// connect are retried, counting towards `max_retries`
+    ///
+    /// # Panic
+    ///
+    /// This will panic if the request body is a stream
+    fn send_retry_with_idempotency(
+        self,
+        config: &RetryConfig,
+        idempotent: bool,
+    ) -> BoxFuture<'static, Result<Response>>;
 }
 
 impl RetryExt for reqwest::RequestBuilder {
     fn send_retry(self, config: &RetryConfig) -> BoxFuture<'static, Result<Response>> {
+        self.send_retry_with_idempotency(config, true)
+    }
+
+    fn send_retry_with_idempotency(
+        self,
+        config: &RetryConfig,
+        idempotent: bool,
+    ) -> BoxFuture<'static, Result<Response>> {
         let mut backoff = Backoff::new(&config.backoff);
         let max_retries = config.max_retries;
+        let max_dns_retries = config.max_dns_retries;
         let retry_timeout = config.retry_timeout;
+        let config = config.clone();
 
         async move {
             let mut retries = 0;
+            let mut dns_retries = 0;
             let now = Instant::now();
 
             loop {
                 let s = self.try_clone().expect("request body must be cloneable");
                 match s.send().await {
                     Ok(r) => match r.error_for_status_ref() {
-                        Ok(_) if r.status().is_success() => return Ok(r),
+                        // Conditional requests may be answered with Not Modified
+                        Ok(_) if r.status().is_success() || r.status() == StatusCode::NOT_MODIFIED => return Ok(r),
                         Ok(r) => {
                             let is_bare_redirect = r.status().is_redirection() && !r.headers().contains_key(LOCATION);
                             let message = match is_bare_redirect {
@@ -156,45 +319,98 @@ impl RetryExt for reqwest::RequestBuilder {
                             return Err(Error{
                                 message,
                                 retries,
+                                response: None,
+                                retry_after: None,
                                 source: None,
                             })
                         }
                         Err(e) => {
                             let status = r.status();
+                            let retry_after = r.headers().get(RETRY_AFTER).and_then(parse_retry_after);
 
-                            if retries == max_retries
+                            if !idempotent
+                                || retries == max_retries
                                 || now.elapsed() > retry_timeout
-                                || !status.is_server_error() {
+                                || !config.is_retryable(status)
+                                || matches!(retry_after, Some(d) if now.elapsed() + d > retry_timeout) {
+
+                                let body = r.text().await;
+                                let response = body.as_deref().ok().and_then(ErrorResponse::parse);
 
                                 // Get the response message if returned a client error
-                                let message = match status.is_client_error() {
-                                    true => match r.text().await {
+                                // or a structured error response
+                                let message = match (&response, body) {
+                                    (Some(response), _) => response.to_message(),
+                                    (None, body) if status.is_client_error() => match body {
                                         Ok(message) if !message.is_empty() => message,
                                         Ok(_) => "No Body".to_string(),
                                         Err(e) => format!("error getting response body: {}", e)
                                     }
-                                    false => status.to_string(),
+                                    (None, _) => status.to_string(),
                                 };
 
                                 return Err(Error{
                                     message,
                                     retries,
+                                    response: response.map(Box::new),
+                                    retry_after,
                                     source: Some(e),
                                 })
 
                             }
 
-                            let sleep = backoff.next();
+                            let sleep = retry_after.unwrap_or_else(|| backoff.next());
                             retries += 1;
-                            info!("Encountered server error, backing off for {} seconds, retry {} of {}", sleep.as_secs_f32(), retries, max_retries);
+                            info!("Received {}, backing off for {} seconds, retry {} of {}", status, sleep.as_secs_f32(), retries, max_retries);
                             tokio::time::sleep(sleep).await;
                         }
                     },
+                    Err(e) if is_dns_error(&e)
+                        && dns_retries < max_dns_retries
+                        && now.elapsed() <= retry_timeout => {
+                        let sleep = backoff.next();
+                        dns_retries += 1;
+                        info!("Failed to resolve host, backing off for {} seconds, retry {} of {}: {}", sleep.as_secs_f32(), dns_retries, max_dns_retries, e);
+                        tokio::time::sleep(sleep).await;
+                    }
+                    // The request timed out, and so can be retried if idempotent
+                    Err(e) if idempotent
+                        && e.is_timeout()
+                        && retries < max_retries
+                        && now.elapsed() <= retry_timeout => {
+                        let sleep = backoff.next();
+                        retries += 1;
+                        info!("Request timed out, backing off for {} seconds, retry {} of {}: {}", sleep.as_secs_f32(), retries, max_retries, e);
+                        tokio::time::sleep(sleep).await;
+                    }
+                    // The connection was reset, and so can be retried if idempotent
+                    Err(e) if idempotent
+                        && is_connection_reset(&e)
+                        && retries < max_retries
+                        && now.elapsed() <= retry_timeout => {
+                        let sleep = backoff.next();
+                        retries += 1;
+                        info!("Connection reset, backing off for {} seconds, retry {} of {}: {}", sleep.as_secs_f32(), retries, max_retries, e);
+                        tokio::time::sleep(sleep).await;
+                    }
+                    // The request was not sent, and so can be retried even if not idempotent
+                    Err(e) if !idempotent
+                        && e.is_connect()
+                        && !is_dns_error(&e)
+                        && retries < max_retries
+                        && now.elapsed() <= retry_timeout => {
+                        let sleep = backoff.next();
+                        retries += 1;
+                        info!("Failed to connect, backing off for {} seconds, retry {} of {}: {}", sleep.as_secs_f32(), retries, max_retries, e);
+                        tokio::time::sleep(sleep).await;
+                    }
                     Err(e) =>
                     {
                         return Err(Error{
-                            retries,
+                            retries: retries + dns_retries,
                             message: "request error".to_string(),
+                            response: None,
+                            retry_after: None,
                             source: Some(e)
                         })
                     }
@@ -205,15 +421,71 @@ impl RetryExt for reqwest::RequestBuilder {
     }
 }
 
+/// Returns true if `e` was caused by a failure to resolve the host name
+fn is_dns_error(e: &reqwest::Error) -> bool {
+    let mut source = std::error::Error::source(e);
+    while let Some(err) = source {
+        if err.to_string().starts_with("dns error") {
+            return true;
+        }
+        source = err.source();
+    }
+    false
+}
+
+/// Returns true if the connection was reset or closed by the server while the
+/// request was in flight
+fn is_connection_reset(e: &reqwest::Error) -> bool {
+    use std::io::ErrorKind;
+    let mut source = std::error::Error::source(e);
+    while let Some(err) = source {
+        if let Some(io) = err.downcast_ref::<std::io::Error>() {
+            if matches!(
+                io.kind(),
+                ErrorKind::ConnectionReset
+                    | ErrorKind::ConnectionAborted
+                    | ErrorKind::BrokenPipe
+            ) {
+                return true;
+            }
+        }
+        if err
+            .to_string()
+            .starts_with("connection closed before message completed")
+        {
+            return true;
+        }
+        source = err.source();
+    }
+    false
+}
+
+/// Parses a `Retry-After` header, either a number of seconds or an HTTP date
+///
+/// <https://www.rfc-editor.org/rfc/rfc9110#name-retry-after>
+fn parse_retry_after(value: &HeaderValue) -> Option<Duration> {
+    let value = value.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    // A date in the past is a request to retry immediately
+    Some(
+        (date.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use crate::client::mock_server::MockServer;
     use crate::client::retry::RetryExt;
-    use crate::RetryConfig;
-    use hyper::header::LOCATION;
+    use crate::{BackoffConfig, RetryConfig};
+    use hyper::header::{LOCATION, RETRY_AFTER};
     use hyper::{Body, Response};
     use reqwest::{Client, Method, StatusCode};
-    use std::time::Duration;
+    use std::time::{Duration, Instant};
 
     #[tokio::test]
     async fn test_retry() {
@@ -223,6 +495,8 @@ mod tests {
             backoff: Default::default(),
             max_retries: 2,
             retry_timeout: Duration::from_secs(1000),
+            max_dns_retries: 0,
+            retryable_statuses: vec![],
         };
 
         let client = Client::new();
@@ -257,6 +531,50 @@ mod tests {
         assert_eq!(e.status().unwrap(), StatusCode::BAD_REQUEST);
         assert_eq!(e.retries, 0);
         assert_eq!(&e.message, "No Body");
+        assert_eq!(e.code(), None);
+
+        // Parses XML error responses
+        mock.push(
+            Response::builder()
+                .status(StatusCode::FORBIDDEN)
+                .body(Body::from(
+                    "\u{feff}<?xml version=\"1.0\" encoding=\"utf-8\"?><Error>\
+                    <Code>AuthorizationPermissionMismatch</Code>\
+                    <Message>This request is not authorized to perform this operation using this permission.\nRequestId:1234</Message>\
+                    </Error>",
+                ))
+                .unwrap(),
+        );
+
+        let e = do_request().await.unwrap_err();
+        assert_eq!(e.status().unwrap(), StatusCode::FORBIDDEN);
+        assert_eq!(e.code(), Some("AuthorizationPermissionMismatch"));
+        assert_eq!(
+            &e.message,
+            "AuthorizationPermissionMismatch: This request is not authorized to perform this operation using this permission.\nRequestId:1234"
+        );
+        assert_eq!(e.header(), None);
+
+        // Reports the header identified by an XML error response
+        mock.push(
+            Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(
+                    "<Error><Code>InvalidHeaderValue</Code>\
+                    <Message>The value for one of the HTTP headers is not in the correct format.</Message>\
+                    <HeaderName>x-ms-version</HeaderName><HeaderValue>2099-01-01</HeaderValue>\
+                    </Error>",
+                ))
+                .unwrap(),
+        );
+
+        let e = do_request().await.unwrap_err();
+        assert_eq!(e.code(), Some("InvalidHeaderValue"));
+        assert_eq!(e.header(), Some(("x-ms-version", Some("2099-01-01"))));
+        assert_eq!(
+            &e.message,
+            "InvalidHeaderValue: The value for one of the HTTP headers is not in the correct format. (x-ms-version: 2099-01-01)"
+        );
 
         // Should retry server error request
         mock.push(
@@ -345,7 +663,403 @@ mod tests {
         assert_eq!(e.retries, retry.max_retries);
         assert_eq!(e.message, "502 Bad Gateway");
 
+        // Includes XML error responses from server errors
+        for _ in 0..=retry.max_retries {
+            mock.push(
+                Response::builder()
+                    .status(StatusCode::SERVICE_UNAVAILABLE)
+                    .body(Body::from(
+                        "<Error><Code>ServerBusy</Code><Message>Try again</Message></Error>",
+                    ))
+                    .unwrap(),
+            );
+        }
+
+        let e = do_request().await.unwrap_err();
+        assert_eq!(e.code(), Some("ServerBusy"));
+        assert_eq!(e.message, "ServerBusy: Try again");
+
         // Shutdown
         mock.shutdown().await
     }
+
+    #[tokio::test]
+    async fn test_retry_dns() {
+        let retry = RetryConfig {
+            backoff: BackoffConfig {
+                init_backoff: Duration::from_millis(1),
+                max_backoff: Duration::from_millis(10),
+                base: 2.,
+                jitter: None,
+            },
+            max_retries: 0,
+            retry_timeout: Duration::from_secs(1000),
+            max_dns_retries: 2,
+            retryable_statuses: vec![],
+        };
+
+        // The .invalid TLD is reserved, and so will never resolve
+        let client = Client::new();
+        let e = client
+            .request(Method::GET, "http://nonexistent.invalid")
+            .send_retry(&retry)
+            .await
+            .unwrap_err();
+
+        assert_eq!(e.retries, 2);
+        assert!(super::is_dns_error(e.source.as_ref().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_retry_non_idempotent() {
+        let mock = MockServer::new();
+
+        let retry = RetryConfig {
+            backoff: BackoffConfig {
+                init_backoff: Duration::from_millis(1),
+                max_backoff: Duration::from_millis(10),
+                base: 2.,
+                jitter: None,
+            },
+            max_retries: 2,
+            retry_timeout: Duration::from_secs(1000),
+            max_dns_retries: 0,
+            retryable_statuses: vec![],
+        };
+
+        // Server errors are not retried, as the request may have been applied
+        mock.push(
+            Response::builder()
+                .status(StatusCode::BAD_GATEWAY)
+                .body(Body::empty())
+                .unwrap(),
+        );
+
+        let client = Client::new();
+        let e = client
+            .request(Method::PUT, mock.url())
+            .send_retry_with_idempotency(&retry, false)
+            .await
+            .unwrap_err();
+        assert_eq!(e.status().unwrap(), StatusCode::BAD_GATEWAY);
+        assert_eq!(e.retries, 0);
+
+        let r = client
+            .request(Method::PUT, mock.url())
+            .send_retry_with_idempotency(&retry, false)
+            .await
+            .unwrap();
+        assert_eq!(r.status(), StatusCode::OK);
+
+        mock.shutdown().await;
+
+        // Connection failures are retried, as the request was never sent
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let e = client
+            .request(Method::PUT, &url)
+            .send_retry_with_idempotency(&retry, false)
+            .await
+            .unwrap_err();
+        assert_eq!(e.retries, 2);
+        assert!(e.source.as_ref().unwrap().is_connect());
+
+        // Whereas idempotent requests are retried as before
+        let e = client
+            .request(Method::PUT, &url)
+            .send_retry(&retry)
+            .await
+            .unwrap_err();
+        assert_eq!(e.retries, 0);
+    }
+
+    #[tokio::test]
+    async fn test_retry_timeout() {
+        let mock = MockServer::new();
+
+        let retry = RetryConfig {
+            backoff: BackoffConfig {
+                init_backoff: Duration::from_millis(1),
+                max_backoff: Duration::from_millis(10),
+                base: 2.,
+                jitter: None,
+            },
+            max_retries: 2,
+            retry_timeout: Duration::from_secs(1000),
+            max_dns_retries: 0,
+            retryable_statuses: vec![],
+        };
+        let client = Client::builder()
+            .timeout(Duration::from_millis(100))
+            .build()
+            .unwrap();
+        let slow = || async {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            Response::new(Body::empty())
+        };
+
+        // Idempotent requests that time out are retried
+        mock.push_async_fn(move |_| slow());
+        mock.push(Response::new(Body::empty()));
+        let r = client
+            .request(Method::GET, mock.url())
+            .send_retry(&retry)
+            .await
+            .unwrap();
+        assert_eq!(r.status(), StatusCode::OK);
+
+        // Up to the maximum number of retries
+        for _ in 0..3 {
+            mock.push_async_fn(move |_| slow());
+        }
+        let e = client
+            .request(Method::GET, mock.url())
+            .send_retry(&retry)
+            .await
+            .unwrap_err();
+        assert_eq!(e.retries, 2);
+        assert!(e.source.as_ref().unwrap().is_timeout());
+
+        // Non-idempotent requests are not, as they may have been applied
+        mock.push_async_fn(move |_| slow());
+        let e = client
+            .request(Method::PUT, mock.url())
+            .send_retry_with_idempotency(&retry, false)
+            .await
+            .unwrap_err();
+        assert_eq!(e.retries, 0);
+        assert!(e.source.as_ref().unwrap().is_timeout());
+
+        mock.shutdown().await
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        use super::parse_retry_after;
+        use hyper::header::HeaderValue;
+
+        let parse = |s: &str| parse_retry_after(&HeaderValue::from_str(s).unwrap());
+        assert_eq!(parse("120"), Some(Duration::from_secs(120)));
+        assert_eq!(parse(" 0 "), Some(Duration::ZERO));
+        assert_eq!(parse("Wed, 21 Oct 2015 07:28:00 GMT"), Some(Duration::ZERO));
+        assert_eq!(parse("soon"), None);
+        assert_eq!(parse("-1"), None);
+
+        let date = (chrono::Utc::now() + chrono::Duration::seconds(60))
+            .format("%a, %d %b %Y %H:%M:%S GMT")
+            .to_string();
+        let d = parse(&date).unwrap();
+        assert!(
+            d > Duration::from_secs(55) && d <= Duration::from_secs(60),
+            "{:?}",
+            d
+        );
+    }
+
+    #[tokio::test]
+    async fn test_retry_statuses() {
+        let mock = MockServer::new();
+
+        let retry = RetryConfig {
+            backoff: BackoffConfig {
+                init_backoff: Duration::from_millis(1),
+                max_backoff: Duration::from_millis(10),
+                base: 2.,
+                jitter: None,
+            },
+            max_retries: 2,
+            retry_timeout: Duration::from_secs(10),
+            max_dns_retries: 0,
+            retryable_statuses: vec![],
+        };
+        let client = Client::new();
+        let too_many_requests = || {
+            Response::builder()
+                .status(StatusCode::TOO_MANY_REQUESTS)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        // Client errors are not retried by default
+        mock.push(too_many_requests());
+        let e = client
+            .request(Method::GET, mock.url())
+            .send_retry(&retry)
+            .await
+            .unwrap_err();
+        assert_eq!(e.status().unwrap(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(e.retries, 0);
+
+        // Unless opted in
+        let retry = retry.with_retryable_statuses(vec![429]);
+        mock.push(too_many_requests());
+        let r = client
+            .request(Method::GET, mock.url())
+            .send_retry(&retry)
+            .await
+            .unwrap();
+        assert_eq!(r.status(), StatusCode::OK);
+
+        // But not for non-idempotent requests
+        mock.push(too_many_requests());
+        let e = client
+            .request(Method::PUT, mock.url())
+            .send_retry_with_idempotency(&retry, false)
+            .await
+            .unwrap_err();
+        assert_eq!(e.retries, 0);
+
+        // Honors Retry-After in seconds
+        mock.push(
+            Response::builder()
+                .status(StatusCode::SERVICE_UNAVAILABLE)
+                .header(RETRY_AFTER, "1")
+                .body(Body::empty())
+                .unwrap(),
+        );
+        let start = Instant::now();
+        let r = client
+            .request(Method::GET, mock.url())
+            .send_retry(&retry)
+            .await
+            .unwrap();
+        assert_eq!(r.status(), StatusCode::OK);
+        assert!(start.elapsed() >= Duration::from_secs(1));
+
+        // And as an HTTP date, retrying immediately if in the past
+        mock.push(
+            Response::builder()
+                .status(StatusCode::TOO_MANY_REQUESTS)
+                .header(RETRY_AFTER, "Wed, 21 Oct 2015 07:28:00 GMT")
+                .body(Body::empty())
+                .unwrap(),
+        );
+        let r = client
+            .request(Method::GET, mock.url())
+            .send_retry(&retry)
+            .await
+            .unwrap();
+        assert_eq!(r.status(), StatusCode::OK);
+
+        // Gives up if the delay would exceed the retry timeout
+        mock.push(
+            Response::builder()
+                .status(StatusCode::SERVICE_UNAVAILABLE)
+                .header(RETRY_AFTER, "60")
+                .body(Body::empty())
+                .unwrap(),
+        );
+        let start = Instant::now();
+        let e = client
+            .request(Method::GET, mock.url())
+            .send_retry(&retry)
+            .await
+            .unwrap_err();
+        assert_eq!(e.status().unwrap(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(e.retries, 0);
+        assert!(start.elapsed() < Duration::from_secs(10));
+
+        mock.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn test_retry_connection_reset() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+
+        let retry = RetryConfig {
+            backoff: BackoffConfig {
+                init_backoff: Duration::from_millis(1),
+                max_backoff: Duration::from_millis(10),
+                base: 2.,
+                jitter: None,
+            },
+            max_retries: 2,
+            retry_timeout: Duration::from_secs(1000),
+            max_dns_retries: 0,
+            retryable_statuses: vec![],
+        };
+
+        // Reads a request and then resets the connection
+        async fn reset(listener: &TcpListener) {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let _ = stream.read(&mut [0; 1024]).await.unwrap();
+            // A zero linger sends a reset on drop, rather than blocking
+            #[allow(deprecated)]
+            stream.set_linger(Some(Duration::ZERO)).unwrap();
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        // Idempotent requests are retried
+        let server = tokio::spawn(async move {
+            reset(&listener).await;
+            reset(&listener).await;
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let _ = stream.read(&mut [0; 1024]).await.unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+            listener
+        });
+        let client = Client::new();
+        let r = client
+            .request(Method::GET, &url)
+            .send_retry(&retry)
+            .await
+            .unwrap();
+        assert_eq!(r.status(), StatusCode::OK);
+        let listener = server.await.unwrap();
+
+        // Non-idempotent requests are not, as they may have been applied
+        let server = tokio::spawn(async move { reset(&listener).await });
+        let client = Client::new();
+        let e = client
+            .request(Method::PUT, &url)
+            .send_retry_with_idempotency(&retry, false)
+            .await
+            .unwrap_err();
+        assert_eq!(e.retries, 0);
+        assert!(super::is_connection_reset(e.source.as_ref().unwrap()));
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_retry_max_elapsed_time() {
+        let mock = MockServer::new();
+
+        let retry = RetryConfig::default()
+            .with_initial_backoff(Duration::from_millis(50))
+            .with_max_backoff(Duration::from_millis(50))
+            .with_backoff_multiplier(1.)
+            .with_jitter(0.)
+            .with_max_retries(100)
+            .with_max_elapsed_time(Duration::from_millis(200));
+        assert_eq!(retry.backoff.init_backoff, Duration::from_millis(50));
+        assert_eq!(retry.backoff.jitter, Some(0.));
+
+        // Gives up once the elapsed time is exceeded, before the maximum retries
+        for _ in 0..=retry.max_retries {
+            mock.push(
+                Response::builder()
+                    .status(StatusCode::BAD_GATEWAY)
+                    .body(Body::empty())
+                    .unwrap(),
+            );
+        }
+        let start = Instant::now();
+        let e = Client::new()
+            .request(Method::GET, mock.url())
+            .send_retry(&retry)
+            .await
+            .unwrap_err();
+        assert!((3..=5).contains(&e.retries), "{}", e.retries);
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        mock.shutdown().await
+    }
 }
@@ -69,4 +69,18 @@ impl<T: Clone + Send> TokenCache<T> {
 
         Ok(token)
     }
+
+    /// Discard the cached token if it is `token`, e.g. as it was rejected, so that
+    /// the next call to [`Self::get_or_insert_with`] fetches a new token
+    ///
+    /// A different cached token is kept, as it has already been refreshed
+    pub async fn invalidate(&self, token: &T)
+    where
+        T: PartialEq + Sync,
+    {
+        let mut locked = self.cache.lock().await;
+        if matches!(locked.as_ref(), Some(cached) if &cached.token == token) {
+            *locked = None;
+        }
+    }
 }
@@ -0,0 +1,424 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! An object store wrapper injecting faults, for testing error handling
+use parking_lot::Mutex;
+use snafu::Snafu;
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::MultipartId;
+use crate::{
+    path::Path, Capabilities, GetResult, ListResult, ObjectMeta, ObjectStore, PutOptions,
+    PutResult, Result, Revalidated,
+};
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use tokio::io::AsyncWrite;
+
+/// A fault that can be injected by [`FaultInjectingStore`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Fault {
+    /// Fail with [`Error::NotFound`](crate::Error::NotFound)
+    NotFound,
+    /// Fail as if the request was throttled by the store
+    Throttled,
+    /// Fail as if the store returned an internal server error
+    ServerError,
+    /// Fail as if the request timed out
+    Timeout,
+}
+
+/// An operation of [`FaultInjectingStore`] that faults can be injected into
+///
+/// Provided methods of [`ObjectStore`], such as [`ObjectStore::get_opts`], are
+/// implemented in terms of these operations, and so are subject to the same faults
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Operation {
+    /// [`ObjectStore::put`] and [`ObjectStore::update`]
+    Put,
+    /// [`ObjectStore::put_multipart`] and [`ObjectStore::put_multipart_bytes`]
+    PutMultipart,
+    /// [`ObjectStore::abort_multipart`]
+    AbortMultipart,
+    /// [`ObjectStore::get`] and [`ObjectStore::revalidate`]
+    Get,
+    /// [`ObjectStore::get_range`]
+    GetRange,
+    /// [`ObjectStore::head`]
+    Head,
+    /// [`ObjectStore::delete`]
+    Delete,
+    /// [`ObjectStore::list`]
+    List,
+    /// [`ObjectStore::list_with_delimiter`]
+    ListWithDelimiter,
+    /// [`ObjectStore::copy`] and [`ObjectStore::copy_if_not_exists`]
+    Copy,
+}
+
+/// A specific error type that describes the injected faults
+#[derive(Debug, Snafu)]
+enum Error {
+    #[snafu(display("Injected fault: object not found"))]
+    NotFound,
+
+    #[snafu(display("Injected fault: request throttled"))]
+    Throttled,
+
+    #[snafu(display("Injected fault: internal server error"))]
+    Server,
+
+    #[snafu(display("Injected fault: request timed out"))]
+    Timeout,
+}
+
+impl Fault {
+    fn into_error(self, location: &str) -> super::Error {
+        let source = match self {
+            Self::NotFound => {
+                return super::Error::NotFound {
+                    path: location.to_string(),
+                    source: Box::new(Error::NotFound),
+                }
+            }
+            Self::Throttled => Error::Throttled,
+            Self::ServerError => Error::Server,
+            Self::Timeout => Error::Timeout,
+        };
+
+        super::Error::Generic {
+            store: "FaultInjectingStore",
+            source: Box::new(source),
+        }
+    }
+}
+
+/// When a [`Rule`] triggers
+#[derive(Debug, Clone, Copy)]
+enum Trigger {
+    /// Only the nth call, counting from 1
+    Nth(usize),
+    /// The given percentage of calls, evenly spread
+    Percentage(u8),
+}
+
+impl Trigger {
+    /// Returns true if the `call`th call, counting from 1, should fail
+    fn matches(&self, call: usize) -> bool {
+        match *self {
+            Self::Nth(n) => call == n,
+            Self::Percentage(percentage) => {
+                let percentage = percentage.min(100) as usize;
+                call * percentage / 100 > (call - 1) * percentage / 100
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Rule {
+    operation: Operation,
+    trigger: Trigger,
+    fault: Fault,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    rules: Vec<Rule>,
+    calls: HashMap<Operation, usize>,
+}
+
+/// Store wrapper that fails calls to the inner store according to configured rules.
+///
+/// This can be used to test how code handles transient errors from a store.
+///
+/// Faults are injected deterministically, based on the number of calls made to each
+/// [`Operation`], and before the inner store is called.
+///
+/// ```
+/// # use object_store::fault::{Fault, FaultInjectingStore, Operation};
+/// # use object_store::memory::InMemory;
+/// let store = FaultInjectingStore::new(InMemory::new());
+/// // The second call to get fails as if the request had timed out
+/// store.fail_nth(Operation::Get, 2, Fault::Timeout);
+/// // A quarter of puts fail as if throttled
+/// store.fail_percentage(Operation::Put, 25, Fault::Throttled);
+/// ```
+#[derive(Debug)]
+pub struct FaultInjectingStore<T: ObjectStore> {
+    inner: T,
+    state: Mutex<State>,
+}
+
+impl<T: ObjectStore> FaultInjectingStore<T> {
+    /// Create new wrapper without any faults configured.
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            state: Default::default(),
+        }
+    }
+
+    /// Fail the `n`th call to `operation`, counting from 1, with `fault`.
+    pub fn fail_nth(&self, operation: Operation, n: usize, fault: Fault) {
+        self.push(operation, Trigger::Nth(n), fault)
+    }
+
+    /// Fail `percentage` percent of the calls to `operation` with `fault`.
+    ///
+    /// The failures are spread evenly, e.g. `25` fails every fourth call.
+    pub fn fail_percentage(&self, operation: Operation, percentage: u8, fault: Fault) {
+        self.push(operation, Trigger::Percentage(percentage), fault)
+    }
+
+    /// Remove all configured faults and reset the call counts.
+    pub fn clear(&self) {
+        *self.state.lock() = Default::default();
+    }
+
+    /// Return the number of calls made to `operation`, including failed ones.
+    pub fn calls(&self, operation: Operation) -> usize {
+        self.state
+            .lock()
+            .calls
+            .get(&operation)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn push(&self, operation: Operation, trigger: Trigger, fault: Fault) {
+        self.state.lock().rules.push(Rule {
+            operation,
+            trigger,
+            fault,
+        })
+    }
+
+    /// Record a call to `operation`, returning an error if a fault should be injected
+    fn check(&self, operation: Operation, location: Option<&Path>) -> Result<()> {
+        let mut state = self.state.lock();
+        let call = state.calls.entry(operation).or_default();
+        *call += 1;
+        let call = *call;
+
+        let rule = state
+            .rules
+            .iter()
+            .find(|r| r.operation == operation && r.trigger.matches(call));
+
+        match rule {
+            Some(rule) => {
+                let location = location.map(|l| l.as_ref()).unwrap_or_default();
+                Err(rule.fault.into_error(location))
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl<T: ObjectStore> std::fmt::Display for FaultInjectingStore<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "FaultInjectingStore({})", self.inner)
+    }
+}
+
+#[async_trait]
+impl<T: ObjectStore> ObjectStore for FaultInjectingStore<T> {
+    async fn put(&self, location: &Path, bytes: Bytes) -> Result<()> {
+        self.check(Operation::Put, Some(location))?;
+        self.inner.put(location, bytes).await
+    }
+
+    async fn put_opts(
+        &self,
+        location: &Path,
+        bytes: Bytes,
+        options: PutOptions,
+    ) -> Result<()> {
+        self.check(Operation::Put, Some(location))?;
+        self.inner.put_opts(location, bytes, options).await
+    }
+
+    async fn update(
+        &self,
+        location: &Path,
+        expected_e_tag: Option<String>,
+        bytes: Bytes,
+    ) -> Result<PutResult> {
+        self.check(Operation::Put, Some(location))?;
+        self.inner.update(location, expected_e_tag, bytes).await
+    }
+
+    async fn revalidate(
+        &self,
+        location: &Path,
+        cached_e_tag: Option<String>,
+    ) -> Result<Revalidated> {
+        self.check(Operation::Get, Some(location))?;
+        self.inner.revalidate(location, cached_e_tag).await
+    }
+
+    /// Conditional gets and renames are not forwarded to the inner store
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            conditional_get: false,
+            atomic_rename: false,
+            ..self.inner.capabilities()
+        }
+    }
+
+    async fn put_multipart(
+        &self,
+        location: &Path,
+    ) -> Result<(MultipartId, Box<dyn AsyncWrite + Unpin + Send>)> {
+        self.check(Operation::PutMultipart, Some(location))?;
+        self.inner.put_multipart(location).await
+    }
+
+    async fn put_multipart_bytes(&self, location: &Path, bytes: Bytes) -> Result<()> {
+        self.check(Operation::PutMultipart, Some(location))?;
+        self.inner.put_multipart_bytes(location, bytes).await
+    }
+
+    async fn abort_multipart(
+        &self,
+        location: &Path,
+        multipart_id: &MultipartId,
+    ) -> Result<()> {
+        self.check(Operation::AbortMultipart, Some(location))?;
+        self.inner.abort_multipart(location, multipart_id).await
+    }
+
+    async fn get(&self, location: &Path) -> Result<GetResult> {
+        self.check(Operation::Get, Some(location))?;
+        self.inner.get(location).await
+    }
+
+    async fn get_range(&self, location: &Path, range: Range<usize>) -> Result<Bytes> {
+        self.check(Operation::GetRange, Some(location))?;
+        self.inner.get_range(location, range).await
+    }
+
+    async fn head(&self, location: &Path) -> Result<ObjectMeta> {
+        self.check(Operation::Head, Some(location))?;
+        self.inner.head(location).await
+    }
+
+    async fn delete(&self, location: &Path) -> Result<()> {
+        self.check(Operation::Delete, Some(location))?;
+        self.inner.delete(location).await
+    }
+
+    async fn list(
+        &self,
+        prefix: Option<&Path>,
+    ) -> Result<BoxStream<'_, Result<ObjectMeta>>> {
+        self.check(Operation::List, prefix)?;
+        self.inner.list(prefix).await
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> Result<ListResult> {
+        self.check(Operation::ListWithDelimiter, prefix)?;
+        self.inner.list_with_delimiter(prefix).await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+        self.check(Operation::Copy, Some(from))?;
+        self.inner.copy(from, to).await
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+        self.check(Operation::Copy, Some(from))?;
+        self.inner.copy_if_not_exists(from, to).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        memory::InMemory,
+        tests::{
+            copy_if_not_exists, list_uses_directories_correctly, list_with_delimiter,
+            put_get_delete_list, rename_and_copy,
+        },
+    };
+
+    #[tokio::test]
+    async fn fault_test() {
+        let store = FaultInjectingStore::new(InMemory::new());
+
+        put_get_delete_list(&store).await;
+        list_uses_directories_correctly(&store).await;
+        list_with_delimiter(&store).await;
+        rename_and_copy(&store).await;
+        copy_if_not_exists(&store).await;
+    }
+
+    #[tokio::test]
+    async fn fail_nth() {
+        let store = FaultInjectingStore::new(InMemory::new());
+        let location = Path::from("test_file");
+        store.put(&location, Bytes::from("data")).await.unwrap();
+
+        store.fail_nth(Operation::Get, 2, Fault::ServerError);
+        store.fail_nth(Operation::Head, 1, Fault::NotFound);
+
+        store.get(&location).await.unwrap();
+        let err = store.get(&location).await.unwrap_err();
+        assert!(matches!(err, crate::Error::Generic { .. }), "{}", err);
+        assert!(err.to_string().contains("internal server error"), "{}", err);
+        store.get(&location).await.unwrap();
+        assert_eq!(store.calls(Operation::Get), 3);
+
+        let err = store.head(&location).await.unwrap_err();
+        assert!(matches!(err, crate::Error::NotFound { .. }), "{}", err);
+        store.head(&location).await.unwrap();
+
+        store.clear();
+        assert_eq!(store.calls(Operation::Get), 0);
+        store.get(&location).await.unwrap();
+        store.get(&location).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn fail_percentage() {
+        let store = FaultInjectingStore::new(InMemory::new());
+        store.fail_percentage(Operation::Put, 25, Fault::Throttled);
+
+        let mut failed = vec![];
+        for i in 0..8 {
+            let location = Path::from(format!("file_{}", i));
+            if let Err(e) = store.put(&location, Bytes::from("data")).await {
+                assert!(e.to_string().contains("throttled"), "{}", e);
+                failed.push(i);
+            }
+        }
+        assert_eq!(failed, vec![3, 7]);
+
+        let store = FaultInjectingStore::new(InMemory::new());
+        store.fail_percentage(Operation::List, 100, Fault::Timeout);
+        for _ in 0..3 {
+            let err = store.list(None).await.err().unwrap();
+            assert!(err.to_string().contains("timed out"), "{}", err);
+        }
+    }
+}
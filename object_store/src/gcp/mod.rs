@@ -55,8 +55,8 @@ use crate::{
     multipart::{CloudMultiPartUpload, CloudMultiPartUploadImpl, UploadPart},
     path::{Path, DELIMITER},
     util::{format_http_range, format_prefix},
-    ClientOptions, GetResult, ListResult, MultipartId, ObjectMeta, ObjectStore, Result,
-    RetryConfig,
+    Capabilities, ClientOptions, GetResult, ListResult, MultipartId, ObjectMeta,
+    ObjectStore, Result, RetryConfig,
 };
 
 use credential::OAuthProvider;
@@ -560,7 +560,7 @@ impl CloudMultiPartUploadImpl for GCSMultipartUpload {
     /// Upload an object part <https://cloud.google.com/storage/docs/xml-api/put-object-multipart>
     async fn put_multipart_part(
         &self,
-        buf: Vec<u8>,
+        buf: Bytes,
         part_idx: usize,
     ) -> Result<UploadPart, io::Error> {
         let upload_id = self.multipart_id.clone();
@@ -697,7 +697,7 @@ impl ObjectStore for GoogleCloudStorage {
             })
             .boxed();
 
-        Ok(GetResult::Stream(stream))
+        Ok(GetResult::Stream(stream, None))
     }
 
     async fn get_range(&self, location: &Path, range: Range<usize>) -> Result<Bytes> {
@@ -772,6 +772,13 @@ impl ObjectStore for GoogleCloudStorage {
     async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
         self.client.copy_request(from, to, true).await
     }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            copy_if_not_exists: true,
+            ..Default::default()
+        }
+    }
 }
 
 fn reader_credentials_file(
@@ -1102,6 +1109,8 @@ fn convert_object_meta(object: &Object) -> Result<ObjectMeta> {
         location,
         last_modified,
         size,
+        content_type: None,
+        e_tag: None,
     })
 }
 
@@ -339,6 +339,8 @@ impl MultiStatusResponse {
             location: self.path(base_url)?,
             last_modified: self.prop_stat.prop.last_modified,
             size: self.size()?,
+            content_type: None,
+            e_tag: None,
         })
     }
 
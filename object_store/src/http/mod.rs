@@ -44,8 +44,8 @@ use url::Url;
 use crate::http::client::Client;
 use crate::path::Path;
 use crate::{
-    ClientOptions, GetResult, ListResult, MultipartId, ObjectMeta, ObjectStore, Result,
-    RetryConfig,
+    Capabilities, ClientOptions, GetResult, ListResult, MultipartId, ObjectMeta,
+    ObjectStore, Result, RetryConfig,
 };
 
 mod client;
@@ -125,7 +125,7 @@ impl ObjectStore for HttpStore {
             .map_err(|source| Error::Reqwest { source }.into())
             .boxed();
 
-        Ok(GetResult::Stream(stream))
+        Ok(GetResult::Stream(stream, None))
     }
 
     async fn get_range(&self, location: &Path, range: Range<usize>) -> Result<Bytes> {
@@ -210,6 +210,13 @@ impl ObjectStore for HttpStore {
     async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
         self.client.copy(from, to, false).await
     }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            copy_if_not_exists: true,
+            ..Default::default()
+        }
+    }
 }
 
 /// Configure a connection to a generic HTTP server
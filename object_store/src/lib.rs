@@ -227,8 +227,12 @@ pub mod aws;
 #[cfg(feature = "azure")]
 pub mod azure;
 #[cfg(not(target_arch = "wasm32"))]
+pub mod cache;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod chunked;
 pub mod delimited;
+#[cfg(feature = "test-util")]
+pub mod fault;
 #[cfg(feature = "gcp")]
 pub mod gcp;
 #[cfg(feature = "http")]
@@ -254,20 +258,27 @@ mod util;
 use crate::path::Path;
 #[cfg(not(target_arch = "wasm32"))]
 use crate::util::maybe_spawn_blocking;
-use crate::util::{coalesce_ranges, collect_bytes, OBJECT_STORE_COALESCE_DEFAULT};
+use crate::util::{
+    cancellable, coalesce_ranges, coalesce_stream, collect_bytes, ensure_source_newer,
+    split_range, CancellableWriter, Glob, ProgressWriter, OBJECT_STORE_COALESCE_DEFAULT,
+    OBJECT_STORE_LIST_PREFIXES_PARALLEL,
+};
 use async_trait::async_trait;
 use bytes::Bytes;
 use chrono::{DateTime, Utc};
-use futures::{stream::BoxStream, StreamExt};
+use futures::{stream::BoxStream, StreamExt, TryStreamExt};
 use snafu::Snafu;
 use std::fmt::{Debug, Formatter};
 #[cfg(not(target_arch = "wasm32"))]
 use std::io::{Read, Seek, SeekFrom};
 use std::ops::Range;
+use std::sync::Arc;
 use tokio::io::AsyncWrite;
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
 
 #[cfg(any(feature = "azure", feature = "aws", feature = "gcp", feature = "http"))]
-pub use client::ClientOptions;
+pub use client::{ClientOptions, TlsVersion};
 
 /// An alias for a dynamically dispatched object store implementation.
 pub type DynObjectStore = dyn ObjectStore;
@@ -281,6 +292,24 @@ pub trait ObjectStore: std::fmt::Display + Send + Sync + Debug + 'static {
     /// Save the provided bytes to the specified location.
     async fn put(&self, location: &Path, bytes: Bytes) -> Result<()>;
 
+    /// Save the provided bytes to the specified location with the given options
+    ///
+    /// Any [progress](PutOptions::progress) callback is invoked once the upload completes,
+    /// and the upload is aborted if [cancelled](PutOptions::cancel) before then
+    async fn put_opts(
+        &self,
+        location: &Path,
+        bytes: Bytes,
+        options: PutOptions,
+    ) -> Result<()> {
+        let len = bytes.len() as u64;
+        cancellable(options.cancel, location, self.put(location, bytes)).await?;
+        if let Some(progress) = options.progress {
+            progress(len, Some(len));
+        }
+        Ok(())
+    }
+
     /// Get a multi-part upload that allows writing data in chunks
     ///
     /// Most cloud-based uploads will buffer and upload parts in parallel.
@@ -296,6 +325,60 @@ pub trait ObjectStore: std::fmt::Display + Send + Sync + Debug + 'static {
         location: &Path,
     ) -> Result<(MultipartId, Box<dyn AsyncWrite + Unpin + Send>)>;
 
+    /// Get a multi-part upload with the given options, see [`ObjectStore::put_multipart`]
+    ///
+    /// By default any [progress](PutOptions::progress) callback is invoked as data is
+    /// written, stores that upload in parts instead invoke it as each part is uploaded
+    ///
+    /// Once [cancelled](PutOptions::cancel) all further writes fail, and the writer
+    /// should be dropped to abort any in-flight requests
+    async fn put_multipart_opts(
+        &self,
+        location: &Path,
+        options: PutOptions,
+    ) -> Result<(MultipartId, Box<dyn AsyncWrite + Unpin + Send>)> {
+        let (id, writer) = self.put_multipart(location).await?;
+        let writer: Box<dyn AsyncWrite + Unpin + Send> = match options.progress {
+            Some(progress) => Box::new(ProgressWriter::new(writer, progress)),
+            None => writer,
+        };
+        match options.cancel {
+            Some(cancel) => {
+                let writer = CancellableWriter::new(writer, cancel, location);
+                Ok((id, Box::new(writer)))
+            }
+            None => Ok((id, writer)),
+        }
+    }
+
+    /// Upload `bytes` to `location` as a multipart upload
+    ///
+    /// Writing `bytes` to [`ObjectStore::put_multipart`] copies it into the buffer of
+    /// each part, whereas stores that support it upload each part as a slice of
+    /// `bytes`. Large objects already in memory are then uploaded without being copied.
+    ///
+    /// If the upload fails it is aborted with [`ObjectStore::abort_multipart`]
+    async fn put_multipart_bytes(&self, location: &Path, bytes: Bytes) -> Result<()> {
+        use tokio::io::AsyncWriteExt;
+
+        let (id, mut writer) = self.put_multipart(location).await?;
+        let upload = async {
+            writer.write_all(&bytes).await?;
+            writer.shutdown().await
+        };
+
+        if let Err(e) = upload.await {
+            if let Err(abort) = self.abort_multipart(location, &id).await {
+                warn!(
+                    "Failed to abort multipart upload to {}: {}",
+                    location, abort
+                );
+            }
+            return Err(multipart_error(e));
+        }
+        Ok(())
+    }
+
     /// Cleanup an aborted upload.
     ///
     /// See documentation for individual stores for exact behavior, as capabilities
@@ -309,12 +392,85 @@ pub trait ObjectStore: std::fmt::Display + Send + Sync + Debug + 'static {
     /// Return the bytes that are stored at the specified location.
     async fn get(&self, location: &Path) -> Result<GetResult>;
 
+    /// Return the bytes that are stored at the specified location with the given options
+    ///
+    /// Any [progress](GetOptions::progress) callback is invoked as each chunk of data
+    /// is received, and the request and any returned stream are aborted once
+    /// [cancelled](GetOptions::cancel)
+    ///
+    /// Returns [`Error::NotModified`] if [`GetOptions::if_none_match`] or
+    /// [`GetOptions::if_modified_since`] is not met, and [`Error::Precondition`] if
+    /// [`GetOptions::if_match`] or [`GetOptions::if_unmodified_since`] is not met.
+    ///
+    /// By default conditions are not supported, returning [`Error::NotImplemented`]
+    /// if any are set
+    async fn get_opts(&self, location: &Path, options: GetOptions) -> Result<GetResult> {
+        if options.has_conditions() {
+            return Err(Error::NotImplemented);
+        }
+        let request = async {
+            match options.range.clone() {
+                Some(range) => {
+                    let bytes = self.get_range(location, range).await?;
+                    let stream = futures::stream::once(async { Ok(bytes) }).boxed();
+                    Ok(GetResult::Stream(stream, None))
+                }
+                None => self.get(location).await,
+            }
+        };
+        let mut result = cancellable(options.cancel.clone(), location, request).await?;
+        if let Some(progress) = options.progress {
+            result = result.with_progress(progress, None);
+        }
+        if let Some(size) = options.read_buffer_size {
+            result = result.with_read_buffer_size(size);
+        }
+        if let Some(cancel) = options.cancel {
+            result = result.with_cancel(cancel, location);
+        }
+        Ok(result)
+    }
+
     /// Return the bytes that are stored at the specified location
     /// in the given byte range
     async fn get_range(&self, location: &Path, range: Range<usize>) -> Result<Bytes>;
 
+    /// Return the bytes that are stored at the specified location in the given byte
+    /// range with the given options
+    ///
+    /// If [`GetOptions::max_range_chunk`] is set, a larger `range` is fetched with
+    /// concurrent requests for consecutive sub-ranges of at most that size, which are
+    /// concatenated in order. Any [progress](GetOptions::progress) callback is invoked
+    /// once all the data has been received, and the requests are aborted once
+    /// [cancelled](GetOptions::cancel)
+    async fn get_range_opts(
+        &self,
+        location: &Path,
+        range: Range<usize>,
+        options: GetOptions,
+    ) -> Result<Bytes> {
+        let fetch = async {
+            match options.max_range_chunk {
+                Some(max) if range.end.saturating_sub(range.start) > max => {
+                    split_range(range, |r| self.get_range(location, r), max).await
+                }
+                _ => self.get_range(location, range).await,
+            }
+        };
+        let bytes = cancellable(options.cancel, location, fetch).await?;
+        if let Some(progress) = options.progress {
+            let len = bytes.len() as u64;
+            progress(len, Some(len));
+        }
+        Ok(bytes)
+    }
+
     /// Return the bytes that are stored at the specified location
     /// in the given byte ranges
+    ///
+    /// The returned [`Bytes`] are in the same order as `ranges`. By default, ranges
+    /// less than 1 MiB apart are coalesced, and up to 10 of the resulting ranges are
+    /// fetched concurrently with [`ObjectStore::get_range`]
     async fn get_ranges(
         &self,
         location: &Path,
@@ -331,26 +487,282 @@ pub trait ObjectStore: std::fmt::Display + Send + Sync + Debug + 'static {
     /// Return the metadata for the specified location
     async fn head(&self, location: &Path) -> Result<ObjectMeta>;
 
+    /// Returns whether an object exists at the specified location
+    ///
+    /// By default this calls [`ObjectStore::head`], returning `false` if it fails
+    /// with [`Error::NotFound`]
+    async fn exists(&self, location: &Path) -> Result<bool> {
+        match self.head(location).await {
+            Ok(_) => Ok(true),
+            Err(Error::NotFound { .. }) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Download the object at `location` to the local file `dest`, returning its metadata
+    ///
+    /// Data is written to `dest` as it is received, rather than buffered in memory, and
+    /// so this can be used for objects larger than the available memory. Any existing
+    /// file at `dest` is truncated, and `dest` is removed if the download fails once
+    /// it has been created.
+    ///
+    /// The metadata is that returned with the data, see [`GetResult::meta`], or else
+    /// fetched with [`ObjectStore::head`]
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn get_to_file(
+        &self,
+        location: &Path,
+        dest: &std::path::Path,
+    ) -> Result<ObjectMeta> {
+        let result = self.get(location).await?;
+        let meta = match result.meta() {
+            Some(meta) => meta.object.clone(),
+            None => self.head(location).await?,
+        };
+        let mut stream = result.into_stream();
+
+        let path = dest.to_path_buf();
+        let mut file = maybe_spawn_blocking(move || {
+            std::fs::File::create(&path)
+                .map_err(|err| local::Error::UnableToCreateFile { path, err }.into())
+        })
+        .await?;
+
+        let written = async move {
+            while let Some(bytes) = stream.try_next().await? {
+                file = maybe_spawn_blocking(move || {
+                    std::io::Write::write_all(&mut file, &bytes).map_err(|source| {
+                        local::Error::UnableToCopyDataToFile { source }
+                    })?;
+                    Ok(file)
+                })
+                .await?;
+            }
+            Ok(())
+        };
+        if let Err(e) = written.await {
+            let path = dest.to_path_buf();
+            let removed = maybe_spawn_blocking(move || {
+                std::fs::remove_file(&path).map_err(|source| {
+                    local::Error::UnableToDeleteFile { source, path }.into()
+                })
+            });
+            if let Err(removal) = removed.await {
+                warn!("Failed to remove partially downloaded file: {}", removal);
+            }
+            return Err(e);
+        }
+        Ok(meta)
+    }
+
     /// Delete the object at the specified location.
     async fn delete(&self, location: &Path) -> Result<()>;
 
+    /// Delete the object at the specified location with the given options
+    ///
+    /// By default the options are ignored
+    async fn delete_opts(&self, location: &Path, options: DeleteOptions) -> Result<()> {
+        let _ = options;
+        self.delete(location).await
+    }
+
+    /// Delete all the objects at the locations in the provided stream, returning a
+    /// stream of the result of each delete, yielding the location once deleted
+    ///
+    /// By default each object is deleted in turn with [`ObjectStore::delete`], stores
+    /// that support it instead delete many objects with each request. Results may be
+    /// returned in a different order than `locations`, and an error deleting one
+    /// object does not stop the others from being deleted.
+    ///
+    /// ```
+    /// # use object_store::{memory::InMemory, ObjectStore};
+    /// # use futures::{StreamExt, TryStreamExt};
+    /// # async fn example() -> object_store::Result<()> {
+    /// let store = InMemory::new();
+    /// let locations = store.list(None).await?.map_ok(|meta| meta.location).boxed();
+    /// let deleted: Vec<_> = store.delete_stream(locations).try_collect().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn delete_stream<'a>(
+        &'a self,
+        locations: BoxStream<'a, Result<Path>>,
+    ) -> BoxStream<'a, Result<Path>> {
+        locations
+            .then(move |location| async move {
+                let location = location?;
+                self.delete(&location).await?;
+                Ok(location)
+            })
+            .boxed()
+    }
+
     /// List all the objects with the given prefix.
     ///
     /// Prefixes are evaluated on a path segment basis, i.e. `foo/bar/` is a prefix of `foo/bar/x` but not of
-    /// `foo/bar_baz/x`.
+    /// `foo/bar_baz/x`. An empty prefix, e.g. `Path::from("/")`, is the same as `None`.
     async fn list(
         &self,
         prefix: Option<&Path>,
     ) -> Result<BoxStream<'_, Result<ObjectMeta>>>;
 
+    /// List all the objects under any of the given prefixes
+    ///
+    /// Up to 10 listings are performed concurrently. If `sorted` is false, objects are
+    /// returned as they are listed, in no particular order, and an object is returned
+    /// once for each of the prefixes it is under. If `sorted` is true, all listings are
+    /// collected before returning, and objects are returned once in order of location
+    async fn list_prefixes(
+        &self,
+        prefixes: Vec<Path>,
+        sorted: bool,
+    ) -> Result<BoxStream<'_, Result<ObjectMeta>>> {
+        let stream = futures::stream::iter(prefixes)
+            .map(move |prefix| {
+                futures::stream::once(async move { self.list(Some(&prefix)).await })
+                    .try_flatten()
+                    .boxed()
+            })
+            .flatten_unordered(OBJECT_STORE_LIST_PREFIXES_PARALLEL);
+
+        if !sorted {
+            return Ok(stream.boxed());
+        }
+
+        let mut objects: Vec<_> = stream.try_collect().await?;
+        objects.sort_unstable_by(|a, b| a.location.cmp(&b.location));
+        objects.dedup_by(|a, b| a.location == b.location);
+        Ok(futures::stream::iter(objects.into_iter().map(Ok)).boxed())
+    }
+
+    /// List all the objects with the given prefix and a location greater than
+    /// `offset`, such as to resume an earlier listing after the last location it
+    /// returned
+    ///
+    /// Prefixes are evaluated as for [`ObjectStore::list`]. The object at `offset`
+    /// itself is not returned. The default implementation filters the output of
+    /// [`ObjectStore::list`], and so returns objects in the same order
+    async fn list_with_offset(
+        &self,
+        prefix: Option<&Path>,
+        offset: &Path,
+    ) -> Result<BoxStream<'_, Result<ObjectMeta>>> {
+        let offset = offset.clone();
+        Ok(self
+            .list(prefix)
+            .await?
+            .try_filter(move |meta| futures::future::ready(meta.location > offset))
+            .boxed())
+    }
+
+    /// List the paths of all the objects with the given prefix, without their metadata
+    ///
+    /// Prefixes are evaluated as for [`ObjectStore::list`]. The default implementation
+    /// maps the output of [`ObjectStore::list`], but implementations may override this
+    /// with a cheaper listing that does not return metadata
+    async fn list_keys(
+        &self,
+        prefix: Option<&Path>,
+    ) -> Result<BoxStream<'_, Result<Path>>> {
+        Ok(self
+            .list(prefix)
+            .await?
+            .map_ok(|meta| meta.location)
+            .boxed())
+    }
+
+    /// Performs [`ObjectStore::list`], returning [`Error::NotFound`] if there are no
+    /// objects with the given prefix
+    ///
+    /// The first object is listed before returning, and so an error listing it is
+    /// also returned rather than streamed
+    async fn list_require_nonempty(
+        &self,
+        prefix: Option<&Path>,
+    ) -> Result<BoxStream<'_, Result<ObjectMeta>>> {
+        let mut stream = self.list(prefix).await?.peekable();
+        match std::pin::Pin::new(&mut stream).peek().await {
+            Some(Ok(_)) => Ok(stream.boxed()),
+            Some(Err(_)) => Err(stream.next().await.unwrap().unwrap_err()),
+            None => Err(Error::NotFound {
+                path: prefix.map(|p| p.to_string()).unwrap_or_default(),
+                source: "no objects found with prefix".into(),
+            }),
+        }
+    }
+
+    /// List the objects below `prefix` whose path relative to `prefix` matches the
+    /// glob `pattern`
+    ///
+    /// Within a path segment `*` matches any sequence of characters and `?` matches
+    /// a single character, whilst a `**` segment matches zero or more segments, e.g.
+    /// `year=*/**/*.parquet`.
+    ///
+    /// Only the leading segments of `pattern` without wildcards are passed to
+    /// [`ObjectStore::list`] as a prefix, the remainder of the pattern is applied
+    /// client-side as a filter. This therefore still enumerates every object under
+    /// that literal prefix, and a pattern starting with a wildcard lists everything
+    /// under `prefix`
+    async fn list_matching(
+        &self,
+        prefix: Option<&Path>,
+        pattern: &str,
+    ) -> Result<BoxStream<'_, Result<ObjectMeta>>> {
+        let glob = Glob::new(prefix, pattern);
+        let list_prefix = glob.list_prefix();
+        Ok(self
+            .list(list_prefix.as_ref())
+            .await?
+            .try_filter(move |meta| futures::future::ready(glob.matches(&meta.location)))
+            .boxed())
+    }
+
     /// List objects with the given prefix and an implementation specific
     /// delimiter. Returns common prefixes (directories) in addition to object
     /// metadata.
     ///
     /// Prefixes are evaluated on a path segment basis, i.e. `foo/bar/` is a prefix of `foo/bar/x` but not of
-    /// `foo/bar_baz/x`.
+    /// `foo/bar_baz/x`. An empty prefix, e.g. `Path::from("/")`, is the same as `None`.
     async fn list_with_delimiter(&self, prefix: Option<&Path>) -> Result<ListResult>;
 
+    /// Performs [`ObjectStore::list_with_delimiter`] treating `prefix` as a literal
+    /// string prefix rather than as a directory
+    ///
+    /// Whereas `foo` and `foo/` are the same prefix for [`ObjectStore::list_with_delimiter`],
+    /// and only match objects and common prefixes under `foo/`, here `foo` also matches
+    /// siblings such as the object `foobar` and common prefix `foobar/`, alongside `foo/`
+    /// itself as a common prefix. The default implementation lists the parent of `prefix`
+    /// and filters the result
+    async fn list_with_delimiter_literal(&self, prefix: &Path) -> Result<ListResult> {
+        let mut parts: Vec<_> = prefix.parts().collect();
+        parts.pop();
+        let parent = Path::from_iter(parts);
+
+        let literal = prefix.as_ref();
+        let result = self.list_with_delimiter(Some(&parent)).await?;
+        Ok(ListResult {
+            common_prefixes: result
+                .common_prefixes
+                .into_iter()
+                .filter(|p| p.as_ref().starts_with(literal))
+                .collect(),
+            objects: result
+                .objects
+                .into_iter()
+                .filter(|o| o.location.as_ref().starts_with(literal))
+                .collect(),
+        })
+    }
+
+    /// Performs [`ObjectStore::list_with_delimiter`], additionally returning the
+    /// number and total size of the objects directly under `prefix`
+    ///
+    /// The totals are aggregated from the listing, and so do not include objects
+    /// below the returned common prefixes
+    async fn list_with_summary(&self, prefix: Option<&Path>) -> Result<ListSummary> {
+        Ok(self.list_with_delimiter(prefix).await?.into())
+    }
+
     /// Copy an object from one path to another in the same object store.
     ///
     /// If there exists an object at the destination, it will be overwritten.
@@ -376,6 +788,33 @@ pub trait ObjectStore: std::fmt::Display + Send + Sync + Debug + 'static {
     /// it will return an error.
     async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> Result<()>;
 
+    /// Copy an object from one path to another, subject to the conditions in `options`
+    ///
+    /// Returns [`Error::Precondition`] if a condition on the source is not met,
+    /// [`Error::CopySkipped`] if [`CopyOptions::only_if_source_newer`] is set and the
+    /// destination is not older than the source, and [`Error::AlreadyExists`] if
+    /// [`CopyOptions::if_not_exists`] is set and the destination already has an object.
+    ///
+    /// By default conditions on the source are not supported, returning
+    /// [`Error::NotImplemented`] if any are set
+    async fn copy_opts(
+        &self,
+        from: &Path,
+        to: &Path,
+        options: CopyOptions,
+    ) -> Result<()> {
+        if options.has_source_conditions() {
+            return Err(Error::NotImplemented);
+        }
+        if options.only_if_source_newer {
+            ensure_source_newer(self, from, to).await?;
+        }
+        match options.if_not_exists {
+            true => self.copy_if_not_exists(from, to).await,
+            false => self.copy(from, to).await,
+        }
+    }
+
     /// Move an object from one path to another in the same object store.
     ///
     /// Will return an error if the destination already has an object.
@@ -383,6 +822,120 @@ pub trait ObjectStore: std::fmt::Display + Send + Sync + Debug + 'static {
         self.copy_if_not_exists(from, to).await?;
         self.delete(from).await
     }
+
+    /// Atomically replace the object at `location` with `bytes`, only if its current
+    /// ETag matches `expected_e_tag`, returning the ETag of the new object
+    ///
+    /// If `expected_e_tag` is `None` the object is only created if it does not already
+    /// exist, returning [`Error::AlreadyExists`] otherwise. If the ETag of the existing
+    /// object does not match, [`Error::Precondition`] is returned.
+    ///
+    /// This can be used as a compare-and-swap primitive, e.g. for leader election
+    async fn update(
+        &self,
+        _location: &Path,
+        _expected_e_tag: Option<String>,
+        _bytes: Bytes,
+    ) -> Result<PutResult> {
+        Err(Error::NotImplemented)
+    }
+
+    /// Fetch the object at `location` unless its ETag matches `cached_e_tag`, the
+    /// ETag of a copy of the object cached by the caller
+    ///
+    /// Returns [`Revalidated::Cached`] if the cached copy is still current, and
+    /// otherwise the current contents of the object along with its metadata. If
+    /// `cached_e_tag` is `None` the object is always fetched.
+    ///
+    /// This is a single conditional `GET` with `If-None-Match`, and so avoids
+    /// transferring the object if it has not changed. By default this is
+    /// [`ObjectStore::get_opts`] with [`GetOptions::if_none_match`], and so returns
+    /// [`Error::NotImplemented`] unless the store reports
+    /// [`Capabilities::conditional_get`] and returns the ETag in [`GetResult::meta`]
+    async fn revalidate(
+        &self,
+        location: &Path,
+        cached_e_tag: Option<String>,
+    ) -> Result<Revalidated> {
+        if !self.capabilities().conditional_get {
+            return Err(Error::NotImplemented);
+        }
+
+        let options = GetOptions {
+            if_none_match: cached_e_tag,
+            ..Default::default()
+        };
+        let result = match self.get_opts(location, options).await {
+            Ok(result) => result,
+            Err(Error::NotModified { .. }) => return Ok(Revalidated::Cached),
+            Err(e) => return Err(e),
+        };
+
+        let meta = match result.meta() {
+            Some(meta) if meta.object.e_tag.is_some() => meta.object.clone(),
+            _ => return Err(Error::NotImplemented),
+        };
+        let bytes = result.bytes().await?;
+        Ok(Revalidated::Fresh(bytes, meta))
+    }
+
+    /// Returns the optional features supported by this store
+    ///
+    /// This allows generic code to choose how to perform an operation up front,
+    /// rather than attempting it and handling [`Error::NotImplemented`]. By default
+    /// no optional features are reported as supported
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+}
+
+/// Move the object at `from` in `from_store` to `to` in `to_store`, overwriting any
+/// existing object at the destination
+///
+/// The data is streamed to the destination with a multipart upload, so the object
+/// only becomes visible at `to` once fully written, and the source is only deleted
+/// once the upload has completed. If any step fails before then the multipart upload
+/// is aborted, leaving the source untouched
+///
+/// Unlike [`ObjectStore::rename`] this works across different stores, but always
+/// transfers the data, even if both stores are the same
+pub async fn transfer(
+    from_store: &dyn ObjectStore,
+    from: &Path,
+    to_store: &dyn ObjectStore,
+    to: &Path,
+) -> Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    let mut stream = from_store.get(from).await?.into_stream();
+    let (id, mut writer) = to_store.put_multipart(to).await?;
+
+    let upload = async {
+        while let Some(bytes) = stream.try_next().await? {
+            writer.write_all(&bytes).await.map_err(multipart_error)?;
+        }
+        writer.shutdown().await.map_err(multipart_error)
+    };
+
+    if let Err(e) = upload.await {
+        if let Err(abort) = to_store.abort_multipart(to, &id).await {
+            warn!("Failed to abort multipart upload to {}: {}", to, abort);
+        }
+        return Err(e);
+    }
+
+    from_store.delete(from).await
+}
+
+/// Converts an error writing a multipart upload into an [`Error`]
+fn multipart_error(e: std::io::Error) -> Error {
+    match e.get_ref().map(|inner| inner.is::<Error>()) {
+        Some(true) => *e.into_inner().unwrap().downcast::<Error>().unwrap(),
+        _ => Error::Generic {
+            store: "multipart",
+            source: Box::new(e),
+        },
+    }
 }
 
 /// Result of a list call that includes objects, prefixes (directories) and a
@@ -396,6 +949,28 @@ pub struct ListResult {
     pub objects: Vec<ObjectMeta>,
 }
 
+/// Result of [`ObjectStore::list_with_summary`], a [`ListResult`] along with
+/// aggregates of the objects it contains
+#[derive(Debug)]
+pub struct ListSummary {
+    /// The result of the listing
+    pub result: ListResult,
+    /// The number of objects in [`ListResult::objects`]
+    pub total_object_count: usize,
+    /// The sum of the sizes in bytes of the objects in [`ListResult::objects`]
+    pub total_object_bytes: u64,
+}
+
+impl From<ListResult> for ListSummary {
+    fn from(result: ListResult) -> Self {
+        Self {
+            total_object_count: result.objects.len(),
+            total_object_bytes: result.objects.iter().map(|o| o.size as u64).sum(),
+            result,
+        }
+    }
+}
+
 /// The metadata that describes an object.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ObjectMeta {
@@ -405,6 +980,260 @@ pub struct ObjectMeta {
     pub last_modified: DateTime<Utc>,
     /// The size in bytes of the object
     pub size: usize,
+    /// The `Content-Type` of the object, if returned by the store
+    pub content_type: Option<String>,
+    /// The unique identifier for the object, if returned by the store
+    ///
+    /// This is stored verbatim, including any quotes, so that it can be used in
+    /// conditional requests such as [`GetOptions::if_match`]
+    pub e_tag: Option<String>,
+}
+
+/// A callback invoked with the number of bytes transferred so far, and the total
+/// number of bytes to transfer if known
+pub type ProgressFn = Arc<dyn Fn(u64, Option<u64>) + Send + Sync>;
+
+/// Options for [`ObjectStore::get_opts`]
+#[derive(Clone, Default)]
+pub struct GetOptions {
+    /// Invoked as data is received
+    pub progress: Option<ProgressFn>,
+    /// Once cancelled, any in-flight request is dropped and [`Error::Cancelled`]
+    /// is returned, including by the stream of a [`GetResult::Stream`]
+    pub cancel: Option<CancellationToken>,
+    /// Coalesce the data received into chunks of at least this many bytes, except
+    /// for the final chunk, before yielding them from a [`GetResult::Stream`]
+    ///
+    /// Defaults to yielding data as it is received
+    pub read_buffer_size: Option<usize>,
+    /// Split ranges larger than this many bytes requested with
+    /// [`ObjectStore::get_range_opts`] into concurrent requests for sub-ranges
+    ///
+    /// Defaults to fetching each range with a single request
+    pub max_range_chunk: Option<usize>,
+    /// Only return the object if its ETag matches, otherwise returning
+    /// [`Error::Precondition`]
+    pub if_match: Option<String>,
+    /// Only return the object if its ETag does not match, otherwise returning
+    /// [`Error::NotModified`]
+    ///
+    /// This can be used to revalidate a cached copy of the object
+    pub if_none_match: Option<String>,
+    /// Only return the object if it has been modified since this time, otherwise
+    /// returning [`Error::NotModified`]
+    pub if_modified_since: Option<DateTime<Utc>>,
+    /// Only return the object if it has not been modified since this time, otherwise
+    /// returning [`Error::Precondition`]
+    pub if_unmodified_since: Option<DateTime<Utc>>,
+    /// Only return this byte range of the object, see [`ObjectStore::get_opts`]
+    pub range: Option<Range<usize>>,
+}
+
+impl GetOptions {
+    /// Returns true if any conditions on the object are set
+    pub fn has_conditions(&self) -> bool {
+        self.if_match.is_some()
+            || self.if_none_match.is_some()
+            || self.if_modified_since.is_some()
+            || self.if_unmodified_since.is_some()
+    }
+}
+
+impl Debug for GetOptions {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GetOptions")
+            .field("progress", &self.progress.is_some())
+            .field("cancel", &self.cancel)
+            .field("read_buffer_size", &self.read_buffer_size)
+            .field("max_range_chunk", &self.max_range_chunk)
+            .field("if_match", &self.if_match)
+            .field("if_none_match", &self.if_none_match)
+            .field("if_modified_since", &self.if_modified_since)
+            .field("if_unmodified_since", &self.if_unmodified_since)
+            .field("range", &self.range)
+            .finish()
+    }
+}
+
+/// Options for [`ObjectStore::put_opts`] and [`ObjectStore::put_multipart_opts`]
+///
+/// The attributes of the object, such as its `content_type` and `metadata`, are only
+/// stored by [`MicrosoftAzure`](crate::azure::MicrosoftAzure), and ignored by other stores
+#[derive(Clone, Default)]
+pub struct PutOptions {
+    /// Invoked as data is uploaded
+    pub progress: Option<ProgressFn>,
+    /// Once cancelled, any in-flight request is dropped and [`Error::Cancelled`]
+    /// is returned
+    pub cancel: Option<CancellationToken>,
+    /// A key identifying this operation, sent with every attempt of the request so
+    /// that a server or proxy can deduplicate retries of it
+    ///
+    /// This is only sent by [`ObjectStore::put_opts`], and only by stores configured
+    /// with the name of the header to send it as, otherwise it is ignored
+    pub idempotency_key: Option<String>,
+    /// Set if repeating this operation after it has been applied would have a
+    /// different effect, for example duplicating data
+    ///
+    /// Failed requests are then only retried if they were never sent, such as when
+    /// the connection is refused, and not after server errors or timeouts, which may
+    /// occur once the request has been applied. This is only supported by
+    /// [`ObjectStore::put_opts`] of stores that retry requests, otherwise it is
+    /// ignored
+    pub non_idempotent: bool,
+    /// The `Content-Type` of the object, instead of any determined by its extension
+    pub content_type: Option<String>,
+    /// The `Content-Encoding` of the object, such as `gzip`
+    pub content_encoding: Option<String>,
+    /// The `Cache-Control` of the object
+    pub cache_control: Option<String>,
+    /// User-defined metadata of the object
+    pub metadata: std::collections::HashMap<String, String>,
+}
+
+impl Debug for PutOptions {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PutOptions")
+            .field("progress", &self.progress.is_some())
+            .field("cancel", &self.cancel)
+            .field("idempotency_key", &self.idempotency_key)
+            .field("non_idempotent", &self.non_idempotent)
+            .field("content_type", &self.content_type)
+            .field("content_encoding", &self.content_encoding)
+            .field("cache_control", &self.cache_control)
+            .field("metadata", &self.metadata)
+            .finish()
+    }
+}
+
+/// Options for [`ObjectStore::copy_opts`]
+#[derive(Debug, Clone, Default)]
+pub struct CopyOptions {
+    /// Only copy if the ETag of the source matches
+    pub source_if_match: Option<String>,
+    /// Only copy if the ETag of the source does not match
+    pub source_if_none_match: Option<String>,
+    /// Only copy if the source has been modified since this time
+    pub source_if_modified_since: Option<DateTime<Utc>>,
+    /// Only copy if the destination does not already exist
+    pub if_not_exists: bool,
+    /// Only copy if the source was modified more recently than the destination, or
+    /// the destination does not exist
+    ///
+    /// The last modified times of both objects are first fetched with
+    /// [`ObjectStore::head`], and [`Error::CopySkipped`] returned without copying
+    /// if the destination is not older
+    pub only_if_source_newer: bool,
+    /// A key identifying this operation, see [`PutOptions::idempotency_key`]
+    pub idempotency_key: Option<String>,
+}
+
+impl CopyOptions {
+    /// Returns true if any conditions on the source of the copy are set
+    pub fn has_source_conditions(&self) -> bool {
+        self.source_if_match.is_some()
+            || self.source_if_none_match.is_some()
+            || self.source_if_modified_since.is_some()
+    }
+}
+
+/// Options for [`ObjectStore::delete_opts`]
+#[derive(Debug, Clone, Default)]
+pub struct DeleteOptions {
+    /// A key identifying this operation, see [`PutOptions::idempotency_key`]
+    pub idempotency_key: Option<String>,
+}
+
+/// Result for a put request
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PutResult {
+    /// The unique identifier for the newly created object, if returned by the store
+    pub e_tag: Option<String>,
+    /// The checksum of the data written, if one was requested
+    pub checksum: Option<Checksum>,
+}
+
+/// The result of [`ObjectStore::revalidate`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Revalidated {
+    /// The object has not been modified since it was cached
+    Cached,
+    /// The current contents of the object and its metadata, which includes its ETag
+    Fresh(Bytes, ObjectMeta),
+}
+
+/// The optional features supported by an [`ObjectStore`], as returned by
+/// [`ObjectStore::capabilities`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Capabilities {
+    /// Objects can be replaced conditional on their ETag with [`ObjectStore::update`]
+    pub conditional_put: bool,
+    /// [`GetOptions`] conditions and [`ObjectStore::revalidate`] are supported
+    pub conditional_get: bool,
+    /// [`ObjectStore::copy_if_not_exists`] is supported
+    pub copy_if_not_exists: bool,
+    /// [`ObjectStore::rename`] atomically moves the object, rather than copying it
+    /// and then deleting the source
+    pub atomic_rename: bool,
+    /// Objects can be tagged with key-value pairs
+    pub tags: bool,
+    /// Previous versions of an object are retained when it is overwritten or deleted
+    pub versioning: bool,
+    /// Objects can be leased, preventing modification by other clients
+    pub leases: bool,
+}
+
+/// An algorithm that can be used to compute a [`Checksum`] of uploaded data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    /// MD5, as used by the `Content-MD5` header
+    Md5,
+    /// SHA-256
+    Sha256,
+    /// SHA-512
+    Sha512,
+}
+
+/// A digest of uploaded data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checksum {
+    /// The algorithm used to compute the digest
+    pub algorithm: ChecksumAlgorithm,
+    /// The raw digest
+    pub digest: Vec<u8>,
+}
+
+impl Checksum {
+    /// Returns the digest as a lowercase hex string
+    pub fn to_hex(&self) -> String {
+        self.digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+/// The number of parts of a multipart upload that are uploaded concurrently
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UploadConcurrency {
+    /// Upload up to this many parts concurrently
+    Fixed(usize),
+    /// Adjust the number of parts uploaded concurrently towards that which maximizes
+    /// the measured throughput, starting from `min`
+    ///
+    /// Throughput is measured over each batch of completed parts, and the concurrency
+    /// is stepped in the same direction whilst throughput improves, reversed if it
+    /// falls, and held once it plateaus
+    Adaptive {
+        /// The minimum number of parts uploaded concurrently
+        min: usize,
+        /// The maximum number of parts uploaded concurrently
+        max: usize,
+    },
+}
+
+impl Default for UploadConcurrency {
+    fn default() -> Self {
+        Self::Fixed(8)
+    }
 }
 
 /// Result for a get request
@@ -414,20 +1243,42 @@ pub struct ObjectMeta {
 pub enum GetResult {
     /// A file and its path on the local filesystem
     File(std::fs::File, std::path::PathBuf),
-    /// An asynchronous stream
-    Stream(BoxStream<'static, Result<Bytes>>),
+    /// An asynchronous stream, and the metadata of the object if it was returned
+    /// along with its data
+    Stream(BoxStream<'static, Result<Bytes>>, Option<GetResultMeta>),
 }
 
 impl Debug for GetResult {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::File(_, _) => write!(f, "GetResult(File)"),
-            Self::Stream(_) => write!(f, "GetResult(Stream)"),
+            Self::Stream(_, meta) => write!(f, "GetResult(Stream, {:?})", meta),
         }
     }
 }
 
+/// The metadata of an object returned by a get request, see [`GetResult::meta`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GetResultMeta {
+    /// The metadata of the object, where the size is that of the whole object
+    pub object: ObjectMeta,
+    /// The byte range of the object that was returned, if not the whole object
+    pub range: Option<Range<usize>>,
+}
+
 impl GetResult {
+    /// Returns the metadata of the object, if it was returned along with its data
+    ///
+    /// Stores that receive the metadata in the response to a get request, such as
+    /// Azure, populate this, avoiding the need for a separate [`ObjectStore::head`]
+    /// request. It is `None` for [`Self::File`] and other stores
+    pub fn meta(&self) -> Option<&GetResultMeta> {
+        match self {
+            Self::Stream(_, meta) => meta.as_ref(),
+            Self::File(_, _) => None,
+        }
+    }
+
     /// Collects the data into a [`Bytes`]
     pub async fn bytes(self) -> Result<Bytes> {
         match self {
@@ -457,12 +1308,66 @@ impl GetResult {
                 })
                 .await
             }
-            Self::Stream(s) => collect_bytes(s, None).await,
+            Self::Stream(s, _) => collect_bytes(s, None).await,
             #[cfg(target_arch = "wasm32")]
             _ => unimplemented!("File IO not implemented on wasm32."),
         }
     }
 
+    /// Invokes `progress` as each chunk of [`Self::Stream`] is received
+    ///
+    /// As [`Self::File`] is already available locally, its size is reported immediately
+    pub(crate) fn with_progress(self, progress: ProgressFn, total: Option<u64>) -> Self {
+        match self {
+            Self::File(file, path) => {
+                if let Ok(metadata) = file.metadata() {
+                    progress(metadata.len(), Some(metadata.len()));
+                }
+                Self::File(file, path)
+            }
+            Self::Stream(s, meta) => {
+                let mut received = 0;
+                let s = s.map_ok(move |bytes| {
+                    received += bytes.len() as u64;
+                    progress(received, total);
+                    bytes
+                });
+                Self::Stream(s.boxed(), meta)
+            }
+        }
+    }
+
+    /// Coalesces the chunks of [`Self::Stream`] into chunks of at least `size` bytes
+    pub(crate) fn with_read_buffer_size(self, size: usize) -> Self {
+        match self {
+            Self::Stream(s, meta) => Self::Stream(coalesce_stream(s, size), meta),
+            file => file,
+        }
+    }
+
+    /// Returns [`Error::Cancelled`] from [`Self::Stream`] once `cancel` is cancelled,
+    /// dropping the underlying stream
+    pub(crate) fn with_cancel(self, cancel: CancellationToken, location: &Path) -> Self {
+        match self {
+            Self::Stream(s, meta) => {
+                let path = location.to_string();
+                let token = cancel.clone();
+                let cancelled = Box::pin(async move { token.cancelled().await });
+                let s = s.take_until(cancelled).chain(
+                    futures::stream::once(async move {
+                        match cancel.is_cancelled() {
+                            true => Some(Err(Error::Cancelled { path })),
+                            false => None,
+                        }
+                    })
+                    .filter_map(futures::future::ready),
+                );
+                Self::Stream(s.boxed(), meta)
+            }
+            file => file,
+        }
+    }
+
     /// Converts this into a byte stream
     ///
     /// If the result is [`Self::File`] will perform chunked reads of the file, otherwise
@@ -507,7 +1412,7 @@ impl GetResult {
                 )
                 .boxed()
             }
-            Self::Stream(s) => s,
+            Self::Stream(s, _) => s,
             #[cfg(target_arch = "wasm32")]
             _ => unimplemented!("File IO not implemented on wasm32."),
         }
@@ -553,6 +1458,27 @@ pub enum Error {
         source: Box<dyn std::error::Error + Send + Sync + 'static>,
     },
 
+    #[snafu(display("Request precondition failure for path {}: {}", path, source))]
+    Precondition {
+        path: String,
+        source: Box<dyn std::error::Error + Send + Sync + 'static>,
+    },
+
+    #[snafu(display("Object at location {} not modified: {}", path, source))]
+    NotModified {
+        path: String,
+        source: Box<dyn std::error::Error + Send + Sync + 'static>,
+    },
+
+    #[snafu(display("Copy to {} skipped: {}", path, source))]
+    CopySkipped {
+        path: String,
+        source: Box<dyn std::error::Error + Send + Sync + 'static>,
+    },
+
+    #[snafu(display("Operation on {} was cancelled", path))]
+    Cancelled { path: String },
+
     #[snafu(display("Operation not yet implemented."))]
     NotImplemented,
 
@@ -648,6 +1574,55 @@ mod tests {
         let content_list = flatten_list_stream(storage, Some(&prefix)).await.unwrap();
         assert_eq!(content_list, &[location.clone()]);
 
+        // List only the keys
+        let keys: Vec<_> = storage
+            .list_keys(Some(&prefix))
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(keys, vec![location.clone()]);
+
+        // List requiring the prefix to have objects
+        let listed: Vec<_> = storage
+            .list_require_nonempty(Some(&prefix))
+            .await
+            .unwrap()
+            .map_ok(|meta| meta.location)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(listed, vec![location.clone()]);
+
+        let empty = Path::from("test_dir/empty");
+        let err = storage
+            .list_require_nonempty(Some(&empty))
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::NotFound { .. }), "{}", err);
+
+        // List only the objects matching a glob
+        let matching: Vec<_> = storage
+            .list_matching(None, "test_dir/*.json")
+            .await
+            .unwrap()
+            .map_ok(|meta| meta.location)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(matching, vec![location.clone()]);
+
+        let matching: Vec<_> = storage
+            .list_matching(Some(&prefix), "*.parquet")
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert!(matching.is_empty());
+
         // List everything starting with a prefix that shouldn't return results
         let prefix = Path::from("something");
         let content_list = flatten_list_stream(storage, Some(&prefix)).await.unwrap();
@@ -676,6 +1651,7 @@ mod tests {
         }
 
         let head = storage.head(&location).await.unwrap();
+        assert!(storage.exists(&location).await.unwrap());
         assert_eq!(head.size, expected_data.len());
 
         storage.delete(&location).await.unwrap();
@@ -814,6 +1790,15 @@ mod tests {
         }
         storage.delete(&path).await.unwrap();
 
+        // Can write zero-byte objects
+        let path = Path::from("empty");
+        storage.put(&path, Bytes::new()).await.unwrap();
+        let meta = storage.head(&path).await.unwrap();
+        assert_eq!(meta.size, 0);
+        let data = storage.get(&path).await.unwrap().bytes().await.unwrap();
+        assert!(data.is_empty());
+        storage.delete(&path).await.unwrap();
+
         let files = flatten_list_stream(storage, None).await.unwrap();
         assert!(files.is_empty(), "{:?}", files);
     }
@@ -824,6 +1809,232 @@ mod tests {
             .collect()
     }
 
+    pub(crate) async fn progress(storage: &DynObjectStore) {
+        let location = Path::from("progress");
+        let events = Arc::new(parking_lot::Mutex::new(vec![]));
+        let captured = Arc::clone(&events);
+        let progress: ProgressFn = Arc::new(move |transferred, total| {
+            captured.lock().push((transferred, total))
+        });
+
+        let options = PutOptions {
+            progress: Some(Arc::clone(&progress)),
+            ..Default::default()
+        };
+        let data = Bytes::from("hello");
+        storage
+            .put_opts(&location, data, options.clone())
+            .await
+            .unwrap();
+        assert_eq!(std::mem::take(&mut *events.lock()), vec![(5, Some(5))]);
+
+        let (_, mut writer) = storage
+            .put_multipart_opts(&location, options)
+            .await
+            .unwrap();
+        writer.write_all(b"hello").await.unwrap();
+        writer.write_all(b" world").await.unwrap();
+        writer.shutdown().await.unwrap();
+        let reported = std::mem::take(&mut *events.lock());
+        assert_eq!(reported.last().unwrap().0, 11);
+
+        let options = GetOptions {
+            progress: Some(progress),
+            ..Default::default()
+        };
+        let result = storage.get_opts(&location, options).await.unwrap();
+        assert_eq!(result.bytes().await.unwrap(), "hello world");
+        let reported = std::mem::take(&mut *events.lock());
+        assert_eq!(reported.last().unwrap().0, 11);
+
+        let options = GetOptions {
+            read_buffer_size: Some(1024),
+            ..Default::default()
+        };
+        let result = storage.get_opts(&location, options).await.unwrap();
+        let chunks: Vec<_> = result.into_stream().try_collect().await.unwrap();
+        assert_eq!(chunks, vec![Bytes::from("hello world")]);
+
+        let options = GetOptions {
+            max_range_chunk: Some(3),
+            ..Default::default()
+        };
+        let bytes = storage
+            .get_range_opts(&location, 1..10, options)
+            .await
+            .unwrap();
+        assert_eq!(bytes, "ello worl");
+
+        let options = GetOptions {
+            range: Some(6..11),
+            ..Default::default()
+        };
+        let result = storage.get_opts(&location, options).await.unwrap();
+        assert_eq!(result.bytes().await.unwrap(), "world");
+
+        storage.delete(&location).await.unwrap();
+    }
+
+    pub(crate) async fn list_prefixes(storage: &DynObjectStore) {
+        delete_fixtures(storage).await;
+
+        let paths = ["a/1", "a/2", "b/1", "b/c/1", "c/1"].map(Path::from);
+        for path in &paths {
+            storage.put(path, Bytes::from("data")).await.unwrap();
+        }
+
+        let prefixes = vec![Path::from("b"), Path::from("a"), Path::from("b/c")];
+        let mut listed: Vec<_> = storage
+            .list_prefixes(prefixes.clone(), false)
+            .await
+            .unwrap()
+            .map_ok(|meta| meta.location)
+            .try_collect()
+            .await
+            .unwrap();
+        listed.sort_unstable();
+        let expected = ["a/1", "a/2", "b/1", "b/c/1", "b/c/1"].map(Path::from);
+        assert_eq!(listed, expected);
+
+        let listed: Vec<_> = storage
+            .list_prefixes(prefixes, true)
+            .await
+            .unwrap()
+            .map_ok(|meta| meta.location)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(listed, paths[..4]);
+
+        for path in &paths {
+            storage.delete(path).await.unwrap();
+        }
+    }
+
+    pub(crate) async fn list_with_offset(storage: &DynObjectStore) {
+        delete_fixtures(storage).await;
+
+        let paths = ["a/1", "a/2", "b/1", "b/1.txt", "b/c/1", "c/1"].map(Path::from);
+        for path in &paths {
+            storage.put(path, Bytes::from("data")).await.unwrap();
+        }
+
+        let list = |prefix: Option<&'static str>, offset: &'static str| async move {
+            let prefix = prefix.map(Path::from);
+            let mut listed: Vec<_> = storage
+                .list_with_offset(prefix.as_ref(), &Path::from(offset))
+                .await
+                .unwrap()
+                .map_ok(|meta| meta.location)
+                .try_collect()
+                .await
+                .unwrap();
+            listed.sort_unstable();
+            listed
+        };
+
+        assert_eq!(list(None, "b/1").await, paths[3..]);
+        assert_eq!(list(None, "b").await, paths[2..]);
+        assert_eq!(list(None, "a/20").await, paths[2..]);
+        assert_eq!(list(Some("b"), "b/1.txt").await, paths[4..5]);
+        assert!(list(None, "c/1").await.is_empty());
+
+        for path in &paths {
+            storage.delete(path).await.unwrap();
+        }
+    }
+
+    pub(crate) async fn cancellation(storage: &DynObjectStore) {
+        let location = Path::from("cancelled");
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+
+        let options = PutOptions {
+            cancel: Some(cancel.clone()),
+            ..Default::default()
+        };
+        let data = Bytes::from("hello");
+        let err = storage
+            .put_opts(&location, data, options.clone())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Cancelled { .. }), "{}", err);
+
+        let (_, mut writer) = storage
+            .put_multipart_opts(&location, options)
+            .await
+            .unwrap();
+        let err = writer.write_all(b"hello").await.unwrap_err();
+        assert!(err.to_string().contains("cancelled"), "{}", err);
+        drop(writer);
+
+        let err = storage.head(&location).await.unwrap_err();
+        assert!(matches!(err, Error::NotFound { .. }), "{}", err);
+
+        storage.put(&location, Bytes::from("hello")).await.unwrap();
+        let options = GetOptions {
+            cancel: Some(cancel),
+            ..Default::default()
+        };
+        let err = storage.get_opts(&location, options).await.unwrap_err();
+        assert!(matches!(err, Error::Cancelled { .. }), "{}", err);
+
+        storage.delete(&location).await.unwrap();
+    }
+
+    pub(crate) async fn get_to_file(storage: &DynObjectStore) {
+        let location = Path::from("test_dir/get_to_file.txt");
+        let data = get_vec_of_bytes(5_000, 10);
+        let expected: Vec<u8> = data.iter().flatten().copied().collect();
+        storage
+            .put(&location, Bytes::from(expected.clone()))
+            .await
+            .unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("downloaded");
+        std::fs::write(&dest, b"existing content that is longer").unwrap();
+
+        let meta = storage.get_to_file(&location, &dest).await.unwrap();
+        assert_eq!(meta.location, location);
+        assert_eq!(meta.size, expected.len());
+        assert_eq!(std::fs::read(&dest).unwrap(), expected);
+
+        let missing = Path::from("test_dir/missing.txt");
+        let err = storage.get_to_file(&missing, &dest).await.unwrap_err();
+        assert!(matches!(err, Error::NotFound { .. }), "{}", err);
+
+        storage.delete(&location).await.unwrap();
+    }
+
+    pub(crate) async fn delete_stream(storage: &DynObjectStore) {
+        let locations: Vec<_> = (0..5)
+            .map(|i| Path::from(format!("test_dir/delete_stream/{}", i)))
+            .collect();
+        for location in &locations {
+            storage.put(location, Bytes::from("data")).await.unwrap();
+        }
+
+        let input = locations
+            .iter()
+            .cloned()
+            .map(Ok)
+            .chain(std::iter::once(Err(Error::NotImplemented)));
+        let results: Vec<_> = storage
+            .delete_stream(futures::stream::iter(input).boxed())
+            .collect()
+            .await;
+        assert_eq!(results.len(), 6);
+
+        let mut deleted: Vec<_> = results.into_iter().filter_map(|r| r.ok()).collect();
+        deleted.sort();
+        assert_eq!(deleted, locations);
+
+        let prefix = Path::from("test_dir/delete_stream");
+        let remaining = flatten_list_stream(storage, Some(&prefix)).await.unwrap();
+        assert!(remaining.is_empty(), "{:?}", remaining);
+    }
+
     pub(crate) async fn stream_get(storage: &DynObjectStore) {
         let location = Path::from("test_dir/test_upload_file.txt");
 
@@ -859,6 +2070,15 @@ mod tests {
         let bytes_written = storage.get(&location).await.unwrap().bytes().await.unwrap();
         assert_eq!(bytes_expected, bytes_written);
 
+        // Can upload bytes spanning multiple parts
+        let bytes_expected = Bytes::from(get_vec_of_bytes(242_880, 23).concat());
+        storage
+            .put_multipart_bytes(&location, bytes_expected.clone())
+            .await
+            .unwrap();
+        let bytes_written = storage.get(&location).await.unwrap().bytes().await.unwrap();
+        assert_eq!(bytes_expected, bytes_written);
+
         // We can abort an empty write
         let location = Path::from("test_dir/test_abort_upload.txt");
         let (upload_id, writer) = storage.put_multipart(&location).await.unwrap();
@@ -918,6 +2138,27 @@ mod tests {
         let prefix = Path::from("foo/x");
         let content_list = flatten_list_stream(storage, Some(&prefix)).await.unwrap();
         assert_eq!(content_list, &[]);
+
+        // An empty prefix lists everything, including top-level objects
+        let location3 = Path::from("z.json");
+        storage.put(&location3, Bytes::from("data")).await.unwrap();
+
+        for prefix in [None, Some(Path::default()), Some(Path::from("/"))] {
+            let mut content_list =
+                flatten_list_stream(storage, prefix.as_ref()).await.unwrap();
+            content_list.sort();
+            assert_eq!(
+                content_list,
+                &[location2.clone(), location1.clone(), location3.clone()]
+            );
+
+            // Some stores, e.g. LocalFileSystem, retain empty directories
+            let result = storage.list_with_delimiter(prefix.as_ref()).await.unwrap();
+            assert!(result.common_prefixes.contains(&Path::from("foo")));
+            assert!(result.common_prefixes.contains(&Path::from("foo.bar")));
+            assert_eq!(result.objects.len(), 1);
+            assert_eq!(result.objects[0].location, location3);
+        }
     }
 
     pub(crate) async fn list_with_delimiter(storage: &DynObjectStore) {
@@ -966,6 +2207,61 @@ mod tests {
         assert_eq!(object.location, expected_location);
         assert_eq!(object.size, data.len());
 
+        let summary = storage
+            .list_with_summary(Some(&Path::from("mydb/wb/000/000")))
+            .await
+            .unwrap();
+        assert!(summary.result.common_prefixes.is_empty());
+        assert_eq!(summary.total_object_count, 3);
+        assert_eq!(summary.total_object_bytes, 3 * data.len() as u64);
+
+        // ==================== check: prefix-list `mydb/wb/` (same as `mydb/wb`) ====================
+        let result = storage
+            .list_with_delimiter(Some(&Path::from("mydb/wb/")))
+            .await
+            .unwrap();
+        assert_eq!(
+            result.common_prefixes,
+            vec![Path::from("mydb/wb/000"), Path::from("mydb/wb/001")]
+        );
+        assert_eq!(result.objects.len(), 1);
+
+        // ==================== check: literal prefix-list `mydb/wb` (matches sibling `mydb/wbwbwb/`) ====================
+        let result = storage
+            .list_with_delimiter_literal(&Path::from("mydb/wb"))
+            .await
+            .unwrap();
+        assert_eq!(
+            result.common_prefixes,
+            vec![Path::from("mydb/wb"), Path::from("mydb/wbwbwb")]
+        );
+        assert!(result.objects.is_empty());
+
+        let result = storage
+            .list_with_delimiter_literal(&Path::from("mydb/wb/0"))
+            .await
+            .unwrap();
+        assert_eq!(
+            result.common_prefixes,
+            vec![Path::from("mydb/wb/000"), Path::from("mydb/wb/001")]
+        );
+        assert!(result.objects.is_empty());
+
+        let result = storage
+            .list_with_delimiter_literal(&Path::from("mydb/wb/f"))
+            .await
+            .unwrap();
+        assert!(result.common_prefixes.is_empty());
+        assert_eq!(result.objects.len(), 1);
+        assert_eq!(result.objects[0].location, expected_location);
+
+        let result = storage
+            .list_with_delimiter_literal(&Path::from("mydb/d"))
+            .await
+            .unwrap();
+        assert_eq!(result.common_prefixes, vec![Path::from("mydb/data")]);
+        assert!(result.objects.is_empty());
+
         // ==================== check: prefix-list `mydb/wb/000/000/001` (partial filename doesn't match) ====================
         let prefix = Path::from("mydb/wb/000/000/001");
 
@@ -999,6 +2295,7 @@ mod tests {
 
         let err = storage.head(&location).await.unwrap_err();
         assert!(matches!(err, crate::Error::NotFound { .. }));
+        assert!(!storage.exists(&location).await.unwrap());
 
         storage.get(&location).await?.bytes().await
     }
@@ -1062,6 +2359,44 @@ mod tests {
         storage.delete(&path2).await.unwrap();
     }
 
+    pub(crate) async fn copy_only_if_source_newer(storage: &DynObjectStore) {
+        let source = Path::from("test_source");
+        let dest = Path::from("test_dest");
+        let options = CopyOptions {
+            only_if_source_newer: true,
+            ..Default::default()
+        };
+
+        // Copies if the destination does not exist
+        storage.put(&source, Bytes::from("cats")).await.unwrap();
+        storage
+            .copy_opts(&source, &dest, options.clone())
+            .await
+            .unwrap();
+
+        // Skips the copy if the destination is newer
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        storage.put(&dest, Bytes::from("dogs")).await.unwrap();
+        let err = storage
+            .copy_opts(&source, &dest, options.clone())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, crate::Error::CopySkipped { .. }), "{}", err);
+        let contents = storage.get(&dest).await.unwrap().bytes().await.unwrap();
+        assert_eq!(contents, Bytes::from("dogs"));
+
+        // Copies if the source is newer
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        storage.put(&source, Bytes::from("birds")).await.unwrap();
+        storage.copy_opts(&source, &dest, options).await.unwrap();
+        let contents = storage.get(&dest).await.unwrap().bytes().await.unwrap();
+        assert_eq!(contents, Bytes::from("birds"));
+
+        // Clean up
+        storage.delete(&source).await.unwrap();
+        storage.delete(&dest).await.unwrap();
+    }
+
     async fn delete_fixtures(storage: &DynObjectStore) {
         let paths = flatten_list_stream(storage, None).await.unwrap();
 
@@ -1086,6 +2421,29 @@ mod tests {
         assert!(stream.next().await.is_none());
     }
 
+    #[tokio::test]
+    async fn test_transfer() {
+        let root = tempfile::TempDir::new().unwrap();
+        let local = local::LocalFileSystem::new_with_prefix(root.path()).unwrap();
+        let memory = memory::InMemory::new();
+
+        let from = Path::from("staging/file");
+        let to = Path::from("dest/file");
+        let data = Bytes::from(vec![42; 10_000]);
+        local.put(&from, data.clone()).await.unwrap();
+        memory.put(&to, Bytes::from("old")).await.unwrap();
+
+        transfer(&local, &from, &memory, &to).await.unwrap();
+        assert_eq!(memory.get(&to).await.unwrap().bytes().await.unwrap(), data);
+        let err = local.head(&from).await.unwrap_err();
+        assert!(matches!(err, Error::NotFound { .. }), "{}", err);
+
+        // A missing source leaves the destination untouched
+        let err = transfer(&local, &from, &memory, &to).await.unwrap_err();
+        assert!(matches!(err, Error::NotFound { .. }), "{}", err);
+        assert_eq!(memory.get(&to).await.unwrap().bytes().await.unwrap(), data);
+    }
+
     // Tests TODO:
     // GET nonexisting location (in_memory/file)
     // DELETE nonexisting location
@@ -18,8 +18,9 @@
 //! An object store that limits the maximum concurrency of the wrapped implementation
 
 use crate::{
-    BoxStream, GetResult, ListResult, MultipartId, ObjectMeta, ObjectStore, Path, Result,
-    StreamExt,
+    BoxStream, Capabilities, CopyOptions, DeleteOptions, GetOptions, GetResult,
+    ListResult, MultipartId, ObjectMeta, ObjectStore, Path, PutOptions, PutResult,
+    Result, Revalidated, StreamExt,
 };
 use async_trait::async_trait;
 use bytes::Bytes;
@@ -44,11 +45,29 @@ use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 /// let store = LimitStore::new(InMemory::new(), 20);
 /// ```
 ///
+/// Operations waiting for the limit are started in the order they were made, and so
+/// a large number of operations can delay those made after them. To prevent a batch
+/// workload from starving latency sensitive operations made through the same store,
+/// the batch workload can use a [`low_priority`](Self::low_priority) handle, which
+/// shares the limit but cannot use the requests reserved with
+/// [`with_reserved_requests`](Self::with_reserved_requests)
+///
+/// ```
+/// # use object_store::memory::InMemory;
+/// # use object_store::limit::LimitStore;
+///
+/// // Reserve 5 of 20 concurrent requests for operations made through `store`
+/// let store = LimitStore::new(InMemory::new(), 20).with_reserved_requests(5);
+/// let batch = store.low_priority();
+/// ```
 #[derive(Debug)]
 pub struct LimitStore<T: ObjectStore> {
-    inner: T,
+    inner: Arc<T>,
     max_requests: usize,
     semaphore: Arc<Semaphore>,
+    /// Limits the requests of low priority operations to those not reserved
+    low_priority: Arc<Semaphore>,
+    is_low_priority: bool,
 }
 
 impl<T: ObjectStore> LimitStore<T> {
@@ -57,9 +76,53 @@ impl<T: ObjectStore> LimitStore<T> {
     /// `max_requests`
     pub fn new(inner: T, max_requests: usize) -> Self {
         Self {
-            inner,
+            inner: Arc::new(inner),
             max_requests,
             semaphore: Arc::new(Semaphore::new(max_requests)),
+            low_priority: Arc::new(Semaphore::new(max_requests)),
+            is_low_priority: false,
+        }
+    }
+
+    /// Reserve `reserved` of the concurrent requests for operations that are not made
+    /// through a [`low_priority`](Self::low_priority) handle
+    ///
+    /// At least one request is always available to low priority operations
+    pub fn with_reserved_requests(mut self, reserved: usize) -> Self {
+        let available = self.max_requests.saturating_sub(reserved).max(1);
+        self.low_priority = Arc::new(Semaphore::new(available));
+        self
+    }
+
+    /// Returns a handle to this store that shares its limit, but whose operations are
+    /// low priority, and so cannot use the requests reserved with
+    /// [`with_reserved_requests`](Self::with_reserved_requests)
+    pub fn low_priority(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+            max_requests: self.max_requests,
+            semaphore: Arc::clone(&self.semaphore),
+            low_priority: Arc::clone(&self.low_priority),
+            is_low_priority: true,
+        }
+    }
+
+    /// Wait for a request to become available to an operation
+    async fn acquire(&self) -> Permit {
+        // The semaphores are never closed
+        let low_priority = match self.is_low_priority {
+            true => Some(
+                Arc::clone(&self.low_priority)
+                    .acquire_owned()
+                    .await
+                    .unwrap(),
+            ),
+            false => None,
+        };
+        let permit = Arc::clone(&self.semaphore).acquire_owned().await.unwrap();
+        Permit {
+            _permit: permit,
+            _low_priority: low_priority,
         }
     }
 }
@@ -73,40 +136,100 @@ impl<T: ObjectStore> std::fmt::Display for LimitStore<T> {
 #[async_trait]
 impl<T: ObjectStore> ObjectStore for LimitStore<T> {
     async fn put(&self, location: &Path, bytes: Bytes) -> Result<()> {
-        let _permit = self.semaphore.acquire().await.unwrap();
+        let _permit = self.acquire().await;
         self.inner.put(location, bytes).await
     }
 
+    async fn put_opts(
+        &self,
+        location: &Path,
+        bytes: Bytes,
+        options: PutOptions,
+    ) -> Result<()> {
+        let _permit = self.acquire().await;
+        self.inner.put_opts(location, bytes, options).await
+    }
+
+    async fn update(
+        &self,
+        location: &Path,
+        expected_e_tag: Option<String>,
+        bytes: Bytes,
+    ) -> Result<PutResult> {
+        let _permit = self.acquire().await;
+        self.inner.update(location, expected_e_tag, bytes).await
+    }
+
+    async fn revalidate(
+        &self,
+        location: &Path,
+        cached_e_tag: Option<String>,
+    ) -> Result<Revalidated> {
+        let _permit = self.acquire().await;
+        self.inner.revalidate(location, cached_e_tag).await
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+
     async fn put_multipart(
         &self,
         location: &Path,
     ) -> Result<(MultipartId, Box<dyn AsyncWrite + Unpin + Send>)> {
-        let permit = Arc::clone(&self.semaphore).acquire_owned().await.unwrap();
+        let permit = self.acquire().await;
         let (id, write) = self.inner.put_multipart(location).await?;
         Ok((id, Box::new(PermitWrapper::new(write, permit))))
     }
 
+    async fn put_multipart_bytes(&self, location: &Path, bytes: Bytes) -> Result<()> {
+        let _permit = self.acquire().await;
+        self.inner.put_multipart_bytes(location, bytes).await
+    }
+
     async fn abort_multipart(
         &self,
         location: &Path,
         multipart_id: &MultipartId,
     ) -> Result<()> {
-        let _permit = self.semaphore.acquire().await.unwrap();
+        let _permit = self.acquire().await;
         self.inner.abort_multipart(location, multipart_id).await
     }
 
+    async fn put_multipart_opts(
+        &self,
+        location: &Path,
+        options: PutOptions,
+    ) -> Result<(MultipartId, Box<dyn AsyncWrite + Unpin + Send>)> {
+        let permit = self.acquire().await;
+        let (id, write) = self.inner.put_multipart_opts(location, options).await?;
+        Ok((id, Box::new(PermitWrapper::new(write, permit))))
+    }
+
+    async fn get_opts(&self, location: &Path, options: GetOptions) -> Result<GetResult> {
+        let permit = self.acquire().await;
+        match self.inner.get_opts(location, options).await? {
+            r @ GetResult::File(_, _) => Ok(r),
+            GetResult::Stream(s, meta) => Ok(GetResult::Stream(
+                PermitWrapper::new(s, permit).boxed(),
+                meta,
+            )),
+        }
+    }
+
     async fn get(&self, location: &Path) -> Result<GetResult> {
-        let permit = Arc::clone(&self.semaphore).acquire_owned().await.unwrap();
+        let permit = self.acquire().await;
         match self.inner.get(location).await? {
             r @ GetResult::File(_, _) => Ok(r),
-            GetResult::Stream(s) => {
-                Ok(GetResult::Stream(PermitWrapper::new(s, permit).boxed()))
-            }
+            GetResult::Stream(s, meta) => Ok(GetResult::Stream(
+                PermitWrapper::new(s, permit).boxed(),
+                meta,
+            )),
         }
     }
 
     async fn get_range(&self, location: &Path, range: Range<usize>) -> Result<Bytes> {
-        let _permit = self.semaphore.acquire().await.unwrap();
+        let _permit = self.acquire().await;
         self.inner.get_range(location, range).await
     }
 
@@ -115,64 +238,95 @@ impl<T: ObjectStore> ObjectStore for LimitStore<T> {
         location: &Path,
         ranges: &[Range<usize>],
     ) -> Result<Vec<Bytes>> {
-        let _permit = self.semaphore.acquire().await.unwrap();
+        let _permit = self.acquire().await;
         self.inner.get_ranges(location, ranges).await
     }
 
     async fn head(&self, location: &Path) -> Result<ObjectMeta> {
-        let _permit = self.semaphore.acquire().await.unwrap();
+        let _permit = self.acquire().await;
         self.inner.head(location).await
     }
 
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn get_to_file(
+        &self,
+        location: &Path,
+        dest: &std::path::Path,
+    ) -> Result<ObjectMeta> {
+        let _permit = self.acquire().await;
+        self.inner.get_to_file(location, dest).await
+    }
+
     async fn delete(&self, location: &Path) -> Result<()> {
-        let _permit = self.semaphore.acquire().await.unwrap();
+        let _permit = self.acquire().await;
         self.inner.delete(location).await
     }
 
+    async fn delete_opts(&self, location: &Path, options: DeleteOptions) -> Result<()> {
+        let _permit = self.acquire().await;
+        self.inner.delete_opts(location, options).await
+    }
+
     async fn list(
         &self,
         prefix: Option<&Path>,
     ) -> Result<BoxStream<'_, Result<ObjectMeta>>> {
-        let permit = Arc::clone(&self.semaphore).acquire_owned().await.unwrap();
+        let permit = self.acquire().await;
         let s = self.inner.list(prefix).await?;
         Ok(PermitWrapper::new(s, permit).boxed())
     }
 
     async fn list_with_delimiter(&self, prefix: Option<&Path>) -> Result<ListResult> {
-        let _permit = self.semaphore.acquire().await.unwrap();
+        let _permit = self.acquire().await;
         self.inner.list_with_delimiter(prefix).await
     }
 
     async fn copy(&self, from: &Path, to: &Path) -> Result<()> {
-        let _permit = self.semaphore.acquire().await.unwrap();
+        let _permit = self.acquire().await;
         self.inner.copy(from, to).await
     }
 
     async fn rename(&self, from: &Path, to: &Path) -> Result<()> {
-        let _permit = self.semaphore.acquire().await.unwrap();
+        let _permit = self.acquire().await;
         self.inner.rename(from, to).await
     }
 
     async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
-        let _permit = self.semaphore.acquire().await.unwrap();
+        let _permit = self.acquire().await;
         self.inner.copy_if_not_exists(from, to).await
     }
 
+    async fn copy_opts(
+        &self,
+        from: &Path,
+        to: &Path,
+        options: CopyOptions,
+    ) -> Result<()> {
+        let _permit = self.acquire().await;
+        self.inner.copy_opts(from, to, options).await
+    }
+
     async fn rename_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
-        let _permit = self.semaphore.acquire().await.unwrap();
+        let _permit = self.acquire().await;
         self.inner.rename_if_not_exists(from, to).await
     }
 }
 
-/// Combines an [`OwnedSemaphorePermit`] with some other type
+/// The [`OwnedSemaphorePermit`]s held by an operation of a [`LimitStore`]
+struct Permit {
+    _permit: OwnedSemaphorePermit,
+    _low_priority: Option<OwnedSemaphorePermit>,
+}
+
+/// Combines a [`Permit`] with some other type
 struct PermitWrapper<T> {
     inner: T,
     #[allow(dead_code)]
-    permit: OwnedSemaphorePermit,
+    permit: Permit,
 }
 
 impl<T> PermitWrapper<T> {
-    fn new(inner: T, permit: OwnedSemaphorePermit) -> Self {
+    fn new(inner: T, permit: Permit) -> Self {
         Self { inner, permit }
     }
 }
@@ -269,4 +423,29 @@ mod tests {
         // Can now make another request
         integration.list(None).await.unwrap();
     }
+
+    #[tokio::test]
+    async fn limit_priority_test() {
+        let integration = LimitStore::new(InMemory::new(), 3).with_reserved_requests(1);
+        let low_priority = integration.low_priority();
+        let t = Duration::from_millis(20);
+
+        let mut streams = Vec::new();
+        for _ in 0..2 {
+            streams.push(low_priority.list(None).await.unwrap());
+        }
+
+        // Low priority operations cannot use the reserved request
+        assert!(timeout(t, low_priority.list(None)).await.is_err());
+        let stream = integration.list(None).await.unwrap();
+
+        // The limit is shared
+        assert!(timeout(t, integration.list(None)).await.is_err());
+        drop(stream);
+        assert!(timeout(t, low_priority.list(None)).await.is_err());
+
+        streams.pop();
+        let _stream = low_priority.list(None).await.unwrap();
+        let _stream = integration.list(None).await.unwrap();
+    }
 }
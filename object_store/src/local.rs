@@ -19,7 +19,7 @@
 use crate::{
     maybe_spawn_blocking,
     path::{absolute_path_to_url, Path},
-    GetResult, ListResult, MultipartId, ObjectMeta, ObjectStore, Result,
+    Capabilities, GetResult, ListResult, MultipartId, ObjectMeta, ObjectStore, Result,
 };
 use async_trait::async_trait;
 use bytes::Bytes;
@@ -376,6 +376,32 @@ impl ObjectStore for LocalFileSystem {
         .await
     }
 
+    async fn get_to_file(
+        &self,
+        location: &Path,
+        dest: &std::path::Path,
+    ) -> Result<ObjectMeta> {
+        let path = self.config.path_to_filesystem(location)?;
+        let location = location.clone();
+        let dest = dest.to_path_buf();
+
+        maybe_spawn_blocking(move || {
+            let mut file = open_file(&path)?;
+            let metadata =
+                file.metadata().map_err(|e| Error::UnableToAccessMetadata {
+                    source: e.into(),
+                    path: location.to_string(),
+                })?;
+
+            let mut dest = File::create(&dest)
+                .map_err(|err| Error::UnableToCreateFile { path: dest, err })?;
+            std::io::copy(&mut file, &mut dest).context(UnableToCopyDataToFileSnafu)?;
+
+            convert_metadata(metadata, location)
+        })
+        .await
+    }
+
     async fn delete(&self, location: &Path) -> Result<()> {
         let path = self.config.path_to_filesystem(location)?;
         maybe_spawn_blocking(move || {
@@ -551,6 +577,14 @@ impl ObjectStore for LocalFileSystem {
         })
         .await
     }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            copy_if_not_exists: true,
+            atomic_rename: true,
+            ..Default::default()
+        }
+    }
 }
 
 fn get_upload_stage_path(dest: &std::path::Path, multipart_id: &MultipartId) -> PathBuf {
@@ -853,6 +887,8 @@ fn convert_metadata(metadata: std::fs::Metadata, location: Path) -> Result<Objec
         location,
         last_modified,
         size,
+        content_type: None,
+        e_tag: None,
     })
 }
 
@@ -909,8 +945,10 @@ mod tests {
     use crate::test_util::flatten_list_stream;
     use crate::{
         tests::{
-            copy_if_not_exists, get_nonexistent_object, list_uses_directories_correctly,
-            list_with_delimiter, put_get_delete_list, rename_and_copy, stream_get,
+            cancellation, copy_if_not_exists, copy_only_if_source_newer, delete_stream,
+            get_nonexistent_object, get_to_file, list_prefixes,
+            list_uses_directories_correctly, list_with_delimiter, list_with_offset,
+            progress, put_get_delete_list, rename_and_copy, stream_get,
         },
         Error as ObjectStoreError, ObjectStore,
     };
@@ -928,7 +966,14 @@ mod tests {
         list_with_delimiter(&integration).await;
         rename_and_copy(&integration).await;
         copy_if_not_exists(&integration).await;
+        copy_only_if_source_newer(&integration).await;
         stream_get(&integration).await;
+        progress(&integration).await;
+        get_to_file(&integration).await;
+        cancellation(&integration).await;
+        list_prefixes(&integration).await;
+        list_with_offset(&integration).await;
+        delete_stream(&integration).await;
     }
 
     #[test]
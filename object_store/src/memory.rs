@@ -17,7 +17,10 @@
 
 //! An in-memory object store implementation
 use crate::MultipartId;
-use crate::{path::Path, GetResult, ListResult, ObjectMeta, ObjectStore, Result};
+use crate::{
+    path::Path, Capabilities, GetResult, GetResultMeta, ListResult, ObjectMeta,
+    ObjectStore, Result,
+};
 use async_trait::async_trait;
 use bytes::Bytes;
 use chrono::Utc;
@@ -114,9 +117,20 @@ impl ObjectStore for InMemory {
 
     async fn get(&self, location: &Path) -> Result<GetResult> {
         let data = self.get_bytes(location).await?;
+        let meta = GetResultMeta {
+            object: ObjectMeta {
+                location: location.clone(),
+                last_modified: Utc::now(),
+                size: data.len(),
+                content_type: None,
+                e_tag: None,
+            },
+            range: None,
+        };
 
         Ok(GetResult::Stream(
             futures::stream::once(async move { Ok(data) }).boxed(),
+            Some(meta),
         ))
     }
 
@@ -151,6 +165,8 @@ impl ObjectStore for InMemory {
             location: location.clone(),
             last_modified,
             size: bytes.len(),
+            content_type: None,
+            e_tag: None,
         })
     }
 
@@ -174,6 +190,8 @@ impl ObjectStore for InMemory {
                     location: key.clone(),
                     last_modified,
                     size: value.len(),
+                    content_type: None,
+                    e_tag: None,
                 })
             })
             .collect();
@@ -213,6 +231,8 @@ impl ObjectStore for InMemory {
                     location: k.clone(),
                     last_modified,
                     size: v.len(),
+                    content_type: None,
+                    e_tag: None,
                 };
                 objects.push(object);
             }
@@ -242,6 +262,13 @@ impl ObjectStore for InMemory {
         storage.insert(to.clone(), data);
         Ok(())
     }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            copy_if_not_exists: true,
+            ..Default::default()
+        }
+    }
 }
 
 impl InMemory {
@@ -311,8 +338,10 @@ mod tests {
 
     use crate::{
         tests::{
-            copy_if_not_exists, get_nonexistent_object, list_uses_directories_correctly,
-            list_with_delimiter, put_get_delete_list, rename_and_copy, stream_get,
+            cancellation, copy_if_not_exists, delete_stream, get_nonexistent_object,
+            get_to_file, list_prefixes, list_uses_directories_correctly,
+            list_with_delimiter, list_with_offset, progress, put_get_delete_list,
+            rename_and_copy, stream_get,
         },
         Error as ObjectStoreError, ObjectStore,
     };
@@ -327,6 +356,12 @@ mod tests {
         rename_and_copy(&integration).await;
         copy_if_not_exists(&integration).await;
         stream_get(&integration).await;
+        progress(&integration).await;
+        get_to_file(&integration).await;
+        cancellation(&integration).await;
+        list_prefixes(&integration).await;
+        list_with_offset(&integration).await;
+        delete_stream(&integration).await;
     }
 
     #[tokio::test]
@@ -340,13 +375,12 @@ mod tests {
 
         integration.put(&location, data).await.unwrap();
 
-        let read_data = integration
-            .get(&location)
-            .await
-            .unwrap()
-            .bytes()
-            .await
-            .unwrap();
+        let result = integration.get(&location).await.unwrap();
+        let meta = result.meta().unwrap();
+        assert_eq!(meta.object.size, expected_data.len());
+        assert_eq!(meta.range, None);
+
+        let read_data = result.bytes().await.unwrap();
         assert_eq!(&*read_data, expected_data);
     }
 
@@ -16,14 +16,117 @@
 // under the License.
 
 use async_trait::async_trait;
-use futures::{stream::FuturesUnordered, Future, StreamExt};
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use futures::{ready, stream::FuturesUnordered, Future, FutureExt, StreamExt};
+use md5::{Digest, Md5};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
 use std::{io, pin::Pin, sync::Arc, task::Poll};
 use tokio::io::AsyncWrite;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 
-use crate::Result;
+use crate::{Checksum, ChecksumAlgorithm, ProgressFn, Result, UploadConcurrency};
 
 type BoxedTryFuture<T> = Pin<Box<dyn Future<Output = Result<T, io::Error>> + Send>>;
 
+/// The relative change in throughput between batches of parts below which
+/// [`UploadConcurrency::Adaptive`] considers throughput to have plateaued
+const THROUGHPUT_TOLERANCE: f64 = 0.1;
+
+/// Tracks the number of parts that may be uploaded concurrently, adjusting it
+/// according to the measured throughput for [`UploadConcurrency::Adaptive`]
+#[derive(Debug)]
+pub(crate) struct ConcurrencyController {
+    limit: usize,
+    /// The minimum and maximum limit, if adaptive
+    bounds: Option<(usize, usize)>,
+    /// The step applied to `limit` whilst throughput improves
+    step: isize,
+    /// The start of the current batch, and the bytes and parts completed within it
+    batch: Option<(Instant, u64, usize)>,
+    /// The throughput in bytes per second of the previous batch
+    previous: Option<f64>,
+}
+
+impl ConcurrencyController {
+    pub fn new(concurrency: UploadConcurrency) -> Self {
+        let (limit, bounds) = match concurrency {
+            UploadConcurrency::Fixed(limit) => (limit.max(1), None),
+            UploadConcurrency::Adaptive { min, max } => {
+                let min = min.max(1);
+                (min, Some((min, max.max(min))))
+            }
+        };
+        Self {
+            limit,
+            bounds,
+            step: 1,
+            batch: None,
+            previous: None,
+        }
+    }
+
+    /// The number of parts that may currently be uploaded concurrently
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Record that a part started uploading at `now`
+    pub fn on_submit(&mut self, now: Instant) {
+        if self.bounds.is_some() && self.batch.is_none() {
+            self.batch = Some((now, 0, 0));
+        }
+    }
+
+    /// Record that a part of `len` bytes finished uploading at `now`, adjusting the
+    /// limit once a batch of as many parts as the limit has completed
+    pub fn on_complete(&mut self, len: u64, now: Instant) {
+        let (min, max) = match self.bounds {
+            Some(bounds) => bounds,
+            None => return,
+        };
+        let (start, bytes, parts) = match &mut self.batch {
+            Some((start, bytes, parts)) => {
+                *bytes += len;
+                *parts += 1;
+                (*start, *bytes, *parts)
+            }
+            None => return,
+        };
+        if parts < self.limit {
+            return;
+        }
+
+        let elapsed = now.duration_since(start).as_secs_f64().max(f64::EPSILON);
+        let throughput = bytes as f64 / elapsed;
+        let step = match self.previous {
+            None => self.step,
+            Some(p) if throughput > p * (1. + THROUGHPUT_TOLERANCE) => self.step,
+            Some(p) if throughput < p * (1. - THROUGHPUT_TOLERANCE) => {
+                self.step = -self.step;
+                self.step
+            }
+            Some(_) => 0,
+        };
+        let limit = if step >= 0 {
+            self.limit.saturating_add(step.unsigned_abs())
+        } else {
+            self.limit.saturating_sub(step.unsigned_abs())
+        };
+        self.limit = limit.clamp(min, max);
+        self.previous = Some(throughput);
+        self.batch = None;
+    }
+}
+
+/// The default minimum size of a part in bytes
+///
+/// Minimum size of 5 MiB
+/// <https://docs.aws.amazon.com/AmazonS3/latest/userguide/qfacts.html>
+/// <https://cloud.google.com/storage/quotas#requests>
+pub(crate) const DEFAULT_MIN_PART_SIZE: usize = 5_242_880;
+
 /// A trait that can be implemented by cloud-based object stores
 /// and used in combination with [`CloudMultiPartUpload`] to provide
 /// multipart upload support
@@ -32,7 +135,7 @@ pub(crate) trait CloudMultiPartUploadImpl: 'static {
     /// Upload a single part
     async fn put_multipart_part(
         &self,
-        buf: Vec<u8>,
+        buf: Bytes,
         part_idx: usize,
     ) -> Result<UploadPart, io::Error>;
 
@@ -42,6 +145,49 @@ pub(crate) trait CloudMultiPartUploadImpl: 'static {
     async fn complete(&self, completed_parts: Vec<UploadPart>) -> Result<(), io::Error>;
 }
 
+/// Upload `bytes` with `inner` as parts of `part_size` bytes, uploading parts
+/// concurrently according to `concurrency`, and complete the upload
+///
+/// Each part is a slice of `bytes`, and so is uploaded without being copied
+pub(crate) async fn put_parts<T: CloudMultiPartUploadImpl + Sync>(
+    inner: &T,
+    bytes: Bytes,
+    part_size: usize,
+    concurrency: UploadConcurrency,
+) -> Result<(), io::Error> {
+    let mut controller = ConcurrencyController::new(concurrency);
+    let mut remaining = (0..bytes.len()).step_by(part_size).enumerate();
+    let mut tasks = FuturesUnordered::new();
+    let mut parts = Vec::new();
+    loop {
+        while tasks.len() < controller.limit() {
+            let (part_idx, start) = match remaining.next() {
+                Some(next) => next,
+                None => break,
+            };
+            let buf = bytes.slice(start..bytes.len().min(start + part_size));
+            let len = buf.len() as u64;
+            controller.on_submit(Instant::now());
+            tasks.push(async move {
+                let part = inner.put_multipart_part(buf, part_idx).await?;
+                Ok::<_, io::Error>((part_idx, part, len))
+            });
+        }
+        match tasks.next().await {
+            Some(result) => {
+                let (part_idx, part, len) = result?;
+                controller.on_complete(len, Instant::now());
+                parts.push((part_idx, part));
+            }
+            None => break,
+        }
+    }
+    parts.sort_unstable_by_key(|(part_idx, _)| *part_idx);
+    inner
+        .complete(parts.into_iter().map(|(_, part)| part).collect())
+        .await
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct UploadPart {
     pub content_id: String,
@@ -54,18 +200,66 @@ where
     inner: Arc<T>,
     /// A list of completed parts, in sequential order.
     completed_parts: Vec<Option<UploadPart>>,
-    /// Part upload tasks currently running
-    tasks: FuturesUnordered<BoxedTryFuture<(usize, UploadPart)>>,
-    /// Maximum number of upload tasks to run concurrently
-    max_concurrency: usize,
+    /// Part upload tasks currently running, returning the part along with its index,
+    /// length and when it completed
+    tasks: FuturesUnordered<BoxedTryFuture<(usize, UploadPart, u64, Instant)>>,
+    /// Number of upload tasks to run concurrently
+    concurrency: ConcurrencyController,
     /// Buffer that will be sent in next upload.
     current_buffer: Vec<u8>,
     /// Minimum size of a part in bytes
     min_part_size: usize,
+    /// Maximum size of a part in bytes, if any
+    max_part_size: Option<usize>,
     /// Index of current part
     current_part_idx: usize,
     /// The completion task
     completion_task: Option<BoxedTryFuture<()>>,
+    /// Budget of bytes shared with other uploads that must be acquired before buffering
+    buffer_budget: Option<Arc<Semaphore>>,
+    /// Budget acquired for `current_buffer`
+    buffer_permit: Option<OwnedSemaphorePermit>,
+    /// In-progress acquisition of `buffer_permit`
+    pending_permit: Option<BoxFuture<'static, OwnedSemaphorePermit>>,
+    /// Digest of all bytes written so far
+    hasher: Option<(ChecksumAlgorithm, Hasher)>,
+    /// Callback and total bytes of completed parts
+    progress: Option<(ProgressFn, Arc<AtomicU64>)>,
+}
+
+/// The in-progress digest of a [`ChecksumAlgorithm`]
+#[derive(Clone)]
+enum Hasher {
+    Md5(Md5),
+    Ring(ring::digest::Context),
+}
+
+impl Hasher {
+    fn new(algorithm: ChecksumAlgorithm) -> Self {
+        match algorithm {
+            ChecksumAlgorithm::Md5 => Self::Md5(Md5::new()),
+            ChecksumAlgorithm::Sha256 => {
+                Self::Ring(ring::digest::Context::new(&ring::digest::SHA256))
+            }
+            ChecksumAlgorithm::Sha512 => {
+                Self::Ring(ring::digest::Context::new(&ring::digest::SHA512))
+            }
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Md5(hasher) => hasher.update(data),
+            Self::Ring(hasher) => hasher.update(data),
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        match self {
+            Self::Md5(hasher) => hasher.finalize().to_vec(),
+            Self::Ring(hasher) => hasher.finish().as_ref().to_vec(),
+        }
+    }
 }
 
 impl<T> CloudMultiPartUpload<T>
@@ -77,20 +271,114 @@ where
             inner: Arc::new(inner),
             completed_parts: Vec::new(),
             tasks: FuturesUnordered::new(),
-            max_concurrency,
+            concurrency: ConcurrencyController::new(UploadConcurrency::Fixed(
+                max_concurrency,
+            )),
             current_buffer: Vec::new(),
             // TODO: Should self vary by provider?
             // TODO: Should we automatically increase then when part index gets large?
-
-            // Minimum size of 5 MiB
-            // https://docs.aws.amazon.com/AmazonS3/latest/userguide/qfacts.html
-            // https://cloud.google.com/storage/quotas#requests
-            min_part_size: 5_242_880,
+            min_part_size: DEFAULT_MIN_PART_SIZE,
+            max_part_size: None,
             current_part_idx: 0,
             completion_task: None,
+            buffer_budget: None,
+            buffer_permit: None,
+            pending_permit: None,
+            hasher: None,
+            progress: None,
         }
     }
 
+    /// Compute a checksum of all bytes written using `algorithm`
+    pub fn with_checksum(mut self, algorithm: ChecksumAlgorithm) -> Self {
+        self.hasher = Some((algorithm, Hasher::new(algorithm)));
+        self
+    }
+
+    /// Returns the checksum of the bytes written so far, if configured
+    pub fn checksum(&self) -> Option<Checksum> {
+        self.hasher.as_ref().map(|(algorithm, hasher)| Checksum {
+            algorithm: *algorithm,
+            digest: hasher.clone().finish(),
+        })
+    }
+
+    /// Upload parts concurrently according to `concurrency`, instead of the
+    /// fixed concurrency passed to [`Self::new`]
+    pub fn with_concurrency(mut self, concurrency: UploadConcurrency) -> Self {
+        self.concurrency = ConcurrencyController::new(concurrency);
+        self
+    }
+
+    /// Buffer at least `min_part_size` bytes before uploading a part, instead of
+    /// the default of 5 MiB
+    pub fn with_min_part_size(mut self, min_part_size: usize) -> Self {
+        self.min_part_size = min_part_size;
+        self
+    }
+
+    /// Split writes so that no part is larger than `max_part_size` bytes, which
+    /// must be at least the minimum part size
+    ///
+    /// By default a single large write is uploaded as a single part
+    pub fn with_max_part_size(mut self, max_part_size: usize) -> Self {
+        self.max_part_size = Some(max_part_size);
+        self
+    }
+
+    /// Report the total bytes of completed parts to `progress` as each part is uploaded
+    pub fn with_progress(mut self, progress: ProgressFn) -> Self {
+        self.progress = Some((progress, Default::default()));
+        self
+    }
+
+    /// Append `buf` to the current buffer, updating the checksum if any
+    fn buffer(&mut self, buf: &[u8]) {
+        if let Some((_, hasher)) = &mut self.hasher {
+            hasher.update(buf);
+        }
+        self.current_buffer.extend_from_slice(buf);
+    }
+
+    /// Limit the bytes buffered by this upload using `budget`, which may be shared
+    /// with other uploads, where each permit corresponds to a single byte
+    ///
+    /// Budget for an entire part is reserved before any of it is buffered, and released
+    /// once that part has been uploaded. `budget` must therefore have at least as many
+    /// permits as the minimum part size, otherwise writes will never complete
+    pub fn with_buffer_budget(mut self, budget: Arc<Semaphore>) -> Self {
+        self.buffer_budget = Some(budget);
+        self
+    }
+
+    /// Reserves budget for `current_buffer` if a budget is configured and
+    /// it has not already been reserved
+    fn poll_buffer_budget(&mut self, cx: &mut std::task::Context<'_>) -> Poll<()> {
+        let budget = match &self.buffer_budget {
+            Some(budget) if self.buffer_permit.is_none() => budget,
+            _ => return Poll::Ready(()),
+        };
+
+        let permits = u32::try_from(self.min_part_size).unwrap_or(u32::MAX);
+        let pending = self.pending_permit.get_or_insert_with(|| {
+            let budget = Arc::clone(budget);
+            Box::pin(async move {
+                // The semaphore is never closed
+                budget.acquire_many_owned(permits).await.unwrap()
+            })
+        });
+
+        let permit = ready!(pending.poll_unpin(cx));
+        self.pending_permit = None;
+        self.buffer_permit = Some(permit);
+        Poll::Ready(())
+    }
+
+    /// Returns true if any parts have been submitted for upload
+    pub fn has_submitted_parts(&self) -> bool {
+        self.current_part_idx > 0
+    }
+
     pub fn poll_tasks(
         mut self: Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
@@ -99,7 +387,8 @@ where
             return Ok(());
         }
         while let Poll::Ready(Some(res)) = self.tasks.poll_next_unpin(cx) {
-            let (part_idx, part) = res?;
+            let (part_idx, part, len, completed) = res?;
+            self.concurrency.on_complete(len, completed);
             let total_parts = self.completed_parts.len();
             self.completed_parts
                 .resize(std::cmp::max(part_idx + 1, total_parts), None);
@@ -113,6 +402,29 @@ impl<T> CloudMultiPartUpload<T>
 where
     T: CloudMultiPartUploadImpl + Send + Sync,
 {
+    /// Start uploading `current_buffer` as the next part
+    fn submit_part(&mut self) {
+        let out_buffer = std::mem::take(&mut self.current_buffer);
+        let permit = self.buffer_permit.take();
+        let progress = self.progress.clone();
+        let inner = Arc::clone(&self.inner);
+        let part_idx = self.current_part_idx;
+        self.concurrency.on_submit(Instant::now());
+        self.tasks.push(Box::pin(async move {
+            let len = out_buffer.len() as u64;
+            let upload_part = inner
+                .put_multipart_part(out_buffer.into(), part_idx)
+                .await?;
+            drop(permit);
+            if let Some((progress, uploaded)) = progress {
+                let uploaded = uploaded.fetch_add(len, Ordering::Relaxed) + len;
+                progress(uploaded, None);
+            }
+            Ok((part_idx, upload_part, len, Instant::now()))
+        }));
+        self.current_part_idx += 1;
+    }
+
     // The `poll_flush` function will only flush the in-progress tasks.
     // The `final_flush` method called during `poll_shutdown` will flush
     // the `current_buffer` along with in-progress tasks.
@@ -125,14 +437,9 @@ where
         self.as_mut().poll_tasks(cx)?;
 
         // If current_buffer is not empty, see if it can be submitted
-        if !self.current_buffer.is_empty() && self.tasks.len() < self.max_concurrency {
-            let out_buffer: Vec<u8> = std::mem::take(&mut self.current_buffer);
-            let inner = Arc::clone(&self.inner);
-            let part_idx = self.current_part_idx;
-            self.tasks.push(Box::pin(async move {
-                let upload_part = inner.put_multipart_part(out_buffer, part_idx).await?;
-                Ok((part_idx, upload_part))
-            }));
+        if !self.current_buffer.is_empty() && self.tasks.len() < self.concurrency.limit()
+        {
+            self.submit_part();
         }
 
         self.as_mut().poll_tasks(cx)?;
@@ -158,29 +465,37 @@ where
         // Poll current tasks
         self.as_mut().poll_tasks(cx)?;
 
+        // Reserve budget for the buffer, and only accept as much of buf as fits in it
+        let buf = match self.buffer_budget.is_some() {
+            true => {
+                ready!(self.poll_buffer_budget(cx));
+                let remaining = self.min_part_size - self.current_buffer.len();
+                &buf[..buf.len().min(remaining)]
+            }
+            false => buf,
+        };
+
+        // Only accept as much of buf as fits in a part of the maximum size
+        let buf = match self.max_part_size {
+            Some(max) => &buf[..buf.len().min(max - self.current_buffer.len())],
+            None => buf,
+        };
+
         // If adding buf to pending buffer would trigger send, check
         // whether we have capacity for another task.
         let enough_to_send =
             (buf.len() + self.current_buffer.len()) >= self.min_part_size;
-        if enough_to_send && self.tasks.len() < self.max_concurrency {
+        if enough_to_send && self.tasks.len() < self.concurrency.limit() {
             // If we do, copy into the buffer and submit the task, and return ready.
-            self.current_buffer.extend_from_slice(buf);
-
-            let out_buffer = std::mem::take(&mut self.current_buffer);
-            let inner = Arc::clone(&self.inner);
-            let part_idx = self.current_part_idx;
-            self.tasks.push(Box::pin(async move {
-                let upload_part = inner.put_multipart_part(out_buffer, part_idx).await?;
-                Ok((part_idx, upload_part))
-            }));
-            self.current_part_idx += 1;
+            self.buffer(buf);
+            self.submit_part();
 
             // We need to poll immediately after adding to setup waker
             self.as_mut().poll_tasks(cx)?;
 
             Poll::Ready(Ok(buf.len()))
         } else if !enough_to_send {
-            self.current_buffer.extend_from_slice(buf);
+            self.buffer(buf);
             Poll::Ready(Ok(buf.len()))
         } else {
             // Waker registered by call to poll_tasks at beginning
@@ -239,3 +554,64 @@ where
         Pin::new(completion_task).poll(cx)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Runs batches of parts through `controller`, where the throughput of each batch
+    /// is `throughput(limit)`, returning the limit of each batch
+    fn simulate(
+        controller: &mut ConcurrencyController,
+        throughput: impl Fn(usize) -> u64,
+        batches: usize,
+    ) -> Vec<usize> {
+        let mut now = Instant::now();
+        let mut limits = Vec::with_capacity(batches);
+        for _ in 0..batches {
+            let limit = controller.limit();
+            limits.push(limit);
+            let elapsed =
+                Duration::from_secs_f64(limit as f64 / throughput(limit) as f64);
+            controller.on_submit(now);
+            now += elapsed;
+            for _ in 0..limit {
+                controller.on_complete(1, now);
+            }
+        }
+        limits
+    }
+
+    #[test]
+    fn test_fixed_concurrency() {
+        let mut controller = ConcurrencyController::new(UploadConcurrency::Fixed(3));
+        let limits = simulate(&mut controller, |limit| limit as u64, 10);
+        assert!(limits.iter().all(|l| *l == 3));
+    }
+
+    #[test]
+    fn test_adaptive_concurrency() {
+        // Throughput increases linearly until the knee at 6 concurrent parts
+        let knee = |limit: usize| limit.min(6) as u64;
+
+        let concurrency = UploadConcurrency::Adaptive { min: 2, max: 16 };
+        let mut controller = ConcurrencyController::new(concurrency);
+        let limits = simulate(&mut controller, knee, 20);
+        assert_eq!(limits[0], 2);
+        assert!(limits.iter().all(|l| (2..=7).contains(l)), "{:?}", limits);
+        assert!(limits[10..].iter().all(|l| *l == 7), "{:?}", limits);
+
+        // Bounded by the maximum
+        let concurrency = UploadConcurrency::Adaptive { min: 1, max: 4 };
+        let mut controller = ConcurrencyController::new(concurrency);
+        let limits = simulate(&mut controller, knee, 20);
+        assert_eq!(*limits.iter().max().unwrap(), 4);
+
+        // Backs off when throughput falls with concurrency
+        let concurrency = UploadConcurrency::Adaptive { min: 4, max: 16 };
+        let mut controller = ConcurrencyController::new(concurrency);
+        let limits = simulate(&mut controller, |limit| 100 / limit as u64, 20);
+        assert!(limits.iter().all(|l| (4..=5).contains(l)), "{:?}", limits);
+    }
+}
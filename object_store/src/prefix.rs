@@ -23,8 +23,9 @@ use tokio::io::AsyncWrite;
 
 use crate::path::Path;
 use crate::{
-    GetResult, ListResult, MultipartId, ObjectMeta, ObjectStore,
-    Result as ObjectStoreResult,
+    Capabilities, CopyOptions, DeleteOptions, GetOptions, GetResult, ListResult,
+    MultipartId, ObjectMeta, ObjectStore, PutOptions, PutResult,
+    Result as ObjectStoreResult, Revalidated,
 };
 
 /// Store wrapper that applies a constant prefix to all paths handled by the store.
@@ -68,6 +69,61 @@ impl<T: ObjectStore> ObjectStore for PrefixObjectStore<T> {
         self.inner.put(&full_path, bytes).await
     }
 
+    async fn put_opts(
+        &self,
+        location: &Path,
+        bytes: Bytes,
+        options: PutOptions,
+    ) -> ObjectStoreResult<()> {
+        let full_path = self.full_path(location);
+        self.inner.put_opts(&full_path, bytes, options).await
+    }
+
+    async fn update(
+        &self,
+        location: &Path,
+        expected_e_tag: Option<String>,
+        bytes: Bytes,
+    ) -> ObjectStoreResult<PutResult> {
+        let full_path = self.full_path(location);
+        self.inner.update(&full_path, expected_e_tag, bytes).await
+    }
+
+    async fn revalidate(
+        &self,
+        location: &Path,
+        cached_e_tag: Option<String>,
+    ) -> ObjectStoreResult<Revalidated> {
+        let full_path = self.full_path(location);
+        self.inner.revalidate(&full_path, cached_e_tag).await
+    }
+
+    /// Renames are not forwarded to the inner store, and so are never atomic
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            atomic_rename: false,
+            ..self.inner.capabilities()
+        }
+    }
+
+    async fn put_multipart_opts(
+        &self,
+        location: &Path,
+        options: PutOptions,
+    ) -> ObjectStoreResult<(MultipartId, Box<dyn AsyncWrite + Unpin + Send>)> {
+        let full_path = self.full_path(location);
+        self.inner.put_multipart_opts(&full_path, options).await
+    }
+
+    async fn get_opts(
+        &self,
+        location: &Path,
+        options: GetOptions,
+    ) -> ObjectStoreResult<GetResult> {
+        let full_path = self.full_path(location);
+        self.inner.get_opts(&full_path, options).await
+    }
+
     /// Return the bytes that are stored at the specified location.
     async fn get(&self, location: &Path) -> ObjectStoreResult<GetResult> {
         let full_path = self.full_path(location);
@@ -86,11 +142,32 @@ impl<T: ObjectStore> ObjectStore for PrefixObjectStore<T> {
     }
 
     /// Return the metadata for the specified location
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn get_to_file(
+        &self,
+        location: &Path,
+        dest: &std::path::Path,
+    ) -> ObjectStoreResult<ObjectMeta> {
+        let full_path = self.full_path(location);
+        self.inner
+            .get_to_file(&full_path, dest)
+            .await
+            .map(|meta| ObjectMeta {
+                last_modified: meta.last_modified,
+                size: meta.size,
+                content_type: meta.content_type,
+                e_tag: meta.e_tag,
+                location: self.strip_prefix(&meta.location).unwrap_or(meta.location),
+            })
+    }
+
     async fn head(&self, location: &Path) -> ObjectStoreResult<ObjectMeta> {
         let full_path = self.full_path(location);
         self.inner.head(&full_path).await.map(|meta| ObjectMeta {
             last_modified: meta.last_modified,
             size: meta.size,
+            content_type: meta.content_type,
+            e_tag: meta.e_tag,
             location: self.strip_prefix(&meta.location).unwrap_or(meta.location),
         })
     }
@@ -101,6 +178,28 @@ impl<T: ObjectStore> ObjectStore for PrefixObjectStore<T> {
         self.inner.delete(&full_path).await
     }
 
+    async fn delete_opts(
+        &self,
+        location: &Path,
+        options: DeleteOptions,
+    ) -> ObjectStoreResult<()> {
+        let full_path = self.full_path(location);
+        self.inner.delete_opts(&full_path, options).await
+    }
+
+    fn delete_stream<'a>(
+        &'a self,
+        locations: BoxStream<'a, ObjectStoreResult<Path>>,
+    ) -> BoxStream<'a, ObjectStoreResult<Path>> {
+        let locations = locations
+            .map_ok(|location| self.full_path(&location))
+            .boxed();
+        self.inner
+            .delete_stream(locations)
+            .map_ok(|location| self.strip_prefix(&location).unwrap_or(location))
+            .boxed()
+    }
+
     /// List all the objects with the given prefix.
     ///
     /// Prefixes are evaluated on a path segment basis, i.e. `foo/bar/` is a prefix of `foo/bar/x` but not of
@@ -116,6 +215,8 @@ impl<T: ObjectStore> ObjectStore for PrefixObjectStore<T> {
             .map_ok(|meta| ObjectMeta {
                 last_modified: meta.last_modified,
                 size: meta.size,
+                content_type: meta.content_type,
+                e_tag: meta.e_tag,
                 location: self.strip_prefix(&meta.location).unwrap_or(meta.location),
             })
             .boxed())
@@ -149,6 +250,8 @@ impl<T: ObjectStore> ObjectStore for PrefixObjectStore<T> {
                         Some(ObjectMeta {
                             last_modified: meta.last_modified,
                             size: meta.size,
+                            content_type: meta.content_type.clone(),
+                            e_tag: meta.e_tag.clone(),
                             location: self.strip_prefix(&meta.location)?,
                         })
                     })
@@ -174,6 +277,18 @@ impl<T: ObjectStore> ObjectStore for PrefixObjectStore<T> {
         self.inner.copy_if_not_exists(&full_from, &full_to).await
     }
 
+    /// Copy an object from one path to another, subject to the conditions in `options`.
+    async fn copy_opts(
+        &self,
+        from: &Path,
+        to: &Path,
+        options: CopyOptions,
+    ) -> ObjectStoreResult<()> {
+        let full_from = self.full_path(from);
+        let full_to = self.full_path(to);
+        self.inner.copy_opts(&full_from, &full_to, options).await
+    }
+
     /// Move an object from one path to another in the same object store.
     ///
     /// Will return an error if the destination already has an object.
@@ -195,6 +310,15 @@ impl<T: ObjectStore> ObjectStore for PrefixObjectStore<T> {
         self.inner.put_multipart(&full_path).await
     }
 
+    async fn put_multipart_bytes(
+        &self,
+        location: &Path,
+        bytes: Bytes,
+    ) -> ObjectStoreResult<()> {
+        let full_path = self.full_path(location);
+        self.inner.put_multipart_bytes(&full_path, bytes).await
+    }
+
     async fn abort_multipart(
         &self,
         location: &Path,
@@ -211,8 +335,8 @@ mod tests {
     use crate::local::LocalFileSystem;
     use crate::test_util::flatten_list_stream;
     use crate::tests::{
-        copy_if_not_exists, list_uses_directories_correctly, list_with_delimiter,
-        put_get_delete_list, rename_and_copy, stream_get,
+        copy_if_not_exists, delete_stream, list_uses_directories_correctly,
+        list_with_delimiter, put_get_delete_list, rename_and_copy, stream_get,
     };
 
     use tempfile::TempDir;
@@ -229,6 +353,11 @@ mod tests {
         rename_and_copy(&integration).await;
         copy_if_not_exists(&integration).await;
         stream_get(&integration).await;
+        delete_stream(&integration).await;
+        // Renames are atomic in the inner store, but not through the prefix
+        let capabilities = integration.capabilities();
+        assert!(capabilities.copy_if_not_exists);
+        assert!(!capabilities.atomic_rename);
     }
 
     #[tokio::test]
@@ -21,7 +21,10 @@ use std::ops::Range;
 use std::{convert::TryInto, sync::Arc};
 
 use crate::MultipartId;
-use crate::{path::Path, GetResult, ListResult, ObjectMeta, ObjectStore, Result};
+use crate::{
+    path::Path, Capabilities, GetResult, ListResult, ObjectMeta, ObjectStore, PutOptions,
+    PutResult, Result, Revalidated,
+};
 use async_trait::async_trait;
 use bytes::Bytes;
 use futures::{stream::BoxStream, StreamExt};
@@ -151,6 +154,51 @@ impl<T: ObjectStore> ObjectStore for ThrottledStore<T> {
         self.inner.put(location, bytes).await
     }
 
+    async fn put_opts(
+        &self,
+        location: &Path,
+        bytes: Bytes,
+        options: PutOptions,
+    ) -> Result<()> {
+        sleep(self.config().wait_put_per_call).await;
+
+        self.inner.put_opts(location, bytes, options).await
+    }
+
+    async fn update(
+        &self,
+        location: &Path,
+        expected_e_tag: Option<String>,
+        bytes: Bytes,
+    ) -> Result<PutResult> {
+        sleep(self.config().wait_put_per_call).await;
+
+        self.inner.update(location, expected_e_tag, bytes).await
+    }
+
+    async fn revalidate(
+        &self,
+        location: &Path,
+        cached_e_tag: Option<String>,
+    ) -> Result<Revalidated> {
+        sleep(self.config().wait_get_per_call).await;
+
+        let result = self.inner.revalidate(location, cached_e_tag).await?;
+        if let Revalidated::Fresh(bytes, _) = &result {
+            let bytes_len: u32 = usize_to_u32_saturate(bytes.len());
+            sleep(self.config().wait_get_per_byte * bytes_len).await;
+        }
+        Ok(result)
+    }
+
+    /// Conditional gets are not forwarded to the inner store
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            conditional_get: false,
+            ..self.inner.capabilities()
+        }
+    }
+
     async fn put_multipart(
         &self,
         _location: &Path,
@@ -158,6 +206,12 @@ impl<T: ObjectStore> ObjectStore for ThrottledStore<T> {
         Err(super::Error::NotImplemented)
     }
 
+    async fn put_multipart_bytes(&self, location: &Path, bytes: Bytes) -> Result<()> {
+        sleep(self.config().wait_put_per_call).await;
+
+        self.inner.put_multipart_bytes(location, bytes).await
+    }
+
     async fn abort_multipart(
         &self,
         _location: &Path,
@@ -173,8 +227,8 @@ impl<T: ObjectStore> ObjectStore for ThrottledStore<T> {
         let wait_get_per_byte = self.config().wait_get_per_byte;
 
         self.inner.get(location).await.map(|result| {
-            let s = match result {
-                GetResult::Stream(s) => s,
+            let (s, meta) = match result {
+                GetResult::Stream(s, meta) => (s, meta),
                 GetResult::File(_, _) => unimplemented!(),
             };
 
@@ -190,6 +244,7 @@ impl<T: ObjectStore> ObjectStore for ThrottledStore<T> {
                     }
                 })
                 .boxed(),
+                meta,
             )
         })
     }
@@ -530,7 +585,7 @@ mod tests {
         if n_bytes.is_some() {
             // need to consume bytes to provoke sleep times
             let s = match res.unwrap() {
-                GetResult::Stream(s) => s,
+                GetResult::Stream(s, _) => s,
                 GetResult::File(_, _) => unimplemented!(),
             };
 
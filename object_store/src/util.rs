@@ -16,9 +16,18 @@
 // under the License.
 
 //! Common logic for interacting with remote object stores
-use super::Result;
-use bytes::Bytes;
-use futures::{stream::StreamExt, Stream, TryStreamExt};
+use super::{Error, ObjectStore, ProgressFn, Result};
+use crate::path::Path;
+use bytes::{Bytes, BytesMut};
+use futures::future::BoxFuture;
+use futures::{
+    ready, stream::BoxStream, stream::StreamExt, Future, FutureExt, Stream, TryStreamExt,
+};
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::AsyncWrite;
+use tokio_util::sync::CancellationToken;
 
 #[cfg(any(feature = "azure", feature = "http"))]
 pub static RFC1123_FMT: &str = "%a, %d %h %Y %T GMT";
@@ -105,6 +114,10 @@ pub const OBJECT_STORE_COALESCE_DEFAULT: usize = 1024 * 1024;
 /// Up to this number of range requests will be performed in parallel by [`coalesce_ranges`]
 pub const OBJECT_STORE_COALESCE_PARALLEL: usize = 10;
 
+/// Up to this number of listings will be performed in parallel by
+/// [`ObjectStore::list_prefixes`](crate::ObjectStore::list_prefixes)
+pub const OBJECT_STORE_LIST_PREFIXES_PARALLEL: usize = 10;
+
 /// Takes a function `fetch` that can fetch a range of bytes and uses this to
 /// fetch the provided byte `ranges`
 ///
@@ -130,7 +143,17 @@ where
         .try_collect()
         .await?;
 
-    Ok(ranges
+    Ok(slice_ranges(ranges, &fetch_ranges, &fetched))
+}
+
+/// Returns each of `ranges` sliced from the `fetched` bytes of the range in
+/// `fetch_ranges`, as returned by [`merge_ranges`], that contains it
+pub(crate) fn slice_ranges(
+    ranges: &[std::ops::Range<usize>],
+    fetch_ranges: &[std::ops::Range<usize>],
+    fetched: &[Bytes],
+) -> Vec<Bytes> {
+    ranges
         .iter()
         .map(|range| {
             let idx = fetch_ranges.partition_point(|v| v.start <= range.start) - 1;
@@ -141,11 +164,46 @@ where
             let end = range.end - fetch_range.start;
             fetch_bytes.slice(start..end)
         })
-        .collect())
+        .collect()
+}
+
+/// Takes a function `fetch` that can fetch a range of bytes and uses this to fetch
+/// `range` as consecutive sub-ranges of at most `max_chunk` bytes, concatenating them
+///
+/// Up to [`OBJECT_STORE_COALESCE_PARALLEL`] sub-ranges are fetched in parallel
+pub(crate) async fn split_range<F, Fut>(
+    range: std::ops::Range<usize>,
+    fetch: F,
+    max_chunk: usize,
+) -> Result<Bytes>
+where
+    F: Send + FnMut(std::ops::Range<usize>) -> Fut,
+    Fut: std::future::Future<Output = Result<Bytes>> + Send,
+{
+    let max_chunk = max_chunk.max(1);
+    let chunks = (range.start..range.end)
+        .step_by(max_chunk)
+        .map(move |start| start..(start + max_chunk).min(range.end));
+
+    let mut fetched: Vec<Bytes> = futures::stream::iter(chunks)
+        .map(fetch)
+        .buffered(OBJECT_STORE_COALESCE_PARALLEL)
+        .try_collect()
+        .await?;
+
+    if fetched.len() == 1 {
+        return Ok(fetched.pop().unwrap());
+    }
+
+    let mut out = BytesMut::with_capacity(fetched.iter().map(|b| b.len()).sum());
+    for bytes in fetched {
+        out.extend_from_slice(&bytes);
+    }
+    Ok(out.freeze())
 }
 
 /// Returns a sorted list of ranges that cover `ranges`
-fn merge_ranges(
+pub(crate) fn merge_ranges(
     ranges: &[std::ops::Range<usize>],
     coalesce: usize,
 ) -> Vec<std::ops::Range<usize>> {
@@ -194,6 +252,267 @@ pub(crate) fn str_is_truthy(val: &str) -> bool {
         | val.eq_ignore_ascii_case("y")
 }
 
+/// An [`AsyncWrite`] that reports the number of bytes written to a [`ProgressFn`]
+pub(crate) struct ProgressWriter<W> {
+    inner: W,
+    progress: ProgressFn,
+    written: u64,
+}
+
+impl<W> ProgressWriter<W> {
+    pub(crate) fn new(inner: W, progress: ProgressFn) -> Self {
+        Self {
+            inner,
+            progress,
+            written: 0,
+        }
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for ProgressWriter<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let written = ready!(Pin::new(&mut self.inner).poll_write(cx, buf))?;
+        self.written += written as u64;
+        (self.progress)(self.written, None);
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Runs `fut` to completion, unless `cancel` is cancelled first, in which case `fut`
+/// is dropped and [`Error::Cancelled`] returned
+pub(crate) async fn cancellable<T, F>(
+    cancel: Option<CancellationToken>,
+    location: &Path,
+    fut: F,
+) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+{
+    let cancel = match cancel {
+        Some(cancel) => cancel,
+        None => return fut.await,
+    };
+
+    tokio::select! {
+        biased;
+        _ = cancel.cancelled() => Err(Error::Cancelled { path: location.to_string() }),
+        result = fut => result,
+    }
+}
+
+/// Returns [`Error::CopySkipped`] unless the object at `from` was modified more
+/// recently than the object at `to`, or there is no object at `to`
+///
+/// Both objects are fetched concurrently with [`ObjectStore::head`]
+pub(crate) async fn ensure_source_newer<T: ObjectStore + ?Sized>(
+    store: &T,
+    from: &Path,
+    to: &Path,
+) -> Result<()> {
+    let (source, dest) = futures::future::join(store.head(from), store.head(to)).await;
+    let source = source?;
+    match dest {
+        Ok(dest) if dest.last_modified >= source.last_modified => {
+            Err(Error::CopySkipped {
+                path: to.to_string(),
+                source: format!(
+                    "destination modified at {} is not older than source modified at {}",
+                    dest.last_modified, source.last_modified
+                )
+                .into(),
+            })
+        }
+        Ok(_) | Err(Error::NotFound { .. }) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// An [`AsyncWrite`] that fails with [`Error::Cancelled`] once a [`CancellationToken`]
+/// is cancelled, including any pending write, flush or shutdown
+pub(crate) struct CancellableWriter<W> {
+    inner: W,
+    cancelled: BoxFuture<'static, ()>,
+    path: String,
+}
+
+impl<W> CancellableWriter<W> {
+    pub(crate) fn new(inner: W, cancel: CancellationToken, location: &Path) -> Self {
+        Self {
+            inner,
+            cancelled: Box::pin(async move { cancel.cancelled().await }),
+            path: location.to_string(),
+        }
+    }
+
+    fn poll_cancelled(&mut self, cx: &mut Context<'_>) -> Result<(), io::Error> {
+        match self.cancelled.poll_unpin(cx) {
+            Poll::Ready(()) => Err(Error::Cancelled {
+                path: self.path.clone(),
+            }
+            .into()),
+            Poll::Pending => Ok(()),
+        }
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for CancellableWriter<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        self.poll_cancelled(cx)?;
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), io::Error>> {
+        self.poll_cancelled(cx)?;
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), io::Error>> {
+        self.poll_cancelled(cx)?;
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Coalesces the chunks of `stream` into chunks of at least `min_size` bytes,
+/// except for the final chunk
+///
+/// Chunks that are already large enough are yielded without copying. Any bytes
+/// buffered when the stream returns an error are discarded
+pub(crate) fn coalesce_stream(
+    stream: BoxStream<'static, Result<Bytes>>,
+    min_size: usize,
+) -> BoxStream<'static, Result<Bytes>> {
+    futures::stream::unfold(Some(stream), move |stream| async move {
+        let mut stream = stream?;
+        let mut buffer = BytesMut::new();
+        loop {
+            match stream.next().await {
+                Some(Ok(bytes)) if buffer.is_empty() && bytes.len() >= min_size => {
+                    return Some((Ok(bytes), Some(stream)))
+                }
+                Some(Ok(bytes)) => {
+                    buffer.extend_from_slice(&bytes);
+                    if buffer.len() >= min_size {
+                        return Some((Ok(buffer.freeze()), Some(stream)));
+                    }
+                }
+                Some(Err(e)) => return Some((Err(e), None)),
+                None if buffer.is_empty() => return None,
+                None => return Some((Ok(buffer.freeze()), None)),
+            }
+        }
+    })
+    .boxed()
+}
+
+/// A glob pattern matched against the path segments of objects below a prefix
+///
+/// `*` matches any run of characters within a segment, `?` matches a single
+/// character within a segment and a `**` segment matches zero or more segments
+#[derive(Debug, Clone)]
+pub(crate) struct Glob {
+    prefix: Option<Path>,
+    segments: Vec<String>,
+}
+
+impl Glob {
+    pub(crate) fn new(prefix: Option<&Path>, pattern: &str) -> Self {
+        Self {
+            prefix: prefix.cloned(),
+            segments: pattern
+                .split(crate::path::DELIMITER)
+                .filter(|s| !s.is_empty())
+                .map(ToString::to_string)
+                .collect(),
+        }
+    }
+
+    /// Returns the prefix to list, i.e. `prefix` followed by the leading segments
+    /// of the pattern that contain no wildcards, excluding the final segment
+    pub(crate) fn list_prefix(&self) -> Option<Path> {
+        let literal = self
+            .segments
+            .iter()
+            .take(self.segments.len().saturating_sub(1))
+            .take_while(|s| !s.contains(['*', '?']))
+            .map(|s| crate::path::PathPart::from(s.as_str()));
+
+        let parts = self.prefix.iter().flat_map(|p| p.parts()).chain(literal);
+        let path = Path::from_iter(parts);
+        (!path.as_ref().is_empty()).then_some(path)
+    }
+
+    /// Returns true if `location` lies below the prefix and its remaining
+    /// segments match the pattern
+    pub(crate) fn matches(&self, location: &Path) -> bool {
+        let parts: Vec<_> = match &self.prefix {
+            Some(prefix) => match location.prefix_match(prefix) {
+                Some(parts) => parts.collect(),
+                None => return false,
+            },
+            None => location.parts().collect(),
+        };
+        let parts: Vec<&str> = parts.iter().map(|p| p.as_ref()).collect();
+        match_segments(&self.segments, &parts)
+    }
+}
+
+fn match_segments(pattern: &[String], parts: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => parts.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=parts.len()).any(|skip| match_segments(rest, &parts[skip..]))
+        }
+        Some((first, rest)) => match parts.split_first() {
+            Some((part, remaining)) => {
+                match_segment(first.as_bytes(), part.as_bytes())
+                    && match_segments(rest, remaining)
+            }
+            None => false,
+        },
+    }
+}
+
+fn match_segment(pattern: &[u8], segment: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => segment.is_empty(),
+        Some((b'*', rest)) => {
+            (0..=segment.len()).any(|skip| match_segment(rest, &segment[skip..]))
+        }
+        Some((b'?', rest)) => !segment.is_empty() && match_segment(rest, &segment[1..]),
+        Some((c, rest)) => {
+            segment.first() == Some(c) && match_segment(rest, &segment[1..])
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -256,6 +575,33 @@ mod tests {
         assert_eq!(fetches, vec![0..1, 6..14]);
     }
 
+    #[tokio::test]
+    async fn test_coalesce_ranges_completion_order() {
+        let src: Bytes = (0..100).collect::<Vec<u8>>().into();
+        let ranges = vec![50..60, 0..10, 80..90, 20..30];
+
+        // Later ranges complete first
+        let fetched = coalesce_ranges(
+            &ranges,
+            |range| {
+                let src = src.clone();
+                async move {
+                    let delay = (100 - range.start) as u64;
+                    tokio::time::sleep(std::time::Duration::from_millis(delay)).await;
+                    Ok(src.slice(range))
+                }
+            },
+            0,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(fetched.len(), ranges.len());
+        for (range, bytes) in ranges.iter().zip(fetched) {
+            assert_eq!(bytes, src.slice(range.clone()));
+        }
+    }
+
     #[tokio::test]
     async fn test_coalesce_fuzz() {
         let mut rand = thread_rng();
@@ -294,4 +640,87 @@ mod tests {
             }
         }
     }
+
+    #[tokio::test]
+    async fn test_coalesce_stream() {
+        let chunks = |sizes: &[usize]| {
+            let chunks: Vec<_> = sizes
+                .iter()
+                .map(|size| Ok(Bytes::from(vec![0; *size])))
+                .collect();
+            futures::stream::iter(chunks).boxed()
+        };
+        let sizes = |stream: BoxStream<'static, Result<Bytes>>| async move {
+            stream
+                .map_ok(|b| b.len())
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap()
+        };
+
+        let stream = coalesce_stream(chunks(&[1, 2, 3, 4, 10, 1]), 5);
+        assert_eq!(sizes(stream).await, vec![6, 4 + 10, 1]);
+
+        let stream = coalesce_stream(chunks(&[5, 5, 2, 3]), 5);
+        assert_eq!(sizes(stream).await, vec![5, 5, 5]);
+
+        let stream = coalesce_stream(chunks(&[]), 5);
+        assert!(sizes(stream).await.is_empty());
+
+        let stream = futures::stream::iter(vec![
+            Ok(Bytes::from("a")),
+            Err(Error::NotImplemented),
+            Ok(Bytes::from("b")),
+        ])
+        .boxed();
+        let mut stream = coalesce_stream(stream, 5);
+        assert!(stream.next().await.unwrap().is_err());
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_split_range() {
+        let src: Bytes = (0..100).collect::<Vec<u8>>().into();
+        let fetches = parking_lot::Mutex::new(vec![]);
+        let fetch = |range: Range<usize>| {
+            fetches.lock().push(range.clone());
+            futures::future::ready(Ok(src.slice(range)))
+        };
+
+        let fetched = split_range(10..95, fetch, 30).await.unwrap();
+        assert_eq!(fetched, src.slice(10..95));
+        assert_eq!(*fetches.lock(), vec![10..40, 40..70, 70..95]);
+
+        fetches.lock().clear();
+        let fetched = split_range(10..20, fetch, 30).await.unwrap();
+        assert_eq!(fetched, src.slice(10..20));
+        assert_eq!(*fetches.lock(), vec![10..20]);
+    }
+
+    #[test]
+    fn test_glob() {
+        let glob = Glob::new(None, "data/*/part-??.parquet");
+        assert_eq!(glob.list_prefix(), Some(Path::from("data")));
+        assert!(glob.matches(&Path::from("data/2023/part-01.parquet")));
+        assert!(!glob.matches(&Path::from("data/2023/part-001.parquet")));
+        assert!(!glob.matches(&Path::from("data/2023/01/part-01.parquet")));
+        assert!(!glob.matches(&Path::from("other/2023/part-01.parquet")));
+
+        let glob = Glob::new(Some(&Path::from("root")), "a/**/*.csv");
+        assert_eq!(glob.list_prefix(), Some(Path::from("root/a")));
+        assert!(glob.matches(&Path::from("root/a/x.csv")));
+        assert!(glob.matches(&Path::from("root/a/b/c/x.csv")));
+        assert!(!glob.matches(&Path::from("root/a/b/x.json")));
+        assert!(!glob.matches(&Path::from("a/x.csv")));
+
+        let glob = Glob::new(None, "*.txt");
+        assert_eq!(glob.list_prefix(), None);
+        assert!(glob.matches(&Path::from("file.txt")));
+        assert!(!glob.matches(&Path::from("dir/file.txt")));
+
+        let glob = Glob::new(None, "dir/file.txt");
+        assert_eq!(glob.list_prefix(), Some(Path::from("dir")));
+        assert!(glob.matches(&Path::from("dir/file.txt")));
+        assert!(!glob.matches(&Path::from("dir/file.txt/nested")));
+    }
 }